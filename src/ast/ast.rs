@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use crate::diagnostics::Span;
+
 #[derive(Debug, Clone)]
 pub enum BinaryOp {
     Add,
@@ -8,6 +10,33 @@ pub enum BinaryOp {
     Divide,
     Equals,
     Mod,
+    NotEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Equals => "==",
+            BinaryOp::Mod => "%",
+            BinaryOp::NotEquals => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEquals => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEquals => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -16,38 +45,94 @@ pub enum UnaryOp {
     Not,
 }
 
+/// Every expression carries the span of the source text it was parsed from
 #[derive(Debug, Clone)]
 pub enum Expression {
-    StringLiteral(String),
-    InterpolatedString(Vec<InterpPart>),
-    Int(i64),
-    Float(f64),
-    Identifier(String),
+    StringLiteral(String, Span),
+    InterpolatedString(Vec<InterpPart>, Span),
+    Int(i64, Span),
+    Float(f64, Span),
+    Bool(bool, Span),
+    /// A colour as written, `#1e90ff`, `#fff` or `rgb(30, 144, 255)`. Colour
+    /// names like `red` are identifiers, see `colour_name`.
+    Color(String, Span),
+    Identifier(String, Span),
     Binary {
         left: Box<Expression>,
         operator: BinaryOp,
         right: Box<Expression>,
+        span: Span,
     },
     Unary {
         operator: UnaryOp,
         expression: Box<Expression>,
+        span: Span,
+    },
+    /// `start..end`, the end is exclusive. Only valid as the iterable of a `for` loop
+    Range {
+        start: Box<Expression>,
+        end: Box<Expression>,
+        span: Span,
+    },
+    StructDefault(String, Span),
+    /// `[a, b, c]`
+    List(Vec<Expression>, Span),
+    /// `{ name: value, ... }`, a record of values by name, in the order written
+    Map(Vec<(String, Expression)>, Span),
+    /// `value[index]`, an item of a list or the entry of a map
+    Index {
+        value: Box<Expression>,
+        index: Box<Expression>,
+        span: Span,
+    },
+    /// `value.name`, an entry of a map
+    Field {
+        value: Box<Expression>,
+        name: String,
+        span: Span,
+    },
+    /// `name(args)`, a call of a function returning a value
+    Call {
+        name: String,
+        args: Vec<Argument>,
+        span: Span,
     },
-    StructDefault(String),
 }
 
 impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::StringLiteral(_, span)
+            | Expression::InterpolatedString(_, span)
+            | Expression::Int(_, span)
+            | Expression::Float(_, span)
+            | Expression::Bool(_, span)
+            | Expression::Color(_, span)
+            | Expression::Identifier(_, span)
+            | Expression::StructDefault(_, span)
+            | Expression::List(_, span)
+            | Expression::Map(_, span)
+            | Expression::Index { span, .. }
+            | Expression::Field { span, .. }
+            | Expression::Binary { span, .. }
+            | Expression::Unary { span, .. }
+            | Expression::Range { span, .. }
+            | Expression::Call { span, .. } => *span,
+        }
+    }
+
     // TODO this is not good find a way to remove
     pub fn as_number(&self) -> Option<i64> {
         match self {
-            Expression::Int(n) => Some(*n),
+            Expression::Int(n, _) => Some(*n),
             _ => None,
         }
     }
 
     pub fn to_string(&self) -> String {
         match self {
-            Expression::StringLiteral(s) => s.clone(),
-            Expression::InterpolatedString(parts) => {
+            Expression::StringLiteral(s, _) => s.clone(),
+            Expression::InterpolatedString(parts, _) => {
                 let mut result = String::new();
                 for part in parts {
                     match part {
@@ -60,14 +145,56 @@ impl Expression {
             Expression::Unary {
                 operator,
                 expression,
+                ..
             } => match operator {
                 UnaryOp::Negate => format!("-{}", expression.to_string()),
                 UnaryOp::Not => format!("!{}", expression.to_string()),
             },
-            Expression::StructDefault(name) => format!("default({})", name),
-            Expression::Int(value) => format!("{}", value),
-            Expression::Float(value) => format!("{}", value),
-            _ => "Error".to_string(),
+            Expression::StructDefault(name, _) => format!("default({})", name),
+            Expression::Int(value, _) => format!("{}", value),
+            Expression::Float(value, _) => format!("{}", value),
+            Expression::Bool(value, _) => value.to_string(),
+            Expression::Color(color, _) => color.clone(),
+            Expression::Identifier(name, _) => name.clone(),
+            Expression::Binary {
+                left,
+                operator,
+                right,
+                ..
+            } => format!(
+                "({} {} {})",
+                left.to_string(),
+                operator.as_str(),
+                right.to_string()
+            ),
+            Expression::Range { start, end, .. } => {
+                format!("{}..{}", start.to_string(), end.to_string())
+            }
+            Expression::List(items, _) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Expression::Map(entries, _) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.to_string()))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Expression::Index { value, index, .. } => {
+                format!("{}[{}]", value.to_string(), index.to_string())
+            }
+            Expression::Field { value, name, .. } => format!("{}.{}", value.to_string(), name),
+            Expression::Call { name, args, .. } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| match &arg.name {
+                        Some(name) => format!("{}={}", name, arg.value.to_string()),
+                        None => arg.value.to_string(),
+                    })
+                    .collect();
+                format!("{}({})", name, args.join(", "))
+            }
         }
     }
 }
@@ -78,10 +205,81 @@ pub enum InterpPart {
     Expression(Expression), // interpolated portion
 }
 
+impl InterpPart {
+    /// Text without `{expression}` parts
+    pub fn literal(text: impl Into<String>) -> Vec<InterpPart> {
+        vec![InterpPart::Text(text.into())]
+    }
+
+    /// The text of `parts`, with each expression written between braces
+    pub fn plain_text(parts: &[InterpPart]) -> String {
+        let mut text = String::new();
+        for part in parts {
+            match part {
+                InterpPart::Text(literal) => text.push_str(literal),
+                InterpPart::Expression(expr) => {
+                    text.push('{');
+                    text.push_str(&expr.to_string());
+                    text.push('}');
+                }
+            }
+        }
+        text
+    }
+}
+
+/// A piece of a text body. Plain strings are split on `*bold*`, `_italic_`
+/// and `` `code` `` markup, `span(...) { ... }` gives part of the text
+/// its own attributes and `link(href=...) { ... }` links part of it. The
+/// `{expression}` parts of the text are parsed along with it.
+#[derive(Debug, Clone)]
+pub enum Inline {
+    Text(Vec<InterpPart>),
+    Emphasis(Emphasis, Vec<InterpPart>),
+    Span {
+        content: Vec<Inline>,
+        attributes: HashMap<String, Expression>,
+        span: Span,
+    },
+    /// Like `DocElement::Link`, inside the text around it
+    Link {
+        href: String,
+        content: Vec<InterpPart>,
+        attributes: HashMap<String, Expression>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emphasis {
+    Bold,
+    Italic,
+    Code,
+}
+
+impl Inline {
+    /// The text of `inlines` without markup, with each expression written
+    /// between braces
+    pub fn plain_text(inlines: &[Inline]) -> String {
+        let mut text = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(content)
+                | Inline::Emphasis(_, content)
+                | Inline::Link { content, .. } => text.push_str(&InterpPart::plain_text(content)),
+                Inline::Span { content, .. } => text.push_str(&Inline::plain_text(content)),
+            }
+        }
+        text
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FuncParam {
     pub ty: String,
     pub value: Expression,
+    pub default: Option<Expression>, // `name: Type = default`
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -93,12 +291,15 @@ pub struct TemplateBlock {
 pub struct KeyValue {
     pub key: String,
     pub value: Expression,
+    pub span: Span,
 }
 
+/// An argument of a call, `name` is set for one written `name = value`
 #[derive(Debug, Clone)]
-pub struct ArgType {
-    pub name: String,
-    pub ty: String,
+pub struct Argument {
+    pub name: Option<String>,
+    pub value: Expression,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -107,76 +308,170 @@ pub enum Statement {
     DefaultSet {
         key: String,
         value: Expression,
+        span: Span,
     },
     VarAssign {
         // value should never be an expression, should always be explicit
         name: String,
+        /// the type written after the name, `let x: Int = 1`
+        ty: Option<String>,
         value: Expression,
+        span: Span,
     },
     ConstAssign {
         name: String,
+        ty: Option<String>,
         value: Expression,
+        span: Span,
     },
     If {
         condition: Expression,
         body: Vec<Statement>,
         else_body: Option<Vec<Statement>>,
+        span: Span,
     },
     While {
         condition: Expression,
         body: Vec<Statement>,
+        span: Span,
     },
     For {
         iterator: String,
         iterable: Expression,
         body: Vec<Statement>,
+        span: Span,
     },
+    /// `return` of an element, or of a fragment: `return { ... }` holding
+    /// any number of elements, which are placed where the function is called.
+    /// A bare `return` returns an empty fragment.
     Return {
-        doc_element: DocElement,
+        doc_elements: Vec<DocElement>,
+        span: Span,
     },
-    /// name(args) { body... }
+    /// `return` of a value, from a function declared `-> Type`
+    ReturnValue { value: Expression, span: Span },
+    /// name(args) -> Type { body... }
     FunctionDecl {
         name: String,
         args: Vec<FuncParam>, // probably empty for now
+        return_type: Option<String>,
         body: Vec<Statement>,
+        span: Span,
     },
 }
 
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::DefaultSet { span, .. }
+            | Statement::VarAssign { span, .. }
+            | Statement::ConstAssign { span, .. }
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::For { span, .. }
+            | Statement::Return { span, .. }
+            | Statement::ReturnValue { span, .. }
+            | Statement::FunctionDecl { span, .. } => *span,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum DocElement {
+    /// `content` is the plain text of `inlines`, with each expression
+    /// written between braces
     Text {
         content: String,
+        inlines: Vec<Inline>,
         attributes: HashMap<String, Expression>,
+        span: Span,
     },
     Image {
         src: String,
         attributes: HashMap<String, Expression>,
+        span: Span,
     },
+    /// `rows` are `TableRow`s, or `if` and `for` around them
     Table {
-        rows: Vec<Vec<DocElement>>,
+        rows: Vec<DocElement>,
         attributes: HashMap<String, Expression>,
+        span: Span,
+    },
+    /// `row { ... }` or `header { ... }`, `cells` are `TableCell`s or
+    /// `if` and `for` around them
+    TableRow {
+        header: bool,
+        cells: Vec<DocElement>,
+        span: Span,
+    },
+    /// `cell(colspan=2) { ... }`, the spans are 1 unless given
+    TableCell {
+        content: Vec<InterpPart>,
+        colspan: usize,
+        rowspan: usize,
+        attributes: HashMap<String, Expression>,
+        span: Span,
     },
     List {
         items: Vec<DocElement>,
         attributes: HashMap<String, Expression>,
+        span: Span,
     },
     Code {
         content: String,
         attributes: HashMap<String, Expression>,
+        span: Span,
     },
     Call {
         name: String,
-        args: Vec<ArgType>,
+        args: Vec<Argument>,
+        span: Span,
     },
     Link {
         href: String,
-        content: String,
+        content: Vec<InterpPart>,
         attributes: HashMap<String, Expression>,
+        span: Span,
     },
     Section {
         elements: Vec<DocElement>,
         attributes: HashMap<String, Expression>,
+        span: Span,
     },
+    /// `if cond { ... } else { ... }` around elements, `else if` is an
+    /// `else` body holding a single `If`
+    If {
+        condition: Expression,
+        body: Vec<DocElement>,
+        else_body: Option<Vec<DocElement>>,
+        span: Span,
+    },
+    /// `for x in iterable { ... }`, the body is repeated for every value
+    For {
+        iterator: String,
+        iterable: Expression,
+        body: Vec<DocElement>,
+        span: Span,
+    },
+}
+
+impl DocElement {
+    pub fn span(&self) -> Span {
+        match self {
+            DocElement::Text { span, .. }
+            | DocElement::Image { span, .. }
+            | DocElement::Table { span, .. }
+            | DocElement::TableRow { span, .. }
+            | DocElement::TableCell { span, .. }
+            | DocElement::List { span, .. }
+            | DocElement::Code { span, .. }
+            | DocElement::Call { span, .. }
+            | DocElement::Link { span, .. }
+            | DocElement::Section { span, .. }
+            | DocElement::If { span, .. }
+            | DocElement::For { span, .. } => *span,
+        }
+    }
 }
 
 // Document Block
@@ -186,15 +481,17 @@ pub struct StyleRule {
     pub selector_list: Vec<Selector>,
     pub declaration_block: Vec<KeyValue>,
     pub specificity: u32, // Pre-computed specificity for cascade ordering
+    pub span: Span,
 }
 
 impl StyleRule {
-    pub fn new(selector_list: Vec<Selector>, declaration_block: Vec<KeyValue>) -> Self {
+    pub fn new(selector_list: Vec<Selector>, declaration_block: Vec<KeyValue>, span: Span) -> Self {
         let specificity = Self::compute_specificity(&selector_list);
         Self {
             selector_list,
             declaration_block,
             specificity,
+            span,
         }
     }
 
@@ -1,6 +1,6 @@
 mod ast;
 
 pub use ast::{
-    ArgType, Ast, BinaryOp, DocElement, DocumentBlock, Expression, FuncParam, InterpPart, KeyValue,
-    Selector, Statement, StyleBlock, StyleRule, TemplateBlock, UnaryOp,
+    Argument, Ast, BinaryOp, DocElement, DocumentBlock, Emphasis, Expression, FuncParam, Inline,
+    InterpPart, KeyValue, Selector, Statement, StyleBlock, StyleRule, TemplateBlock, UnaryOp,
 };
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use crate::backend::render::pdf::pdf_renderer::PdfRenderer;
 use crate::hlir::HLIRModule;
 
@@ -16,11 +18,12 @@ impl Backend {
         Self { renderer }
     }
 
-    pub fn render(&self, hlir: HLIRModule) -> Result<(), std::io::Error> {
+    /// Render the module and write the result to `output`
+    pub fn render(&self, hlir: HLIRModule, output: &Path) -> Result<(), std::io::Error> {
         match self.renderer {
             Renderer::Pdf => {
                 let renderer = PdfRenderer::new();
-                renderer.render(hlir)
+                renderer.render(hlir, output)
             }
             Renderer::Epub => todo!(),
             Renderer::Wasm => todo!(),
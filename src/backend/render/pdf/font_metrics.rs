@@ -0,0 +1,26 @@
+/// Advance widths of Helvetica for the printable ASCII characters, in
+/// thousandths of the font size (from the Adobe font metrics)
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, // ' '../
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // 0..?
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // @..O
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // P.._
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // `..o
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, // p..~
+];
+
+/// Width of `text` set in Helvetica at `size` points. Characters outside
+/// ASCII are counted as wide as a digit.
+pub fn helvetica_width(text: &str, size: f32) -> f32 {
+    let units: u32 = text
+        .chars()
+        .map(|c| {
+            (c as usize)
+                .checked_sub(32)
+                .and_then(|i| HELVETICA_WIDTHS.get(i))
+                .copied()
+                .unwrap_or(556) as u32
+        })
+        .sum();
+    units as f32 * size / 1000.0
+}
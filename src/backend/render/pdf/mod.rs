@@ -1 +1,3 @@
+pub mod font_metrics;
+pub mod pdf_image;
 pub mod pdf_renderer;
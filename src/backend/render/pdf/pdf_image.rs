@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use printpdf::{DictItem, ExternalStream, ExternalXObject};
+
+use crate::image::{ImageFormat, ImageInfo, PixelFormat, decode_png};
+
+/// Build the image XObject for a file. printpdf can only embed decoded
+/// images with its `images` feature, so the stream is written by hand:
+/// JPEG data goes in as is for the viewer to decode (`DCTDecode`), PNGs are
+/// decoded to raw samples.
+pub fn image_xobject(path: &Path, info: &ImageInfo) -> io::Result<ExternalXObject> {
+    let bytes = fs::read(path)?;
+    let (colour_space, content, filter) = match info.format {
+        ImageFormat::Jpeg { components } => {
+            let colour_space = match components {
+                1 => "DeviceGray",
+                4 => "DeviceCMYK",
+                _ => "DeviceRGB",
+            };
+            (colour_space, bytes, Some("DCTDecode"))
+        }
+        ImageFormat::Png => {
+            let image = decode_png(&bytes).map_err(|message| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), message),
+                )
+            })?;
+            let colour_space = match image.format {
+                PixelFormat::Grey => "DeviceGray",
+                PixelFormat::Rgb => "DeviceRGB",
+            };
+            (colour_space, image.pixels, None)
+        }
+    };
+
+    let name = |name: &str| DictItem::Name(name.as_bytes().to_vec());
+    let mut dict = BTreeMap::new();
+    dict.insert("Type".to_string(), name("XObject"));
+    dict.insert("Subtype".to_string(), name("Image"));
+    dict.insert("Width".to_string(), DictItem::Int(info.width as i64));
+    dict.insert("Height".to_string(), DictItem::Int(info.height as i64));
+    dict.insert("ColorSpace".to_string(), name(colour_space));
+    dict.insert("BitsPerComponent".to_string(), DictItem::Int(8));
+    if let Some(filter) = filter {
+        dict.insert("Filter".to_string(), name(filter));
+    }
+
+    Ok(ExternalXObject {
+        stream: ExternalStream {
+            dict,
+            content,
+            compress: filter.is_none(), // JPEG data is already compressed
+        },
+        // no size, so the image fills the unit square and the transform
+        // scales it to its size on the page
+        width: None,
+        height: None,
+        dpi: None,
+    })
+}
@@ -1,47 +1,160 @@
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::BufWriter;
 use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
 
 use printpdf::{
-    BuiltinFont, Mm, Op, PdfDocument, PdfFontHandle, PdfPage, PdfSaveOptions, Point, Pt, TextItem,
+    Actions, BorderArray, BuiltinFont, Color, Destination, LinkAnnotation, Mm, Op, PdfDocument,
+    PdfFontHandle, PdfPage, PdfSaveOptions, Point, Pt, Rect, Rgb, TextItem, XObjectTransform,
 };
 
-use crate::hlir::{FuncId, HLIRModule, HlirElement, Id, Op as HlirOp};
+use crate::ast::Emphasis;
+use crate::backend::render::pdf::font_metrics::helvetica_width;
+use crate::backend::render::pdf::pdf_image::image_xobject;
+use crate::hlir::{FuncId, HLIRModule, HlirElement, Id, Op as HlirOp, StyleAttributes, TextRun};
+use crate::layout::{
+    CELL_PADDING, ComputedLayout, LINE_HEIGHT, LayoutEngine, cell_padding, image_size, setup_layout,
+};
+
+// A4, with the content area inset by the margin on every side
+const PAGE_WIDTH: Mm = Mm(210.0);
+const PAGE_HEIGHT: Mm = Mm(297.0);
+const MARGIN: Mm = Mm(10.0);
+
+/// Share of the line height below the baseline, for Helvetica's descenders
+const DESCENT: f32 = 0.2;
+
+/// Code is set a little smaller than text, on the same line height
+const CODE_FONT_SIZE: f32 = 10.0;
 
 pub struct PdfRenderer;
 
+/// Splits the layout, one long column, into pages. Elements keep the place
+/// the layout engine gave them, moved down by the page breaks before them.
+struct Pages {
+    ops: Vec<Vec<Op>>,
+    height: f32, // of the content area of a page
+    shift: f32,  // how far page breaks so far pushed the content down
+    /// Page and y on that page of every element drawn so far
+    placed: HashMap<usize, (usize, f32)>,
+    /// Links to `#id`s, annotated once every element is placed
+    internal_links: Vec<(usize, Rect, String)>,
+}
+
+impl Pages {
+    fn new(height: f32) -> Self {
+        Self {
+            ops: vec![Vec::new()],
+            height,
+            shift: 0.0,
+            placed: HashMap::new(),
+            internal_links: Vec::new(),
+        }
+    }
+
+    /// Page for a box that is not split: the one its top is on, or the next
+    /// one when it would run off the bottom. Boxes taller than a page stay
+    /// where they start.
+    fn place(&mut self, top: f32, height: f32) -> usize {
+        let y = top + self.shift;
+        let page = (y / self.height).floor() as usize;
+        let page_top = page as f32 * self.height;
+        if y + height <= page_top + self.height || y <= page_top || height > self.height {
+            return page;
+        }
+        self.shift += page_top + self.height - y;
+        page + 1
+    }
+
+    /// `bounds` on `page`, measured from the top of its content area
+    fn on_page(&self, bounds: &ComputedLayout, page: usize) -> ComputedLayout {
+        ComputedLayout {
+            y: bounds.y + self.shift - page as f32 * self.height,
+            ..bounds.clone()
+        }
+    }
+
+    fn ops(&mut self, page: usize) -> &mut Vec<Op> {
+        if self.ops.len() <= page {
+            self.ops.resize_with(page + 1, Vec::new);
+        }
+        &mut self.ops[page]
+    }
+}
+
 impl PdfRenderer {
     pub fn new() -> Self {
         Self
     }
 
-    pub fn render(&self, hlir: HLIRModule) -> Result<(), std::io::Error> {
+    pub fn render(&self, hlir: HLIRModule, output: &Path) -> Result<(), std::io::Error> {
         let mut doc = PdfDocument::new("Document");
 
-        let pages = self.setup_pages(hlir);
+        let mut layout = setup_layout(&hlir);
+        let content_width = Pt::from(PAGE_WIDTH).0 - 2.0 * Pt::from(MARGIN).0;
+        let content_height = Pt::from(PAGE_HEIGHT).0 - 2.0 * Pt::from(MARGIN).0;
+        layout.compute_layout(content_width, content_height);
+
+        let pages = self.setup_pages(&hlir, &layout, &mut doc)?;
         let pdf_bytes = doc
             .with_pages(pages)
             .save(&PdfSaveOptions::default(), &mut Vec::new());
 
-        fs::create_dir_all("generated")?;
-        let file = File::create("generated/output.pdf")?;
+        if let Some(parent) = output.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(output)?;
         let mut writer = BufWriter::new(file);
         writer.write_all(&pdf_bytes)?;
 
         Ok(())
     }
 
-    fn setup_pages(&self, hlir: HLIRModule) -> Vec<PdfPage> {
-        let mut pages = Vec::new();
+    fn setup_pages(
+        &self,
+        hlir: &HLIRModule,
+        layout: &LayoutEngine,
+        doc: &mut PdfDocument,
+    ) -> Result<Vec<PdfPage>, std::io::Error> {
+        let content_height = Pt::from(PAGE_HEIGHT).0 - 2.0 * Pt::from(MARGIN).0;
+        let mut pages = Pages::new(content_height);
+        self.setup_ops(hlir, layout, doc, &mut pages)?;
 
-        let ops = self.setup_ops(hlir);
-        let page = PdfPage::new(Mm(210.0), Mm(297.0), ops);
-        pages.push(page);
+        for (page, area, id) in std::mem::take(&mut pages.internal_links) {
+            // targets are checked when lowering, a missing one is not clickable
+            let Some(&(target_page, target_y)) = layout
+                .get_layout_by_id(&id)
+                .and_then(|target| pages.placed.get(&target.element_index))
+            else {
+                continue;
+            };
+            let actions = Actions::go_to(Destination::Xyz {
+                page: target_page + 1,
+                left: None,
+                top: Some(Self::page_y(target_y).0),
+                zoom: None,
+            });
+            pages.ops(page).push(Self::link_annotation(area, actions));
+        }
 
-        pages
+        Ok(pages
+            .ops
+            .into_iter()
+            .map(|ops| PdfPage::new(PAGE_WIDTH, PAGE_HEIGHT, ops))
+            .collect())
     }
 
-    fn setup_ops(&self, hlir: HLIRModule) -> Vec<Op> {
+    fn setup_ops(
+        &self,
+        hlir: &HLIRModule,
+        layout: &LayoutEngine,
+        doc: &mut PdfDocument,
+        pages: &mut Pages,
+    ) -> Result<(), std::io::Error> {
         // vec![
         //     Op::StartTextSection,
         //     Op::SetTextCursor {
@@ -56,9 +169,6 @@ impl PdfRenderer {
         //     },
         //     Op::EndTextSection,
         // ]
-        let mut pdf_ops = Vec::new();
-        let mut point = Point::new(Mm(10.0), Mm(270.0));
-
         let document_id = FuncId(hlir.functions.len() - 1);
         let document = hlir
             .functions
@@ -66,67 +176,474 @@ impl PdfRenderer {
             .expect("document function not found");
         for op in &document.body.ops {
             match op {
-                HlirOp::HlirElementEmit { index } => {
-                    let element = hlir.elements.get(*index).expect("element not found");
-                    self.format_hlir_to_pdf_op(element.clone(), &hlir, &mut pdf_ops, &mut point);
+                HlirOp::HlirElementEmit { index, .. } => {
+                    self.format_hlir_to_pdf_op(*index, hlir, layout, doc, pages)?;
                 }
-                HlirOp::Call { result, func, args } => {
-                    let func = hlir.functions.get(&func).expect("func not found");
-                    let returned_element_ref = func.body.returned_element_ref;
-                    if let Some(ref returned_element_ref) = returned_element_ref {
-                        let element = hlir
-                            .elements
-                            .get(*returned_element_ref)
-                            .expect("element not found");
-                        self.format_hlir_to_pdf_op(
-                            element.clone(),
-                            &hlir,
-                            &mut pdf_ops,
-                            &mut point,
-                        );
-                    }
+                HlirOp::Call {
+                    instance: Some(instance),
+                    ..
+                } => {
+                    self.format_hlir_to_pdf_op(*instance, hlir, layout, doc, pages)?;
                 }
                 _ => {}
             }
         }
-        pdf_ops
+        Ok(())
     }
 
+    /// Draw the element at `index` where the layout engine placed it, on
+    /// the page it falls on
     fn format_hlir_to_pdf_op(
         &self,
-        element: HlirElement,
+        index: usize,
         hlir: &HLIRModule,
-        pdf_ops: &mut Vec<Op>,
-        point: &mut Point,
-    ) {
+        layout: &LayoutEngine,
+        doc: &mut PdfDocument,
+        pages: &mut Pages,
+    ) -> Result<(), std::io::Error> {
+        let element = hlir.elements.get(index).expect("element not found");
+        // a fragment has no layout of its own, its elements are drawn in place
+        if let HlirElement::Fragment { children, .. } = element {
+            for child_idx in children {
+                self.format_hlir_to_pdf_op(*child_idx, hlir, layout, doc, pages)?;
+            }
+            if let Some(first) = children.iter().find_map(|child| pages.placed.get(child)) {
+                pages.placed.insert(index, *first);
+            }
+            return Ok(());
+        }
+        let Some(bounds) = layout.get_element_layout(index) else {
+            return Ok(()); // not part of the document's layout
+        };
+
+        let children = match element {
+            HlirElement::List { children, .. } | HlirElement::Section { children, .. } => children,
+            HlirElement::Table {
+                children,
+                header_rows,
+                ..
+            } => {
+                self.draw_table(children, *header_rows, hlir, layout, pages);
+                children
+            }
+            HlirElement::TableCell { .. } => return Ok(()), // drawn with their table
+            HlirElement::Code { .. } => {
+                self.draw_code(index, element, &bounds, hlir, pages);
+                return Ok(());
+            }
+            _ => {
+                let page = pages.place(bounds.y, bounds.height);
+                let bounds = pages.on_page(&bounds, page);
+                pages.placed.insert(index, (page, bounds.y));
+                self.draw_leaf(element, &bounds, page, hlir, doc, pages)?;
+                return Ok(());
+            }
+        };
+
+        for child_idx in children {
+            self.format_hlir_to_pdf_op(*child_idx, hlir, layout, doc, pages)?;
+        }
+        // a container is where its first element ended up
+        let first = children.iter().find_map(|child| pages.placed.get(child));
+        let placement = match first {
+            Some(placement) => *placement,
+            None => {
+                let page = pages.place(bounds.y, 0.0);
+                (page, pages.on_page(&bounds, page).y)
+            }
+        };
+        pages.placed.insert(index, placement);
+        Ok(())
+    }
+
+    /// Draw text, a link or an image at `bounds` on `page`
+    fn draw_leaf(
+        &self,
+        element: &HlirElement,
+        bounds: &ComputedLayout,
+        page: usize,
+        hlir: &HLIRModule,
+        doc: &mut PdfDocument,
+        pages: &mut Pages,
+    ) -> Result<(), std::io::Error> {
+        let pdf_ops = pages.ops(page);
         match element {
-            HlirElement::Text { content, .. } => {
-                pdf_ops.push(Op::StartTextSection);
-                pdf_ops.push(Op::SetTextCursor { pos: *point });
-                pdf_ops.push(Op::SetFont {
-                    font: PdfFontHandle::Builtin(BuiltinFont::Helvetica),
-                    size: Pt(12.0),
+            HlirElement::Text { runs, .. } => {
+                for (area, href) in Self::push_runs(runs, bounds, hlir, pdf_ops) {
+                    Self::link_area(&href, area, page, pages);
+                }
+            }
+            HlirElement::Link { href, content, .. } => {
+                let blue = Color::Rgb(Rgb::new(0.0, 0.0, 0.8, None));
+                let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+                pdf_ops.push(Op::SetFillColor { col: blue });
+                Self::push_text(content, bounds, BuiltinFont::Helvetica, pdf_ops);
+                pdf_ops.push(Op::SetFillColor { col: black });
+
+                let width = helvetica_width(content, LINE_HEIGHT).min(bounds.width);
+                let area = Rect::from_xywh(
+                    Self::page_x(bounds.x),
+                    Self::page_y(bounds.y + bounds.height),
+                    Pt(width),
+                    Pt(bounds.height),
+                );
+                Self::link_area(href, area, page, pages);
+            }
+            HlirElement::Image {
+                path,
+                info: Some(info),
+                attributes,
+                ..
+            } => {
+                let (width, height) = match hlir.attributes.find_node(*attributes) {
+                    Some(node) => image_size(info, &node.computed),
+                    None => info.display_size(None, None),
+                };
+                let id = doc.add_xobject(&image_xobject(path, info)?);
+                pdf_ops.push(Op::UseXobject {
+                    id,
+                    transform: XObjectTransform {
+                        translate_x: Some(Self::page_x(bounds.x)),
+                        translate_y: Some(Self::page_y(bounds.y + bounds.height)),
+                        scale_x: Some(width),
+                        scale_y: Some(height),
+                        ..Default::default()
+                    },
                 });
+            }
+            _ => {} // unreadable images are reported by `load_images`
+        }
+        Ok(())
+    }
+
+    /// Draw a table a band of rows at a time. A page only breaks between
+    /// rows that no `rowspan` joins and never right below the header, which
+    /// is drawn again at the top of every page the table continues on.
+    fn draw_table(
+        &self,
+        children: &[usize],
+        header_rows: usize,
+        hlir: &HLIRModule,
+        layout: &LayoutEngine,
+        pages: &mut Pages,
+    ) {
+        // every cell with its layout and the rows it covers
+        let cells: Vec<(usize, ComputedLayout, Range<usize>)> = children
+            .iter()
+            .filter_map(|&index| {
+                let HlirElement::TableCell { row, rowspan, .. } = hlir.elements[index] else {
+                    return None;
+                };
+                Some((index, layout.get_element_layout(index)?, row..row + rowspan))
+            })
+            .collect();
+        let row_count = cells.iter().map(|(_, _, rows)| rows.end).max().unwrap_or(0);
+
+        let mut bands = Vec::new();
+        let mut band_start = 0;
+        for end in 1..=row_count {
+            let joined = cells
+                .iter()
+                .any(|(_, _, rows)| rows.start < end && end < rows.end);
+            if !joined && end > header_rows {
+                bands.push(band_start..end);
+                band_start = end;
+            }
+        }
+        if band_start < row_count {
+            bands.push(band_start..row_count); // a table of nothing but headers
+        }
+
+        // top and bottom of the cells starting in `rows`
+        let extent = |rows: &Range<usize>| {
+            cells
+                .iter()
+                .filter(|(_, _, cell_rows)| rows.contains(&cell_rows.start))
+                .map(|(_, bounds, _)| (bounds.y, bounds.y + bounds.height))
+                .reduce(|(top, bottom), (y, end)| (top.min(y), bottom.max(end)))
+        };
+        let header = extent(&(0..header_rows));
+
+        for (i, band) in bands.iter().enumerate() {
+            let Some((top, bottom)) = extent(band) else {
+                continue;
+            };
+            let shift = pages.shift;
+            let page = pages.place(top, bottom - top);
+            if i > 0
+                && pages.shift > shift
+                && let Some((header_top, header_bottom)) = header
+            {
+                // the header goes at the top of the new page, above the band
+                for (index, bounds, _) in
+                    cells.iter().filter(|(_, _, rows)| rows.start < header_rows)
+                {
+                    let bounds = ComputedLayout {
+                        y: bounds.y - header_top,
+                        ..bounds.clone()
+                    };
+                    Self::draw_cell(&hlir.elements[*index], &bounds, hlir, pages.ops(page));
+                }
+                pages.shift += header_bottom - header_top;
+            }
+            for (index, bounds, _) in cells
+                .iter()
+                .filter(|(_, _, rows)| band.contains(&rows.start))
+            {
+                let bounds = pages.on_page(bounds, page);
+                pages.placed.insert(*index, (page, bounds.y));
+                Self::draw_cell(&hlir.elements[*index], &bounds, hlir, pages.ops(page));
+            }
+        }
+    }
+
+    /// Draw a code block a line at a time, so long listings continue on the
+    /// next page, with the line numbers in a gutter on the left when enabled
+    fn draw_code(
+        &self,
+        index: usize,
+        code: &HlirElement,
+        bounds: &ComputedLayout,
+        hlir: &HLIRModule,
+        pages: &mut Pages,
+    ) {
+        let HlirElement::Code {
+            lines,
+            line_numbers,
+            colors,
+            ..
+        } = code
+        else {
+            return;
+        };
+        let padding = hlir
+            .attributes
+            .find_node(code.attributes())
+            .and_then(|node| node.computed.padding)
+            .unwrap_or(0.0);
+        // Courier is monospaced, so numbers padded to the same width line up
+        let digits = lines.len().to_string().len();
+        let grey = Color::Rgb(Rgb::new(0.5, 0.5, 0.5, None));
+        let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+
+        for (number, line) in lines.iter().enumerate() {
+            let top = bounds.y + padding + number as f32 * LINE_HEIGHT;
+            let page = pages.place(top, LINE_HEIGHT);
+            let y = top + pages.shift - page as f32 * pages.height;
+            if number == 0 {
+                pages.placed.insert(index, (page, y));
+            }
+            let pdf_ops = pages.ops(page);
+            pdf_ops.push(Op::StartTextSection);
+            pdf_ops.push(Op::SetTextCursor {
+                pos: Point {
+                    x: Self::page_x(bounds.x + padding),
+                    y: Self::page_y(y + LINE_HEIGHT) + Pt(LINE_HEIGHT * DESCENT),
+                },
+            });
+            pdf_ops.push(Op::SetFont {
+                font: PdfFontHandle::Builtin(BuiltinFont::Courier),
+                size: Pt(CODE_FONT_SIZE),
+            });
+            if *line_numbers {
+                pdf_ops.push(Op::SetFillColor { col: grey.clone() });
                 pdf_ops.push(Op::ShowText {
-                    items: vec![TextItem::Text(content.clone())],
+                    items: vec![TextItem::Text(format!("{:>digits$}  ", number + 1))],
                 });
-                pdf_ops.push(Op::EndTextSection);
-                point.y -= Pt(12.0);
-            }
-            HlirElement::List { children, .. } => {
-                for child_idx in children {
-                    if let Some(child) = hlir.elements.get(child_idx) {
-                        self.format_hlir_to_pdf_op(child.clone(), hlir, pdf_ops, point);
-                    }
-                }
             }
-            HlirElement::Section { children, .. } => {
-                for child_idx in children {
-                    if let Some(child) = hlir.elements.get(child_idx) {
-                        self.format_hlir_to_pdf_op(child.clone(), hlir, pdf_ops, point);
-                    }
-                }
+            for run in line {
+                let col = match run.class.and_then(|class| colors.get(&class)) {
+                    Some(color) => Color::Rgb(Rgb::new(color.r, color.g, color.b, None)),
+                    None => black.clone(),
+                };
+                pdf_ops.push(Op::SetFillColor { col });
+                pdf_ops.push(Op::ShowText {
+                    items: vec![TextItem::Text(run.text.replace('\t', "    "))],
+                });
             }
+            pdf_ops.push(Op::SetFillColor { col: black.clone() });
+            pdf_ops.push(Op::EndTextSection);
         }
     }
+
+    /// The border of a table cell and its text, set in bold in the header
+    fn draw_cell(
+        cell: &HlirElement,
+        bounds: &ComputedLayout,
+        hlir: &HLIRModule,
+        pdf_ops: &mut Vec<Op>,
+    ) {
+        let HlirElement::TableCell {
+            content, header, ..
+        } = cell
+        else {
+            return;
+        };
+        let border = Rect::from_xywh(
+            Self::page_x(bounds.x),
+            Self::page_y(bounds.y + bounds.height),
+            Pt(bounds.width),
+            Pt(bounds.height),
+        );
+        pdf_ops.push(Op::SetOutlineColor {
+            col: Color::Rgb(Rgb::new(0.6, 0.6, 0.6, None)),
+        });
+        pdf_ops.push(Op::SetOutlineThickness { pt: Pt(0.5) });
+        pdf_ops.push(Op::DrawLine {
+            line: border.to_line(),
+        });
+
+        let padding = hlir
+            .attributes
+            .find_node(cell.attributes())
+            .map_or(CELL_PADDING, |node| cell_padding(&node.computed));
+        let line = ComputedLayout {
+            x: bounds.x + padding,
+            y: bounds.y + padding,
+            width: bounds.width - 2.0 * padding,
+            height: LINE_HEIGHT,
+            element_index: bounds.element_index,
+        };
+        let font = if *header {
+            BuiltinFont::HelveticaBold
+        } else {
+            BuiltinFont::Helvetica
+        };
+        Self::push_text(content, &line, font, pdf_ops);
+    }
+
+    fn link_annotation(area: Rect, actions: Actions) -> Op {
+        let no_border = BorderArray::Solid([0.0, 0.0, 0.0]);
+        Op::LinkAnnotation {
+            link: LinkAnnotation::new(area, actions, Some(no_border), None, None),
+        }
+    }
+
+    /// One line of text on the baseline of its layout box
+    fn push_text(content: &str, bounds: &ComputedLayout, font: BuiltinFont, pdf_ops: &mut Vec<Op>) {
+        let baseline = Self::page_y(bounds.y + bounds.height) + Pt(LINE_HEIGHT * DESCENT);
+        pdf_ops.push(Op::StartTextSection);
+        pdf_ops.push(Op::SetTextCursor {
+            pos: Point {
+                x: Self::page_x(bounds.x),
+                y: baseline,
+            },
+        });
+        pdf_ops.push(Op::SetFont {
+            font: PdfFontHandle::Builtin(font),
+            size: Pt(LINE_HEIGHT),
+        });
+        pdf_ops.push(Op::ShowText {
+            items: vec![TextItem::Text(content.to_string())],
+        });
+        pdf_ops.push(Op::EndTextSection);
+    }
+
+    /// Make `area` on `page` a link to `href`. Links to `#id`s are annotated
+    /// once every element is placed.
+    fn link_area(href: &str, area: Rect, page: usize, pages: &mut Pages) {
+        match href.strip_prefix('#') {
+            Some(id) => pages.internal_links.push((page, area, id.to_string())),
+            None => {
+                let actions = Actions::uri(href.to_string());
+                pages.ops(page).push(Self::link_annotation(area, actions));
+            }
+        }
+    }
+
+    /// The runs of a text element one after the other on a line, each in
+    /// the font and colour of its computed style and markup. Gives the area
+    /// and target of each run that is a link, which is blue unless styled.
+    fn push_runs(
+        runs: &[TextRun],
+        bounds: &ComputedLayout,
+        hlir: &HLIRModule,
+        pdf_ops: &mut Vec<Op>,
+    ) -> Vec<(Rect, String)> {
+        let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+        let blue = Color::Rgb(Rgb::new(0.0, 0.0, 0.8, None));
+        let mut links = Vec::new();
+        let mut x = bounds.x;
+        pdf_ops.push(Op::StartTextSection);
+        pdf_ops.push(Op::SetTextCursor {
+            pos: Point {
+                x: Self::page_x(bounds.x),
+                y: Self::page_y(bounds.y + bounds.height) + Pt(LINE_HEIGHT * DESCENT),
+            },
+        });
+        for run in runs {
+            let style = hlir
+                .attributes
+                .find_node(run.attributes)
+                .map(|node| &node.computed);
+            pdf_ops.push(Op::SetFont {
+                font: PdfFontHandle::Builtin(run_font(style, run.emphasis)),
+                size: Pt(LINE_HEIGHT),
+            });
+            let color = style
+                .and_then(|style| style.get("color"))
+                .and_then(|color| color.parse::<crate::hlir::Color>().ok());
+            let col = match color {
+                Some(color) => Color::Rgb(Rgb::new(color.r, color.g, color.b, None)),
+                None if run.href.is_some() => blue.clone(),
+                None => black.clone(),
+            };
+            pdf_ops.push(Op::SetFillColor { col });
+            pdf_ops.push(Op::ShowText {
+                items: vec![TextItem::Text(run.text.clone())],
+            });
+
+            let width = helvetica_width(&run.text, LINE_HEIGHT);
+            if let Some(href) = &run.href {
+                let area = Rect::from_xywh(
+                    Self::page_x(x),
+                    Self::page_y(bounds.y + bounds.height),
+                    Pt(width.min(bounds.x + bounds.width - x).max(0.0)),
+                    Pt(bounds.height),
+                );
+                links.push((area, href.clone()));
+            }
+            x += width;
+        }
+        pdf_ops.push(Op::SetFillColor { col: black });
+        pdf_ops.push(Op::EndTextSection);
+        links
+    }
+
+    /// Layout coordinates start at the top left of the content area, PDF
+    /// coordinates at the bottom left of the page
+    fn page_x(x: f32) -> Pt {
+        Pt::from(MARGIN) + Pt(x)
+    }
+
+    fn page_y(y: f32) -> Pt {
+        Pt::from(PAGE_HEIGHT) - Pt::from(MARGIN) - Pt(y)
+    }
+}
+
+/// The builtin font for a run: bold for a bold `font-weight`, oblique for an
+/// italic `font-style` and Courier for a monospace `font-family`, or for the
+/// same markup on the run
+fn run_font(style: Option<&StyleAttributes>, emphasis: Option<Emphasis>) -> BuiltinFont {
+    let property = |name| style.and_then(|style| style.get(name)).unwrap_or_default();
+    let weight = property("font-weight");
+    let bold = emphasis == Some(Emphasis::Bold)
+        || weight == "bold"
+        || weight == "bolder"
+        || weight.parse::<u32>().is_ok_and(|weight| weight >= 600);
+    let italic = emphasis == Some(Emphasis::Italic)
+        || matches!(&*property("font-style"), "italic" | "oblique");
+    let family = property("font-family").to_lowercase();
+    let mono =
+        emphasis == Some(Emphasis::Code) || family.contains("mono") || family.contains("courier");
+    match (mono, bold, italic) {
+        (false, false, false) => BuiltinFont::Helvetica,
+        (false, true, false) => BuiltinFont::HelveticaBold,
+        (false, false, true) => BuiltinFont::HelveticaOblique,
+        (false, true, true) => BuiltinFont::HelveticaBoldOblique,
+        (true, false, false) => BuiltinFont::Courier,
+        (true, true, false) => BuiltinFont::CourierBold,
+        (true, false, true) => BuiltinFont::CourierOblique,
+        (true, true, true) => BuiltinFont::CourierBoldOblique,
+    }
 }
@@ -0,0 +1,195 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: pyrus <command> [options]
+
+Commands:
+    compile <file> [-o <out.pdf>]   Compile a document to PDF
+    check <file>                    Check a document for errors without rendering
+    fmt <file>... [--check]         Reformat source files in place
+    import <file.md>... [-o <out.ink>]
+                                    Convert Markdown files to documents
+    dump <file> [--tokens] [--ast] [--hlir] [--layout]
+                                    Print compiler internals (all stages by default)
+
+Options:
+    -o, --output <path>   Output file for `compile` (defaults to <file>.pdf) or
+                          `import` (defaults to <file>.ink)
+    -v, --verbose         Print progress and timing information to stderr
+    --message-format <human|json>
+                          How to print errors and warnings. `json` prints one
+                          object per line on stdout (defaults to `human`)
+    -h, --help            Print this help
+    -V, --version         Print the version";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpStage {
+    Tokens,
+    Ast,
+    Hlir,
+    Layout,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Compile {
+        input: PathBuf,
+        output: Option<PathBuf>,
+    },
+    Check {
+        input: PathBuf,
+    },
+    Fmt {
+        inputs: Vec<PathBuf>,
+        check: bool,
+    },
+    Import {
+        inputs: Vec<PathBuf>,
+        output: Option<PathBuf>,
+    },
+    Dump {
+        input: PathBuf,
+        stages: Vec<DumpStage>,
+    },
+    Help,
+    Version,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    pub verbose: bool,
+    pub message_format: MessageFormat,
+}
+
+pub fn parse_args(args: &[OsString]) -> Result<Args, String> {
+    let mut verbose = false;
+    let mut message_format = MessageFormat::Human;
+    let mut output = None;
+    let mut check = false;
+    let mut stages = Vec::new();
+    let mut positional: Vec<PathBuf> = Vec::new();
+
+    let mut iter = args.iter();
+    let Some(command) = iter.next() else {
+        return Ok(Args {
+            command: Command::Help,
+            verbose,
+            message_format,
+        });
+    };
+    let command = command.to_string_lossy().to_string();
+
+    while let Some(arg) = iter.next() {
+        match arg.to_string_lossy().as_ref() {
+            "-v" | "--verbose" => verbose = true,
+            "-h" | "--help" => {
+                return Ok(Args {
+                    command: Command::Help,
+                    verbose,
+                    message_format,
+                });
+            }
+            "-o" | "--output" => match iter.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return Err(format!("`{}` expects a path", arg.to_string_lossy())),
+            },
+            "--message-format" => match iter.next() {
+                Some(format) => message_format = parse_message_format(&format.to_string_lossy())?,
+                None => return Err("`--message-format` expects `human` or `json`".to_string()),
+            },
+            flag if flag.starts_with("--message-format=") => {
+                message_format = parse_message_format(&flag["--message-format=".len()..])?;
+            }
+            "--check" => check = true,
+            "--tokens" => stages.push(DumpStage::Tokens),
+            "--ast" => stages.push(DumpStage::Ast),
+            "--hlir" => stages.push(DumpStage::Hlir),
+            "--layout" => stages.push(DumpStage::Layout),
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let command = match command.as_str() {
+        "compile" => Command::Compile {
+            input: single_input(&command, positional)?,
+            output,
+        },
+        "check" => Command::Check {
+            input: single_input(&command, positional)?,
+        },
+        "fmt" => {
+            if positional.is_empty() {
+                return Err("`fmt` expects at least one file".to_string());
+            }
+            Command::Fmt {
+                inputs: positional,
+                check,
+            }
+        }
+        "import" => {
+            if positional.is_empty() {
+                return Err("`import` expects at least one file".to_string());
+            }
+            if output.is_some() && positional.len() > 1 {
+                return Err("`-o` can only be used when importing a single file".to_string());
+            }
+            Command::Import {
+                inputs: positional,
+                output,
+            }
+        }
+        "dump" => {
+            if stages.is_empty() {
+                stages = vec![
+                    DumpStage::Tokens,
+                    DumpStage::Ast,
+                    DumpStage::Hlir,
+                    DumpStage::Layout,
+                ];
+            }
+            Command::Dump {
+                input: single_input(&command, positional)?,
+                stages,
+            }
+        }
+        "help" | "-h" | "--help" => Command::Help,
+        "version" | "-V" | "--version" => Command::Version,
+        other => return Err(format!("unknown command `{}`", other)),
+    };
+
+    Ok(Args {
+        command,
+        verbose,
+        message_format,
+    })
+}
+
+fn parse_message_format(format: &str) -> Result<MessageFormat, String> {
+    match format {
+        "human" => Ok(MessageFormat::Human),
+        "json" => Ok(MessageFormat::Json),
+        other => Err(format!(
+            "unknown message format `{}`, expected `human` or `json`",
+            other
+        )),
+    }
+}
+
+fn single_input(command: &str, mut positional: Vec<PathBuf>) -> Result<PathBuf, String> {
+    match positional.len() {
+        0 => Err(format!("`{}` expects an input file", command)),
+        1 => Ok(positional.remove(0)),
+        _ => Err(format!("`{}` expects a single input file", command)),
+    }
+}
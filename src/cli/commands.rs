@@ -0,0 +1,235 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use pyrus::ast::Ast;
+use pyrus::backend;
+use pyrus::diagnostics::{self, Diagnostic, has_errors};
+use pyrus::format::{format_source, print_ast};
+use pyrus::hlir::{self, HLIRModule, resolve_styles};
+use pyrus::layout::setup_layout;
+use pyrus::lexer::{self, TokenStream};
+use pyrus::markdown;
+use pyrus::parser;
+
+use crate::cli::args::{DumpStage, MessageFormat};
+
+// A4 page size in points (1 inch = 72 points)
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+
+/// Everything the front end produced for one source file
+struct Frontend {
+    tokens: TokenStream,
+    ast: Ast,
+    hlir: HLIRModule,
+}
+
+pub fn compile(
+    input: &Path,
+    output: Option<PathBuf>,
+    verbose: bool,
+    format: MessageFormat,
+) -> ExitCode {
+    let start = Instant::now();
+    let Some(frontend) = run_frontend(input, format) else {
+        return ExitCode::FAILURE;
+    };
+
+    let output = output.unwrap_or_else(|| input.with_extension("pdf"));
+    let backend = backend::Backend::new(backend::Renderer::Pdf);
+    if let Err(e) = backend.render(frontend.hlir, &output) {
+        eprintln!("error: failed to write {}: {}", output.display(), e);
+        return ExitCode::FAILURE;
+    }
+
+    if verbose {
+        eprintln!(
+            "compiled {} -> {} in {:?}",
+            input.display(),
+            output.display(),
+            start.elapsed()
+        );
+    }
+    ExitCode::SUCCESS
+}
+
+pub fn check(input: &Path, verbose: bool, format: MessageFormat) -> ExitCode {
+    let start = Instant::now();
+    if run_frontend(input, format).is_none() {
+        return ExitCode::FAILURE;
+    }
+    if verbose {
+        eprintln!("checked {} in {:?}", input.display(), start.elapsed());
+    }
+    ExitCode::SUCCESS
+}
+
+pub fn fmt(inputs: &[PathBuf], check: bool, verbose: bool, format: MessageFormat) -> ExitCode {
+    let mut failed = false;
+    for input in inputs {
+        let Some(source) = read_source(input) else {
+            failed = true;
+            continue;
+        };
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                report_diagnostics(input, &source, &errors, format);
+                failed = true;
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+        if check {
+            eprintln!("{} is not formatted", input.display());
+            failed = true;
+        } else if let Err(e) = fs::write(input, formatted) {
+            eprintln!("error: failed to write {}: {}", input.display(), e);
+            failed = true;
+        } else if verbose {
+            eprintln!("formatted {}", input.display());
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Write each Markdown file out as a document next to it, or to `output`
+pub fn import(inputs: &[PathBuf], output: Option<PathBuf>, verbose: bool) -> ExitCode {
+    let mut failed = false;
+    for input in inputs {
+        let Some(markdown) = read_source(input) else {
+            failed = true;
+            continue;
+        };
+        let source = print_ast(&markdown::to_ast(&markdown));
+        let output = output
+            .clone()
+            .unwrap_or_else(|| input.with_extension("ink"));
+        if let Err(e) = fs::write(&output, source) {
+            eprintln!("error: failed to write {}: {}", output.display(), e);
+            failed = true;
+        } else if verbose {
+            eprintln!("imported {} -> {}", input.display(), output.display());
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+pub fn dump(input: &Path, stages: &[DumpStage], format: MessageFormat) -> ExitCode {
+    let Some(frontend) = run_frontend(input, format) else {
+        return ExitCode::FAILURE;
+    };
+
+    for stage in stages {
+        match stage {
+            DumpStage::Tokens => println!("{:?}", frontend.tokens),
+            DumpStage::Ast => println!("{:#?}", frontend.ast),
+            DumpStage::Hlir => println!("{:#?}", frontend.hlir),
+            DumpStage::Layout => {
+                let mut layout = setup_layout(&frontend.hlir);
+                layout.compute_layout(PAGE_WIDTH, PAGE_HEIGHT);
+                for (idx, metadata) in frontend.hlir.element_metadata.iter().enumerate() {
+                    if let Some(computed) = layout.get_element_layout(idx) {
+                        println!(
+                            "Element {} (type: {:?}, id: {:?}): x={:.1}, y={:.1}, w={:.1}, h={:.1}",
+                            idx,
+                            metadata.element_type,
+                            metadata.id,
+                            computed.x,
+                            computed.y,
+                            computed.width,
+                            computed.height
+                        );
+                    }
+                }
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Lex, parse, lower, evaluate and style a file, reading the headers of the
+/// images it uses. Diagnostics from every stage are reported, the first stage
+/// with errors stops the pipeline.
+fn run_frontend(input: &Path, format: MessageFormat) -> Option<Frontend> {
+    let source = read_source(input)?;
+
+    let tokens = lexer::lex(&source);
+    report_diagnostics(input, &source, &tokens.errors, format);
+    if has_errors(&tokens.errors) {
+        return None;
+    }
+
+    let (ast, diagnostics) = parser::parse(tokens.clone());
+    report_diagnostics(input, &source, &diagnostics, format);
+    if has_errors(&diagnostics) {
+        return None;
+    }
+
+    let mut hlir = hlir::lower(&ast);
+    if let Err(diagnostics) = hlir::validate(&hlir) {
+        hlir.diagnostics = diagnostics;
+    }
+    if !has_errors(&hlir.diagnostics) {
+        hlir::evaluate(&mut hlir);
+    }
+    if !has_errors(&hlir.diagnostics) {
+        let base_dir = input.parent().unwrap_or(Path::new(""));
+        hlir::load_images(&mut hlir, base_dir);
+    }
+    if !has_errors(&hlir.diagnostics) {
+        resolve_styles(&mut hlir);
+    }
+
+    report_diagnostics(input, &source, &hlir.diagnostics, format);
+    if has_errors(&hlir.diagnostics) {
+        return None;
+    }
+    Some(Frontend { tokens, ast, hlir })
+}
+
+fn read_source(input: &Path) -> Option<String> {
+    match fs::read_to_string(input) {
+        Ok(source) => Some(source),
+        Err(e) => {
+            eprintln!("error: failed to read {}: {}", input.display(), e);
+            None
+        }
+    }
+}
+
+/// Human readable diagnostics go to stderr, JSON ones to stdout so tools can
+/// read them without picking through other output
+fn report_diagnostics(
+    input: &Path,
+    source: &str,
+    diagnostics: &[Diagnostic],
+    format: MessageFormat,
+) {
+    let file = input.display().to_string();
+    for diagnostic in diagnostics {
+        match format {
+            MessageFormat::Human => {
+                eprintln!("{}", diagnostics::render(diagnostic, &file, source))
+            }
+            MessageFormat::Json => {
+                println!("{}", diagnostics::render_json(diagnostic, &file, source))
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+mod args;
+mod commands;
+
+use std::ffi::OsString;
+use std::process::ExitCode;
+
+use args::{Command, USAGE, parse_args};
+
+/// Exit code for invalid command-line usage
+const USAGE_ERROR: u8 = 2;
+
+pub fn run(args: &[OsString]) -> ExitCode {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+
+    match args.command {
+        Command::Compile { input, output } => {
+            commands::compile(&input, output, args.verbose, args.message_format)
+        }
+        Command::Check { input } => commands::check(&input, args.verbose, args.message_format),
+        Command::Fmt { inputs, check } => {
+            commands::fmt(&inputs, check, args.verbose, args.message_format)
+        }
+        Command::Import { inputs, output } => commands::import(&inputs, output, args.verbose),
+        Command::Dump { input, stages } => commands::dump(&input, &stages, args.message_format),
+        Command::Help => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        Command::Version => {
+            println!("pyrus {}", env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+    }
+}
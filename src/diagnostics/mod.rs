@@ -0,0 +1,5 @@
+mod diagnostic;
+mod render;
+
+pub use diagnostic::{Diagnostic, Severity, Span, has_errors, line_col};
+pub use render::{render, render_json};
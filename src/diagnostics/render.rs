@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+use crate::diagnostics::diagnostic::{Diagnostic, line_col};
+
+/// Render a diagnostic the way rustc does: the message, the location, the
+/// offending source line with the span underlined, then any notes and help.
+///
+/// ```text
+/// error: Function not found: `missing`
+///  --> report.ink:3:5
+///   |
+/// 3 |     missing(1)
+///   |     ^^^^^^^^^^
+///   |
+///   = help: declare it in the `template` block
+/// ```
+pub fn render(diagnostic: &Diagnostic, file: &str, source: &str) -> String {
+    let (line, col) = line_col(source, diagnostic.span.start);
+    let line_text = source_line(source, line);
+    let gutter = " ".repeat(line.to_string().len());
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{}: {}",
+        diagnostic.severity.as_str(),
+        diagnostic.message
+    );
+    let _ = writeln!(out, "{}--> {}:{}:{}", gutter, file, line, col);
+    let _ = writeln!(out, "{} |", gutter);
+    let _ = writeln!(out, "{} | {}", line, line_text);
+
+    // the underline stops at the end of the first line of a multi-line span
+    let start_col = col as usize - 1;
+    let line_len = line_text.chars().count();
+    let span_len = source
+        .get(diagnostic.span.start..diagnostic.span.end)
+        .map_or(0, |text| text.chars().take_while(|&c| c != '\n').count());
+    let width = span_len.min(line_len.saturating_sub(start_col)).max(1);
+    // tabs stay tabs, so the carets line up however wide the terminal shows them
+    let padding: String = line_text
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .chain(std::iter::repeat(' '))
+        .take(start_col)
+        .collect();
+    let _ = write!(out, "{} | {}{}", gutter, padding, "^".repeat(width));
+    if let Some(label) = &diagnostic.label {
+        let _ = write!(out, " {}", label);
+    }
+    out.push('\n');
+
+    if !diagnostic.notes.is_empty() || diagnostic.help.is_some() {
+        let _ = writeln!(out, "{} |", gutter);
+        for note in &diagnostic.notes {
+            let _ = writeln!(out, "{} = note: {}", gutter, note);
+        }
+        if let Some(help) = &diagnostic.help {
+            let _ = writeln!(out, "{} = help: {}", gutter, help);
+        }
+    }
+    out
+}
+
+/// Render a diagnostic as a single line of JSON, for editors and CI.
+/// Lines and columns are 1-based, `start`/`end` are byte offsets.
+pub fn render_json(diagnostic: &Diagnostic, file: &str, source: &str) -> String {
+    let (line, column) = line_col(source, diagnostic.span.start);
+    let (end_line, end_column) = line_col(source, diagnostic.span.end);
+    let notes: Vec<String> = diagnostic
+        .notes
+        .iter()
+        .map(|note| json_string(note))
+        .collect();
+
+    format!(
+        concat!(
+            "{{\"severity\":{},\"message\":{},\"file\":{},",
+            "\"span\":{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}}},",
+            "\"label\":{},\"notes\":[{}],\"help\":{},\"rendered\":{}}}"
+        ),
+        json_string(diagnostic.severity.as_str()),
+        json_string(&diagnostic.message),
+        json_string(file),
+        diagnostic.span.start,
+        diagnostic.span.end,
+        line,
+        column,
+        end_line,
+        end_column,
+        json_optional(diagnostic.label.as_deref()),
+        notes.join(","),
+        json_optional(diagnostic.help.as_deref()),
+        json_string(&render(diagnostic, file, source)),
+    )
+}
+
+fn source_line(source: &str, line: u32) -> &str {
+    source
+        .lines()
+        .nth(line as usize - 1)
+        .unwrap_or("")
+        .trim_end_matches('\r')
+}
+
+fn json_optional(value: Option<&str>) -> String {
+    value.map_or_else(|| "null".to_string(), json_string)
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,150 @@
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{self, TokenKind};
+
+const INDENT: &str = "    ";
+
+/// A single printable chunk of the source: either a token or something the
+/// lexer skipped over (comments).
+struct Piece<'a> {
+    text: &'a str,
+    kind: Option<TokenKind>, // None for comments and other skipped text
+    newlines_before: usize,
+    space_before: bool,
+    /// Original whitespace before the piece on its line, kept for trailing
+    /// comments so aligned comment columns survive formatting
+    original_space: &'a str,
+}
+
+/// Reformat a pyrus source file.
+///
+/// The formatter works on the token stream rather than the AST so comments and
+/// the author's line breaks survive. It re-indents every line by brace/paren
+/// depth, collapses runs of blank lines and spaces, and strips trailing
+/// whitespace. Sources with lex errors are returned untouched as an error.
+pub fn format_source(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let tokens = lexer::lex(source);
+    if !tokens.errors.is_empty() {
+        return Err(tokens.errors);
+    }
+
+    let mut pieces = Vec::new();
+    let mut prev_end = 0;
+    for (idx, kind) in tokens.kinds.iter().enumerate() {
+        let range = &tokens.ranges[idx];
+        let (newlines, space) = split_gap(&source[prev_end..range.start], &mut pieces);
+        if *kind == TokenKind::Eof {
+            break;
+        }
+        pieces.push(Piece {
+            text: &source[range.start..range.end],
+            kind: Some(*kind),
+            newlines_before: newlines,
+            space_before: space,
+            original_space: "",
+        });
+        prev_end = range.end;
+    }
+
+    Ok(print_pieces(&pieces))
+}
+
+/// Collect comments out of the gap between two tokens.
+/// Returns the line break / space information for whatever comes next.
+fn split_gap<'a>(gap: &'a str, pieces: &mut Vec<Piece<'a>>) -> (usize, bool) {
+    let bytes = gap.as_bytes();
+    let mut newlines = 0;
+    let mut space = false;
+    let mut space_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c == b'\n' {
+            newlines += 1;
+            i += 1;
+            space_start = i;
+            continue;
+        }
+        if c.is_ascii_whitespace() {
+            space = true;
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        if gap[i..].starts_with("//") {
+            i = gap[i..].find('\n').map_or(bytes.len(), |n| i + n);
+        } else if gap[i..].starts_with("/*") {
+            i = gap[i + 2..]
+                .find("*/")
+                .map_or(bytes.len(), |n| i + 2 + n + 2);
+        } else {
+            while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !gap[i..].starts_with("/") {
+                i += 1;
+            }
+            if i == start {
+                i += 1; // lone '/' that does not start a comment
+            }
+        }
+
+        pieces.push(Piece {
+            text: gap[start..i].trim_end(),
+            kind: None,
+            newlines_before: newlines,
+            space_before: space,
+            original_space: &gap[space_start..start],
+        });
+        newlines = 0;
+        space = false;
+        space_start = i;
+    }
+
+    (newlines, space)
+}
+
+fn print_pieces(pieces: &[Piece]) -> String {
+    let mut out = String::new();
+    let mut depth: usize = 0;
+    let mut prev_kind: Option<TokenKind> = None;
+
+    for (idx, piece) in pieces.iter().enumerate() {
+        let closes = matches!(
+            piece.kind,
+            Some(TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket)
+        );
+        if closes {
+            depth = depth.saturating_sub(1);
+        }
+
+        if idx == 0 {
+            out.push_str(&INDENT.repeat(depth));
+        } else if piece.newlines_before > 0 {
+            out.push('\n');
+            if piece.newlines_before > 1 {
+                out.push('\n');
+            }
+            out.push_str(&INDENT.repeat(depth));
+        } else if piece.kind == Some(TokenKind::Comma) {
+            // never a space before a comma
+        } else if piece.kind.is_none() && !piece.original_space.is_empty() {
+            out.push_str(piece.original_space);
+        } else if piece.space_before || prev_kind == Some(TokenKind::Comma) {
+            out.push(' ');
+        }
+
+        out.push_str(piece.text);
+
+        if matches!(
+            piece.kind,
+            Some(TokenKind::LeftBrace | TokenKind::LeftParen | TokenKind::LeftBracket)
+        ) {
+            depth += 1;
+        }
+        prev_kind = piece.kind;
+    }
+
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,5 @@
+mod formatter;
+mod printer;
+
+pub use formatter::format_source;
+pub use printer::print_ast;
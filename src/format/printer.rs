@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+
+use crate::ast::{
+    Argument, Ast, DocElement, Emphasis, Expression, Inline, InterpPart, Selector, StyleRule,
+    UnaryOp,
+};
+use crate::diagnostics::Span;
+
+const INDENT: &str = "    ";
+
+/// Print the document and style blocks of an AST as pyrus source, laid out
+/// the way `pyrus fmt` would leave it. There is no printer for templates
+/// yet, so a template block is left out.
+pub fn print_ast(ast: &Ast) -> String {
+    let mut out = String::new();
+    if let Some(document) = &ast.document {
+        out.push_str("document {\n");
+        for element in &document.elements {
+            print_element(element, 1, &mut out);
+        }
+        out.push_str("}\n");
+    }
+    if let Some(style) = &ast.style {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("style {\n");
+        for (i, rule) in style.statements.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            print_rule(rule, &mut out);
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+fn print_rule(rule: &StyleRule, out: &mut String) {
+    let selectors: Vec<String> = rule
+        .selector_list
+        .iter()
+        .map(|selector| match selector {
+            Selector::Type(name) => name.clone(),
+            Selector::Class(name) => format!(".{}", name),
+            Selector::Id(name) => format!("#{}", name),
+        })
+        .collect();
+    line(1, &format!("{} {{", selectors.join(", ")), out);
+    for declaration in &rule.declaration_block {
+        let text = format!("{} = {}", declaration.key, expression(&declaration.value));
+        line(2, &text, out);
+    }
+    line(1, "}", out);
+}
+
+fn print_element(element: &DocElement, depth: usize, out: &mut String) {
+    match element {
+        DocElement::Text {
+            inlines,
+            attributes,
+            ..
+        } => {
+            let head = format!("text{}", attribute_list(attributes, &[]));
+            line(
+                depth,
+                &format!("{} {{ {} }}", head, inline_content(inlines)),
+                out,
+            );
+        }
+        DocElement::Image {
+            src, attributes, ..
+        } => {
+            let src = (
+                "src",
+                Expression::StringLiteral(src.clone(), element.span()),
+            );
+            line(
+                depth,
+                &format!("image{}", attribute_list(attributes, &[src])),
+                out,
+            );
+        }
+        DocElement::Link {
+            href,
+            content,
+            attributes,
+            span,
+        } => line(depth, &link(href, content, attributes, *span), out),
+        DocElement::Code {
+            content,
+            attributes,
+            ..
+        } => {
+            let head = format!("code{}", attribute_list(attributes, &[]));
+            // a raw string can not be empty or hold its own closing quotes
+            if content.is_empty() || content.contains("\"\"\"") {
+                let content = InterpPart::literal(content.clone());
+                line(depth, &format!("{} {{ {} }}", head, quote(&content)), out);
+                return;
+            }
+            line(depth, &format!("{} {{", head), out);
+            line(depth + 1, "\"\"\"", out);
+            for code_line in content.split('\n') {
+                line(depth + 1, code_line, out);
+            }
+            line(depth + 1, "\"\"\"", out);
+            line(depth, "}", out);
+        }
+        DocElement::Section {
+            elements,
+            attributes,
+            ..
+        } => print_block(
+            &format!("section{}", attribute_list(attributes, &[])),
+            elements,
+            depth,
+            out,
+        ),
+        DocElement::List {
+            items, attributes, ..
+        } => {
+            line(
+                depth,
+                &format!("list{} {{", attribute_list(attributes, &[])),
+                out,
+            );
+            for item in items {
+                match item {
+                    DocElement::Text {
+                        inlines,
+                        attributes,
+                        ..
+                    } => {
+                        let head = format!("item{}", attribute_list(attributes, &[]));
+                        let text = format!("{} {{ {} }}", head, inline_content(inlines));
+                        line(depth + 1, &text, out);
+                    }
+                    other => print_element(other, depth + 1, out),
+                }
+            }
+            line(depth, "}", out);
+        }
+        DocElement::Table {
+            rows, attributes, ..
+        } => print_block(
+            &format!("table{}", attribute_list(attributes, &[])),
+            rows,
+            depth,
+            out,
+        ),
+        DocElement::TableRow { header, cells, .. } => {
+            print_block(if *header { "header" } else { "row" }, cells, depth, out)
+        }
+        DocElement::TableCell {
+            content,
+            colspan,
+            rowspan,
+            attributes,
+            ..
+        } => {
+            let span = element.span();
+            let mut spans = Vec::new();
+            if *colspan > 1 {
+                spans.push(("colspan", Expression::Int(*colspan as i64, span)));
+            }
+            if *rowspan > 1 {
+                spans.push(("rowspan", Expression::Int(*rowspan as i64, span)));
+            }
+            let head = format!("cell{}", attribute_list(attributes, &spans));
+            line(depth, &format!("{} {{ {} }}", head, quote(content)), out);
+        }
+        DocElement::Call { name, args, .. } => {
+            line(depth, &format!("{}({})", name, arguments(args)), out);
+        }
+        DocElement::If {
+            condition,
+            body,
+            else_body,
+            ..
+        } => print_if(condition, body, else_body, "", depth, out),
+        DocElement::For {
+            iterator,
+            iterable,
+            body,
+            ..
+        } => print_block(
+            &format!("for {} in {}", iterator, expression(iterable)),
+            body,
+            depth,
+            out,
+        ),
+    }
+}
+
+/// `if`, with an `else if` chain kept on the lines of the closing braces
+fn print_if(
+    condition: &Expression,
+    body: &[DocElement],
+    else_body: &Option<Vec<DocElement>>,
+    prefix: &str,
+    depth: usize,
+    out: &mut String,
+) {
+    line(
+        depth,
+        &format!("{}if {} {{", prefix, expression(condition)),
+        out,
+    );
+    for element in body {
+        print_element(element, depth + 1, out);
+    }
+    match else_body.as_deref() {
+        None => line(depth, "}", out),
+        Some(
+            [
+                DocElement::If {
+                    condition,
+                    body,
+                    else_body,
+                    ..
+                },
+            ],
+        ) => print_if(condition, body, else_body, "} else ", depth, out),
+        Some(otherwise) => {
+            line(depth, "} else {", out);
+            for element in otherwise {
+                print_element(element, depth + 1, out);
+            }
+            line(depth, "}", out);
+        }
+    }
+}
+
+fn print_block(head: &str, elements: &[DocElement], depth: usize, out: &mut String) {
+    line(depth, &format!("{} {{", head), out);
+    for element in elements {
+        print_element(element, depth + 1, out);
+    }
+    line(depth, "}", out);
+}
+
+fn line(depth: usize, text: &str, out: &mut String) {
+    if !text.is_empty() {
+        out.push_str(&INDENT.repeat(depth));
+        out.push_str(text);
+    }
+    out.push('\n');
+}
+
+/// `(name=value, ...)`, or nothing without attributes. The attributes an
+/// element takes apart, like a link's `href`, come first.
+fn attribute_list(
+    attributes: &HashMap<String, Expression>,
+    leading: &[(&str, Expression)],
+) -> String {
+    let mut sorted: Vec<(&String, &Expression)> = attributes.iter().collect();
+    sorted.sort_by_key(|(name, value)| (value.span().start, name.as_str()));
+    let written: Vec<String> = leading
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, expression(value)))
+        .chain(
+            sorted
+                .into_iter()
+                .map(|(name, value)| format!("{}={}", name, expression(value))),
+        )
+        .collect();
+    if written.is_empty() {
+        String::new()
+    } else {
+        format!("({})", written.join(", "))
+    }
+}
+
+/// A `link`, on its own or inside text
+fn link(
+    href: &str,
+    content: &[InterpPart],
+    attributes: &HashMap<String, Expression>,
+    span: Span,
+) -> String {
+    let href_attribute = ("href", Expression::StringLiteral(href.to_string(), span));
+    let head = format!("link{}", attribute_list(attributes, &[href_attribute]));
+    // without a body a link shows its target
+    if matches!(content, [InterpPart::Text(text)] if text == href) {
+        head
+    } else {
+        format!("{} {{ {} }}", head, quote(content))
+    }
+}
+
+/// The body of a text element: its runs as one string with markup, and
+/// spans and links around the parts of it that have them
+fn inline_content(inlines: &[Inline]) -> String {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let runs = split_spacing(inlines);
+    for (i, inline) in runs.iter().enumerate() {
+        match inline {
+            Inline::Text(plain) => {
+                for part in plain {
+                    match part {
+                        InterpPart::Text(plain) => {
+                            text.push_str(&escape_markup(plain, text.chars().last()))
+                        }
+                        InterpPart::Expression(expr) => text.push_str(&interpolation(expr)),
+                    }
+                }
+            }
+            Inline::Emphasis(emphasis, content) => {
+                let marked = emphasis_markup(*emphasis, content);
+                // a marker next to a word does not count, so set it apart
+                let next = runs.get(i + 1).and_then(|next| match next {
+                    Inline::Text(next) => InterpPart::plain_text(next).chars().next(),
+                    _ => None,
+                });
+                let cramped = *emphasis != Emphasis::Code
+                    && (text.chars().last().is_some_and(char::is_alphanumeric)
+                        || next.is_some_and(char::is_alphanumeric));
+                if cramped {
+                    parts.push(format!("\"{}\"", std::mem::take(&mut text)));
+                    parts.push(format!("span {{ \"{}\" }}", marked));
+                } else {
+                    text.push_str(&marked);
+                }
+            }
+            Inline::Span {
+                content,
+                attributes,
+                ..
+            } => {
+                parts.push(format!("\"{}\"", std::mem::take(&mut text)));
+                let head = format!("span{}", attribute_list(attributes, &[]));
+                parts.push(format!("{} {{ {} }}", head, inline_content(content)));
+            }
+            Inline::Link {
+                href,
+                content,
+                attributes,
+                span,
+            } => {
+                parts.push(format!("\"{}\"", std::mem::take(&mut text)));
+                parts.push(link(href, content, attributes, *span));
+            }
+        }
+    }
+    parts.push(format!("\"{}\"", text));
+    parts.retain(|part| part != "\"\"");
+    parts.join(" ")
+}
+
+/// Runs with the space around emphasised text moved out of them, since
+/// markers have to hug the text they mark
+fn split_spacing(inlines: &[Inline]) -> Vec<Inline> {
+    let mut runs = Vec::new();
+    for inline in inlines {
+        match inline {
+            Inline::Emphasis(emphasis, content) if *emphasis != Emphasis::Code => {
+                if InterpPart::plain_text(content).trim().is_empty() {
+                    runs.push(Inline::Text(content.clone()));
+                    continue;
+                }
+                let mut trimmed = content.clone();
+                let mut leading = String::new();
+                if let Some(InterpPart::Text(first)) = trimmed.first_mut() {
+                    leading = first[..first.len() - first.trim_start().len()].to_string();
+                    *first = first.trim_start().to_string();
+                }
+                let mut trailing = String::new();
+                if let Some(InterpPart::Text(last)) = trimmed.last_mut() {
+                    trailing = last[last.trim_end().len()..].to_string();
+                    last.truncate(last.trim_end().len());
+                }
+                if !leading.is_empty() {
+                    runs.push(Inline::Text(InterpPart::literal(leading)));
+                }
+                runs.push(Inline::Emphasis(*emphasis, trimmed));
+                if !trailing.is_empty() {
+                    runs.push(Inline::Text(InterpPart::literal(trailing)));
+                }
+            }
+            Inline::Emphasis(Emphasis::Code, content)
+                if InterpPart::plain_text(content).is_empty() => {}
+            other => runs.push(other.clone()),
+        }
+    }
+    runs
+}
+
+fn emphasis_markup(emphasis: Emphasis, content: &[InterpPart]) -> String {
+    let marker = match emphasis {
+        Emphasis::Bold => '*',
+        Emphasis::Italic => '_',
+        Emphasis::Code => '`',
+    };
+    let mut marked = String::from(marker);
+    for part in content {
+        let text = match part {
+            InterpPart::Text(text) => text,
+            InterpPart::Expression(expr) => {
+                marked.push_str(&interpolation(expr));
+                continue;
+            }
+        };
+        for c in text.chars() {
+            if matches!(c, '\\' | '"' | '{' | '}') || c == marker {
+                marked.push('\\');
+            }
+            marked.push(c);
+        }
+    }
+    marked.push(marker);
+    marked
+}
+
+/// Plain text for a string with markup. A `*` or `_` that could open
+/// emphasis is escaped, and `\`` and braces always are.
+fn escape_markup(text: &str, before: Option<char>) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let previous = if i == 0 { before } else { Some(chars[i - 1]) };
+        let opens = matches!(c, '*' | '_')
+            && !previous.is_some_and(char::is_alphanumeric)
+            && !chars.get(i + 1).is_some_and(|next| next.is_whitespace());
+        if matches!(c, '\\' | '"' | '`' | '{' | '}') || opens {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A string without markup, like the body of a link or a cell
+fn quote(content: &[InterpPart]) -> String {
+    let mut quoted = String::from('"');
+    for part in content {
+        let text = match part {
+            InterpPart::Text(text) => text,
+            InterpPart::Expression(expr) => {
+                quoted.push_str(&interpolation(expr));
+                continue;
+            }
+        };
+        for c in text.chars() {
+            if matches!(c, '\\' | '"' | '{' | '}') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// An `{expression}` part of document text
+fn interpolation(expr: &Expression) -> String {
+    format!("{{{}}}", expression(expr))
+}
+
+fn expression(expr: &Expression) -> String {
+    match expr {
+        Expression::StringLiteral(text, _) => {
+            format!("\"{}\"", escape_string(text))
+        }
+        Expression::InterpolatedString(parts, _) => {
+            let mut text = String::from('"');
+            for part in parts {
+                match part {
+                    InterpPart::Text(part) => text.push_str(&escape_string(part)),
+                    InterpPart::Expression(part) => {
+                        text.push('{');
+                        text.push_str(&expression(part));
+                        text.push('}');
+                    }
+                }
+            }
+            text.push('"');
+            text
+        }
+        Expression::Float(value, _) => format!("{:?}", value),
+        Expression::Binary {
+            left,
+            operator,
+            right,
+            ..
+        } => format!("{} {} {}", operand(left), operator.as_str(), operand(right)),
+        Expression::Unary {
+            operator,
+            expression: operand_expr,
+            ..
+        } => {
+            let sign = match operator {
+                UnaryOp::Negate => "-",
+                UnaryOp::Not => "!",
+            };
+            format!("{}{}", sign, operand(operand_expr))
+        }
+        Expression::Range { start, end, .. } => {
+            format!("{}..{}", operand(start), operand(end))
+        }
+        Expression::Call { name, args, .. } => format!("{}({})", name, arguments(args)),
+        Expression::List(items, _) => {
+            let items: Vec<String> = items.iter().map(expression).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Expression::Map(entries, _) => {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", map_key(key), expression(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        Expression::Index { value, index, .. } => {
+            format!("{}[{}]", indexed(value), expression(index))
+        }
+        Expression::Field { value, name, .. } => format!("{}.{}", indexed(value), name),
+        Expression::Int(..)
+        | Expression::Bool(..)
+        | Expression::Color(..)
+        | Expression::Identifier(..)
+        | Expression::StructDefault(..) => expr.to_string(),
+    }
+}
+
+/// The arguments of a call, separated by commas
+fn arguments(args: &[Argument]) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| match &arg.name {
+            Some(name) => format!("{}={}", name, expression(&arg.value)),
+            None => expression(&arg.value),
+        })
+        .collect();
+    args.join(", ")
+}
+
+/// The key of a map entry, quoted unless it is a name
+fn map_key(key: &str) -> String {
+    let name = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if name {
+        key.to_string()
+    } else {
+        format!("\"{}\"", escape_string(key))
+    }
+}
+
+/// A part of a larger expression, in parentheses when it has parts itself
+fn operand(expr: &Expression) -> String {
+    match expr {
+        Expression::Binary { .. } | Expression::Range { .. } => format!("({})", expression(expr)),
+        _ => expression(expr),
+    }
+}
+
+/// The value before `[index]` or `.name`, which binds tighter than any
+/// operator
+fn indexed(expr: &Expression) -> String {
+    match expr {
+        Expression::Unary { .. } => format!("({})", expression(expr)),
+        _ => operand(expr),
+    }
+}
+
+/// Text for inside the quotes of a string expression, where braces
+/// interpolate
+fn escape_string(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' | '"' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '{' => escaped.push_str("{{"),
+            '}' => escaped.push_str("}}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
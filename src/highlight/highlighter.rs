@@ -0,0 +1,134 @@
+use crate::highlight::Language;
+
+/// Kind of a highlighted token, styled through the `.code-*` classes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+impl TokenClass {
+    pub const ALL: [TokenClass; 4] = [
+        TokenClass::Keyword,
+        TokenClass::String,
+        TokenClass::Number,
+        TokenClass::Comment,
+    ];
+
+    /// The class a style rule selects the tokens with
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            TokenClass::Keyword => "code-keyword",
+            TokenClass::String => "code-string",
+            TokenClass::Number => "code-number",
+            TokenClass::Comment => "code-comment",
+        }
+    }
+
+    /// Colour used when no style rule sets one
+    pub fn default_color(&self) -> &'static str {
+        match self {
+            TokenClass::Keyword => "#7f0055",
+            TokenClass::String => "#2a7f00",
+            TokenClass::Number => "#1750eb",
+            TokenClass::Comment => "#808080",
+        }
+    }
+}
+
+/// A piece of one line of code, `class` is `None` for plain text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeRun {
+    pub text: String,
+    pub class: Option<TokenClass>,
+}
+
+/// Split `code` into lines of highlighted runs. Without a language every
+/// line is a single plain run.
+pub fn highlight(code: &str, language: Option<&Language>) -> Vec<Vec<CodeRun>> {
+    let runs = match language {
+        Some(language) => tokenize(code, language),
+        None => vec![(code, None)],
+    };
+
+    // runs can span lines (block comments, strings), cut them at line breaks
+    let mut lines = vec![Vec::new()];
+    for (text, class) in runs {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Vec::new());
+            }
+            if !part.is_empty() {
+                let line = lines.last_mut().expect("there is always a line");
+                match line.last_mut() {
+                    Some(CodeRun { text, class: last }) if *last == class => text.push_str(part),
+                    _ => line.push(CodeRun {
+                        text: part.to_string(),
+                        class,
+                    }),
+                }
+            }
+        }
+    }
+    lines
+}
+
+fn tokenize<'a>(code: &'a str, language: &Language) -> Vec<(&'a str, Option<TokenClass>)> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < code.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().expect("not at the end");
+        let (len, class) = if let Some((open, close)) = language.block_comment
+            && rest.starts_with(open)
+        {
+            let end = rest[open.len()..]
+                .find(close)
+                .map_or(rest.len(), |end| open.len() + end + close.len());
+            (end, Some(TokenClass::Comment))
+        } else if let Some(marker) = language.line_comment
+            && rest.starts_with(marker)
+        {
+            (
+                rest.find('\n').unwrap_or(rest.len()),
+                Some(TokenClass::Comment),
+            )
+        } else if language.quotes.contains(&c) {
+            (string_len(rest, c), Some(TokenClass::String))
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            (end, Some(TokenClass::Number))
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let keyword = language.keywords.contains(&&rest[..end]);
+            (end, keyword.then_some(TokenClass::Keyword))
+        } else {
+            (c.len_utf8(), None)
+        };
+        runs.push((&rest[..len], class));
+        i += len;
+    }
+    runs
+}
+
+/// Length of the string starting at the `quote` that `rest` starts with, up
+/// to the closing quote or the end of the line when it is not closed
+fn string_len(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    rest.len()
+}
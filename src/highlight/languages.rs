@@ -0,0 +1,116 @@
+/// What the highlighter needs to know to split a language into tokens
+#[derive(Debug)]
+pub struct Language {
+    /// Names accepted by `code(lang=...)`, the first one is shown in messages
+    pub names: &'static [&'static str],
+    pub keywords: &'static [&'static str],
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    pub quotes: &'static [char],
+}
+
+impl Language {
+    pub fn find(name: &str) -> Option<&'static Language> {
+        LANGUAGES
+            .iter()
+            .find(|language| language.names.contains(&name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.names[0]
+    }
+}
+
+pub static LANGUAGES: [Language; 5] = [
+    Language {
+        names: &["pyrus", "ink"],
+        keywords: &[
+            "template", "document", "style", "func", "let", "const", "var", "if", "else", "for",
+            "in", "while", "return", "text", "image", "list", "section", "table", "link", "code",
+        ],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+    },
+    Language {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+            "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+            "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        // `'` also starts lifetimes, so character literals stay plain
+        quotes: &['"'],
+    },
+    Language {
+        names: &["python", "py"],
+        keywords: &[
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+            "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+            "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+            "return", "try", "while", "with", "yield",
+        ],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        names: &["javascript", "js", "typescript", "ts"],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "null",
+            "of",
+            "return",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "type",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Language {
+        names: &["json"],
+        keywords: &["true", "false", "null"],
+        line_comment: None,
+        block_comment: None,
+        quotes: &['"'],
+    },
+];
@@ -0,0 +1,5 @@
+mod highlighter;
+mod languages;
+
+pub use highlighter::{CodeRun, TokenClass, highlight};
+pub use languages::{LANGUAGES, Language};
@@ -1,15 +1,146 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
 
-use crate::ast::{Ast, Expression, Statement};
+use crate::ast::{Ast, DocElement, Expression, FuncParam, Inline, InterpPart, Statement};
+use crate::diagnostics::{Diagnostic, Span};
+use crate::highlight::{LANGUAGES, Language, highlight};
 use crate::hlir::ir_types::{
-    AttributeNode, AttributeTree, ElementMetadata, Func, FuncBlock, FuncId, GlobalId, HLIRModule,
-    HlirElement, Id, Op, Type,
+    AttributeNode, AttributeTree, ColumnWidth, ElementMetadata, Func, FuncBlock, FuncId, GlobalId,
+    HLIRModule, HlirElement, Id, Op, StyleAttributes, TextRun, Type, ValueId,
 };
+use crate::hlir::util::evaluator::{self, Evaluator, Value};
+
+/// Whether an attribute value calls a function, on its own or inside a string
+fn calls_function(value: &Expression) -> bool {
+    match value {
+        Expression::Call { .. } => true,
+        Expression::InterpolatedString(parts, _) => parts.iter().any(
+            |part| matches!(part, InterpPart::Expression(expression) if calls_function(expression)),
+        ),
+        _ => false,
+    }
+}
+
+/// The index of a function's id, functions are numbered in the order they
+/// are declared
+fn id_index(id: Id) -> usize {
+    match id {
+        Id::Func(FuncId(index)) => index,
+        _ => usize::MAX,
+    }
+}
+
+/// The names of the functions called in `expr`
+fn expression_calls<'a>(expr: &'a Expression, names: &mut Vec<&'a str>) {
+    match expr {
+        Expression::Call { name, args, .. } => {
+            names.push(name);
+            for arg in args {
+                expression_calls(&arg.value, names);
+            }
+        }
+        Expression::InterpolatedString(parts, _) => {
+            for part in parts {
+                if let InterpPart::Expression(expr) = part {
+                    expression_calls(expr, names);
+                }
+            }
+        }
+        Expression::Binary { left, right, .. } => {
+            expression_calls(left, names);
+            expression_calls(right, names);
+        }
+        Expression::Range { start, end, .. } => {
+            expression_calls(start, names);
+            expression_calls(end, names);
+        }
+        Expression::Index { value, index, .. } => {
+            expression_calls(value, names);
+            expression_calls(index, names);
+        }
+        Expression::Unary {
+            expression: value, ..
+        }
+        | Expression::Field { value, .. } => expression_calls(value, names),
+        Expression::List(items, _) => {
+            for item in items {
+                expression_calls(item, names);
+            }
+        }
+        Expression::Map(entries, _) => {
+            for (_, value) in entries {
+                expression_calls(value, names);
+            }
+        }
+        Expression::StringLiteral(..)
+        | Expression::Int(..)
+        | Expression::Float(..)
+        | Expression::Bool(..)
+        | Expression::Color(..)
+        | Expression::Identifier(..)
+        | Expression::StructDefault(..) => {}
+    }
+}
+
+/// The names of the functions called in the values computed by a function
+/// body. The elements it returns are not run when its value is computed.
+fn statement_calls<'a>(statements: &'a [Statement], names: &mut Vec<&'a str>) {
+    for statement in statements {
+        match statement {
+            Statement::DefaultSet { value, .. }
+            | Statement::VarAssign { value, .. }
+            | Statement::ConstAssign { value, .. }
+            | Statement::ReturnValue { value, .. } => expression_calls(value, names),
+            Statement::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                expression_calls(condition, names);
+                statement_calls(body, names);
+                statement_calls(else_body.as_deref().unwrap_or_default(), names);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                expression_calls(condition, names);
+                statement_calls(body, names);
+            }
+            Statement::For { iterable, body, .. } => {
+                expression_calls(iterable, names);
+                statement_calls(body, names);
+            }
+            Statement::Return { .. } | Statement::FunctionDecl { .. } => {}
+        }
+    }
+}
+
+/// The type named in a parameter or return type annotation, if it is one
+/// a value can have
+fn value_type(name: &str) -> Option<Type> {
+    match name {
+        "Int" => Some(Type::Int),
+        "Float" => Some(Type::Float),
+        "String" => Some(Type::String),
+        "Bool" => Some(Type::Bool),
+        "Color" => Some(Type::Color),
+        "List" => Some(Type::List),
+        "Map" => Some(Type::Map),
+        _ => None,
+    }
+}
 
 pub fn lower(ast: &Ast) -> HLIRModule {
     let mut pass = HLIRPass {
         ast: ast.clone(),
         symbol_table: Vec::new(),
+        diagnostics: Vec::new(),
+        constants: HashSet::new(),
+        params: HashSet::new(),
+        loop_values: HashMap::new(),
+        return_type: None,
+        value_count: 0,
     };
     pass.lower()
 }
@@ -18,6 +149,12 @@ pub struct HLIRPass {
     // Fields and methods for the Hir struct
     ast: Ast,
     pub symbol_table: Vec<HashMap<String, Id>>, // Scope stack
+    pub diagnostics: Vec<Diagnostic>,           // moved into HLIRModule::diagnostics
+    pub constants: HashSet<Id>,                 // locals that cannot be reassigned
+    pub params: HashSet<Id>,                    // locals bound to function parameters
+    pub loop_values: HashMap<Id, Value>,        // current values of document `for` variables
+    pub return_type: Option<Type>,              // of the function being lowered
+    value_count: usize,
 }
 
 impl HLIRPass {
@@ -30,12 +167,15 @@ impl HLIRPass {
             css_rules: Vec::new(),
             elements: Vec::new(),
             element_metadata: Vec::new(),
+            span_metadata: Vec::new(),
+            diagnostics: Vec::new(),
         };
 
         self.symbol_table.push(HashMap::new()); // add new scope (global)
 
         self.lower_template_block(&mut hlirmodule);
         self.lower_document_block(&mut hlirmodule);
+        self.check_link_targets(&hlirmodule);
         // Store CSS rules from AST
         if let Some(style) = &self.ast.style {
             hlirmodule.css_rules = style.statements.clone();
@@ -43,9 +183,21 @@ impl HLIRPass {
 
         self.symbol_table.pop(); // remove scope (global)
 
+        hlirmodule.diagnostics = std::mem::take(&mut self.diagnostics);
         hlirmodule
     }
 
+    pub fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.diagnostics.push(Diagnostic::error(message, span));
+    }
+
+    /// Fresh id for a local value, unique within the module
+    pub fn new_value(&mut self) -> Id {
+        let id = Id::Value(ValueId(self.value_count));
+        self.value_count += 1;
+        id
+    }
+
     fn lower_template_block(&mut self, hlirmodule: &mut HLIRModule) {
         // all global, default and function declarations
         // handle defaults and globals inside this function call since they are small
@@ -55,57 +207,203 @@ impl HLIRPass {
         };
 
         let statements = template.statements.clone();
+        // functions can call themselves and the functions declared after
+        // them, so every function is declared before any body is lowered
+        let mut pending = Vec::new();
         for statement in &statements {
+            if let Statement::FunctionDecl {
+                name,
+                args,
+                return_type,
+                span,
+                ..
+            } = statement
+            {
+                self.declare_function(name, args, return_type.as_deref(), *span, hlirmodule);
+                pending.push(Some(statement));
+            }
+        }
+
+        let mut functions = 0;
+        for statement in &statements {
+            if let Statement::DefaultSet { value, .. }
+            | Statement::ConstAssign { value, .. }
+            | Statement::VarAssign { value, .. } = statement
+            {
+                self.lower_callees(value, &mut pending, hlirmodule);
+            }
             match statement {
-                Statement::DefaultSet { key, value } => {
+                Statement::DefaultSet { key, value, span } => {
                     let global_id = Id::Global(GlobalId(hlirmodule.globals.len()));
                     let global_name = "__".to_string() + &key.clone();
-                    let global = self.assign_global(&global_name, &value, global_id, false);
+                    let Some(global) = self.assign_global(
+                        &global_name,
+                        value,
+                        global_id,
+                        false,
+                        *span,
+                        hlirmodule,
+                    ) else {
+                        continue;
+                    };
                     hlirmodule.globals.insert(global_id, global);
-                    self.add_symbol(key.clone(), global_id);
+                    self.add_symbol(key.clone(), global_id, *span);
                 }
-                Statement::ConstAssign { name, value } => {
+                Statement::ConstAssign {
+                    name,
+                    ty,
+                    value,
+                    span,
+                } => {
                     let global_id = Id::Global(GlobalId(hlirmodule.globals.len()));
-                    let global = self.assign_global(&name, &value, global_id, false);
+                    let declared = self.declared_type(ty.as_deref(), *span);
+                    let Some(mut global) =
+                        self.assign_global(name, value, global_id, false, *span, hlirmodule)
+                    else {
+                        continue;
+                    };
+                    // checked against the value by the validation pass
+                    global.ty = declared.unwrap_or(global.ty);
                     hlirmodule.globals.insert(global_id, global);
-                    self.add_symbol(name.clone(), global_id);
+                    self.add_symbol(name.clone(), global_id, *span);
                 }
-                Statement::VarAssign { name, value } => {
+                Statement::VarAssign {
+                    name,
+                    ty,
+                    value,
+                    span,
+                } => {
                     let global_id = Id::Global(GlobalId(hlirmodule.globals.len()));
-                    let global = self.assign_global(&name, &value, global_id, true);
+                    let declared = self.declared_type(ty.as_deref(), *span);
+                    let Some(mut global) =
+                        self.assign_global(name, value, global_id, true, *span, hlirmodule)
+                    else {
+                        continue;
+                    };
+                    // checked against the value by the validation pass
+                    global.ty = declared.unwrap_or(global.ty);
                     hlirmodule.globals.insert(global_id, global);
-                    self.add_symbol(name.clone(), global_id);
+                    self.add_symbol(name.clone(), global_id, *span);
                 }
-                Statement::FunctionDecl { name, args, body } => {
-                    let func_id = FuncId(hlirmodule.functions.len());
-                    let hlir_body = self.lower_function_block(body, hlirmodule);
-                    self.add_symbol(name.clone(), Id::Func(func_id)); // adds function name to symbol table
-                    let mut arg_list = Vec::new();
-                    for arg in args {
-                        match arg.ty.as_str() {
-                            "Int" => arg_list.push(Type::Int),
-                            "Float" => arg_list.push(Type::Float),
-                            "String" => arg_list.push(Type::String),
-                            _ => panic!("type not known"),
-                        }
+                Statement::FunctionDecl { .. } => {
+                    let id = Id::Func(FuncId(functions));
+                    functions += 1;
+                    if let Some(statement) = pending[id_index(id)].take() {
+                        self.lower_function(id, statement, hlirmodule);
                     }
-
-                    hlirmodule.functions.insert(
-                        Id::Func(func_id),
-                        Func {
-                            id: Id::Func(func_id),
-                            name: name.clone(),
-                            args: arg_list,
-                            return_type: Some(Type::DocElement), // TODO check return type before setting (right now only DocElement)
-                            body: hlir_body,
-                        },
-                    );
                 }
+                Statement::If { span, .. }
+                | Statement::While { span, .. }
+                | Statement::For { span, .. } => self.diagnostics.push(
+                    Diagnostic::error("Control flow is only allowed inside a function", *span)
+                        .with_help("move it into a `func` in the `template` block"),
+                ),
                 _ => {}
             }
         }
     }
 
+    /// Add a function with its parameters and return type, and an empty body
+    /// lowered by `lower_function`
+    fn declare_function(
+        &mut self,
+        name: &str,
+        args: &[FuncParam],
+        return_type: Option<&str>,
+        span: Span,
+        hlirmodule: &mut HLIRModule,
+    ) {
+        let func_id = Id::Func(FuncId(hlirmodule.functions.len()));
+        let mut arg_list = Vec::new();
+        for arg in args {
+            match value_type(&arg.ty) {
+                Some(ty) => arg_list.push(ty),
+                None => self.diagnostics.push(
+                    Diagnostic::error(format!("Unknown type `{}`", arg.ty), arg.span).with_help(
+                        "parameter types are `Int`, `Float`, `String`, `Bool`, `Color`, `List` and `Map`",
+                    ),
+                ),
+            }
+        }
+        // without an annotation a function returns an element
+        let return_type = match return_type {
+            None | Some("DocElement") => Type::DocElement,
+            Some(ty) => value_type(ty).unwrap_or_else(|| {
+                self.diagnostics.push(
+                    Diagnostic::error(format!("Unknown return type `{}`", ty), span).with_help(
+                        "return types are `Int`, `Float`, `String`, `Bool`, `Color`, `List`, `Map` and `DocElement`",
+                    ),
+                );
+                Type::DocElement
+            }),
+        };
+        let params = self.function_params(args, hlirmodule);
+        self.add_symbol(name.to_string(), func_id, span); // adds function name to symbol table
+
+        hlirmodule.functions.insert(
+            func_id,
+            Func {
+                id: func_id,
+                name: name.to_string(),
+                args: arg_list,
+                params,
+                return_type: Some(return_type),
+                body: FuncBlock {
+                    ops: Vec::new(),
+                    returned_element_ref: None,
+                },
+                span,
+            },
+        );
+    }
+
+    /// Lower the body of a function added by `declare_function`
+    fn lower_function(&mut self, id: Id, statement: &Statement, hlirmodule: &mut HLIRModule) {
+        let Statement::FunctionDecl { args, body, .. } = statement else {
+            return;
+        };
+        let Some(function) = hlirmodule.functions.get(&id) else {
+            return;
+        };
+        let mut params = function.params.clone();
+        let return_type = function.return_type.unwrap_or(Type::DocElement);
+        self.param_defaults(args, &mut params, hlirmodule);
+        let body = self.lower_function_block(&params, return_type, body, hlirmodule);
+        if let Some(function) = hlirmodule.functions.get_mut(&id) {
+            function.params = params;
+            function.body = body;
+        }
+    }
+
+    /// Lower the functions not lowered yet that evaluating `value` can call,
+    /// directly or through other functions, so a global can call a function
+    /// declared after it
+    fn lower_callees(
+        &mut self,
+        value: &Expression,
+        pending: &mut [Option<&Statement>],
+        hlirmodule: &mut HLIRModule,
+    ) {
+        let mut names = Vec::new();
+        expression_calls(value, &mut names);
+        let mut reachable = BTreeSet::new();
+        while let Some(name) = names.pop() {
+            let Some(id @ Id::Func(_)) = self.find_symbol(name) else {
+                continue;
+            };
+            if let Some(Some(Statement::FunctionDecl { body, .. })) = pending.get(id_index(id))
+                && reachable.insert(id_index(id))
+            {
+                statement_calls(body, &mut names);
+            }
+        }
+        for index in reachable {
+            if let Some(statement) = pending[index].take() {
+                self.lower_function(Id::Func(FuncId(index)), statement, hlirmodule);
+            }
+        }
+    }
+
     fn lower_document_block(&mut self, hlirmodule: &mut HLIRModule) {
         let mut ir_body = FuncBlock {
             ops: Vec::new(),
@@ -117,13 +415,23 @@ impl HLIRPass {
         if let Some(document) = &self.ast.document {
             let elements = document.elements.clone();
             for element in &elements {
-                let index = self.lower_document_element(element, hlirmodule, &mut ir_body, None);
+                self.expand_document_element(
+                    element,
+                    hlirmodule,
+                    &mut |pass, element, hlirmodule| {
+                        let index =
+                            pass.lower_document_element(element, hlirmodule, &mut ir_body, None);
 
-                // Only emit HlirElementEmit for actual elements, not for function calls
-                // Calls handle element emission separately via Op::Call
-                if !matches!(element, crate::ast::DocElement::Call { .. }) {
-                    ir_body.ops.push(Op::HlirElementEmit { index });
-                }
+                        // Only emit HlirElementEmit for actual elements, not for function calls
+                        // Calls handle element emission separately via Op::Call
+                        if !matches!(element, crate::ast::DocElement::Call { .. }) {
+                            ir_body.ops.push(Op::HlirElementEmit {
+                                index,
+                                span: element.span(),
+                            });
+                        }
+                    },
+                );
             }
         }
         let func_id = FuncId(TryInto::<usize>::try_into(hlirmodule.functions.len()).unwrap());
@@ -133,8 +441,19 @@ impl HLIRPass {
                 id: Id::Func(func_id),
                 name: "__document".to_string(),
                 args: Vec::new(),
+                params: Vec::new(),
                 return_type: Some(Type::DocElement), // For right now only DocElements are supported TODO add in other types support later
                 body: ir_body,
+                span: self
+                    .ast
+                    .document
+                    .as_ref()
+                    .and_then(|document| {
+                        let first = document.elements.first()?.span();
+                        let last = document.elements.last()?.span();
+                        Some(first.to(last))
+                    })
+                    .unwrap_or_default(),
             },
         );
 
@@ -149,55 +468,62 @@ impl HLIRPass {
         parent_index: Option<usize>,
     ) -> usize {
         match element {
-            crate::ast::DocElement::Call { name, args } => {
-                let func_id = match self.find_symbol(name.as_str()) {
-                    Some(id) => Some(id),
-                    None => panic!("Function not found: {}", name),
+            crate::ast::DocElement::Call { name, args, span } => {
+                // a call in the document body is drawn where its op is, and
+                // the index is not used. One inside an element is drawn
+                // where its placeholder is.
+                let placeholder = parent_index.map(|parent| {
+                    let placeholder = self.call_placeholder(*span, Some(parent), hlirmodule);
+                    hlirmodule.elements.push(placeholder);
+                    hlirmodule.elements.len() - 1
+                });
+                let index = placeholder.unwrap_or_default();
+                let Some((func_id, function)) = self.element_function(name, *span, hlirmodule)
+                else {
+                    return index;
+                };
+                let values = self.handle_args(function, args, &mut ir_body.ops, hlirmodule);
+                let Some(arg_value_ids) =
+                    self.bind_args(function, args, values, *span, &mut ir_body.ops)
+                else {
+                    return index;
                 };
-
-                let arg_value_ids = self.handle_args(args, ir_body);
                 ir_body.ops.push(Op::Call {
-                    func: func_id.unwrap(),
+                    func: func_id,
                     result: None,
                     args: arg_value_ids,
+                    instance: None,
+                    placeholder,
+                    span: *span,
                 });
-                // Call ops don't need to return an index - they handle element emission separately
-                // The returned_element_ref in the function body is used instead
-                0 // TODO magic number
+                index
             }
             crate::ast::DocElement::Text {
-                content,
+                inlines,
                 attributes,
+                span,
+                ..
             } => {
-                let (id, classes) = self.extract_id_and_classes(attributes);
-                let element_type = "text".to_string();
-                let attribute_node =
-                    AttributeNode::new_with_attributes(attributes, hlirmodule.attributes.size);
-                let attributes_ref = hlirmodule.attributes.add_attribute(attribute_node);
-                hlirmodule.element_metadata.push(ElementMetadata {
-                    id,
-                    classes,
-                    element_type,
-                    parent: parent_index,
-                    attributes_ref,
-                });
-
-                hlirmodule.elements.push(HlirElement::Text {
-                    content: content.to_string(),
-                    attributes: attributes_ref,
-                });
+                let text = self.text_element(
+                    inlines,
+                    attributes,
+                    *span,
+                    parent_index,
+                    &mut Vec::new(),
+                    hlirmodule,
+                );
+                hlirmodule.elements.push(text);
 
                 hlirmodule.elements.len() - 1
             }
             crate::ast::DocElement::Section {
                 elements: section_elements,
                 attributes,
+                span,
             } => {
-                let (id, classes) = self.extract_id_and_classes(attributes);
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
                 let element_type = "section".to_string();
-                let attribute_node =
-                    AttributeNode::new_with_attributes(attributes, hlirmodule.attributes.size);
-                let attributes_ref = hlirmodule.attributes.add_attribute(attribute_node);
 
                 // Reserve index before processing children so children get correct parent
                 let index = hlirmodule.elements.len();
@@ -207,6 +533,7 @@ impl HLIRPass {
                     element_type,
                     parent: parent_index,
                     attributes_ref,
+                    span: *span,
                 });
                 // Push placeholder first to reserve the slot
                 hlirmodule.elements.push(HlirElement::Section {
@@ -216,12 +543,18 @@ impl HLIRPass {
 
                 let mut children = Vec::new();
                 for child in section_elements {
-                    children.push(self.lower_document_element(
+                    self.expand_document_element(
                         child,
                         hlirmodule,
-                        ir_body,
-                        Some(index),
-                    ));
+                        &mut |pass, child, hlirmodule| {
+                            children.push(pass.lower_document_element(
+                                child,
+                                hlirmodule,
+                                ir_body,
+                                Some(index),
+                            ));
+                        },
+                    );
                 }
 
                 // Update with actual children
@@ -232,12 +565,14 @@ impl HLIRPass {
 
                 index
             }
-            crate::ast::DocElement::List { items, attributes } => {
-                let (id, classes) = self.extract_id_and_classes(attributes);
+            crate::ast::DocElement::List {
+                items,
+                attributes,
+                span,
+            } => {
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
                 let element_type = "list".to_string();
-                let attribute_node =
-                    AttributeNode::new_with_attributes(attributes, hlirmodule.attributes.size);
-                let attributes_ref = hlirmodule.attributes.add_attribute(attribute_node);
 
                 // Reserve index before processing children so children get correct parent
                 let index = hlirmodule.elements.len();
@@ -247,6 +582,7 @@ impl HLIRPass {
                     element_type,
                     parent: parent_index,
                     attributes_ref,
+                    span: *span,
                 });
                 // Push placeholder first to reserve the slot
                 hlirmodule.elements.push(HlirElement::List {
@@ -256,12 +592,18 @@ impl HLIRPass {
 
                 let mut children = Vec::new();
                 for child in items {
-                    children.push(self.lower_document_element(
+                    self.expand_document_element(
                         child,
                         hlirmodule,
-                        ir_body,
-                        Some(index),
-                    ));
+                        &mut |pass, child, hlirmodule| {
+                            children.push(pass.lower_document_element(
+                                child,
+                                hlirmodule,
+                                ir_body,
+                                Some(index),
+                            ));
+                        },
+                    );
                 }
 
                 // Update with actual children
@@ -272,21 +614,619 @@ impl HLIRPass {
 
                 index
             }
-            // TODO: Handle Image, Code, Link, Table similarly
+            crate::ast::DocElement::Image {
+                src,
+                attributes,
+                span,
+            } => {
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
+                hlirmodule.element_metadata.push(ElementMetadata {
+                    id,
+                    classes,
+                    element_type: "image".to_string(),
+                    parent: parent_index,
+                    attributes_ref,
+                    span: *span,
+                });
+
+                hlirmodule.elements.push(HlirElement::Image {
+                    src: src.clone(),
+                    alt: attributes.get("alt").map(|alt| alt.to_string()),
+                    path: PathBuf::from(src),
+                    info: None,
+                    attributes: attributes_ref,
+                });
+
+                hlirmodule.elements.len() - 1
+            }
+            crate::ast::DocElement::Link {
+                href,
+                content,
+                attributes,
+                span,
+            } => {
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
+                hlirmodule.element_metadata.push(ElementMetadata {
+                    id,
+                    classes,
+                    element_type: "link".to_string(),
+                    parent: parent_index,
+                    attributes_ref,
+                    span: *span,
+                });
+
+                let (content, value) = self.lower_text(content, *span, &mut Vec::new(), hlirmodule);
+                hlirmodule.elements.push(HlirElement::Link {
+                    href: href.clone(),
+                    content,
+                    value,
+                    attributes: attributes_ref,
+                });
+
+                hlirmodule.elements.len() - 1
+            }
+            crate::ast::DocElement::Table {
+                rows,
+                attributes,
+                span,
+            } => self.lower_table(rows, attributes, *span, hlirmodule, parent_index),
+            crate::ast::DocElement::Code {
+                content,
+                attributes,
+                span,
+            } => {
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
+                hlirmodule.element_metadata.push(ElementMetadata {
+                    id,
+                    classes,
+                    element_type: "code".to_string(),
+                    parent: parent_index,
+                    attributes_ref,
+                    span: *span,
+                });
+
+                let code = self.code_element(content, attributes, attributes_ref);
+                hlirmodule.elements.push(code);
+
+                hlirmodule.elements.len() - 1
+            }
+            crate::ast::DocElement::If { .. } | crate::ast::DocElement::For { .. } => {
+                unreachable!("control flow is expanded by expand_document_element")
+            }
             _ => {
-                panic!(
-                    "Unsupported document element: {:?}  (HLIR document lowering)",
-                    element
+                self.error("Unsupported document element", element.span());
+                0
+            }
+        }
+    }
+
+    /// A text element and its metadata, split into runs
+    fn text_element(
+        &mut self,
+        inlines: &[Inline],
+        attributes: &HashMap<String, Expression>,
+        span: Span,
+        parent_index: Option<usize>,
+        ops: &mut Vec<Op>,
+        hlirmodule: &mut HLIRModule,
+    ) -> HlirElement {
+        let attributes_ref = self.add_attributes(attributes, hlirmodule);
+        let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
+        let index = hlirmodule.element_metadata.len();
+        hlirmodule.element_metadata.push(ElementMetadata {
+            id,
+            classes,
+            element_type: "text".to_string(),
+            parent: parent_index,
+            attributes_ref,
+            span,
+        });
+
+        let mut runs = Vec::new();
+        let outer = ElementMetadata {
+            id: None,
+            classes: Vec::new(),
+            element_type: "span".to_string(),
+            parent: Some(index),
+            attributes_ref,
+            span,
+        };
+        self.lower_inlines(inlines, &outer, ops, hlirmodule, &mut runs);
+        HlirElement::Text {
+            content: runs.iter().map(|run| run.text.as_str()).collect(),
+            runs,
+            attributes: attributes_ref,
+        }
+    }
+
+    /// The text of `parts`. Outside functions its `{expression}` parts are
+    /// evaluated now. Inside a function body they are lowered to `ops`,
+    /// building the value the text is filled in from every time the function
+    /// is called, and the text is kept as written until then.
+    fn lower_text(
+        &mut self,
+        parts: &[InterpPart],
+        span: Span,
+        ops: &mut Vec<Op>,
+        hlirmodule: &HLIRModule,
+    ) -> (String, Option<Id>) {
+        if !parts
+            .iter()
+            .any(|part| matches!(part, InterpPart::Expression(_)))
+        {
+            return (InterpPart::plain_text(parts), None);
+        }
+        if self.return_type.is_some() {
+            let text = Expression::InterpolatedString(parts.to_vec(), span);
+            let value = self.lower_expression(&text, ops, hlirmodule);
+            return (InterpPart::plain_text(parts), value);
+        }
+        let mut text = String::new();
+        for part in parts {
+            match part {
+                InterpPart::Text(literal) => text.push_str(literal),
+                InterpPart::Expression(expr) => {
+                    if let Some(value) = self.evaluate_document_expression(expr, hlirmodule) {
+                        text.push_str(&value.to_string());
+                    }
+                }
+            }
+        }
+        (text, None)
+    }
+
+    /// Append the runs of `inlines`, styled by the node of `outer`. Each
+    /// `span` and `link` gets metadata of its own whose parent is the text
+    /// element, with the classes of the spans around it, so the cascade can
+    /// style it.
+    fn lower_inlines(
+        &mut self,
+        inlines: &[Inline],
+        outer: &ElementMetadata,
+        ops: &mut Vec<Op>,
+        hlirmodule: &mut HLIRModule,
+        runs: &mut Vec<TextRun>,
+    ) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(parts) | Inline::Emphasis(_, parts) => {
+                    let (text, value) = self.lower_text(parts, outer.span, ops, hlirmodule);
+                    let emphasis = match inline {
+                        Inline::Emphasis(emphasis, _) => Some(*emphasis),
+                        _ => None,
+                    };
+                    runs.push(TextRun {
+                        text,
+                        emphasis,
+                        attributes: outer.attributes_ref,
+                        value,
+                        href: None,
+                    });
+                }
+                Inline::Span {
+                    content,
+                    attributes,
+                    span,
+                } => {
+                    let metadata =
+                        self.inline_metadata("span", attributes, *span, outer, hlirmodule);
+                    self.lower_inlines(content, &metadata, ops, hlirmodule, runs);
+                }
+                Inline::Link {
+                    href,
+                    content,
+                    attributes,
+                    span,
+                } => {
+                    let metadata =
+                        self.inline_metadata("link", attributes, *span, outer, hlirmodule);
+                    let (text, value) = self.lower_text(content, *span, ops, hlirmodule);
+                    runs.push(TextRun {
+                        text,
+                        emphasis: None,
+                        attributes: metadata.attributes_ref,
+                        value,
+                        href: Some(href.clone()),
+                    });
+                }
+            }
+        }
+    }
+
+    /// The metadata of a `span` or `link` inside the text `outer` is part of
+    fn inline_metadata(
+        &mut self,
+        element_type: &str,
+        attributes: &HashMap<String, Expression>,
+        span: Span,
+        outer: &ElementMetadata,
+        hlirmodule: &mut HLIRModule,
+    ) -> ElementMetadata {
+        let attributes_ref = self.add_attributes(attributes, hlirmodule);
+        let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
+        let metadata = ElementMetadata {
+            id: id.or_else(|| outer.id.clone()),
+            classes: outer.classes.iter().cloned().chain(classes).collect(),
+            element_type: element_type.to_string(),
+            parent: outer.parent,
+            attributes_ref,
+            span,
+        };
+        hlirmodule.span_metadata.push(metadata.clone());
+        metadata
+    }
+
+    /// A code block highlighted for its `lang`, code in a language without
+    /// highlighting is kept as plain text
+    fn code_element(
+        &mut self,
+        content: &str,
+        attributes: &HashMap<String, Expression>,
+        attributes_ref: usize,
+    ) -> HlirElement {
+        let lang = attributes.get("lang").map(|lang| lang.to_string());
+        let language = lang.as_deref().and_then(Language::find);
+        if let Some(lang) = &lang
+            && language.is_none()
+        {
+            let names: Vec<_> = LANGUAGES.iter().map(Language::name).collect();
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    format!("Unknown code language `{}`", lang),
+                    attributes["lang"].span(),
+                )
+                .with_note("the code is shown without highlighting")
+                .with_help(format!("highlighted languages are {}", names.join(", "))),
+            );
+        }
+
+        HlirElement::Code {
+            content: content.to_string(),
+            lines: highlight(content, language),
+            lang,
+            line_numbers: attributes
+                .get("line_numbers")
+                .is_some_and(|value| value.to_string() == "true"),
+            colors: HashMap::new(),
+            attributes: attributes_ref,
+        }
+    }
+
+    /// Lower a table and its cells, placing every cell on the table's grid
+    fn lower_table(
+        &mut self,
+        rows: &[DocElement],
+        attributes: &HashMap<String, Expression>,
+        span: Span,
+        hlirmodule: &mut HLIRModule,
+        parent_index: Option<usize>,
+    ) -> usize {
+        let attributes_ref = self.add_attributes(attributes, hlirmodule);
+        let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
+
+        // Reserve index before processing cells so cells get correct parent
+        let index = hlirmodule.elements.len();
+        hlirmodule.element_metadata.push(ElementMetadata {
+            id,
+            classes,
+            element_type: "table".to_string(),
+            parent: parent_index,
+            attributes_ref,
+            span,
+        });
+        hlirmodule.elements.push(HlirElement::Table {
+            children: Vec::new(), // Will be updated
+            columns: Vec::new(),
+            header_rows: 0,
+            attributes: attributes_ref,
+        });
+
+        let mut grid = CellGrid::default();
+        let mut header_rows = 0;
+        let mut children = Vec::new();
+        for row in rows {
+            self.expand_document_element(row, hlirmodule, &mut |pass, row, hlirmodule| {
+                let DocElement::TableRow {
+                    header,
+                    cells,
+                    span,
+                } = row
+                else {
+                    unreachable!("tables only hold rows")
+                };
+                // a header is only repeated on new pages when it leads the table
+                let leading = header_rows == grid.rows;
+                if *header && !leading {
+                    pass.diagnostics.push(
+                        Diagnostic::error("Header rows must come first in a table", *span)
+                            .with_help("move the `header` above the first `row`"),
+                    );
+                }
+                let header = *header && leading;
+                if header {
+                    header_rows += 1;
+                }
+                grid.start_row();
+                for cell in cells {
+                    pass.expand_document_element(
+                        cell,
+                        hlirmodule,
+                        &mut |pass, cell, hlirmodule| {
+                            let DocElement::TableCell {
+                                content,
+                                colspan,
+                                rowspan,
+                                attributes,
+                                span,
+                            } = cell
+                            else {
+                                unreachable!("rows only hold cells")
+                            };
+                            let attributes_ref = pass.add_attributes(attributes, hlirmodule);
+                            let (id, classes) =
+                                pass.extract_id_and_classes(attributes_ref, hlirmodule);
+                            hlirmodule.element_metadata.push(ElementMetadata {
+                                id,
+                                classes,
+                                element_type: "cell".to_string(),
+                                parent: Some(index),
+                                attributes_ref,
+                                span: *span,
+                            });
+
+                            let (row, column) = grid.place(*rowspan, *colspan);
+                            let (content, _) =
+                                pass.lower_text(content, *span, &mut Vec::new(), hlirmodule);
+                            hlirmodule.elements.push(HlirElement::TableCell {
+                                content,
+                                row,
+                                column,
+                                rowspan: *rowspan,
+                                colspan: *colspan,
+                                header,
+                                attributes: attributes_ref,
+                            });
+                            children.push(hlirmodule.elements.len() - 1);
+                        },
+                    );
+                }
+            });
+        }
+
+        // like HTML, a `rowspan` stops at the last row
+        for child in &children {
+            if let HlirElement::TableCell { row, rowspan, .. } = &mut hlirmodule.elements[*child] {
+                *rowspan = (*rowspan).min(grid.rows - *row);
+            }
+        }
+
+        // an invalid hint was already warned about by `add_attributes`
+        let mut columns = attributes
+            .get("columns")
+            .and_then(|hint| ColumnWidth::parse_list(&hint.to_string()).ok())
+            .unwrap_or_default();
+        if columns.len() < grid.columns {
+            columns.resize(grid.columns, ColumnWidth::Fraction(1.0));
+        }
+
+        // Update with actual cells
+        hlirmodule.elements[index] = HlirElement::Table {
+            children,
+            columns,
+            header_rows,
+            attributes: attributes_ref,
+        };
+
+        index
+    }
+
+    /// Call `lower` for every element `element` stands for: the element
+    /// itself, the elements of the branch an `if` takes, or the body of a
+    /// `for` once per value with the loop variable bound. Conditions and
+    /// iterables are evaluated here, so every iteration gets its own elements.
+    fn expand_document_element(
+        &mut self,
+        element: &DocElement,
+        hlirmodule: &mut HLIRModule,
+        lower: &mut dyn FnMut(&mut Self, &DocElement, &mut HLIRModule),
+    ) {
+        match element {
+            DocElement::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                let Some(value) = self.evaluate_document_expression(condition, hlirmodule) else {
+                    return;
+                };
+                let taken = match evaluator::condition(value, condition.span()) {
+                    Ok(true) => body.as_slice(),
+                    Ok(false) => else_body.as_deref().unwrap_or_default(),
+                    Err(diagnostic) => {
+                        self.diagnostics.push(diagnostic);
+                        return;
+                    }
+                };
+                for child in taken {
+                    self.expand_document_element(child, hlirmodule, lower);
+                }
+            }
+            DocElement::For {
+                iterator,
+                iterable,
+                body,
+                span,
+            } => {
+                let Some(value) = self.evaluate_document_expression(iterable, hlirmodule) else {
+                    return;
+                };
+                let values = match evaluator::iterate(value, iterable.span()) {
+                    Ok(values) => values,
+                    Err(diagnostic) => {
+                        self.diagnostics.push(diagnostic);
+                        return;
+                    }
+                };
+
+                self.symbol_table.push(HashMap::new()); // add new scope (loop)
+                let id = self.new_value();
+                self.add_symbol(iterator.clone(), id, *span);
+                for value in values {
+                    self.loop_values.insert(id, value);
+                    for child in body {
+                        self.expand_document_element(child, hlirmodule, lower);
+                    }
+                }
+                self.loop_values.remove(&id);
+                self.symbol_table.pop(); // remove scope (loop)
+            }
+            _ => lower(self, element, hlirmodule),
+        }
+    }
+
+    /// Every `#id` link has to point at an element with that id
+    fn check_link_targets(&mut self, hlirmodule: &HLIRModule) {
+        let mut links = Vec::new();
+        for (element, metadata) in hlirmodule.elements.iter().zip(&hlirmodule.element_metadata) {
+            match element {
+                HlirElement::Link { href, .. } => links.push((href, metadata.span)),
+                // links inside text are found by their node
+                HlirElement::Text { runs, .. } => {
+                    for run in runs {
+                        let Some(href) = &run.href else {
+                            continue;
+                        };
+                        let span = hlirmodule
+                            .span_metadata
+                            .iter()
+                            .find(|span| span.attributes_ref == run.attributes)
+                            .map_or(metadata.span, |span| span.span);
+                        links.push((href, span));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (href, span) in links {
+            let Some(target) = href.strip_prefix('#') else {
+                continue;
+            };
+            let found = hlirmodule
+                .element_metadata
+                .iter()
+                .any(|other| other.id.as_deref() == Some(target));
+            if !found {
+                self.diagnostics.push(
+                    Diagnostic::error(format!("No element with id `{}`", target), span).with_help(
+                        format!(
+                            "give the target element an id: `section(id=\"{}\") {{ ... }}`",
+                            target
+                        ),
+                    ),
                 );
             }
         }
     }
 
+    /// Evaluate an expression outside a function body, where only globals
+    /// and loop variables are in scope
+    pub fn evaluate_document_expression(
+        &mut self,
+        expr: &Expression,
+        hlirmodule: &HLIRModule,
+    ) -> Option<Value> {
+        let mut ops = Vec::new();
+        let result = self.lower_expression(expr, &mut ops, hlirmodule)?;
+        let mut evaluator = Evaluator::with_values(hlirmodule, self.loop_values.clone());
+        match evaluator.evaluate(&ops, result, expr.span()) {
+            Ok(value) => Some(value),
+            Err(diagnostic) => {
+                self.diagnostics.push(diagnostic);
+                None
+            }
+        }
+    }
+
+    /// Add an element's attributes to the attribute tree, warning about
+    /// style values that will be ignored
+    fn add_attributes(
+        &mut self,
+        attributes: &HashMap<String, Expression>,
+        hlirmodule: &mut HLIRModule,
+    ) -> usize {
+        let mut attributes = attributes.clone();
+        let mut calls = HashMap::new();
+        let mut sorted: Vec<_> = attributes.iter_mut().collect();
+        sorted.sort_by_key(|(_, value)| value.span().start);
+        for (name, value) in sorted {
+            // globals and arithmetic are folded now, like calls outside functions
+            let constant = self.literal_of(value).is_none() && self.is_constant(value);
+            if constant || calls_function(value) {
+                if !constant && self.return_type.is_some() {
+                    // computed every time the function is called
+                    let mut ops = Vec::new();
+                    if let Some(result) = self.lower_expression(value, &mut ops, hlirmodule) {
+                        calls.insert(name.clone(), (ops, result));
+                    }
+                    continue;
+                }
+                match self.evaluate_document_expression(value, hlirmodule) {
+                    Some(result) => *value = result.to_expression(value.span()),
+                    None => continue,
+                }
+            }
+            // the value of a parameter is checked once the function is called
+            if self.names_param(value) {
+                continue;
+            }
+            if let Err(message) = StyleAttributes::check_attribute(name, value) {
+                self.diagnostics.push(
+                    Diagnostic::warning(message, value.span())
+                        .with_note(format!("the `{}` attribute is ignored", name)),
+                );
+            }
+        }
+        let mut attribute_node =
+            AttributeNode::new_with_attributes(&attributes, hlirmodule.attributes.size);
+        attribute_node.calls = calls;
+        hlirmodule.attributes.add_attribute(attribute_node)
+    }
+
+    /// Whether an attribute is a parameter, on its own or inside a string
+    fn names_param(&mut self, value: &Expression) -> bool {
+        let names: Vec<&str> = match value {
+            Expression::Identifier(name, _) => vec![name],
+            Expression::InterpolatedString(parts, _) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    InterpPart::Expression(Expression::Identifier(name, _)) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        names.into_iter().any(|name| {
+            self.find_symbol(name)
+                .is_some_and(|id| self.params.contains(&id))
+        })
+    }
+
     /// Extract id and classes from element attributes
+    /// The id and classes of the element styled by the attribute node `node`,
+    /// from its attributes as written or with the calls in them made
     fn extract_id_and_classes(
         &self,
-        attributes: &HashMap<String, Expression>,
+        node: usize,
+        hlirmodule: &HLIRModule,
     ) -> (Option<String>, Vec<String>) {
+        let Some(node) = hlirmodule.attributes.find_node(node) else {
+            return (None, Vec::new());
+        };
+        let attributes = &node.source;
         let id = attributes.get("id").map(|e| e.to_string());
 
         let classes = attributes
@@ -297,20 +1237,30 @@ impl HLIRPass {
         (id, classes)
     }
 
-    pub fn add_symbol(&mut self, name: String, id: Id) {
-        for scope in self.symbol_table.iter_mut().rev() {
-            if let Some(_symbol) = scope.get(&name) {
-                // TODO check if the the id types match (Func/value/global), if there is a function defined with the same name as a variable then it should be ok or vice versa
-                panic!("Symbol {} already exists", name);
+    /// Add a name to the innermost scope. Parameters, locals and loop
+    /// variables shadow globals and functions, wherever those are declared,
+    /// but not each other.
+    pub fn add_symbol(&mut self, name: String, id: Id, span: Span) {
+        if self.symbol_table.len() == 1 {
+            // both of two globals or functions with the same name are kept,
+            // and reported by the validation pass
+            if self.symbol_table[0].contains_key(&name) {
+                return;
             }
+        } else if self.symbol_table[1..]
+            .iter()
+            .any(|scope| scope.contains_key(&name))
+        {
+            self.error(format!("Duplicate symbol `{}`", name), span);
+            return;
         }
         let len = self.symbol_table.len();
         let scope = self.symbol_table.get_mut(len - 1).unwrap(); // most recent scope
         scope.insert(name.clone(), id); // add to known symbols
     }
 
-    fn find_symbol(&mut self, name: &str) -> Option<Id> {
-        for scope in self.symbol_table.iter_mut().rev() {
+    pub fn find_symbol(&self, name: &str) -> Option<Id> {
+        for scope in self.symbol_table.iter().rev() {
             if let Some(symbol) = scope.get(name) {
                 return Some(*symbol);
             }
@@ -318,41 +1268,104 @@ impl HLIRPass {
         None
     }
 
+    /// The type written in `let name: Type = ...`, reporting one that is
+    /// unknown
+    pub fn declared_type(&mut self, ty: Option<&str>, span: Span) -> Option<Type> {
+        let ty = ty?;
+        let declared = value_type(ty);
+        if declared.is_none() {
+            self.diagnostics.push(
+                Diagnostic::error(format!("Unknown type `{}`", ty), span)
+                    .with_help("variable types are `Int`, `Float`, `String`, `Bool`, `Color`, `List` and `Map`"),
+            );
+        }
+        declared
+    }
+
+    /// The elements of a `return { ... }`, converted like the children of a
+    /// section. The fragment has to be pushed right after.
+    pub fn fragment_element(
+        &mut self,
+        elements: &[DocElement],
+        span: Span,
+        ops: &mut Vec<Op>,
+        hlirmodule: &mut HLIRModule,
+    ) -> HlirElement {
+        let attributes_ref = self.add_attributes(&HashMap::new(), hlirmodule);
+        let children: Vec<usize> = elements
+            .iter()
+            .map(|child| {
+                let child_hlir = self.convert_doc_element_to_hlir(child, ops, hlirmodule);
+                hlirmodule.elements.push(child_hlir);
+                hlirmodule.elements.len() - 1
+            })
+            .collect();
+        // the fragment is pushed right after its children
+        let index = hlirmodule.elements.len();
+        for child in &children {
+            hlirmodule.element_metadata[*child].parent = Some(index);
+        }
+        hlirmodule.element_metadata.push(ElementMetadata {
+            id: None,
+            classes: Vec::new(),
+            element_type: "fragment".to_string(),
+            parent: None,
+            attributes_ref,
+            span,
+        });
+        HlirElement::Fragment {
+            children,
+            attributes: attributes_ref,
+        }
+    }
+
+    /// An empty fragment standing for the element a call returns, until
+    /// `evaluate` puts the copy made for the call in its place. The
+    /// placeholder has to be pushed right after.
+    pub fn call_placeholder(
+        &mut self,
+        span: Span,
+        parent: Option<usize>,
+        hlirmodule: &mut HLIRModule,
+    ) -> HlirElement {
+        let attributes_ref = self.add_attributes(&HashMap::new(), hlirmodule);
+        hlirmodule.element_metadata.push(ElementMetadata {
+            id: None,
+            classes: Vec::new(),
+            element_type: "fragment".to_string(),
+            parent,
+            attributes_ref,
+            span,
+        });
+        HlirElement::Fragment {
+            children: Vec::new(),
+            attributes: attributes_ref,
+        }
+    }
+
     pub fn convert_doc_element_to_hlir(
-        &self,
+        &mut self,
         element: &crate::ast::DocElement,
+        ops: &mut Vec<Op>,
         hlirmodule: &mut HLIRModule,
     ) -> HlirElement {
         match element {
+            crate::ast::DocElement::Call { name, args, span } => {
+                self.lower_element_call(name, args, *span, ops, hlirmodule)
+            }
             crate::ast::DocElement::Text {
-                content,
+                inlines,
                 attributes,
-            } => {
-                let (id, classes) = self.extract_id_and_classes(attributes);
-                let attribute_node =
-                    AttributeNode::new_with_attributes(attributes, hlirmodule.attributes.size);
-                let attributes_ref = hlirmodule.attributes.add_attribute(attribute_node);
-                // Push metadata for this element
-                hlirmodule.element_metadata.push(ElementMetadata {
-                    id,
-                    classes,
-                    element_type: "text".to_string(),
-                    parent: None,
-                    attributes_ref,
-                });
-                HlirElement::Text {
-                    content: content.clone(),
-                    attributes: attributes_ref,
-                }
-            }
+                span,
+                ..
+            } => self.text_element(inlines, attributes, *span, None, ops, hlirmodule),
             crate::ast::DocElement::Section {
                 elements,
                 attributes,
+                span,
             } => {
-                let (id, classes) = self.extract_id_and_classes(attributes);
-                let attribute_node =
-                    AttributeNode::new_with_attributes(attributes, hlirmodule.attributes.size);
-                let attributes_ref = hlirmodule.attributes.add_attribute(attribute_node);
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
                 // Get the parent index for children (current section's index)
                 let parent_index = hlirmodule.elements.len();
                 // Recursively convert all children
@@ -360,7 +1373,7 @@ impl HLIRPass {
                     .iter()
                     .map(|child| {
                         // Update parent in child's metadata after we know the parent's index
-                        let child_hlir = self.convert_doc_element_to_hlir(child, hlirmodule);
+                        let child_hlir = self.convert_doc_element_to_hlir(child, ops, hlirmodule);
                         let child_index = hlirmodule.elements.len();
                         hlirmodule.elements.push(child_hlir);
                         // Update the child's metadata to set the parent
@@ -377,24 +1390,27 @@ impl HLIRPass {
                     element_type: "section".to_string(),
                     parent: None,
                     attributes_ref,
+                    span: *span,
                 });
                 HlirElement::Section {
                     children,
                     attributes: attributes_ref,
                 }
             }
-            crate::ast::DocElement::List { items, attributes } => {
-                let (id, classes) = self.extract_id_and_classes(attributes);
-                let attribute_node =
-                    AttributeNode::new_with_attributes(attributes, hlirmodule.attributes.size);
-                let attributes_ref = hlirmodule.attributes.add_attribute(attribute_node);
+            crate::ast::DocElement::List {
+                items,
+                attributes,
+                span,
+            } => {
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
                 // Get the parent index for children (current list's index)
                 let parent_index = hlirmodule.elements.len();
                 // Recursively convert all list items
                 let children: Vec<usize> = items
                     .iter()
                     .map(|item| {
-                        let child_hlir = self.convert_doc_element_to_hlir(item, hlirmodule);
+                        let child_hlir = self.convert_doc_element_to_hlir(item, ops, hlirmodule);
                         let child_index = hlirmodule.elements.len();
                         hlirmodule.elements.push(child_hlir);
                         // Update the child's metadata to set the parent
@@ -411,28 +1427,150 @@ impl HLIRPass {
                     element_type: "list".to_string(),
                     parent: None,
                     attributes_ref,
+                    span: *span,
                 });
                 HlirElement::List {
                     children,
                     attributes: attributes_ref,
                 }
             }
-            _ => {
-                // For other element types, create a placeholder text element
-                // This is a temporary bandaid for the MLIR migration
-                // TODO fix
+            crate::ast::DocElement::Link {
+                href,
+                content,
+                attributes,
+                span,
+            } => {
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
                 hlirmodule.element_metadata.push(ElementMetadata {
-                    id: None,
-                    classes: Vec::new(),
-                    element_type: "text".to_string(),
+                    id,
+                    classes,
+                    element_type: "link".to_string(),
+                    parent: None,
+                    attributes_ref,
+                    span: *span,
+                });
+                let (content, value) = self.lower_text(content, *span, ops, hlirmodule);
+                HlirElement::Link {
+                    href: href.clone(),
+                    content,
+                    value,
+                    attributes: attributes_ref,
+                }
+            }
+            crate::ast::DocElement::Image {
+                src,
+                attributes,
+                span,
+            } => {
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
+                hlirmodule.element_metadata.push(ElementMetadata {
+                    id,
+                    classes,
+                    element_type: "image".to_string(),
                     parent: None,
-                    attributes_ref: 1,
+                    attributes_ref,
+                    span: *span,
                 });
-                HlirElement::Text {
-                    content: String::new(),
-                    attributes: 1, // Root attribute node
+                HlirElement::Image {
+                    src: src.clone(),
+                    alt: attributes.get("alt").map(|alt| alt.to_string()),
+                    path: PathBuf::from(src),
+                    info: None,
+                    attributes: attributes_ref,
                 }
             }
+            crate::ast::DocElement::Code {
+                content,
+                attributes,
+                span,
+            } => {
+                let attributes_ref = self.add_attributes(attributes, hlirmodule);
+                let (id, classes) = self.extract_id_and_classes(attributes_ref, hlirmodule);
+                hlirmodule.element_metadata.push(ElementMetadata {
+                    id,
+                    classes,
+                    element_type: "code".to_string(),
+                    parent: None,
+                    attributes_ref,
+                    span: *span,
+                });
+                self.code_element(content, attributes, attributes_ref)
+            }
+            // not lowered inside a function yet, and reported instead. An
+            // empty fragment takes their place, so nothing is drawn for them.
+            DocElement::If { span, .. } | DocElement::For { span, .. } => {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        "`if` and `for` cannot be used inside an element a function returns",
+                        *span,
+                    )
+                    .with_help(
+                        "return a different element from each branch of an `if` statement, \
+                         or loop over the call in the `document` block",
+                    ),
+                );
+                self.fragment_element(&[], *span, ops, hlirmodule)
+            }
+            DocElement::Table { span, .. } => {
+                self.diagnostics.push(
+                    Diagnostic::error("Tables cannot be returned from a function", *span)
+                        .with_help("write the table in the `document` block"),
+                );
+                self.fragment_element(&[], *span, ops, hlirmodule)
+            }
+            DocElement::TableRow { span, .. } | DocElement::TableCell { span, .. } => {
+                self.error("Unsupported document element", *span);
+                self.fragment_element(&[], *span, ops, hlirmodule)
+            }
         }
     }
 }
+
+/// Places table cells the way HTML does: left to right along their row,
+/// skipping the slots taken by `rowspan` cells of the rows above
+#[derive(Default)]
+struct CellGrid {
+    taken: Vec<Vec<bool>>, // [row][column]
+    rows: usize,
+    columns: usize,
+    next_column: usize,
+}
+
+impl CellGrid {
+    fn start_row(&mut self) {
+        self.rows += 1;
+        self.next_column = 0;
+    }
+
+    /// Row and column of the next cell of the current row
+    fn place(&mut self, rowspan: usize, colspan: usize) -> (usize, usize) {
+        let row = self.rows - 1;
+        let mut column = self.next_column;
+        while self.is_taken(row, column) {
+            column += 1;
+        }
+        for r in row..row + rowspan {
+            if self.taken.len() <= r {
+                self.taken.resize(r + 1, Vec::new());
+            }
+            let taken = &mut self.taken[r];
+            if taken.len() < column + colspan {
+                taken.resize(column + colspan, false);
+            }
+            taken[column..column + colspan].fill(true);
+        }
+        self.next_column = column + colspan;
+        self.columns = self.columns.max(self.next_column);
+        (row, column)
+    }
+
+    fn is_taken(&self, row: usize, column: usize) -> bool {
+        self.taken
+            .get(row)
+            .and_then(|columns| columns.get(column))
+            .copied()
+            .unwrap_or(false)
+    }
+}
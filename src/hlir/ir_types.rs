@@ -1,7 +1,11 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::str::FromStr;
 
-use crate::ast::{DocElement, Expression, StyleRule};
+use crate::ast::{DocElement, Emphasis, Expression, StyleRule};
+use crate::diagnostics::{Diagnostic, Span};
+use crate::highlight::{CodeRun, TokenClass};
+use crate::image::ImageInfo;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
@@ -10,6 +14,10 @@ pub enum Type {
     Bool,
     String,
     Color,
+    /// A list of values of any type
+    List,
+    /// Values by name, the entries can have different types
+    Map,
     DocElement,
 }
 
@@ -20,6 +28,9 @@ pub enum Literal {
     Bool(bool),
     String(String),
     Color(String),
+    List(Vec<Literal>),
+    /// The entries in the order they were written
+    Map(Vec<(String, Literal)>),
 }
 
 // IDs
@@ -48,48 +59,214 @@ pub enum Id {
     Value(ValueId),
 }
 
+/// Every op keeps the span of the source construct it was lowered from
 #[derive(Debug, Clone)]
 pub enum Op {
     Const {
         result: Id,
         literal: Literal,
         ty: Type,
+        span: Span,
     },
+    /// The type declared for the local `result` in `let name: ty = ...`,
+    /// checked by the validation pass
     Var {
         result: Id,
         name: String,
         ty: Type,
+        span: Span,
     },
     Binary {
         result: Id,
         op: BinOp,
         lhs: Id,
         rhs: Id,
+        span: Span,
     },
+    /// `lhs && rhs` or `lhs || rhs`. `rhs_ops` compute `rhs` and only run
+    /// when `lhs` does not decide the result on its own.
+    Logical {
+        result: Id,
+        op: BinOp,
+        lhs: Id,
+        rhs_ops: Vec<Op>,
+        rhs: Id,
+        span: Span,
+    },
+    Unary {
+        result: Id,
+        op: UnOp,
+        operand: Id,
+        span: Span,
+    },
+    /// Integers from `start` up to but not including `end`
+    Range {
+        result: Id,
+        start: Id,
+        end: Id,
+        span: Span,
+    },
+    /// `[items...]`
+    List {
+        result: Id,
+        items: Vec<Id>,
+        span: Span,
+    },
+    /// `{ name: value, ... }`
+    Map {
+        result: Id,
+        entries: Vec<(String, Id)>,
+        span: Span,
+    },
+    /// The item of a list at an `Int` index, or the entry of a map
+    /// with a `String` key
+    Index {
+        result: Id,
+        value: Id,
+        index: Id,
+        span: Span,
+    },
+    /// The entry `name` of a map
+    Field {
+        result: Id,
+        value: Id,
+        name: String,
+        span: Span,
+    },
+    /// The built-in `len()`, the number of items of a list, entries of a map
+    /// or characters of a string
+    Len {
+        result: Id,
+        value: Id,
+        span: Span,
+    },
+    /// Store `value` in the local `target`, used for `let` with a computed
+    /// value and for reassignment
+    Assign {
+        target: Id,
+        value: Id,
+        span: Span,
+    },
+    /// `instance` is the copy of the function's returned element made for
+    /// this call, with the arguments filled in, set by `evaluate`. A call
+    /// inside another element has a `placeholder` instead, an empty fragment
+    /// among the children of that element, which `evaluate` replaces with
+    /// the copy.
     Call {
         result: Option<Id>,
         func: Id,
         args: Vec<Id>,
+        instance: Option<usize>,
+        placeholder: Option<usize>,
+        span: Span,
     },
     Return {
         doc_element_ref: usize,
+        span: Span,
+    },
+    /// Return the value of `value` from a function with a declared return type
+    ReturnValue {
+        value: Id,
+        span: Span,
     },
     HlirElementEmit {
         index: usize,
+        span: Span,
     },
     StringConcat {
         result: Id,
         parts: Vec<Id>,
+        span: Span,
+    },
+    If {
+        condition: Id,
+        then_ops: Vec<Op>,
+        else_ops: Vec<Op>,
+        span: Span,
+    },
+    /// `condition_ops` are run before every iteration to recompute `condition`
+    While {
+        condition_ops: Vec<Op>,
+        condition: Id,
+        body: Vec<Op>,
+        span: Span,
+    },
+    For {
+        iterator: Id,
+        iterable: Id,
+        body: Vec<Op>,
+        span: Span,
     },
 }
 
-#[derive(Debug, Clone)]
+impl Op {
+    pub fn span(&self) -> Span {
+        match self {
+            Op::Const { span, .. }
+            | Op::Var { span, .. }
+            | Op::Binary { span, .. }
+            | Op::Logical { span, .. }
+            | Op::Unary { span, .. }
+            | Op::Range { span, .. }
+            | Op::List { span, .. }
+            | Op::Map { span, .. }
+            | Op::Index { span, .. }
+            | Op::Field { span, .. }
+            | Op::Len { span, .. }
+            | Op::Assign { span, .. }
+            | Op::Call { span, .. }
+            | Op::Return { span, .. }
+            | Op::ReturnValue { span, .. }
+            | Op::HlirElementEmit { span, .. }
+            | Op::StringConcat { span, .. }
+            | Op::If { span, .. }
+            | Op::While { span, .. }
+            | Op::For { span, .. } => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinOp {
     Add,
     Sub,
     Mul,
     Div,
+    Mod,
     Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
 }
 
 // how globals + Variables are handled
@@ -101,6 +278,7 @@ pub struct Global {
     pub ty: Type,
     pub init: Literal,
     pub mutable: bool,
+    pub span: Span,
 }
 
 pub struct Local {
@@ -111,13 +289,25 @@ pub struct Local {
 
 // how functions are handled
 
+/// A parameter of a function, bound to the local `id` inside its body.
+/// `default` is the value it gets when a call leaves it out.
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub id: Id,
+    pub default: Option<(Literal, Type)>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub struct Func {
     pub id: Id,
     pub name: String,
     pub args: Vec<Type>,
+    pub params: Vec<Param>, // Parallel to args
     pub return_type: Option<Type>,
     pub body: FuncBlock,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -141,6 +331,21 @@ pub struct ElementMetadata {
     pub element_type: String,
     pub parent: Option<usize>, // Index into elements vector
     pub attributes_ref: usize, // Index into AttributeTree
+    pub span: Span,            // Source of the element, for error reporting
+}
+
+/// A piece of a text element with one look. `attributes` is the node the
+/// run is styled by: the text's own, or that of the innermost `span` around it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun {
+    pub text: String,
+    pub emphasis: Option<Emphasis>,
+    pub attributes: usize,
+    /// Inside a function body, the value the text is built from by the
+    /// function's ops, filled in every time it is called
+    pub value: Option<Id>,
+    /// Where the run links to, for a `link` inside text
+    pub href: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -151,6 +356,8 @@ pub struct HLIRModule {
     pub css_rules: Vec<StyleRule>, // Parsed CSS rules (unapplied)
     pub elements: Vec<HlirElement>,
     pub element_metadata: Vec<ElementMetadata>, // Parallel to elements, for CSS matching
+    pub span_metadata: Vec<ElementMetadata>,    // `span`s inside text, matched like elements
+    pub diagnostics: Vec<Diagnostic>,           // Errors found while lowering
 }
 
 #[derive(Debug, Clone)]
@@ -163,11 +370,98 @@ pub enum HlirElement {
         children: Vec<usize>,
         attributes: usize,
     },
+    /// `content` is the whole text, `runs` the same text split where its
+    /// look changes
     Text {
         content: String,
+        runs: Vec<TextRun>,
+        attributes: usize,
+    },
+    /// `href` is a URL, or `#id` for an element of this document
+    /// `value` is the value `content` is built from, like that of a `TextRun`
+    Link {
+        href: String,
+        content: String,
+        value: Option<Id>,
+        attributes: usize,
+    },
+    Image {
+        src: String, // as written in the source
+        alt: Option<String>,
+        path: PathBuf, // `src` resolved against the input file by `load_images`
+        info: Option<ImageInfo>, // header of the file, set by `load_images`
+        attributes: usize,
+    },
+    /// `children` are `TableCell`s placed on a grid with one column per
+    /// entry of `columns`. The first `header_rows` rows are repeated at the
+    /// top of every page the table continues on.
+    Table {
+        children: Vec<usize>,
+        columns: Vec<ColumnWidth>,
+        header_rows: usize,
+        attributes: usize,
+    },
+    /// `row` and `column` are the grid position of the cell's top left
+    /// corner, counted from 0
+    TableCell {
+        content: String,
+        row: usize,
+        column: usize,
+        rowspan: usize,
+        colspan: usize,
+        header: bool,
+        attributes: usize,
+    },
+    /// `content` is the code as written and `lines` the same text split into
+    /// highlighted runs. `colors` holds the colour of each kind of token,
+    /// filled in by `resolve_styles`.
+    Code {
+        content: String,
+        lang: Option<String>,
+        line_numbers: bool,
+        lines: Vec<Vec<CodeRun>>,
+        colors: HashMap<TokenClass, Color>,
+        attributes: usize,
+    },
+    /// The elements a function returned together. They are laid out and
+    /// drawn as children of the container the function is called in, the
+    /// fragment itself takes no room.
+    Fragment {
+        children: Vec<usize>,
         attributes: usize,
     },
-    // TODO code, images, etc
+}
+
+impl HlirElement {
+    /// Index of the element's node in the `AttributeTree`
+    pub fn attributes(&self) -> usize {
+        match self {
+            HlirElement::Section { attributes, .. }
+            | HlirElement::List { attributes, .. }
+            | HlirElement::Text { attributes, .. }
+            | HlirElement::Link { attributes, .. }
+            | HlirElement::Image { attributes, .. }
+            | HlirElement::Table { attributes, .. }
+            | HlirElement::TableCell { attributes, .. }
+            | HlirElement::Code { attributes, .. }
+            | HlirElement::Fragment { attributes, .. } => *attributes,
+        }
+    }
+
+    /// Indices of the child elements, empty for leaves
+    pub fn children(&self) -> &[usize] {
+        match self {
+            HlirElement::Section { children, .. }
+            | HlirElement::List { children, .. }
+            | HlirElement::Table { children, .. }
+            | HlirElement::Fragment { children, .. } => children,
+            HlirElement::Text { .. }
+            | HlirElement::Link { .. }
+            | HlirElement::Image { .. }
+            | HlirElement::TableCell { .. }
+            | HlirElement::Code { .. } => &[],
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -198,6 +492,19 @@ impl AttributeTree {
     pub fn find_node_mut(&mut self, id: usize) -> Option<&mut AttributeNode> {
         self.root.find_node_mut_recursive(id)
     }
+
+    /// Add a copy of the node `id` with its computed styles reset, returning
+    /// the id of the copy
+    pub fn copy_node(&mut self, id: usize) -> usize {
+        let mut copy = self
+            .find_node(id)
+            .cloned()
+            .unwrap_or_else(AttributeNode::new);
+        copy.parent = Some(self.size);
+        copy.id = self.size + 1;
+        copy.computed = StyleAttributes::default();
+        self.add_attribute(copy)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -207,6 +514,13 @@ pub struct AttributeNode {
     pub inline: StyleAttributes, // Inline styles from element attributes
     pub computed: StyleAttributes, // Final computed styles after CSS resolution
     pub children: HashMap<usize, AttributeNode>,
+    /// The attributes as written, so those naming function parameters can be
+    /// filled in once the function is called
+    pub source: HashMap<String, Expression>,
+    /// Attributes calling a function inside a function body, lowered to the
+    /// ops computing them and the id of the result. They are computed every
+    /// time the function returning the element is called.
+    pub calls: HashMap<String, (Vec<Op>, Id)>,
 }
 
 impl AttributeNode {
@@ -217,6 +531,8 @@ impl AttributeNode {
             inline: StyleAttributes::default(),
             computed: StyleAttributes::default(),
             children: HashMap::new(),
+            source: HashMap::new(),
+            calls: HashMap::new(),
         }
     }
 
@@ -227,6 +543,8 @@ impl AttributeNode {
             inline: StyleAttributes::new_with_attributes(attributes),
             computed: StyleAttributes::default(),
             children: HashMap::new(),
+            source: attributes.clone(),
+            calls: HashMap::new(),
         }
     }
 
@@ -313,6 +631,108 @@ impl FromStr for Align {
     }
 }
 
+/// An RGB colour with channels from 0 to 1, written as `#rgb`, `#rrggbb`,
+/// `rgb(r, g, b)` with channels from 0 to 255, or one of the basic CSS
+/// colour names
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let channels = match s.strip_prefix("rgb(").and_then(|s| s.strip_suffix(')')) {
+            Some(channels) => channels
+                .split(',')
+                .map(|channel| channel.trim().parse::<u8>().ok())
+                .collect::<Option<Vec<_>>>(),
+            None => hex_channels(s),
+        };
+        match channels.as_deref() {
+            Some(&[r, g, b]) => Ok(Color {
+                r: f32::from(r) / 255.0,
+                g: f32::from(g) / 255.0,
+                b: f32::from(b) / 255.0,
+            }),
+            _ => Err(format!("Invalid color value: {}", s)),
+        }
+    }
+}
+
+/// The channels of a colour written as a name or in hex
+fn hex_channels(s: &str) -> Option<Vec<u8>> {
+    let hex = match s {
+        "black" => "000000",
+        "silver" => "c0c0c0",
+        "gray" | "grey" => "808080",
+        "white" => "ffffff",
+        "maroon" => "800000",
+        "red" => "ff0000",
+        "purple" => "800080",
+        "fuchsia" => "ff00ff",
+        "green" => "008000",
+        "lime" => "00ff00",
+        "olive" => "808000",
+        "yellow" => "ffff00",
+        "navy" => "000080",
+        "blue" => "0000ff",
+        "teal" => "008080",
+        "aqua" => "00ffff",
+        "orange" => "ffa500",
+        _ => s.strip_prefix('#').unwrap_or_default(),
+    };
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        // `#abc` is `#aabbcc`
+        3 => hex
+            .chars()
+            .map(|c| channel(&c.to_string().repeat(2)))
+            .collect(),
+        6 if hex.is_ascii() => (0..3).map(|i| channel(&hex[2 * i..2 * i + 2])).collect(),
+        _ => None,
+    }
+}
+
+/// Width hint for a table column, one entry of the `columns` attribute:
+/// `auto`, a length in points, a percentage of the table or a share of the
+/// remaining width like `2fr`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnWidth {
+    Auto,
+    Points(f32),
+    Percent(f32),
+    Fraction(f32),
+}
+
+impl ColumnWidth {
+    /// All the widths of a `columns` attribute, separated by whitespace
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, String> {
+        s.split_whitespace().map(str::parse).collect()
+    }
+}
+
+impl FromStr for ColumnWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |n: &str| n.parse::<f32>().ok().filter(|n| *n >= 0.0);
+        let width = if s == "auto" {
+            Some(ColumnWidth::Auto)
+        } else if let Some(n) = s.strip_suffix("fr") {
+            number(n).map(ColumnWidth::Fraction)
+        } else if let Some(n) = s.strip_suffix('%') {
+            number(n).map(ColumnWidth::Percent)
+        } else {
+            number(s).map(ColumnWidth::Points)
+        };
+        width.ok_or_else(|| format!("Invalid column width: {}", s))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PageBreak {
     Before,
@@ -390,6 +810,44 @@ impl StyleAttributes {
         }
     }
 
+    /// Check a value for one of the typed properties. `set` ignores values
+    /// that fail this check, so they are reported as warnings.
+    pub fn check_property(property: &str, value: &str) -> Result<(), String> {
+        match property {
+            "margin" | "padding" | "width" | "height" => value
+                .parse::<f32>()
+                .map(|_| ())
+                .map_err(|_| format!("Invalid {} value: {}", property, value)),
+            "align" => value.parse::<Align>().map(|_| ()),
+            "page_break" => value.parse::<PageBreak>().map(|_| ()),
+            "columns" => ColumnWidth::parse_list(value).map(|_| ()),
+            "color" => value.parse::<Color>().map(|_| ()),
+            "hidden" | "line_numbers" | "ordered" => value
+                .parse::<bool>()
+                .map(|_| ())
+                .map_err(|_| format!("Invalid {} value: {}", property, value)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the value of an element attribute. `hidden` and `condition`
+    /// take a `Bool`, the others are checked like a style property.
+    pub fn check_attribute(property: &str, value: &Expression) -> Result<(), String> {
+        match (property, value) {
+            ("hidden" | "condition", Expression::Bool(..)) => Ok(()),
+            ("hidden" | "condition", Expression::StringLiteral(text, _)) => Err(format!(
+                "Invalid {} value: \"{}\", expected `true` or `false` without quotes",
+                property, text
+            )),
+            ("hidden" | "condition", value) => Err(format!(
+                "Invalid {} value: {}, expected `true` or `false`",
+                property,
+                value.to_string()
+            )),
+            _ => Self::check_property(property, &value.to_string()),
+        }
+    }
+
     pub fn set(&mut self, property: &str, value: String) {
         match property {
             "id" => self.id = Some(value),
@@ -473,6 +931,11 @@ impl StyleAttributes {
                 self.style.insert("font-weight".to_string(), val.clone());
             }
         }
+        if !self.style.contains_key("font-style")
+            && let Some(val) = parent.style.get("font-style")
+        {
+            self.style.insert("font-style".to_string(), val.clone());
+        }
         if self.style.get("color").is_none() {
             if let Some(val) = parent.style.get("color") {
                 self.style.insert("color".to_string(), val.clone());
@@ -520,12 +983,12 @@ impl StyleAttributes {
             result.align = expr.to_string().parse().ok();
         }
 
-        if let Some(expr) = attributes.get("hidden") {
-            result.hidden = expr.to_string().parse().unwrap_or(false);
+        if let Some(Expression::Bool(hidden, _)) = attributes.get("hidden") {
+            result.hidden = *hidden;
         }
 
-        if let Some(expr) = attributes.get("condition") {
-            result.condition = expr.to_string().parse().ok();
+        if let Some(Expression::Bool(condition, _)) = attributes.get("condition") {
+            result.condition = Some(*condition);
         }
 
         if let Some(expr) = attributes.get("page_break") {
@@ -536,6 +999,12 @@ impl StyleAttributes {
             result.role = Some(expr.to_string());
         }
 
+        for property in ["width", "height"] {
+            if let Some(expr) = attributes.get(property) {
+                result.style.insert(property.to_string(), expr.to_string());
+            }
+        }
+
         result
     }
 
@@ -6,8 +6,12 @@ mod ir_types;
 mod util;
 
 pub use ir_types::{
-    ElementMetadata, Func, FuncId, HLIRModule, HlirElement, Id, Literal, Op, StyleAttributes, Type,
+    BinOp, Color, ColumnWidth, ElementMetadata, Func, FuncId, HLIRModule, HlirElement, Id, Literal,
+    Op, Param, StyleAttributes, TextRun, Type, UnOp,
 };
 pub use util::assign_func;
 pub use util::assign_vars;
+pub use util::evaluator::{Value, evaluate};
+pub use util::image_loader::load_images;
 pub use util::style_resolver::resolve_styles;
+pub use util::validation_pass::validate;
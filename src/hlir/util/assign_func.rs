@@ -1,14 +1,66 @@
 use std::collections::HashMap;
 
+use crate::ast::{Argument, Expression, FuncParam, Statement};
+use crate::diagnostics::{Diagnostic, Span};
 use crate::hlir::hlir::HLIRPass;
-use crate::hlir::ir_types::{AttributeNode, FuncBlock, HLIRModule, HlirElement, Id, Op, ValueId};
-
-use crate::ast::ArgType;
+use crate::hlir::ir_types::{
+    Func, FuncBlock, HLIRModule, HlirElement, Id, Literal, Op, Param, Type,
+};
+use crate::hlir::util::evaluator::Value;
 
 impl HLIRPass {
+    /// The parameters of a function, each bound to a fresh local. Defaults
+    /// that are literals are known right away, others are computed by
+    /// `param_defaults` where the function is declared.
+    pub fn function_params(&mut self, params: &[FuncParam], hlirmodule: &HLIRModule) -> Vec<Param> {
+        let mut bound = Vec::new();
+        for param in params {
+            let Expression::Identifier(name, _) = &param.value else {
+                self.error("Expected a parameter name", param.value.span());
+                continue;
+            };
+            let default = if self.literal_default(param) {
+                self.param_default(param, hlirmodule)
+            } else {
+                None
+            };
+            let id = self.new_value();
+            self.params.insert(id);
+            bound.push(Param {
+                name: name.clone(),
+                id,
+                default,
+                span: param.span,
+            });
+        }
+        bound
+    }
+
+    /// Compute the defaults left out by `function_params`, which can use the
+    /// globals declared before the function
+    pub fn param_defaults(
+        &mut self,
+        params: &[FuncParam],
+        bound: &mut [Param],
+        hlirmodule: &HLIRModule,
+    ) {
+        for param in params {
+            let computed = param.default.is_some() && !self.literal_default(param);
+            if let Some(bound) = bound.iter_mut().find(|bound| bound.span == param.span)
+                && computed
+            {
+                bound.default = self.param_default(param, hlirmodule);
+            }
+        }
+    }
+
+    /// Lower a function body with its parameters bound as locals, which get
+    /// the values of the arguments when the function is called
     pub fn lower_function_block(
         &mut self,
-        body: &Vec<crate::ast::Statement>,
+        params: &[Param],
+        return_type: Type,
+        body: &[Statement],
         hlirmodule: &mut HLIRModule,
     ) -> FuncBlock {
         let mut ir_body = FuncBlock {
@@ -17,113 +69,615 @@ impl HLIRPass {
         };
 
         self.symbol_table.push(HashMap::new()); // add new scope (function)
+        for param in params {
+            self.add_symbol(param.name.clone(), param.id, param.span);
+        }
+        self.return_type = Some(return_type);
+        self.lower_statements(body, &mut ir_body.ops, hlirmodule);
+        self.return_type = None;
+        self.symbol_table.pop(); // remove scope (function)
 
+        // a return nested in control flow is only known once the body is evaluated
+        ir_body.returned_element_ref = ir_body.ops.iter().rev().find_map(|op| match op {
+            Op::Return {
+                doc_element_ref, ..
+            } => Some(*doc_element_ref),
+            _ => None,
+        });
+        ir_body
+    }
+
+    /// Whether a parameter's default is a literal, or a colour name for a
+    /// `Color`
+    fn literal_default(&self, param: &FuncParam) -> bool {
+        param.default.as_ref().is_some_and(|default| {
+            self.literal_of(default).is_some()
+                || self.colour_name(default, param_type(param)).is_some()
+        })
+    }
+
+    /// The value of a parameter's default, which has to be of the
+    /// parameter's type. An `Int` is accepted for a `Float`.
+    fn param_default(
+        &mut self,
+        param: &FuncParam,
+        hlirmodule: &HLIRModule,
+    ) -> Option<(Literal, Type)> {
+        let default = param.default.as_ref()?;
+        if let Some(colour) = self.colour_name(default, param_type(param)) {
+            return Some(colour);
+        }
+        let value = self.evaluate_document_expression(default, hlirmodule)?;
+        let accepted = value.type_name() == param.ty
+            || (matches!(value, Value::Int(_)) && param.ty == "Float");
+        if !accepted {
+            self.error(
+                format!(
+                    "Default value must be `{}`, found `{}`",
+                    param.ty,
+                    value.type_name()
+                ),
+                default.span(),
+            );
+            return None;
+        }
+        value.to_literal()
+    }
+
+    fn lower_statements(
+        &mut self,
+        body: &[Statement],
+        ops: &mut Vec<Op>,
+        hlirmodule: &mut HLIRModule,
+    ) {
         for stmt in body {
             match stmt {
-                crate::ast::Statement::ConstAssign { name, value } => {
-                    let id = Id::Value(ValueId(ir_body.ops.len()));
-                    let value = self.assign_local(name.clone(), value.clone(), id, false);
-                    ir_body.ops.push(value);
-                    self.add_symbol(name.clone(), id);
+                Statement::ConstAssign {
+                    name,
+                    ty,
+                    value,
+                    span,
                 }
-                crate::ast::Statement::VarAssign { name, value } => {
-                    let id = Id::Value(ValueId(ir_body.ops.len()));
-                    let value = self.assign_local(name.clone(), value.clone(), id, true);
-                    ir_body.ops.push(value);
-                    self.add_symbol(name.clone(), id);
+                | Statement::VarAssign {
+                    name,
+                    ty,
+                    value,
+                    span,
+                } => {
+                    let mutable = matches!(stmt, Statement::VarAssign { .. });
+                    let id = self.lower_local(name, value, mutable, *span, ops, hlirmodule);
+                    if let Some(ty) = self.declared_type(ty.as_deref(), *span) {
+                        ops.push(Op::Var {
+                            result: id,
+                            name: name.clone(),
+                            ty,
+                            span: *span,
+                        });
+                    }
                 }
-
-                crate::ast::Statement::Return { doc_element } => {
-                    let hlir_element = self.convert_doc_element_to_hlir(doc_element, hlirmodule);
+                Statement::DefaultSet { key, value, span } => {
+                    self.lower_reassign(key, value, *span, ops, hlirmodule);
+                }
+                Statement::Return { doc_elements, span } => {
+                    let hlir_element = match &doc_elements[..] {
+                        [doc_element] => {
+                            self.convert_doc_element_to_hlir(doc_element, ops, hlirmodule)
+                        }
+                        doc_elements => self.fragment_element(doc_elements, *span, ops, hlirmodule),
+                    };
                     hlirmodule.elements.push(hlir_element);
                     let element_id = hlirmodule.elements.len() - 1;
-                    ir_body.ops.push(Op::Return {
+                    ops.push(Op::Return {
                         doc_element_ref: element_id,
+                        span: *span,
                     });
-                    ir_body.returned_element_ref = Some(element_id);
                 }
-                _ => {
-                    todo!("other types not handled yet")
+                Statement::ReturnValue { value, span } => {
+                    if let Some(value) = self.lower_expression(value, ops, hlirmodule) {
+                        ops.push(Op::ReturnValue { value, span: *span });
+                    }
+                }
+                Statement::If {
+                    condition,
+                    body,
+                    else_body,
+                    span,
+                } => {
+                    let Some(condition) = self.lower_expression(condition, ops, hlirmodule) else {
+                        continue;
+                    };
+                    let then_ops = self.lower_scoped_block(body, hlirmodule);
+                    let else_ops = match else_body {
+                        Some(else_body) => self.lower_scoped_block(else_body, hlirmodule),
+                        None => Vec::new(),
+                    };
+                    ops.push(Op::If {
+                        condition,
+                        then_ops,
+                        else_ops,
+                        span: *span,
+                    });
+                }
+                Statement::While {
+                    condition,
+                    body,
+                    span,
+                } => {
+                    let mut condition_ops = Vec::new();
+                    let Some(condition) =
+                        self.lower_expression(condition, &mut condition_ops, hlirmodule)
+                    else {
+                        continue;
+                    };
+                    let body = self.lower_scoped_block(body, hlirmodule);
+                    ops.push(Op::While {
+                        condition_ops,
+                        condition,
+                        body,
+                        span: *span,
+                    });
                 }
+                Statement::For {
+                    iterator,
+                    iterable,
+                    body,
+                    span,
+                } => {
+                    let Some(iterable) = self.lower_expression(iterable, ops, hlirmodule) else {
+                        continue;
+                    };
+                    self.symbol_table.push(HashMap::new()); // add new scope (loop)
+                    let iterator_id = self.new_value();
+                    self.add_symbol(iterator.clone(), iterator_id, *span);
+                    self.constants.insert(iterator_id);
+                    let mut body_ops = Vec::new();
+                    self.lower_statements(body, &mut body_ops, hlirmodule);
+                    self.symbol_table.pop(); // remove scope (loop)
+                    ops.push(Op::For {
+                        iterator: iterator_id,
+                        iterable,
+                        body: body_ops,
+                        span: *span,
+                    });
+                }
+                Statement::FunctionDecl { span, .. } => self.diagnostics.push(
+                    Diagnostic::error("Functions cannot be declared inside a function", *span)
+                        .with_help("move it to the top of the `template` block"),
+                ),
             }
         }
+    }
 
-        self.symbol_table.pop(); // remove scope (function)
-        ir_body
+    /// Lower the body of an `if`, `else` or `while` in its own scope
+    fn lower_scoped_block(&mut self, body: &[Statement], hlirmodule: &mut HLIRModule) -> Vec<Op> {
+        let mut ops = Vec::new();
+        self.symbol_table.push(HashMap::new()); // add new scope (block)
+        self.lower_statements(body, &mut ops, hlirmodule);
+        self.symbol_table.pop(); // remove scope (block)
+        ops
     }
 
-    pub fn handle_args(&mut self, arguments: &Vec<ArgType>, ir_body: &mut FuncBlock) -> Vec<Id> {
-        self.symbol_table.push(HashMap::new()); // adding new table for arg scope
-        let mut args = Vec::new();
-        for crate::ast::ArgType { name, ty } in arguments {
-            // TODO handle cases where raw arguments are passed in
-            // maybe look at instead of passing "arg" pass the variable type or
-            // somethig if the var is not decalred, pass "var" if declared
-            // for right now if there is a quotes or number, assume raw arg
-            //
-            // update: raw args are captured but in a shitty way, now just saying:
-            // raw_arg_{index}
-            match ty.as_str() {
-                "var" => {
-                    for table in self.symbol_table.iter_mut().rev() {
-                        if let Some(symbol) = table.get(name) {
-                            match symbol {
-                                Id::Value(id) => {
-                                    args.push(Id::Value(*id));
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+    /// `let`, `var` and `const` inside a function. Constants are folded into
+    /// a single `Op::Const`, anything else is computed and stored with
+    /// `Op::Assign`. Gives the id of the local.
+    fn lower_local(
+        &mut self,
+        name: &str,
+        value: &Expression,
+        mutable: bool,
+        span: Span,
+        ops: &mut Vec<Op>,
+        hlirmodule: &HLIRModule,
+    ) -> Id {
+        let id = self.new_value();
+        if !mutable {
+            self.constants.insert(id);
+        }
+        if self.is_constant(value) {
+            // folded before the name is added, so it cannot refer to it
+            let op = self.assign_local(value, id, hlirmodule);
+            self.add_symbol(name.to_string(), id, span);
+            ops.extend(op);
+            return id;
+        }
+
+        // the value is lowered first so it cannot refer to the new name
+        let value = self.lower_expression(value, ops, hlirmodule);
+        self.add_symbol(name.to_string(), id, span);
+        if let Some(value) = value {
+            ops.push(Op::Assign {
+                target: id,
+                value,
+                span,
+            });
+        }
+        id
+    }
+
+    /// `name = value` inside a function, which must name a mutable local
+    fn lower_reassign(
+        &mut self,
+        name: &str,
+        value: &Expression,
+        span: Span,
+        ops: &mut Vec<Op>,
+        hlirmodule: &HLIRModule,
+    ) {
+        let target = match self.find_symbol(name) {
+            Some(id @ Id::Value(_)) if self.constants.contains(&id) => {
+                self.diagnostics.push(
+                    Diagnostic::error(format!("Cannot assign twice to constant `{}`", name), span)
+                        .with_help(format!("declare it with `let {}` to make it mutable", name)),
+                );
+                return;
+            }
+            Some(id @ Id::Value(_)) => id,
+            Some(_) => {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        format!("Cannot assign to `{}` inside a function", name),
+                        span,
+                    )
+                    .with_help(format!("declare a local copy with `let {0} = {0}`", name)),
+                );
+                return;
+            }
+            None => {
+                self.error(format!("Unknown variable `{}`", name), span);
+                return;
+            }
+        };
+        if let Some(value) = self.lower_expression(value, ops, hlirmodule) {
+            ops.push(Op::Assign {
+                target,
+                value,
+                span,
+            });
+        }
+    }
+
+    /// The function called `name`, reporting a name that is unknown or not
+    /// a function
+    pub fn called_function<'m>(
+        &mut self,
+        name: &str,
+        span: Span,
+        hlirmodule: &'m HLIRModule,
+    ) -> Option<(Id, &'m Func)> {
+        let Some(func) = self.find_symbol(name) else {
+            self.diagnostics.push(
+                Diagnostic::error(format!("Function not found: `{}`", name), span).with_help(
+                    format!(
+                        "declare it in the `template` block: `func {}() {{ ... }}`",
+                        name
+                    ),
+                ),
+            );
+            return None;
+        };
+        let Some(function) = hlirmodule.functions.get(&func) else {
+            self.error(format!("`{}` is not a function", name), span);
+            return None;
+        };
+        Some((func, function))
+    }
+
+    /// The function called `name` where an element goes, reporting one that
+    /// returns a value
+    pub fn element_function<'m>(
+        &mut self,
+        name: &str,
+        span: Span,
+        hlirmodule: &'m HLIRModule,
+    ) -> Option<(Id, &'m Func)> {
+        let (func, function) = self.called_function(name, span, hlirmodule)?;
+        if let Some(ty) = function.return_type.filter(|ty| *ty != Type::DocElement) {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    format!("`{}` returns `{:?}`, not an element", name, ty),
+                    span,
+                )
+                .with_help(format!(
+                    "use its value in an element: `text(class=\"{{{}(...)}}\")`",
+                    name
+                )),
+            );
+            return None;
+        }
+        Some((func, function))
+    }
+
+    /// A call inside an expression, of a function returning a value
+    pub fn lower_call(
+        &mut self,
+        name: &str,
+        arguments: &[Argument],
+        span: Span,
+        ops: &mut Vec<Op>,
+        hlirmodule: &HLIRModule,
+    ) -> Option<Id> {
+        let (func, function) = self.called_function(name, span, hlirmodule)?;
+        if function.return_type == Some(Type::DocElement) {
+            self.diagnostics.push(
+                Diagnostic::error(format!("`{}` returns an element, not a value", name), span)
+                    .with_help("call it in the `document` block instead"),
+            );
+            return None;
+        }
+        let values = self.lower_args(function, arguments, ops, hlirmodule);
+        let args = self.bind_args(function, arguments, values, span, ops)?;
+        let result = self.new_value();
+        ops.push(Op::Call {
+            result: Some(result),
+            func,
+            args,
+            instance: None,
+            placeholder: None,
+            span,
+        });
+        Some(result)
+    }
+
+    /// A call of a function returning an element, inside the element a
+    /// function returns. The arguments are computed before the `return`,
+    /// and the call is run by `evaluate` for every copy of the element.
+    /// Gives the placeholder to push right after.
+    pub fn lower_element_call(
+        &mut self,
+        name: &str,
+        arguments: &[Argument],
+        span: Span,
+        ops: &mut Vec<Op>,
+        hlirmodule: &mut HLIRModule,
+    ) -> HlirElement {
+        let placeholder = self.call_placeholder(span, None, hlirmodule);
+        let index = hlirmodule.elements.len();
+        let Some((func, function)) = self.element_function(name, span, hlirmodule) else {
+            return placeholder;
+        };
+        let values = self.lower_args(function, arguments, ops, hlirmodule);
+        if let Some(args) = self.bind_args(function, arguments, values, span, ops) {
+            ops.push(Op::Call {
+                result: None,
+                func,
+                args,
+                instance: None,
+                placeholder: Some(index),
+                span,
+            });
+        }
+        placeholder
+    }
+
+    /// Lower the arguments of a call inside a function, with the id of each
+    /// one's value and its type when known before the call is run
+    fn lower_args(
+        &mut self,
+        function: &Func,
+        arguments: &[Argument],
+        ops: &mut Vec<Op>,
+        hlirmodule: &HLIRModule,
+    ) -> Vec<Option<(Id, Option<Type>)>> {
+        arguments
+            .iter()
+            .enumerate()
+            .map(|(index, argument)| {
+                let expected = argument_type(function, arguments, index);
+                if let Some((literal, ty)) = self.colour_name(&argument.value, expected) {
+                    let result = self.new_value();
+                    ops.push(Op::Const {
+                        result,
+                        literal,
+                        ty,
+                        span: argument.value.span(),
+                    });
+                    return Some((result, Some(ty)));
                 }
-                "int" => {
-                    let value = name.as_str().parse::<i64>().unwrap();
-                    let id = ValueId(ir_body.ops.len());
-                    let var_name = "raw_arg_".to_string() + id.to_string().as_str();
-                    let var = self.assign_local(
-                        var_name.clone(),
-                        crate::ast::Expression::Int(value),
-                        Id::Value(id),
-                        false,
+                let ty = self.literal_of(&argument.value).map(|(_, ty)| ty);
+                let id = self.lower_expression(&argument.value, ops, hlirmodule)?;
+                Some((id, ty))
+            })
+            .collect()
+    }
+
+    /// Evaluate the arguments of a call in the document to constants, with
+    /// an `Op::Const` each. Only globals and loop variables are in scope.
+    /// Arguments that cannot be evaluated are reported and give `None`.
+    pub fn handle_args(
+        &mut self,
+        function: &Func,
+        arguments: &[Argument],
+        ops: &mut Vec<Op>,
+        hlirmodule: &HLIRModule,
+    ) -> Vec<Option<(Id, Option<Type>)>> {
+        let mut args = Vec::new();
+        for (index, argument) in arguments.iter().enumerate() {
+            let span = argument.value.span();
+            let expected = argument_type(function, arguments, index);
+            if let Some((literal, ty)) = self.colour_name(&argument.value, expected) {
+                let result = self.new_value();
+                ops.push(Op::Const {
+                    result,
+                    literal,
+                    ty,
+                    span,
+                });
+                args.push(Some((result, Some(ty))));
+                continue;
+            }
+            let value = self.evaluate_document_expression(&argument.value, hlirmodule);
+            let literal = value.and_then(|value| {
+                let literal = value.to_literal();
+                if literal.is_none() {
+                    self.error(
+                        format!("Cannot pass a `{}` to a function", value.type_name()),
+                        span,
                     );
-                    ir_body.ops.push(var);
-                    args.push(Id::Value(id));
                 }
-                "float" => {
-                    let value = name.as_str().parse::<f64>().unwrap();
-                    let id = ValueId(ir_body.ops.len());
-                    let var_name = "raw_arg_".to_string() + id.to_string().as_str();
-                    let var = self.assign_local(
-                        var_name.clone(),
-                        crate::ast::Expression::Float(value),
-                        Id::Value(id),
-                        false,
+                literal
+            });
+            args.push(literal.map(|(literal, ty)| {
+                let result = self.new_value();
+                ops.push(Op::Const {
+                    result,
+                    literal,
+                    ty,
+                    span,
+                });
+                (result, Some(ty))
+            }));
+        }
+        args
+    }
+
+    /// Match the arguments of a call to the parameters of `function`:
+    /// positional ones in order, then named ones by name, and the default of
+    /// every parameter left out. `values` holds the id of each argument's
+    /// value, and its type when known before the call is run. An argument of
+    /// a known type has to be of the parameter's type, an `Int` is accepted
+    /// for a `Float`. Returns the ids holding the value of every parameter,
+    /// in order, or `None` if the call is invalid.
+    pub fn bind_args(
+        &mut self,
+        function: &Func,
+        arguments: &[Argument],
+        values: Vec<Option<(Id, Option<Type>)>>,
+        span: Span,
+        ops: &mut Vec<Op>,
+    ) -> Option<Vec<Id>> {
+        let name = &function.name;
+        let params = &function.params;
+        let required = params
+            .iter()
+            .filter(|param| param.default.is_none())
+            .count();
+        let positional = arguments.iter().filter(|arg| arg.name.is_none()).count();
+        let named = positional < arguments.len();
+        if positional > params.len() || (!named && positional < required) {
+            let expected = if required == params.len() {
+                params.len().to_string()
+            } else {
+                format!("{} to {}", required, params.len())
+            };
+            let plural = if params.len() == 1 { "" } else { "s" };
+            self.diagnostics.push(
+                Diagnostic::error(
+                    format!(
+                        "Wrong number of arguments to `{}`: expected {}, found {}",
+                        name,
+                        expected,
+                        arguments.len()
+                    ),
+                    span,
+                )
+                .with_note(format!("`{}` takes {} argument{}", name, expected, plural)),
+            );
+            return None;
+        }
+
+        let mut bound: Vec<Option<Option<Id>>> = vec![None; params.len()];
+        let mut valid = true;
+        for (i, (argument, value)) in arguments.iter().zip(values).enumerate() {
+            let slot = match &argument.name {
+                None if named && arguments[..i].iter().any(|arg| arg.name.is_some()) => {
+                    self.diagnostics.push(
+                        Diagnostic::error(
+                            "Positional argument after a named argument",
+                            argument.span,
+                        )
+                        .with_help("name it, or move it before the named arguments"),
                     );
-                    ir_body.ops.push(var);
-                    args.push(Id::Value(id));
+                    valid = false;
+                    continue;
                 }
-                "string" => {
-                    let value = name
-                        .as_str()
-                        .parse::<String>()
-                        .unwrap()
-                        .trim_matches('"')
-                        .to_string();
-                    let id = ValueId(ir_body.ops.len());
-                    let var_name = "raw_arg_".to_string() + id.to_string().as_str();
-                    let var = self.assign_local(
-                        var_name.clone(),
-                        crate::ast::Expression::StringLiteral(value),
-                        Id::Value(id),
-                        false,
+                None => i,
+                Some(param) => match params.iter().position(|p| &p.name == param) {
+                    Some(slot) => slot,
+                    None => {
+                        self.error(
+                            format!("`{}` has no parameter named `{}`", name, param),
+                            argument.span,
+                        );
+                        valid = false;
+                        continue;
+                    }
+                },
+            };
+            if bound[slot].is_some() {
+                self.error(
+                    format!(
+                        "Parameter `{}` of `{}` is given more than once",
+                        params[slot].name, name
+                    ),
+                    argument.span,
+                );
+                valid = false;
+                continue;
+            }
+            if let Some((_, Some(found))) = &value
+                && let Some(expected) = function.args.get(slot)
+                && found != expected
+                && !(*found == Type::Int && *expected == Type::Float)
+            {
+                let which = match &argument.name {
+                    Some(param) => format!("`{}`", param),
+                    None => (i + 1).to_string(),
+                };
+                self.error(
+                    format!(
+                        "Type mismatch in argument {} of `{}`: expected `{:?}`, found `{:?}`",
+                        which, name, expected, found
+                    ),
+                    argument.value.span(),
+                );
+                valid = false;
+            }
+            valid &= value.is_some();
+            bound[slot] = Some(value.map(|(id, _)| id));
+        }
+
+        let mut ids = Vec::new();
+        for (param, value) in params.iter().zip(bound) {
+            match (value, &param.default) {
+                (Some(value), _) => ids.extend(value),
+                (None, Some((literal, ty))) => {
+                    let result = self.new_value();
+                    ops.push(Op::Const {
+                        result,
+                        literal: literal.clone(),
+                        ty: *ty,
+                        span,
+                    });
+                    ids.push(result);
+                }
+                (None, None) => {
+                    self.error(
+                        format!("Missing argument `{}` in call to `{}`", param.name, name),
+                        span,
                     );
-                    ir_body.ops.push(var);
-                    args.push(Id::Value(id));
+                    valid = false;
                 }
-                _ => {}
             }
         }
-        self.symbol_table.pop();
-        args
+        valid.then_some(ids)
     }
 }
+
+/// The type of the parameter the argument at `index` is given for, if it
+/// names one
+fn argument_type(function: &Func, arguments: &[Argument], index: usize) -> Option<Type> {
+    let slot = match &arguments[index].name {
+        None => index,
+        Some(name) => function
+            .params
+            .iter()
+            .position(|param| &param.name == name)?,
+    };
+    function.args.get(slot).copied()
+}
+
+/// `Color` for a parameter declared one, the only type a colour name is
+/// read as
+fn param_type(param: &FuncParam) -> Option<Type> {
+    (param.ty == "Color").then_some(Type::Color)
+}
@@ -1,126 +1,407 @@
+use crate::ast::{Argument, BinaryOp, Expression, InterpPart, UnaryOp};
+use crate::diagnostics::Span;
 use crate::hlir::hlir::HLIRPass;
 
-use crate::hlir::ir_types::{Global, GlobalId, Id, Literal, Op, Type, ValueId};
+use crate::hlir::ir_types::{BinOp, Color, Global, HLIRModule, Id, Literal, Op, Type, UnOp};
+use crate::hlir::util::evaluator::Value;
 
 impl HLIRPass {
+    /// A global from `const`, `let` or a default in the template block. Its
+    /// value is folded while lowering, so it can use the globals declared
+    /// before it, arithmetic and calls.
     pub fn assign_global(
         &mut self,
-        name: &String,
-        value: &crate::ast::Expression,
+        name: &str,
+        value: &Expression,
         id: Id,
         mutable: bool,
-    ) -> Global {
-        let global = match value {
-            crate::ast::Expression::StringLiteral(s) => Global {
-                id: id,
-                name: name.clone(),
-                ty: Type::String,
-                init: Literal::String(s.clone()),
-                mutable: mutable,
-            },
-            crate::ast::Expression::Int(n) => Global {
-                id: id,
-                name: name.clone(),
-                ty: Type::Int,
-                init: Literal::Int(*n),
-                mutable: mutable,
-            },
-            crate::ast::Expression::Float(n) => Global {
-                id: id,
-                name: name.clone(),
-                ty: Type::Float,
-                init: Literal::Float(*n),
-                mutable: mutable,
-            },
-            crate::ast::Expression::InterpolatedString(parts) => {
-                // For globals with interpolated strings, we evaluate at initialization time
-                // by converting to a string immediately (since globals are evaluated once)
-                let result = self.eval_interpolated_string_to_literal(&parts);
-                Global {
-                    id,
-                    name: name.clone(),
-                    ty: Type::String,
-                    init: result,
-                    mutable: mutable,
-                }
-            }
-            _ => {
-                todo!("implement other expression types")
-            }
-        };
-        global
+        span: Span,
+        hlirmodule: &HLIRModule,
+    ) -> Option<Global> {
+        let (init, ty) = self.fold_constant(value, hlirmodule)?;
+        Some(Global {
+            id,
+            name: name.to_string(),
+            ty,
+            init,
+            mutable,
+            span,
+        })
     }
 
+    /// The `Op::Const` of a local whose value is known while lowering, see
+    /// `is_constant`. `None` if folding it failed, which has been reported.
     pub fn assign_local(
         &mut self,
-        name: String,
-        value: crate::ast::Expression,
+        value: &Expression,
         id: Id,
-        mutable: bool,
-    ) -> Op {
-        let op = match value {
-            crate::ast::Expression::StringLiteral(s) => Op::Const {
-                result: id,
-                literal: Literal::String(s.clone()),
-                ty: Type::String,
-            },
-            crate::ast::Expression::Int(n) => Op::Const {
-                result: id,
-                literal: Literal::Int(n),
-                ty: Type::Int,
-            },
-            crate::ast::Expression::Float(n) => Op::Const {
-                result: id,
-                literal: Literal::Float(n),
-                ty: Type::Float,
+        hlirmodule: &HLIRModule,
+    ) -> Option<Op> {
+        let (literal, ty) = self.fold_constant(value, hlirmodule)?;
+        Some(Op::Const {
+            result: id,
+            literal,
+            ty,
+            span: value.span(),
+        })
+    }
+
+    /// Work out the value of `expr` now, by lowering it and running the ops.
+    /// Errors are reported and give `None`.
+    pub fn fold_constant(
+        &mut self,
+        expr: &Expression,
+        hlirmodule: &HLIRModule,
+    ) -> Option<(Literal, Type)> {
+        if let Some(literal) = self.literal_of(expr) {
+            return Some(literal);
+        }
+        let value = self.evaluate_document_expression(expr, hlirmodule)?;
+        let literal = value.to_literal();
+        if literal.is_none() {
+            self.error(
+                format!("Cannot store a `{}` in a variable", value.type_name()),
+                expr.span(),
+            );
+        }
+        literal
+    }
+
+    /// Whether `expr` only uses literals, globals, the variables of document
+    /// loops and operators, so its value is known while lowering
+    pub fn is_constant(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::StringLiteral(..)
+            | Expression::Int(..)
+            | Expression::Float(..)
+            | Expression::Bool(..)
+            | Expression::Color(..) => true,
+            Expression::Identifier(name, _) => match self.find_symbol(name) {
+                Some(Id::Global(_)) => true,
+                Some(id) => self.loop_values.contains_key(&id),
+                None => false,
             },
-            crate::ast::Expression::InterpolatedString(parts) => {
-                // For simplicity in local assignment, we convert to a literal string
-                // In a full implementation, this would generate ops to build the string at runtime
-                let result = self.eval_interpolated_string_to_literal(&parts);
-                Op::Const {
-                    result: id,
-                    literal: result,
-                    ty: Type::String,
-                }
+            Expression::Binary { left, right, .. } => {
+                self.is_constant(left) && self.is_constant(right)
             }
-            _ => {
-                todo!("implement other expression types")
+            Expression::Unary { expression, .. } => self.is_constant(expression),
+            Expression::List(items, _) => items.iter().all(|item| self.is_constant(item)),
+            Expression::Map(entries, _) => entries.iter().all(|(_, value)| self.is_constant(value)),
+            Expression::Index { value, index, .. } => {
+                self.is_constant(value) && self.is_constant(index)
+            }
+            Expression::Field { value, .. } => self.is_constant(value),
+            Expression::Call { name, args, .. } if self.is_len(name) => {
+                args.iter().all(|arg| self.is_constant(&arg.value))
             }
-        };
+            Expression::InterpolatedString(parts, _) => parts.iter().all(|part| match part {
+                InterpPart::Text(_) => true,
+                InterpPart::Expression(expr) => self.is_constant(expr),
+            }),
+            _ => false,
+        }
+    }
 
-        // add variable to symbol table
-        let len = self.symbol_table.len();
-        let scope = self.symbol_table.get_mut(len - 1).unwrap(); // most recent scope
-        scope.insert(name.clone(), id); // add to known symbols
+    /// The literal an expression stands for, if it needs no evaluation
+    pub fn literal_of(&self, value: &Expression) -> Option<(Literal, Type)> {
+        match value {
+            Expression::StringLiteral(s, _) => Some((Literal::String(s.clone()), Type::String)),
+            Expression::Int(n, _) => Some((Literal::Int(*n), Type::Int)),
+            Expression::Float(n, _) => Some((Literal::Float(*n), Type::Float)),
+            Expression::Bool(b, _) => Some((Literal::Bool(*b), Type::Bool)),
+            Expression::Color(c, _) => Some((Literal::Color(c.clone()), Type::Color)),
+            Expression::List(items, _) => {
+                let items = items
+                    .iter()
+                    .map(|item| Some(self.literal_of(item)?.0))
+                    .collect::<Option<_>>()?;
+                Some((Literal::List(items), Type::List))
+            }
+            Expression::Map(entries, _) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| Some((key.clone(), self.literal_of(value)?.0)))
+                    .collect::<Option<_>>()?;
+                Some((Literal::Map(entries), Type::Map))
+            }
+            // anything else in a string is computed, see `lower_expression`
+            Expression::InterpolatedString(parts, _) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        InterpPart::Text(part) => text.push_str(part),
+                        InterpPart::Expression(expr) => match self.literal_of(expr)? {
+                            (Literal::String(part), _) => text.push_str(&part),
+                            (literal, _) => text.push_str(&Value::from(&literal).to_string()),
+                        },
+                    }
+                }
+                Some((Literal::String(text), Type::String))
+            }
+            _ => None,
+        }
+    }
 
-        op
+    /// A colour name such as `red` given where a `Color` is `expected`,
+    /// unless a variable has the same name. Anywhere else a colour is
+    /// written `#1e90ff` or `rgb(30, 144, 255)`.
+    pub fn colour_name(
+        &self,
+        value: &Expression,
+        expected: Option<Type>,
+    ) -> Option<(Literal, Type)> {
+        match value {
+            Expression::Identifier(name, _)
+                if expected == Some(Type::Color)
+                    && self.find_symbol(name).is_none()
+                    && name.parse::<Color>().is_ok() =>
+            {
+                Some((Literal::Color(name.clone()), Type::Color))
+            }
+            _ => None,
+        }
     }
 
-    fn eval_interpolated_string_to_literal(&self, parts: &[crate::ast::InterpPart]) -> Literal {
-        let mut result = String::new();
-        for part in parts {
-            match part {
-                crate::ast::InterpPart::Text(text) => result.push_str(text),
-                crate::ast::InterpPart::Expression(expr) => {
-                    // Try to evaluate the expression to a constant
-                    match expr {
-                        crate::ast::Expression::StringLiteral(s) => result.push_str(s),
-                        crate::ast::Expression::Int(n) => result.push_str(&n.to_string()),
-                        crate::ast::Expression::Float(f) => result.push_str(&f.to_string()),
-                        crate::ast::Expression::Identifier(name) => {
-                            // For identifiers, we can't resolve at compile time without
-                            // more sophisticated constant propagation, so we keep the placeholder
-                            result.push_str(&format!("{{{}}}", name));
+    /// Whether a call of `name` is the built-in `len`, which a function of
+    /// the same name hides
+    fn is_len(&self, name: &str) -> bool {
+        name == "len" && self.find_symbol(name).is_none()
+    }
+
+    /// Append the ops computing `expr` to `ops` and return the id holding its
+    /// value. Identifiers refer to their global or local directly, and
+    /// `hlirmodule` holds the functions that can be called. Errors are
+    /// reported and give `None`.
+    pub fn lower_expression(
+        &mut self,
+        expr: &Expression,
+        ops: &mut Vec<Op>,
+        hlirmodule: &HLIRModule,
+    ) -> Option<Id> {
+        let span = expr.span();
+        if let Some((literal, ty)) = self.literal_of(expr) {
+            let result = self.new_value();
+            ops.push(Op::Const {
+                result,
+                literal,
+                ty,
+                span,
+            });
+            return Some(result);
+        }
+
+        match expr {
+            Expression::Identifier(name, _) => match self.find_symbol(name) {
+                Some(id @ (Id::Value(_) | Id::Global(_))) => Some(id),
+                Some(Id::Func(_)) => {
+                    self.error(format!("`{}` is a function, not a value", name), span);
+                    None
+                }
+                None => {
+                    self.error(format!("Unknown variable `{}`", name), span);
+                    None
+                }
+            },
+            Expression::Binary {
+                left,
+                operator: operator @ (BinaryOp::And | BinaryOp::Or),
+                right,
+                ..
+            } => {
+                let lhs = self.lower_expression(left, ops, hlirmodule);
+                let mut rhs_ops = Vec::new();
+                let rhs = self.lower_expression(right, &mut rhs_ops, hlirmodule);
+                let result = self.new_value();
+                ops.push(Op::Logical {
+                    result,
+                    op: bin_op(operator),
+                    lhs: lhs?,
+                    rhs_ops,
+                    rhs: rhs?,
+                    span,
+                });
+                Some(result)
+            }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                let lhs = self.lower_expression(left, ops, hlirmodule);
+                let rhs = self.lower_expression(right, ops, hlirmodule);
+                let result = self.new_value();
+                ops.push(Op::Binary {
+                    result,
+                    op: bin_op(operator),
+                    lhs: lhs?,
+                    rhs: rhs?,
+                    span,
+                });
+                Some(result)
+            }
+            Expression::Unary {
+                operator,
+                expression,
+                ..
+            } => {
+                let operand = self.lower_expression(expression, ops, hlirmodule)?;
+                let result = self.new_value();
+                let op = match operator {
+                    UnaryOp::Negate => UnOp::Neg,
+                    UnaryOp::Not => UnOp::Not,
+                };
+                ops.push(Op::Unary {
+                    result,
+                    op,
+                    operand,
+                    span,
+                });
+                Some(result)
+            }
+            Expression::Range { start, end, .. } => {
+                let start = self.lower_expression(start, ops, hlirmodule);
+                let end = self.lower_expression(end, ops, hlirmodule);
+                let result = self.new_value();
+                ops.push(Op::Range {
+                    result,
+                    start: start?,
+                    end: end?,
+                    span,
+                });
+                Some(result)
+            }
+            Expression::List(items, _) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.lower_expression(item, ops, hlirmodule))
+                    .collect::<Vec<_>>();
+                let result = self.new_value();
+                ops.push(Op::List {
+                    result,
+                    items: items.into_iter().collect::<Option<_>>()?,
+                    span,
+                });
+                Some(result)
+            }
+            Expression::Map(entries, _) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        Some((key.clone(), self.lower_expression(value, ops, hlirmodule)?))
+                    })
+                    .collect::<Vec<_>>();
+                let result = self.new_value();
+                ops.push(Op::Map {
+                    result,
+                    entries: entries.into_iter().collect::<Option<_>>()?,
+                    span,
+                });
+                Some(result)
+            }
+            Expression::Index { value, index, .. } => {
+                let value = self.lower_expression(value, ops, hlirmodule);
+                let index = self.lower_expression(index, ops, hlirmodule);
+                let result = self.new_value();
+                ops.push(Op::Index {
+                    result,
+                    value: value?,
+                    index: index?,
+                    span,
+                });
+                Some(result)
+            }
+            Expression::Field { value, name, .. } => {
+                let value = self.lower_expression(value, ops, hlirmodule)?;
+                let result = self.new_value();
+                ops.push(Op::Field {
+                    result,
+                    value,
+                    name: name.clone(),
+                    span,
+                });
+                Some(result)
+            }
+            Expression::Call { name, args, .. } if self.is_len(name) => {
+                let [
+                    Argument {
+                        name: None, value, ..
+                    },
+                ] = args.as_slice()
+                else {
+                    self.error(
+                        format!("`len` takes 1 argument, found {}", args.len()),
+                        span,
+                    );
+                    return None;
+                };
+                let value = self.lower_expression(value, ops, hlirmodule)?;
+                let result = self.new_value();
+                ops.push(Op::Len {
+                    result,
+                    value,
+                    span,
+                });
+                Some(result)
+            }
+            Expression::Call { name, args, .. } => {
+                self.lower_call(name, args, span, ops, hlirmodule)
+            }
+            // only strings with more than literals in them get here, see
+            // `literal_of`
+            Expression::InterpolatedString(parts, _) => {
+                let mut ids = Vec::new();
+                for part in parts {
+                    let id = match part {
+                        InterpPart::Text(text) => {
+                            let result = self.new_value();
+                            ops.push(Op::Const {
+                                result,
+                                literal: Literal::String(text.clone()),
+                                ty: Type::String,
+                                span,
+                            });
+                            result
                         }
-                        _ => {
-                            // For other expressions, we use a placeholder
-                            result.push_str(&format!("{{{}}}", expr.to_string()));
+                        InterpPart::Expression(part) => {
+                            self.lower_expression(part, ops, hlirmodule)?
                         }
-                    }
+                    };
+                    ids.push(id);
                 }
+                let result = self.new_value();
+                ops.push(Op::StringConcat {
+                    result,
+                    parts: ids,
+                    span,
+                });
+                Some(result)
+            }
+            _ => {
+                self.error(
+                    format!("Unsupported expression `{}`", expr.to_string()),
+                    span,
+                );
+                None
             }
         }
-        Literal::String(result)
+    }
+}
+
+fn bin_op(operator: &BinaryOp) -> BinOp {
+    match operator {
+        BinaryOp::Add => BinOp::Add,
+        BinaryOp::Subtract => BinOp::Sub,
+        BinaryOp::Multiply => BinOp::Mul,
+        BinaryOp::Divide => BinOp::Div,
+        BinaryOp::Mod => BinOp::Mod,
+        BinaryOp::Equals => BinOp::Eq,
+        BinaryOp::NotEquals => BinOp::Ne,
+        BinaryOp::Less => BinOp::Lt,
+        BinaryOp::LessEquals => BinOp::Le,
+        BinaryOp::Greater => BinOp::Gt,
+        BinaryOp::GreaterEquals => BinOp::Ge,
+        BinaryOp::And => BinOp::And,
+        BinaryOp::Or => BinOp::Or,
     }
 }
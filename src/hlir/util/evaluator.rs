@@ -0,0 +1,919 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::Expression;
+use crate::diagnostics::{Diagnostic, Span};
+use crate::hlir::ir_types::{BinOp, FuncId, HLIRModule, Id, Literal, Op, Type, UnOp};
+use crate::hlir::util::instantiate::{fill_params, instantiate, replace_placeholder};
+
+/// Upper bound on the iterations of a single loop, so a `while` whose
+/// condition never turns false is reported instead of hanging the compiler
+const MAX_LOOP_ITERATIONS: usize = 100_000;
+
+/// Upper bound on the function bodies and blocks being run at once, so a
+/// function that keeps calling itself is reported instead of overflowing
+/// the stack
+const MAX_DEPTH: usize = 128;
+
+/// Run every function the document calls and give each call its own copy
+/// of the element the function returns, with the values of the call's
+/// arguments filled in. Calls inside that element are run in turn, and their
+/// copies put in the place of their placeholders. Errors such as a division
+/// by zero or a non-`Bool` condition are added to `hlir.diagnostics`.
+pub fn evaluate(hlir: &mut HLIRModule) {
+    let document_id = Id::Func(FuncId(hlir.functions.len() - 1));
+    let mut calls = Vec::new();
+    if let Some(document) = hlir.functions.get(&document_id) {
+        // the arguments are constants computed in the document body
+        let mut arguments = Evaluator::new(hlir);
+        let ran = arguments.run(&document.body.ops).map(|_| ());
+        for (index, op) in document.body.ops.iter().enumerate() {
+            let Op::Call {
+                func,
+                args,
+                placeholder,
+                span,
+                ..
+            } = op
+            else {
+                continue;
+            };
+            let values = ran.clone().and_then(|()| {
+                args.iter()
+                    .map(|arg| arguments.value(*arg, *span))
+                    .collect::<Result<Vec<_>, _>>()
+            });
+            calls.push((index, *func, values, *placeholder, *span));
+        }
+    }
+
+    let mut called = Vec::new();
+    for (index, func, values, placeholder, span) in calls {
+        let parent = placeholder.and_then(|placeholder| hlir.element_metadata[placeholder].parent);
+        let copy = values.and_then(|values| {
+            let call = ElementCall {
+                func,
+                parent,
+                depth: 0,
+                span,
+            };
+            call.instance(hlir, &values, &mut called)
+        });
+        let copy = match copy {
+            Ok(Some(copy)) => copy,
+            Ok(None) => continue,
+            Err(diagnostic) => {
+                hlir.diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+        if let Some(placeholder) = placeholder {
+            replace_placeholder(hlir, placeholder, copy);
+        } else if let Some(document) = hlir.functions.get_mut(&document_id)
+            && let Some(Op::Call { instance, .. }) = document.body.ops.get_mut(index)
+        {
+            *instance = Some(copy);
+        }
+    }
+}
+
+/// A call of a function returning an element, made inside `parent`
+struct ElementCall {
+    func: Id,
+    parent: Option<usize>,
+    /// How many calls the element of this one is nested in
+    depth: usize,
+    span: Span,
+}
+
+impl ElementCall {
+    /// Run the function with `args` and copy the element it returns, filling
+    /// in the arguments and the elements of the calls inside it. Gives the
+    /// index of the copy.
+    fn instance(
+        &self,
+        hlir: &mut HLIRModule,
+        args: &[Value],
+        called: &mut Vec<Id>,
+    ) -> Result<Option<usize>, Diagnostic> {
+        let Some(function) = hlir.functions.get(&self.func) else {
+            return Ok(None);
+        };
+        if self.depth >= MAX_DEPTH {
+            return Err(Diagnostic::error(
+                format!("Recursion too deep in `{}`", function.name),
+                self.span,
+            )
+            .with_note(format!(
+                "more than {} calls were nested in each other's elements",
+                MAX_DEPTH
+            )));
+        }
+        let mut callee = Evaluator::new(hlir);
+        let returned = callee.call(self.func, args)?;
+        let locals = callee.values;
+
+        let Some(function) = hlir.functions.get_mut(&self.func) else {
+            return Ok(None);
+        };
+        // the function's own element is the one its first call returned
+        if !called.contains(&self.func) {
+            called.push(self.func);
+            function.body.returned_element_ref = returned;
+        }
+        let params: HashMap<String, Value> = function
+            .params
+            .iter()
+            .filter_map(|param| Some((param.name.clone(), locals.get(&param.id)?.clone())))
+            .collect();
+        let mut nested = Vec::new();
+        placeholder_calls(&function.body.ops, &mut nested);
+        let Some(element) = returned else {
+            return Ok(None);
+        };
+        let (mut copy, copies) = instantiate(hlir, element, self.parent);
+        fill_params(hlir, copy, &params, &locals);
+
+        // only the calls inside the returned element, whose arguments were
+        // computed before it was returned
+        let arguments = Evaluator::with_values(hlir, locals);
+        let nested: Vec<_> = nested
+            .into_iter()
+            .filter_map(|(placeholder, func, args, span)| {
+                let placeholder = *copies.get(&placeholder)?;
+                let values = args
+                    .iter()
+                    .map(|arg| arguments.value(*arg, span))
+                    .collect::<Result<Vec<_>, _>>();
+                Some((placeholder, func, values, span))
+            })
+            .collect();
+        for (placeholder, func, values, span) in nested {
+            let call = ElementCall {
+                func,
+                parent: hlir.element_metadata[placeholder].parent,
+                depth: self.depth + 1,
+                span,
+            };
+            match values.and_then(|values| call.instance(hlir, &values, called)) {
+                // a function returning only a call returns the call's element
+                Ok(Some(instance)) if placeholder == copy => copy = instance,
+                Ok(Some(instance)) => replace_placeholder(hlir, placeholder, instance),
+                Ok(None) => {}
+                Err(diagnostic) => hlir.diagnostics.push(diagnostic),
+            }
+        }
+        Ok(Some(copy))
+    }
+}
+
+/// The placeholder, function, arguments and span of every call in `ops`
+/// made inside the element a function returns
+fn placeholder_calls(ops: &[Op], calls: &mut Vec<(usize, Id, Vec<Id>, Span)>) {
+    for op in ops {
+        match op {
+            Op::Call {
+                func,
+                args,
+                placeholder: Some(placeholder),
+                span,
+                ..
+            } => calls.push((*placeholder, *func, args.clone(), *span)),
+            Op::If {
+                then_ops, else_ops, ..
+            } => {
+                placeholder_calls(then_ops, calls);
+                placeholder_calls(else_ops, calls);
+            }
+            Op::While { body, .. } | Op::For { body, .. } => placeholder_calls(body, calls),
+            _ => {}
+        }
+    }
+}
+
+/// A value produced while evaluating a function body
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Color(String),
+    List(Vec<Value>),
+    Map(Vec<(String, Value)>),
+    /// `start..end`, produced by `Op::Range`
+    Range(i64, i64),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::Bool(_) => "Bool",
+            Value::String(_) => "String",
+            Value::Color(_) => "Color",
+            Value::List(_) => "List",
+            Value::Map(_) => "Map",
+            Value::Range(..) => "Range",
+        }
+    }
+
+    /// The value as an attribute expression, written at `span`
+    pub fn to_expression(&self, span: Span) -> Expression {
+        match self {
+            Value::Int(n) => Expression::Int(*n, span),
+            Value::Float(f) => Expression::Float(*f, span),
+            Value::Bool(b) => Expression::Bool(*b, span),
+            Value::Color(c) => Expression::Color(c.clone(), span),
+            value => Expression::StringLiteral(value.to_string(), span),
+        }
+    }
+
+    /// Whether the value can be used where a `ty` is expected. An `Int` is
+    /// accepted for a `Float`.
+    pub fn has_type(&self, ty: Type) -> bool {
+        matches!(
+            (self, ty),
+            (Value::Int(_), Type::Int | Type::Float)
+                | (Value::Float(_), Type::Float)
+                | (Value::Bool(_), Type::Bool)
+                | (Value::String(_), Type::String)
+                | (Value::Color(_), Type::Color)
+                | (Value::List(_), Type::List)
+                | (Value::Map(_), Type::Map)
+        )
+    }
+
+    /// The literal for a value, and its type. A range, or a list or map
+    /// holding one, has none.
+    pub fn to_literal(&self) -> Option<(Literal, Type)> {
+        match self {
+            Value::Int(n) => Some((Literal::Int(*n), Type::Int)),
+            Value::Float(f) => Some((Literal::Float(*f), Type::Float)),
+            Value::Bool(b) => Some((Literal::Bool(*b), Type::Bool)),
+            Value::String(s) => Some((Literal::String(s.clone()), Type::String)),
+            Value::Color(c) => Some((Literal::Color(c.clone()), Type::Color)),
+            Value::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| Some(item.to_literal()?.0))
+                    .collect::<Option<_>>()?;
+                Some((Literal::List(items), Type::List))
+            }
+            Value::Map(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| Some((key.clone(), value.to_literal()?.0)))
+                    .collect::<Option<_>>()?;
+                Some((Literal::Map(entries), Type::Map))
+            }
+            Value::Range(..) => None,
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Literal> for Value {
+    fn from(literal: &Literal) -> Self {
+        match literal {
+            Literal::Int(n) => Value::Int(*n),
+            Literal::Float(f) => Value::Float(*f),
+            Literal::Bool(b) => Value::Bool(*b),
+            Literal::String(s) => Value::String(s.clone()),
+            Literal::Color(c) => Value::Color(c.clone()),
+            Literal::List(items) => Value::List(items.iter().map(Value::from).collect()),
+            Literal::Map(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Value::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) | Value::Color(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            Value::Range(start, end) => write!(f, "{}..{}", start, end),
+        }
+    }
+}
+
+/// What running a list of ops ended with
+enum Flow {
+    Next,
+    Return(usize),
+    /// A value returned by `Op::ReturnValue` at the span
+    Value(Value, Span),
+}
+
+pub struct Evaluator<'a> {
+    hlir: &'a HLIRModule,
+    values: HashMap<Id, Value>,
+    /// The function bodies and blocks being run, counting the ones of the
+    /// calls this evaluator was started from
+    depth: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(hlir: &'a HLIRModule) -> Self {
+        Self::with_values(hlir, HashMap::new())
+    }
+
+    /// Start with `values` already assigned, such as loop variables
+    pub fn with_values(hlir: &'a HLIRModule, values: HashMap<Id, Value>) -> Self {
+        Self {
+            hlir,
+            values,
+            depth: 0,
+        }
+    }
+
+    /// Run the ops computing an expression and return its value
+    pub fn evaluate(&mut self, ops: &[Op], result: Id, span: Span) -> Result<Value, Diagnostic> {
+        self.run(ops)?;
+        self.value(result, span)
+    }
+
+    /// Run a function body with `args` bound to its parameters, returning
+    /// the element it returns, if any
+    pub fn call(&mut self, func: Id, args: &[Value]) -> Result<Option<usize>, Diagnostic> {
+        let Some(function) = self.hlir.functions.get(&func) else {
+            return Ok(None);
+        };
+        for (param, value) in function.params.iter().zip(args) {
+            self.values.insert(param.id, value.clone());
+        }
+        match self.run(&function.body.ops)? {
+            Flow::Return(element) => Ok(Some(element)),
+            Flow::Value(..) | Flow::Next => Ok(None),
+        }
+    }
+
+    /// Run a function with a declared return type, called at `span`, and
+    /// return its value, which has to be of that type
+    pub fn call_value(
+        &mut self,
+        func: Id,
+        args: &[Value],
+        span: Span,
+    ) -> Result<Value, Diagnostic> {
+        let Some(function) = self.hlir.functions.get(&func) else {
+            return Err(Diagnostic::error("Call of an unknown function", span));
+        };
+        if self.depth >= MAX_DEPTH {
+            return Err(Diagnostic::error(
+                format!("Recursion too deep in `{}`", function.name),
+                span,
+            )
+            .with_note(format!(
+                "more than {} function bodies and blocks were being run at once",
+                MAX_DEPTH
+            )));
+        }
+        for (param, value) in function.params.iter().zip(args) {
+            self.values.insert(param.id, value.clone());
+        }
+        let (value, returned_at) = match self.run(&function.body.ops)? {
+            Flow::Value(value, returned_at) => (value, returned_at),
+            Flow::Return(_) | Flow::Next => {
+                return Err(Diagnostic::error(
+                    format!("Missing return statement in `{}`", function.name),
+                    span,
+                )
+                .with_note("the function ended without returning a value"));
+            }
+        };
+        match function.return_type {
+            Some(ty) if !value.has_type(ty) => Err(Diagnostic::error(
+                format!(
+                    "Return type mismatch in `{}`: expected `{:?}`, found `{}`",
+                    function.name,
+                    ty,
+                    value.type_name()
+                ),
+                returned_at,
+            )),
+            _ => Ok(value),
+        }
+    }
+
+    fn run(&mut self, ops: &[Op]) -> Result<Flow, Diagnostic> {
+        self.depth += 1;
+        let flow = self.run_ops(ops);
+        self.depth -= 1;
+        flow
+    }
+
+    fn run_ops(&mut self, ops: &[Op]) -> Result<Flow, Diagnostic> {
+        // only the ops that run other ops are matched here, the rest are in
+        // `compute`, which keeps the frames of nested calls small
+        for op in ops {
+            match op {
+                Op::Logical {
+                    result,
+                    op,
+                    lhs,
+                    rhs_ops,
+                    rhs,
+                    span,
+                } => {
+                    let lhs = self.value(*lhs, *span)?;
+                    let decided = matches!(
+                        (op, &lhs),
+                        (BinOp::And, Value::Bool(false)) | (BinOp::Or, Value::Bool(true))
+                    );
+                    let value = if decided {
+                        lhs
+                    } else {
+                        self.run(rhs_ops)?;
+                        let rhs = self.value(*rhs, *span)?;
+                        binary(*op, lhs, rhs)
+                            .map_err(|message| Diagnostic::error(message, *span))?
+                    };
+                    self.values.insert(*result, value);
+                }
+                Op::Return {
+                    doc_element_ref, ..
+                } => return Ok(Flow::Return(*doc_element_ref)),
+                Op::ReturnValue { value, span } => {
+                    return Ok(Flow::Value(self.value(*value, *span)?, *span));
+                }
+                Op::Call {
+                    result: Some(result),
+                    func,
+                    args,
+                    span,
+                    ..
+                } => {
+                    let args = args
+                        .iter()
+                        .map(|arg| self.value(*arg, *span))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let mut callee = Evaluator::new(self.hlir);
+                    callee.depth = self.depth;
+                    let value = callee.call_value(*func, &args, *span)?;
+                    self.values.insert(*result, value);
+                }
+                Op::If {
+                    condition,
+                    then_ops,
+                    else_ops,
+                    span,
+                } => {
+                    let ops = if self.condition(*condition, *span)? {
+                        then_ops
+                    } else {
+                        else_ops
+                    };
+                    match self.run(ops)? {
+                        Flow::Next => {}
+                        flow => return Ok(flow),
+                    }
+                }
+                Op::While {
+                    condition_ops,
+                    condition,
+                    body,
+                    span,
+                } => {
+                    let mut iterations = 0;
+                    loop {
+                        self.run(condition_ops)?;
+                        if !self.condition(*condition, *span)? {
+                            break;
+                        }
+                        iterations += 1;
+                        if iterations > MAX_LOOP_ITERATIONS {
+                            return Err(Diagnostic::error(
+                                format!(
+                                    "`while` loop did not finish after {} iterations",
+                                    MAX_LOOP_ITERATIONS
+                                ),
+                                *span,
+                            )
+                            .with_help("make sure the condition eventually becomes `false`"));
+                        }
+                        match self.run(body)? {
+                            Flow::Next => {}
+                            flow => return Ok(flow),
+                        }
+                    }
+                }
+                Op::For {
+                    iterator,
+                    iterable,
+                    body,
+                    span,
+                } => {
+                    for value in iterate(self.value(*iterable, *span)?, *span)? {
+                        self.values.insert(*iterator, value);
+                        match self.run(body)? {
+                            Flow::Next => {}
+                            flow => return Ok(flow),
+                        }
+                    }
+                }
+                op => self.compute(op)?,
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Run an op that computes a value without running other ops
+    fn compute(&mut self, op: &Op) -> Result<(), Diagnostic> {
+        match op {
+            Op::Const {
+                result, literal, ..
+            } => {
+                self.values.insert(*result, Value::from(literal));
+            }
+            Op::Binary {
+                result,
+                op,
+                lhs,
+                rhs,
+                span,
+            } => {
+                let lhs = self.value(*lhs, *span)?;
+                let rhs = self.value(*rhs, *span)?;
+                let value =
+                    binary(*op, lhs, rhs).map_err(|message| Diagnostic::error(message, *span))?;
+                self.values.insert(*result, value);
+            }
+            Op::Unary {
+                result,
+                op,
+                operand,
+                span,
+            } => {
+                let value = match (op, self.value(*operand, *span)?) {
+                    (UnOp::Neg, Value::Int(n)) => match n.checked_neg() {
+                        Some(n) => Value::Int(n),
+                        None => {
+                            return Err(Diagnostic::error("Integer overflow in `-`", *span));
+                        }
+                    },
+                    (UnOp::Neg, Value::Float(f)) => Value::Float(-f),
+                    (UnOp::Not, Value::Bool(b)) => Value::Bool(!b),
+                    (op, value) => {
+                        let op = if *op == UnOp::Neg { "-" } else { "!" };
+                        return Err(Diagnostic::error(
+                            format!("Cannot apply `{}` to `{}`", op, value.type_name()),
+                            *span,
+                        ));
+                    }
+                };
+                self.values.insert(*result, value);
+            }
+            Op::Range {
+                result,
+                start,
+                end,
+                span,
+            } => {
+                let value = match (self.value(*start, *span)?, self.value(*end, *span)?) {
+                    (Value::Int(start), Value::Int(end)) => Value::Range(start, end),
+                    (start, end) => {
+                        return Err(Diagnostic::error(
+                            format!(
+                                "Range bounds must be `Int`, found `{}` and `{}`",
+                                start.type_name(),
+                                end.type_name()
+                            ),
+                            *span,
+                        ));
+                    }
+                };
+                self.values.insert(*result, value);
+            }
+            Op::List {
+                result,
+                items,
+                span,
+            } => {
+                let items = items
+                    .iter()
+                    .map(|item| self.value(*item, *span))
+                    .collect::<Result<_, _>>()?;
+                self.values.insert(*result, Value::List(items));
+            }
+            Op::Map {
+                result,
+                entries,
+                span,
+            } => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), self.value(*value, *span)?)))
+                    .collect::<Result<_, _>>()?;
+                self.values.insert(*result, Value::Map(entries));
+            }
+            Op::Index {
+                result,
+                value,
+                index,
+                span,
+            } => {
+                let value = index_value(self.value(*value, *span)?, self.value(*index, *span)?)
+                    .map_err(|message| Diagnostic::error(message, *span))?;
+                self.values.insert(*result, value);
+            }
+            Op::Field {
+                result,
+                value,
+                name,
+                span,
+            } => {
+                let value = field(self.value(*value, *span)?, name)
+                    .map_err(|message| Diagnostic::error(message, *span))?;
+                self.values.insert(*result, value);
+            }
+            Op::Len {
+                result,
+                value,
+                span,
+            } => {
+                let value = len(self.value(*value, *span)?)
+                    .map_err(|message| Diagnostic::error(message, *span))?;
+                self.values.insert(*result, value);
+            }
+            Op::Assign {
+                target,
+                value,
+                span,
+            } => {
+                let value = self.value(*value, *span)?;
+                self.values.insert(*target, value);
+            }
+            Op::StringConcat {
+                result,
+                parts,
+                span,
+            } => {
+                let mut text = String::new();
+                for part in parts {
+                    text.push_str(&self.value(*part, *span)?.to_string());
+                }
+                self.values.insert(*result, Value::String(text));
+            }
+            // element emission is handled by layout and rendering
+            Op::Var { .. } | Op::Call { .. } | Op::HlirElementEmit { .. } => {}
+            Op::Logical { .. }
+            | Op::Return { .. }
+            | Op::ReturnValue { .. }
+            | Op::If { .. }
+            | Op::While { .. }
+            | Op::For { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Current value of a local, or the initial value of a global
+    fn value(&self, id: Id, span: Span) -> Result<Value, Diagnostic> {
+        if let Some(value) = self.values.get(&id) {
+            return Ok(value.clone());
+        }
+        if let Some(global) = self.hlir.globals.get(&id) {
+            return Ok(Value::from(&global.init));
+        }
+        Err(Diagnostic::error("Value used before it was assigned", span))
+    }
+
+    fn condition(&self, id: Id, span: Span) -> Result<bool, Diagnostic> {
+        condition(self.value(id, span)?, span)
+    }
+}
+
+/// The value of an `if` or `while` condition, which must be a `Bool`
+pub fn condition(value: Value, span: Span) -> Result<bool, Diagnostic> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        value => Err(Diagnostic::error(
+            format!("Condition must be a `Bool`, found `{}`", value.type_name()),
+            span,
+        )
+        .with_help("compare the value, for example `count > 0`")),
+    }
+}
+
+/// The values a `for` loop visits: the numbers of a range, the items of a
+/// list, or the keys of a map
+pub fn iterate(iterable: Value, span: Span) -> Result<Vec<Value>, Diagnostic> {
+    match iterable {
+        Value::Range(start, end) => {
+            if end.saturating_sub(start) > MAX_LOOP_ITERATIONS as i64 {
+                return Err(Diagnostic::error(
+                    format!("Range {}..{} is too long to loop over", start, end),
+                    span,
+                )
+                .with_note(format!(
+                    "loops are limited to {} iterations",
+                    MAX_LOOP_ITERATIONS
+                )));
+            }
+            Ok((start..end).map(Value::Int).collect())
+        }
+        Value::List(items) => Ok(items),
+        Value::Map(entries) => Ok(entries
+            .into_iter()
+            .map(|(key, _)| Value::String(key))
+            .collect()),
+        value => Err(Diagnostic::error(
+            format!("Cannot iterate over a `{}`", value.type_name()),
+            span,
+        )
+        .with_help("loop over a range such as `0..3`, or a list")),
+    }
+}
+
+/// `value[index]`: the item of a list, counting from 0, or the entry of a
+/// map
+pub fn index_value(value: Value, index: Value) -> Result<Value, String> {
+    match (value, index) {
+        (Value::List(items), Value::Int(i)) => {
+            let length = items.len();
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| items.into_iter().nth(i))
+                .ok_or_else(|| {
+                    format!(
+                        "Index {} is out of bounds for a list of length {}",
+                        i, length
+                    )
+                })
+        }
+        (Value::Map(entries), Value::String(key)) => field(Value::Map(entries), &key),
+        (Value::List(_), index) => Err(format!(
+            "List index must be an `Int`, found `{}`",
+            index.type_name()
+        )),
+        (Value::Map(_), index) => Err(format!(
+            "Map key must be a `String`, found `{}`",
+            index.type_name()
+        )),
+        (value, _) => Err(format!("Cannot index a `{}`", value.type_name())),
+    }
+}
+
+/// `value.name`, the entry `name` of a map
+pub fn field(value: Value, name: &str) -> Result<Value, String> {
+    match value {
+        Value::Map(entries) => entries
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| format!("No entry `{}` in map", name)),
+        value => Err(format!(
+            "Cannot access `.{}` of a `{}`",
+            name,
+            value.type_name()
+        )),
+    }
+}
+
+/// The built-in `len(value)`
+pub fn len(value: Value) -> Result<Value, String> {
+    let length = match &value {
+        Value::List(items) => items.len(),
+        Value::Map(entries) => entries.len(),
+        Value::String(text) => text.chars().count(),
+        value => {
+            return Err(format!(
+                "`len` takes a `List`, `Map` or `String`, found `{}`",
+                value.type_name()
+            ));
+        }
+    };
+    Ok(Value::Int(length as i64))
+}
+
+/// The error for a binary operator applied to values of the wrong types
+pub fn binary_mismatch(op: BinOp, lhs: &str, rhs: &str) -> String {
+    format!(
+        "Binary operation type mismatch: cannot apply `{}` to `{}` and `{}`",
+        op.as_str(),
+        lhs,
+        rhs
+    )
+}
+
+/// The type of the result of `binary` for operands of these types, without
+/// running it
+pub fn binary_type(op: BinOp, lhs: Type, rhs: Type) -> Result<Type, String> {
+    let numeric = |ty: Type| matches!(ty, Type::Int | Type::Float);
+    let ty = match op {
+        BinOp::Add if lhs == Type::String && rhs == Type::String => Some(Type::String),
+        BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => match (lhs, rhs) {
+            (Type::Int, Type::Int) => Some(Type::Int),
+            _ if numeric(lhs) && numeric(rhs) => Some(Type::Float),
+            _ => None,
+        },
+        BinOp::Eq | BinOp::Ne if lhs == rhs || numeric(lhs) && numeric(rhs) => Some(Type::Bool),
+        BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge
+            if lhs == Type::String && rhs == Type::String || numeric(lhs) && numeric(rhs) =>
+        {
+            Some(Type::Bool)
+        }
+        BinOp::And | BinOp::Or if lhs == Type::Bool && rhs == Type::Bool => Some(Type::Bool),
+        _ => None,
+    };
+    ty.ok_or_else(|| binary_mismatch(op, &format!("{:?}", lhs), &format!("{:?}", rhs)))
+}
+
+/// Apply a binary operator. `+` joins two strings, and an `Int` mixed with
+/// a `Float` is treated as a `Float`.
+pub fn binary(op: BinOp, lhs: Value, rhs: Value) -> Result<Value, String> {
+    let mismatch = |lhs: &Value, rhs: &Value| binary_mismatch(op, lhs.type_name(), rhs.type_name());
+
+    match op {
+        BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => match (&lhs, &rhs) {
+            (Value::String(l), Value::String(r)) if op == BinOp::Add => {
+                Ok(Value::String(format!("{}{}", l, r)))
+            }
+            (Value::Int(l), Value::Int(r)) => {
+                let result = match op {
+                    BinOp::Add => l.checked_add(*r),
+                    BinOp::Sub => l.checked_sub(*r),
+                    BinOp::Mul => l.checked_mul(*r),
+                    BinOp::Div if *r == 0 => return Err("Division by zero".to_string()),
+                    BinOp::Mod if *r == 0 => return Err("Division by zero".to_string()),
+                    BinOp::Div => l.checked_div(*r),
+                    _ => l.checked_rem(*r),
+                };
+                result
+                    .map(Value::Int)
+                    .ok_or_else(|| format!("Integer overflow in `{}`", op.as_str()))
+            }
+            _ => match (lhs.as_float(), rhs.as_float()) {
+                (Some(l), Some(r)) => Ok(Value::Float(match op {
+                    BinOp::Add => l + r,
+                    BinOp::Sub => l - r,
+                    BinOp::Mul => l * r,
+                    BinOp::Div => l / r,
+                    _ => l % r,
+                })),
+                _ => Err(mismatch(&lhs, &rhs)),
+            },
+        },
+        BinOp::Eq | BinOp::Ne => {
+            let equal = match (lhs.as_float(), rhs.as_float()) {
+                (Some(l), Some(r)) => l == r,
+                _ if lhs.type_name() == rhs.type_name() => lhs == rhs,
+                _ => return Err(mismatch(&lhs, &rhs)),
+            };
+            Ok(Value::Bool(if op == BinOp::Eq { equal } else { !equal }))
+        }
+        BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+            let ordering = match (&lhs, &rhs) {
+                (Value::Int(l), Value::Int(r)) => l.partial_cmp(r),
+                (Value::String(l), Value::String(r)) => l.partial_cmp(r),
+                _ => match (lhs.as_float(), rhs.as_float()) {
+                    (Some(l), Some(r)) => l.partial_cmp(&r),
+                    _ => return Err(mismatch(&lhs, &rhs)),
+                },
+            };
+            let Some(ordering) = ordering else {
+                return Ok(Value::Bool(false)); // NaN compares false
+            };
+            Ok(Value::Bool(match op {
+                BinOp::Lt => ordering.is_lt(),
+                BinOp::Le => ordering.is_le(),
+                BinOp::Gt => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }))
+        }
+        BinOp::And | BinOp::Or => match (&lhs, &rhs) {
+            (Value::Bool(l), Value::Bool(r)) => Ok(Value::Bool(if op == BinOp::And {
+                *l && *r
+            } else {
+                *l || *r
+            })),
+            _ => Err(mismatch(&lhs, &rhs)),
+        },
+    }
+}
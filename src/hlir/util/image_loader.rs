@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::Path;
+
+use crate::diagnostics::Diagnostic;
+use crate::hlir::ir_types::{HLIRModule, HlirElement};
+use crate::image::{ImageFormat, ImageInfo, decode_png};
+
+/// Resolve every image `src` against `base_dir` (the directory of the input
+/// file) and read the file header, so layout knows the intrinsic size.
+/// PNG files are decoded too, as the renderer has to. Missing, unsupported
+/// and corrupt files are reported as errors.
+pub fn load_images(hlir: &mut HLIRModule, base_dir: &Path) {
+    let mut diagnostics = Vec::new();
+    for (index, element) in hlir.elements.iter_mut().enumerate() {
+        let HlirElement::Image {
+            src, path, info, ..
+        } = element
+        else {
+            continue;
+        };
+        let span = hlir
+            .element_metadata
+            .get(index)
+            .map(|metadata| metadata.span)
+            .unwrap_or_default();
+
+        *path = base_dir.join(&*src);
+        let bytes = match fs::read(&*path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                diagnostics.push(
+                    Diagnostic::error(format!("Cannot read image `{}`", src), span)
+                        .with_note(format!("{}: {}", path.display(), e)),
+                );
+                continue;
+            }
+        };
+        let header = match ImageInfo::from_bytes(&bytes) {
+            Ok(header) => header,
+            Err(message) => {
+                let mut diagnostic =
+                    Diagnostic::error(format!("Cannot use image `{}`: {}", src, message), span)
+                        .with_note(path.display().to_string());
+                if message == "unsupported image format" {
+                    diagnostic = diagnostic.with_help("images must be PNG or JPEG files");
+                } else if message.starts_with("interlaced") {
+                    diagnostic = diagnostic.with_help("save the image again without interlacing");
+                }
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+        if header.format == ImageFormat::Png
+            && let Err(message) = decode_png(&bytes)
+        {
+            diagnostics.push(
+                Diagnostic::error(format!("Cannot decode image `{}`: {}", src, message), span)
+                    .with_note(path.display().to_string()),
+            );
+            continue;
+        }
+        *info = Some(header);
+    }
+    hlir.diagnostics.extend(diagnostics);
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use crate::ast::{Expression, InterpPart};
+use crate::diagnostics::Diagnostic;
+use crate::hlir::ir_types::{HLIRModule, HlirElement, Id, StyleAttributes};
+use crate::hlir::util::evaluator::{Evaluator, Value};
+
+/// Copy the element a function returned, and its children, for one call
+/// inside `parent`. Every copy gets its own metadata and attribute nodes, so
+/// the arguments can be filled in and styles resolved per call. Returns the
+/// index of the copy, and the copy of every element by the index of the
+/// original.
+pub fn instantiate(
+    hlir: &mut HLIRModule,
+    element: usize,
+    parent: Option<usize>,
+) -> (usize, HashMap<usize, usize>) {
+    let mut copies = HashMap::new();
+    let copy = copy_element(hlir, element, parent, &mut copies);
+    (copy, copies)
+}
+
+/// Put `copy` in the place of the call placeholder `placeholder`, among the
+/// children of its parent
+pub fn replace_placeholder(hlir: &mut HLIRModule, placeholder: usize, copy: usize) {
+    let parent = hlir.element_metadata[placeholder].parent;
+    hlir.element_metadata[copy].parent = parent;
+    if let Some(parent) = parent
+        && let HlirElement::Section { children, .. }
+        | HlirElement::List { children, .. }
+        | HlirElement::Fragment { children, .. } = &mut hlir.elements[parent]
+    {
+        for child in children {
+            if *child == placeholder {
+                *child = copy;
+            }
+        }
+    }
+}
+
+fn copy_element(
+    hlir: &mut HLIRModule,
+    element: usize,
+    parent: Option<usize>,
+    copies: &mut HashMap<usize, usize>,
+) -> usize {
+    let mut copy = hlir.elements[element].clone();
+    let index = hlir.elements.len();
+    copies.insert(element, index);
+
+    // the element's node, and those of the `span`s inside a text
+    let mut nodes = HashMap::new();
+    let own = copy.attributes();
+    nodes.insert(own, hlir.attributes.copy_node(own));
+    if let HlirElement::Text { runs, .. } = &mut copy {
+        for run in runs {
+            run.attributes = *nodes
+                .entry(run.attributes)
+                .or_insert_with(|| copy_span(hlir, run.attributes, parent));
+        }
+    }
+    set_attributes(&mut copy, nodes[&own]);
+
+    let mut metadata = hlir.element_metadata[element].clone();
+    metadata.parent = parent;
+    metadata.attributes_ref = nodes[&own];
+    let children = copy.children().to_vec();
+    hlir.elements.push(copy);
+    hlir.element_metadata.push(metadata);
+
+    // children come after their parent, which styles are inherited from
+    let copies: Vec<usize> = children
+        .into_iter()
+        .map(|child| copy_element(hlir, child, Some(index), copies))
+        .collect();
+    if let HlirElement::Section { children, .. }
+    | HlirElement::List { children, .. }
+    | HlirElement::Table { children, .. }
+    | HlirElement::Fragment { children, .. } = &mut hlir.elements[index]
+    {
+        *children = copies;
+    }
+    index
+}
+
+/// Copy the node of a `span` and its metadata
+fn copy_span(hlir: &mut HLIRModule, node: usize, parent: Option<usize>) -> usize {
+    let copy = hlir.attributes.copy_node(node);
+    let spans: Vec<_> = hlir
+        .span_metadata
+        .iter()
+        .filter(|metadata| metadata.attributes_ref == node)
+        .cloned()
+        .collect();
+    for mut metadata in spans {
+        metadata.parent = parent;
+        metadata.attributes_ref = copy;
+        hlir.span_metadata.push(metadata);
+    }
+    copy
+}
+
+fn set_attributes(element: &mut HlirElement, node: usize) {
+    match element {
+        HlirElement::Section { attributes, .. }
+        | HlirElement::List { attributes, .. }
+        | HlirElement::Text { attributes, .. }
+        | HlirElement::Link { attributes, .. }
+        | HlirElement::Image { attributes, .. }
+        | HlirElement::Table { attributes, .. }
+        | HlirElement::TableCell { attributes, .. }
+        | HlirElement::Code { attributes, .. }
+        | HlirElement::Fragment { attributes, .. } => *attributes = node,
+    }
+}
+
+/// Fill the values of a call's arguments into the element a function
+/// returned and its children. Text with `{expression}` parts is built from
+/// `locals`, the values of the function's locals when it returned, and
+/// attributes that name a parameter or call a function are recomputed. Code
+/// is left as written.
+pub fn fill_params(
+    hlir: &mut HLIRModule,
+    element: usize,
+    values: &HashMap<String, Value>,
+    locals: &HashMap<Id, Value>,
+) {
+    for child in hlir.elements[element].children().to_vec() {
+        fill_params(hlir, child, values, locals);
+    }
+
+    let mut nodes = vec![hlir.elements[element].attributes()];
+    let fill = |text: &mut String, value: Option<Id>| {
+        if let Some(value) = value.and_then(|value| locals.get(&value)) {
+            *text = value.to_string();
+        }
+    };
+    match &mut hlir.elements[element] {
+        HlirElement::Text { content, runs, .. } => {
+            for run in runs.iter_mut() {
+                fill(&mut run.text, run.value);
+                if !nodes.contains(&run.attributes) {
+                    nodes.push(run.attributes); // the node of a `span`
+                }
+            }
+            *content = runs.iter().map(|run| run.text.as_str()).collect();
+        }
+        HlirElement::Link { content, value, .. } => fill(content, *value),
+        _ => {}
+    }
+    for node in nodes {
+        fill_attributes(hlir, node, values, locals);
+    }
+}
+
+/// Recompute the inline style of an attribute node from its attributes as
+/// written, and the id and classes of the elements styled by it
+fn fill_attributes(
+    hlir: &mut HLIRModule,
+    node: usize,
+    values: &HashMap<String, Value>,
+    locals: &HashMap<Id, Value>,
+) {
+    let Some(attributes) = hlir.attributes.find_node(node) else {
+        return;
+    };
+    if attributes.calls.is_empty()
+        && !attributes
+            .source
+            .values()
+            .any(|value| names_param(value, values))
+    {
+        return;
+    }
+    let mut filled: HashMap<String, Expression> = attributes
+        .source
+        .iter()
+        .map(|(name, value)| (name.clone(), fill_expression(value, values)))
+        .collect();
+    let mut diagnostics = Vec::new();
+    for (name, (ops, result)) in &attributes.calls {
+        let span = attributes.source[name].span();
+        let mut evaluator = Evaluator::with_values(hlir, locals.clone());
+        match evaluator.evaluate(ops, *result, span) {
+            Ok(value) => {
+                filled.insert(name.clone(), value.to_expression(span));
+            }
+            Err(diagnostic) => {
+                filled.remove(name);
+                diagnostics.push(diagnostic);
+            }
+        }
+    }
+    hlir.diagnostics.extend(diagnostics);
+
+    let Some(attributes) = hlir.attributes.find_node_mut(node) else {
+        return;
+    };
+    attributes.inline = StyleAttributes::new_with_attributes(&filled);
+    let mut warnings = Vec::new();
+    for (name, value) in &attributes.source {
+        if (names_param(value, values) || attributes.calls.contains_key(name))
+            && filled.contains_key(name)
+            && let Err(message) = StyleAttributes::check_attribute(name, &filled[name])
+        {
+            warnings.push(
+                Diagnostic::warning(message, value.span())
+                    .with_note(format!("the `{}` attribute is ignored", name)),
+            );
+        }
+    }
+    hlir.diagnostics.extend(warnings);
+
+    let id = filled.get("id").map(|id| id.to_string());
+    let classes: Vec<String> = filled
+        .get("class")
+        .map(|class| {
+            class
+                .to_string()
+                .split_whitespace()
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    for metadata in hlir
+        .element_metadata
+        .iter_mut()
+        .chain(hlir.span_metadata.iter_mut())
+        .filter(|metadata| metadata.attributes_ref == node)
+    {
+        metadata.id = id.clone();
+        metadata.classes = classes.clone();
+    }
+}
+
+fn names_param(value: &Expression, values: &HashMap<String, Value>) -> bool {
+    match value {
+        Expression::Identifier(name, _) => values.contains_key(name),
+        Expression::InterpolatedString(parts, _) => parts.iter().any(|part| {
+            matches!(part, InterpPart::Expression(Expression::Identifier(name, _))
+                if values.contains_key(name))
+        }),
+        _ => false,
+    }
+}
+
+fn fill_expression(value: &Expression, values: &HashMap<String, Value>) -> Expression {
+    match value {
+        Expression::Identifier(name, span) => match values.get(name) {
+            Some(value) => value.to_expression(*span),
+            None => value.clone(),
+        },
+        Expression::InterpolatedString(parts, span) => {
+            let parts: Vec<InterpPart> = parts
+                .iter()
+                .map(|part| match part {
+                    InterpPart::Expression(Expression::Identifier(name, _))
+                        if values.contains_key(name) =>
+                    {
+                        InterpPart::Text(values[name].to_string())
+                    }
+                    other => other.clone(),
+                })
+                .collect();
+            if parts.iter().all(|part| matches!(part, InterpPart::Text(_))) {
+                let text = parts
+                    .iter()
+                    .map(|part| match part {
+                        InterpPart::Text(text) => text.as_str(),
+                        InterpPart::Expression(_) => "",
+                    })
+                    .collect();
+                Expression::StringLiteral(text, *span)
+            } else {
+                Expression::InterpolatedString(parts, *span)
+            }
+        }
+        other => other.clone(),
+    }
+}
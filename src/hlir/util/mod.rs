@@ -1,5 +1,8 @@
 pub mod assign_func;
 pub mod assign_vars;
+pub mod evaluator;
+pub mod image_loader;
+pub mod instantiate;
 pub mod style_pass;
 pub mod style_resolver;
 pub mod validation_pass;
@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+
 use crate::ast::{KeyValue, Selector, StyleRule};
-use crate::hlir::ir_types::{HLIRModule, StyleAttributes};
+use crate::diagnostics::Diagnostic;
+use crate::highlight::TokenClass;
+use crate::hlir::ir_types::{ElementMetadata, HLIRModule, HlirElement, StyleAttributes};
 
 pub fn resolve_styles(hlir: &mut HLIRModule) {
     let mut resolver = StyleResolver::new(hlir);
@@ -16,18 +20,69 @@ impl<'a> StyleResolver<'a> {
     }
 
     pub fn resolve(&mut self) {
+        self.check_declarations();
+
         // Sort CSS rules by specificity for cascade order
         let mut sorted_rules = self.hlir.css_rules.clone();
         sorted_rules.sort_by_key(|r| r.specificity);
 
-        for element_idx in 0..self.hlir.element_metadata.len() {
-            self.compute_element_styles(element_idx, &sorted_rules);
+        for metadata in self.hlir.element_metadata.clone() {
+            self.compute_element_styles(&metadata, &sorted_rules);
+        }
+        // spans inherit from the text around them, which is resolved by now
+        for metadata in self.hlir.span_metadata.clone() {
+            self.compute_element_styles(&metadata, &sorted_rules);
+        }
+        self.resolve_code_colors(&sorted_rules);
+    }
+
+    /// Highlighted code takes the `color` of the rules selecting its token
+    /// class, like `.code-keyword`
+    fn resolve_code_colors(&mut self, sorted_rules: &[StyleRule]) {
+        let mut colors = HashMap::new();
+        for class in TokenClass::ALL {
+            // rules are sorted by specificity, so the last match wins
+            let from_rules = sorted_rules
+                .iter()
+                .filter(|rule| {
+                    rule.selector_list.iter().any(
+                        |selector| matches!(selector, Selector::Class(name) if name == class.class_name()),
+                    )
+                })
+                .flat_map(|rule| &rule.declaration_block)
+                .filter(|decl| decl.key == "color")
+                .filter_map(|decl| expr_to_string(&decl.value).parse().ok())
+                .next_back();
+            if let Some(color) = from_rules.or_else(|| class.default_color().parse().ok()) {
+                colors.insert(class, color);
+            }
+        }
+
+        for element in &mut self.hlir.elements {
+            if let HlirElement::Code { colors: code, .. } = element {
+                *code = colors.clone();
+            }
         }
     }
 
-    fn compute_element_styles(&mut self, element_idx: usize, sorted_rules: &[StyleRule]) {
-        let metadata = &self.hlir.element_metadata[element_idx].clone();
+    /// Warn about declarations whose values `StyleAttributes::set` would ignore
+    fn check_declarations(&mut self) {
+        let mut warnings = Vec::new();
+        for rule in &self.hlir.css_rules {
+            for decl in &rule.declaration_block {
+                let value = expr_to_string(&decl.value);
+                if let Err(message) = StyleAttributes::check_property(&decl.key, &value) {
+                    warnings.push(
+                        Diagnostic::warning(message, decl.span)
+                            .with_note(format!("the `{}` declaration is ignored", decl.key)),
+                    );
+                }
+            }
+        }
+        self.hlir.diagnostics.extend(warnings);
+    }
 
+    fn compute_element_styles(&mut self, metadata: &ElementMetadata, sorted_rules: &[StyleRule]) {
         // Start with inherited styles from parent
         let mut computed = StyleAttributes::default();
         if let Some(parent_idx) = metadata.parent {
@@ -35,7 +90,7 @@ impl<'a> StyleResolver<'a> {
         }
 
         for rule in sorted_rules {
-            if self.rule_matches(rule, element_idx) {
+            if self.rule_matches(rule, metadata) {
                 self.apply_rule_declarations(&mut computed, &rule.declaration_block);
             }
         }
@@ -56,16 +111,14 @@ impl<'a> StyleResolver<'a> {
         }
     }
 
-    fn rule_matches(&self, rule: &StyleRule, element_idx: usize) -> bool {
+    fn rule_matches(&self, rule: &StyleRule, metadata: &ElementMetadata) -> bool {
         // A rule matches if ANY of its selectors match the element
         rule.selector_list
             .iter()
-            .any(|selector| self.selector_matches(selector, element_idx))
+            .any(|selector| self.selector_matches(selector, metadata))
     }
 
-    fn selector_matches(&self, selector: &Selector, element_idx: usize) -> bool {
-        let metadata = &self.hlir.element_metadata[element_idx];
-
+    fn selector_matches(&self, selector: &Selector, metadata: &ElementMetadata) -> bool {
         match selector {
             Selector::Id(id) => metadata.id.as_ref() == Some(id),
             Selector::Class(class) => metadata.classes.contains(class),
@@ -130,12 +183,14 @@ fn expr_to_string(expr: &crate::ast::Expression) -> String {
     use crate::ast::Expression;
 
     match expr {
-        Expression::StringLiteral(s) => s.clone(),
-        Expression::Int(n) => n.to_string(),
-        Expression::Float(f) => f.to_string(),
-        Expression::Identifier(s) => s.clone(),
-        Expression::StructDefault(s) => format!("default({})", s),
-        Expression::InterpolatedString(parts) => {
+        Expression::StringLiteral(s, _) => s.clone(),
+        Expression::Int(n, _) => n.to_string(),
+        Expression::Float(f, _) => f.to_string(),
+        Expression::Bool(b, _) => b.to_string(),
+        Expression::Color(color, _) => color.clone(),
+        Expression::Identifier(s, _) => s.clone(),
+        Expression::StructDefault(s, _) => format!("default({})", s),
+        Expression::InterpolatedString(parts, _) => {
             let mut result = String::new();
             for part in parts {
                 match part {
@@ -151,6 +206,7 @@ fn expr_to_string(expr: &crate::ast::Expression) -> String {
             left,
             operator,
             right,
+            ..
         } => {
             format!(
                 "{} {:?} {}",
@@ -162,8 +218,17 @@ fn expr_to_string(expr: &crate::ast::Expression) -> String {
         Expression::Unary {
             operator,
             expression,
+            ..
         } => {
             format!("{:?} {}", operator, expr_to_string(expression))
         }
+        Expression::Range { start, end, .. } => {
+            format!("{}..{}", expr_to_string(start), expr_to_string(end))
+        }
+        Expression::Call { .. }
+        | Expression::List(..)
+        | Expression::Map(..)
+        | Expression::Index { .. }
+        | Expression::Field { .. } => expr.to_string(),
     }
 }
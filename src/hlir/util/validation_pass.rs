@@ -1,17 +1,548 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::hlir::HLIRModule;
-use crate::hlir::ir_types::{Func, FuncBlock, Global, Id, Op};
+use crate::diagnostics::{Diagnostic, Span, has_errors};
+use crate::hlir::ir_types::{Func, HLIRModule, Id, Op, Type, UnOp};
+use crate::hlir::util::evaluator::{Value, binary_type};
 
-impl HLIRModule {
-    pub fn validate(&self, hlir: &HLIRModule) -> Result<(), String> {
-        self.validate_globals(&hlir.globals);
-        self.validate_functions(&hlir.functions);
+/// Check a lowered module before it is evaluated: declared types, return
+/// types, missing returns, operator and argument types, and names defined
+/// twice. Types are worked out from the ops without running them, so a value
+/// whose type depends on how the code runs is left to `evaluate`.
+///
+/// On error every diagnostic is returned, those found while lowering first.
+/// Lowering already runs calls in constants, so an error it found that way,
+/// with the same message at the same place, is not repeated.
+pub fn validate(hlir: &HLIRModule) -> Result<(), Vec<Diagnostic>> {
+    let mut diagnostics = hlir.diagnostics.clone();
+    let found = validate_names(hlir)
+        .into_iter()
+        .chain(validate_globals(hlir))
+        .chain(validate_functions(hlir));
+    for diagnostic in found {
+        if !diagnostics
+            .iter()
+            .any(|d| d.message == diagnostic.message && d.span == diagnostic.span)
+        {
+            diagnostics.push(diagnostic);
+        }
+    }
 
+    if has_errors(&diagnostics) {
+        Err(diagnostics)
+    } else {
         Ok(())
     }
+}
+
+/// Whether a value of type `found` can be used where `expected` is
+fn accepts(expected: Type, found: Type) -> bool {
+    found == expected || (found == Type::Int && expected == Type::Float)
+}
+
+fn functions(hlir: &HLIRModule) -> Vec<&Func> {
+    let mut functions: Vec<&Func> = hlir.functions.values().collect();
+    functions.sort_by_key(|function| match function.id {
+        Id::Func(id) => id.0,
+        _ => usize::MAX,
+    });
+    functions
+}
+
+/// Two globals or functions with the same name. Both are kept while
+/// lowering, and the first one is used.
+fn validate_names(hlir: &HLIRModule) -> Vec<Diagnostic> {
+    let globals = hlir.globals.values().map(|global| {
+        // defaults are stored as `__key`
+        let name = global.name.strip_prefix("__").unwrap_or(&global.name);
+        (name, global.span, false)
+    });
+    let functions = hlir
+        .functions
+        .values()
+        .filter(|function| function.name != "__document")
+        .map(|function| (function.name.as_str(), function.span, true));
+    let mut symbols: Vec<(&str, Span, bool)> = globals.chain(functions).collect();
+    symbols.sort_by_key(|(_, span, _)| span.start);
+
+    let mut diagnostics = Vec::new();
+    let mut seen: HashMap<&str, bool> = HashMap::new();
+    for (name, span, is_function) in symbols {
+        let Some(&first_is_function) = seen.get(name) else {
+            seen.insert(name, is_function);
+            continue;
+        };
+        let message = if is_function && first_is_function {
+            format!("Duplicate function `{}`", name)
+        } else {
+            format!("Duplicate symbol `{}`", name)
+        };
+        diagnostics.push(
+            Diagnostic::error(message, span)
+                .with_note(format!("`{}` is already defined above", name)),
+        );
+    }
+    diagnostics
+}
+
+/// A global declared with a type holds a value of that type
+fn validate_globals(hlir: &HLIRModule) -> Vec<Diagnostic> {
+    let mut globals: Vec<_> = hlir.globals.values().collect();
+    globals.sort_by_key(|global| global.span.start);
 
-    fn validate_globals(&self, globals: &HashMap<Id, Global>) {}
+    let mut diagnostics = Vec::new();
+    for global in globals {
+        let value = Value::from(&global.init);
+        if !value.has_type(global.ty) {
+            diagnostics.push(Diagnostic::error(
+                format!(
+                    "Type mismatch: `{}` is declared `{:?}`, found `{}`",
+                    global.name,
+                    global.ty,
+                    value.type_name()
+                ),
+                global.span,
+            ));
+        }
+    }
+    diagnostics
+}
+
+fn validate_functions(hlir: &HLIRModule) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for function in functions(hlir) {
+        let mut check = FunctionCheck::new(hlir, function);
+        check.ops(&function.body.ops);
+        diagnostics.append(&mut check.diagnostics);
+
+        // a function with errors from lowering may have lost its returns
+        let lowering_failed = hlir.diagnostics.iter().any(|diagnostic| {
+            diagnostic.is_error()
+                && function.span.start <= diagnostic.span.start
+                && diagnostic.span.end <= function.span.end
+        });
+        // running a call in a constant reports it at the call instead
+        let message = format!("Missing return statement in `{}`", function.name);
+        let reported = hlir.diagnostics.iter().any(|d| d.message == message);
+        if function.name != "__document"
+            && !lowering_failed
+            && !reported
+            && !returns(&function.body.ops)
+        {
+            diagnostics.push(
+                Diagnostic::error(message, function.span)
+                    .with_note("every path through a function has to end in a `return`"),
+            );
+        }
+    }
+    diagnostics
+}
+
+/// Whether running `ops` always ends in a `return`
+fn returns(ops: &[Op]) -> bool {
+    ops.iter().any(|op| match op {
+        Op::Return { .. } | Op::ReturnValue { .. } => true,
+        Op::If {
+            then_ops, else_ops, ..
+        } => returns(then_ops) && returns(else_ops),
+        _ => false,
+    })
+}
+
+/// The types of the values in one function body, worked out in order
+struct FunctionCheck<'a> {
+    hlir: &'a HLIRModule,
+    function: &'a Func,
+    types: HashMap<Id, Type>,
+    /// locals declared `let name: Type`, with their name
+    declared: HashMap<Id, (Type, &'a str)>,
+    /// values known only while running: locals given values more than once
+    /// without a declared type, since a loop may change their type
+    varying: HashSet<Id>,
+    ranges: HashSet<Id>,
+    /// where each `Op::Const` is written
+    consts: HashMap<Id, Span>,
+    diagnostics: Vec<Diagnostic>,
+}
 
-    fn validate_functions(&self, functions: &HashMap<Id, Func>) {}
+impl<'a> FunctionCheck<'a> {
+    fn new(hlir: &'a HLIRModule, function: &'a Func) -> Self {
+        let mut types: HashMap<Id, Type> = hlir
+            .globals
+            .iter()
+            .map(|(id, global)| (*id, global.ty))
+            .collect();
+        if function.params.len() == function.args.len() {
+            for (param, ty) in function.params.iter().zip(&function.args) {
+                types.insert(param.id, *ty);
+            }
+        }
+
+        let mut declared = HashMap::new();
+        let mut assigned = HashMap::new();
+        collect_locals(&function.body.ops, &mut declared, &mut assigned);
+        let varying = assigned
+            .into_iter()
+            .filter(|(id, count)| *count > 1 && !declared.contains_key(id))
+            .map(|(id, _)| id)
+            .collect();
+
+        FunctionCheck {
+            hlir,
+            function,
+            types,
+            declared,
+            varying,
+            ranges: HashSet::new(),
+            consts: HashMap::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic::error(message, span));
+    }
+
+    fn set_type(&mut self, id: Id, ty: Type) {
+        if !self.varying.contains(&id) {
+            self.types.insert(id, ty);
+        }
+    }
+
+    fn ops(&mut self, ops: &'a [Op]) {
+        for op in ops {
+            self.op(op);
+        }
+    }
+
+    fn op(&mut self, op: &'a Op) {
+        match op {
+            Op::Const {
+                result, ty, span, ..
+            } => {
+                self.consts.insert(*result, *span);
+                self.store(*result, *ty, *span);
+            }
+            Op::Var { result, ty, .. } => {
+                // the value before it has been checked by `store`
+                self.set_type(*result, *ty);
+            }
+            Op::Binary {
+                result,
+                op,
+                lhs,
+                rhs,
+                span,
+            } => {
+                let (Some(&lhs), Some(&rhs)) = (self.types.get(lhs), self.types.get(rhs)) else {
+                    return;
+                };
+                match binary_type(*op, lhs, rhs) {
+                    Ok(ty) => self.set_type(*result, ty),
+                    Err(message) => self.error(message, *span),
+                }
+            }
+            Op::Logical {
+                result,
+                op,
+                lhs,
+                rhs_ops,
+                rhs,
+                span,
+            } => {
+                self.ops(rhs_ops);
+                let (Some(&lhs), Some(&rhs)) = (self.types.get(lhs), self.types.get(rhs)) else {
+                    return;
+                };
+                match binary_type(*op, lhs, rhs) {
+                    Ok(ty) => self.set_type(*result, ty),
+                    Err(message) => self.error(message, *span),
+                }
+            }
+            Op::Unary {
+                result,
+                op,
+                operand,
+                span,
+            } => {
+                let Some(&ty) = self.types.get(operand) else {
+                    return;
+                };
+                match (op, ty) {
+                    (UnOp::Neg, Type::Int | Type::Float) | (UnOp::Not, Type::Bool) => {
+                        self.set_type(*result, ty)
+                    }
+                    (op, ty) => {
+                        let op = if *op == UnOp::Neg { "-" } else { "!" };
+                        self.error(format!("Cannot apply `{}` to `{:?}`", op, ty), *span);
+                    }
+                }
+            }
+            Op::Range {
+                result,
+                start,
+                end,
+                span,
+            } => {
+                self.ranges.insert(*result);
+                if let (Some(&start), Some(&end)) = (self.types.get(start), self.types.get(end))
+                    && (start != Type::Int || end != Type::Int)
+                {
+                    self.error(
+                        format!(
+                            "Range bounds must be `Int`, found `{:?}` and `{:?}`",
+                            start, end
+                        ),
+                        *span,
+                    );
+                }
+            }
+            Op::List { result, .. } => self.set_type(*result, Type::List),
+            Op::Map { result, .. } => self.set_type(*result, Type::Map),
+            // the items and entries have no type of their own
+            Op::Index {
+                value, index, span, ..
+            } => {
+                let (Some(&value), Some(&index)) = (self.types.get(value), self.types.get(index))
+                else {
+                    return;
+                };
+                match (value, index) {
+                    (Type::List, Type::Int) | (Type::Map, Type::String) => {}
+                    (Type::List, index) => self.error(
+                        format!("List index must be an `Int`, found `{:?}`", index),
+                        *span,
+                    ),
+                    (Type::Map, index) => self.error(
+                        format!("Map key must be a `String`, found `{:?}`", index),
+                        *span,
+                    ),
+                    (value, _) => self.error(format!("Cannot index a `{:?}`", value), *span),
+                }
+            }
+            Op::Field {
+                value, name, span, ..
+            } => {
+                if let Some(&ty) = self.types.get(value)
+                    && ty != Type::Map
+                {
+                    self.error(format!("Cannot access `.{}` of a `{:?}`", name, ty), *span);
+                }
+            }
+            Op::Len {
+                result,
+                value,
+                span,
+            } => {
+                if let Some(&ty) = self.types.get(value)
+                    && !matches!(ty, Type::List | Type::Map | Type::String)
+                {
+                    self.error(
+                        format!("`len` takes a `List`, `Map` or `String`, found `{:?}`", ty),
+                        *span,
+                    );
+                }
+                self.set_type(*result, Type::Int);
+            }
+            Op::Assign {
+                target,
+                value,
+                span,
+            } => {
+                if let Some(&ty) = self.types.get(value) {
+                    self.store(*target, ty, *span);
+                }
+            }
+            Op::Call {
+                result,
+                func,
+                args,
+                span,
+                ..
+            } => self.call(*result, *func, args, *span),
+            Op::Return { span, .. } => {
+                if let Some(ty) = self.function.return_type
+                    && ty != Type::DocElement
+                {
+                    self.error(
+                        format!(
+                            "Return type mismatch: expected `{:?}`, found an element",
+                            ty
+                        ),
+                        *span,
+                    );
+                }
+            }
+            Op::ReturnValue { value, span } => match self.function.return_type {
+                Some(Type::DocElement) => self.diagnostics.push(
+                    Diagnostic::error(
+                        "Return type mismatch: expected an element, found a value",
+                        *span,
+                    )
+                    .with_help("declare the type it returns: `func name() -> String`"),
+                ),
+                Some(expected) => {
+                    if let Some(&found) = self.types.get(value)
+                        && !accepts(expected, found)
+                    {
+                        self.error(
+                            format!(
+                                "Return type mismatch in `{}`: expected `{:?}`, found `{:?}`",
+                                self.function.name, expected, found
+                            ),
+                            *span,
+                        );
+                    }
+                }
+                None => {}
+            },
+            Op::StringConcat { result, .. } => self.set_type(*result, Type::String),
+            Op::If {
+                condition,
+                then_ops,
+                else_ops,
+                span,
+            } => {
+                self.condition(*condition, *span);
+                self.ops(then_ops);
+                self.ops(else_ops);
+            }
+            Op::While {
+                condition_ops,
+                condition,
+                body,
+                span,
+            } => {
+                self.ops(condition_ops);
+                self.condition(*condition, *span);
+                self.ops(body);
+            }
+            Op::For {
+                iterator,
+                iterable,
+                body,
+                span,
+            } => {
+                match self.types.get(iterable) {
+                    _ if self.ranges.contains(iterable) => self.set_type(*iterator, Type::Int),
+                    // the items of a list can have any type
+                    Some(Type::List) | None => {}
+                    Some(Type::Map) => self.set_type(*iterator, Type::String),
+                    Some(ty) => self.diagnostics.push(
+                        Diagnostic::error(format!("Cannot iterate over a `{:?}`", ty), *span)
+                            .with_help("loop over a range such as `0..3`, or a list"),
+                    ),
+                }
+                self.ops(body);
+            }
+            Op::HlirElementEmit { .. } => {}
+        }
+    }
+
+    /// A value of type `ty` stored in `target`, which has to match the type
+    /// it was declared with
+    fn store(&mut self, target: Id, ty: Type, span: Span) {
+        if let Some(&(expected, name)) = self.declared.get(&target)
+            && !accepts(expected, ty)
+        {
+            self.error(
+                format!(
+                    "Type mismatch: `{}` is declared `{:?}`, found `{:?}`",
+                    name, expected, ty
+                ),
+                span,
+            );
+        }
+        self.set_type(target, ty);
+    }
+
+    fn condition(&mut self, condition: Id, span: Span) {
+        if let Some(&ty) = self.types.get(&condition)
+            && ty != Type::Bool
+        {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    format!("Condition must be a `Bool`, found `{:?}`", ty),
+                    span,
+                )
+                .with_help("compare the value, for example `count > 0`"),
+            );
+        }
+    }
+
+    /// The types of the arguments, except for constants written in the call
+    /// which were checked while lowering. The result has the callee's
+    /// return type.
+    fn call(&mut self, result: Option<Id>, func: Id, args: &[Id], span: Span) {
+        let Some(callee) = self.hlir.functions.get(&func) else {
+            return;
+        };
+        if callee.args.len() == callee.params.len() {
+            for (i, (arg, &expected)) in args.iter().zip(&callee.args).enumerate() {
+                let written = self
+                    .consts
+                    .get(arg)
+                    .is_some_and(|written| span.start <= written.start && written.end <= span.end);
+                if let Some(&found) = self.types.get(arg)
+                    && !written
+                    && !accepts(expected, found)
+                {
+                    self.error(
+                        format!(
+                            "Type mismatch in argument {} of `{}`: expected `{:?}`, found `{:?}`",
+                            i + 1,
+                            callee.name,
+                            expected,
+                            found
+                        ),
+                        span,
+                    );
+                }
+            }
+        }
+        if let Some(result) = result
+            && let Some(ty) = callee.return_type
+            && ty != Type::DocElement
+        {
+            self.set_type(result, ty);
+        }
+    }
+}
+
+/// The declared type of every local in `ops`, and how many times each one
+/// is given a value
+fn collect_locals<'a>(
+    ops: &'a [Op],
+    declared: &mut HashMap<Id, (Type, &'a str)>,
+    assigned: &mut HashMap<Id, usize>,
+) {
+    for op in ops {
+        match op {
+            Op::Var {
+                result, name, ty, ..
+            } => {
+                declared.insert(*result, (*ty, name.as_str()));
+            }
+            Op::Const { result: target, .. } | Op::Assign { target, .. } => {
+                *assigned.entry(*target).or_default() += 1;
+            }
+            Op::If {
+                then_ops, else_ops, ..
+            } => {
+                collect_locals(then_ops, declared, assigned);
+                collect_locals(else_ops, declared, assigned);
+            }
+            Op::While {
+                condition_ops,
+                body,
+                ..
+            } => {
+                collect_locals(condition_ops, declared, assigned);
+                collect_locals(body, declared, assigned);
+            }
+            Op::For { body, .. } | Op::Logical { rhs_ops: body, .. } => {
+                collect_locals(body, declared, assigned)
+            }
+            _ => {}
+        }
+    }
 }
@@ -0,0 +1,126 @@
+use std::fmt;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// `components` is 1 for greyscale, 3 for RGB and 4 for CMYK
+    Jpeg {
+        components: u8,
+    },
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageFormat::Png => write!(f, "PNG"),
+            ImageFormat::Jpeg { .. } => write!(f, "JPEG"),
+        }
+    }
+}
+
+/// What the layout engine needs to know about an image, read from the file
+/// header without decoding the pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageInfo {
+    /// Read the format and pixel size of a PNG or JPEG file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.starts_with(PNG_SIGNATURE) {
+            Self::from_png(bytes)
+        } else if bytes.starts_with(&[0xFF, 0xD8]) {
+            Self::from_jpeg(bytes)
+        } else {
+            Err("unsupported image format".to_string())
+        }
+    }
+
+    /// Size at 72 dpi, so one pixel is one point, scaled to fit `width`
+    /// and/or `height` when they are given. A single dimension keeps the
+    /// aspect ratio.
+    pub fn display_size(&self, width: Option<f32>, height: Option<f32>) -> (f32, f32) {
+        let (w, h) = (self.width as f32, self.height as f32);
+        match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, width * h / w),
+            (None, Some(height)) => (height * w / h, height),
+            (None, None) => (w, h),
+        }
+    }
+
+    /// The IHDR chunk always comes first, right after the signature
+    fn from_png(bytes: &[u8]) -> Result<Self, String> {
+        let ihdr = bytes
+            .get(8..8 + 8 + 13)
+            .filter(|chunk| &chunk[4..8] == b"IHDR")
+            .ok_or("truncated PNG header")?;
+        let width = u32::from_be_bytes([ihdr[8], ihdr[9], ihdr[10], ihdr[11]]);
+        let height = u32::from_be_bytes([ihdr[12], ihdr[13], ihdr[14], ihdr[15]]);
+        if ihdr[20] != 0 {
+            return Err("interlaced PNG images are not supported".to_string());
+        }
+        Self::sized(ImageFormat::Png, width, height)
+    }
+
+    /// Walk the marker segments up to the first start-of-frame, which holds
+    /// the size and number of colour components
+    fn from_jpeg(bytes: &[u8]) -> Result<Self, String> {
+        let mut i = 2;
+        while i + 4 <= bytes.len() {
+            if bytes[i] != 0xFF {
+                return Err("corrupt JPEG marker".to_string());
+            }
+            let marker = bytes[i + 1];
+            match marker {
+                // padding before a marker
+                0xFF => {
+                    i += 1;
+                    continue;
+                }
+                // markers without a segment
+                0x01 | 0xD0..=0xD7 => {
+                    i += 2;
+                    continue;
+                }
+                _ => {}
+            }
+            let length = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+            let is_frame = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+            if is_frame {
+                let frame = bytes.get(i + 4..i + 10).ok_or("truncated JPEG header")?;
+                let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                let components = frame[5];
+                if !matches!(components, 1 | 3 | 4) {
+                    return Err(format!(
+                        "JPEG images with {} colour components are not supported",
+                        components
+                    ));
+                }
+                return Self::sized(ImageFormat::Jpeg { components }, width, height);
+            }
+            if marker == 0xDA {
+                break; // image data starts without a frame header
+            }
+            i += 2 + length;
+        }
+        Err("truncated JPEG header".to_string())
+    }
+
+    fn sized(format: ImageFormat, width: u32, height: u32) -> Result<Self, String> {
+        if width == 0 || height == 0 {
+            return Err(format!("{} image has no pixels", format));
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+        })
+    }
+}
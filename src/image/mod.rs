@@ -0,0 +1,5 @@
+mod image_info;
+mod png;
+
+pub use image_info::{ImageFormat, ImageInfo};
+pub use png::{DecodedImage, PixelFormat, decode_png};
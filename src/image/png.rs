@@ -0,0 +1,241 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+/// Upper bound on the pixels of a decoded image, so a header claiming a
+/// huge size is reported instead of exhausting memory
+const MAX_PIXELS: usize = 1 << 26;
+
+/// 8 bit samples, row by row without padding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Grey,
+    Rgb,
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub pixels: Vec<u8>,
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            2 => 3,
+            4 => 2,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes in a row of samples, without the filter type
+    fn stride(&self) -> Option<usize> {
+        Some(self.width.checked_mul(self.bits_per_pixel())?.div_ceil(8))
+    }
+
+    /// Bytes of the decompressed data: every row and its filter type
+    fn raw_size(&self) -> Option<usize> {
+        self.stride()?.checked_add(1)?.checked_mul(self.height)
+    }
+}
+
+/// Decode a non-interlaced PNG to 8 bit grey or RGB pixels. Transparent
+/// pixels are blended onto white, the colour of the page.
+pub fn decode_png(bytes: &[u8]) -> Result<DecodedImage, String> {
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut data = Vec::new();
+
+    let mut i = 8; // skip the signature
+    while i + 8 <= bytes.len() {
+        let length = u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let kind = &bytes[i + 4..i + 8];
+        let body = bytes
+            .get(i + 8..i + 8 + length as usize)
+            .ok_or("truncated PNG chunk")?;
+        match kind {
+            b"IHDR" if body.len() == 13 => {
+                if body[12] != 0 {
+                    return Err("interlaced PNG images are not supported".to_string());
+                }
+                header = Some(Header {
+                    width: u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize,
+                    height: u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize,
+                    bit_depth: body[8],
+                    color_type: body[9],
+                });
+            }
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => data.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        i += 12 + length as usize; // length, kind, body and CRC
+    }
+
+    let header = header.ok_or("missing PNG header")?;
+    if !matches!(header.color_type, 0 | 2 | 3 | 4 | 6)
+        || !matches!(header.bit_depth, 1 | 2 | 4 | 8 | 16)
+    {
+        return Err(format!(
+            "unsupported PNG colour type {} with bit depth {}",
+            header.color_type, header.bit_depth
+        ));
+    }
+
+    let pixel_count = header
+        .width
+        .checked_mul(header.height)
+        .filter(|count| *count <= MAX_PIXELS)
+        .ok_or_else(|| {
+            format!(
+                "PNG image of {}x{} pixels is too large",
+                header.width, header.height
+            )
+        })?;
+    if pixel_count == 0 {
+        return Err("PNG image has no pixels".to_string());
+    }
+
+    // the filtered rows are never longer than this, anything after them is
+    // not read
+    let expected = header.raw_size().ok_or("PNG image is too large")?;
+    let mut raw = Vec::new();
+    ZlibDecoder::new(data.as_slice())
+        .take(expected as u64)
+        .read_to_end(&mut raw)
+        .map_err(|e| format!("corrupt PNG data: {}", e))?;
+    let samples = unfilter(&header, &raw)?;
+
+    let format = match header.color_type {
+        0 | 4 => PixelFormat::Grey,
+        _ => PixelFormat::Rgb,
+    };
+    let mut pixels =
+        Vec::with_capacity(pixel_count.checked_mul(3).ok_or("PNG image is too large")?);
+    for pixel in samples.chunks_exact(header.channels()) {
+        match header.color_type {
+            0 => pixels.push(pixel[0]),
+            2 => pixels.extend_from_slice(pixel),
+            3 => {
+                let index = pixel[0] as usize;
+                let rgb = palette
+                    .get(index * 3..index * 3 + 3)
+                    .ok_or("PNG palette index out of range")?;
+                let alpha = transparency.get(index).copied().unwrap_or(255);
+                pixels.extend(rgb.iter().map(|c| blend(*c, alpha)));
+            }
+            4 => pixels.push(blend(pixel[0], pixel[1])),
+            _ => pixels.extend(pixel[..3].iter().map(|c| blend(*c, pixel[3]))),
+        }
+    }
+
+    Ok(DecodedImage {
+        width: header.width as u32,
+        height: header.height as u32,
+        format,
+        pixels,
+    })
+}
+
+/// Undo the per-row filters and widen every sample to one byte. Palette
+/// indices are kept as they are, other samples are scaled to 0..=255.
+fn unfilter(header: &Header, raw: &[u8]) -> Result<Vec<u8>, String> {
+    let stride = header.stride().ok_or("PNG image is too large")?;
+    let bytes_per_pixel = header.bits_per_pixel().div_ceil(8);
+    if raw.len() < header.raw_size().ok_or("PNG image is too large")? {
+        return Err("truncated PNG data".to_string());
+    }
+
+    let capacity = header
+        .width
+        .checked_mul(header.height)
+        .and_then(|count| count.checked_mul(header.channels()))
+        .ok_or("PNG image is too large")?;
+    let mut samples = Vec::with_capacity(capacity);
+    let mut previous = vec![0u8; stride];
+    let mut row = vec![0u8; stride];
+    for line in raw.chunks_exact(stride + 1).take(header.height) {
+        let filter = line[0];
+        row.copy_from_slice(&line[1..]);
+        for x in 0..stride {
+            let left = if x >= bytes_per_pixel {
+                row[x - bytes_per_pixel]
+            } else {
+                0
+            };
+            let up = previous[x];
+            let up_left = if x >= bytes_per_pixel {
+                previous[x - bytes_per_pixel]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(format!("unknown PNG filter type {}", filter)),
+            };
+            row[x] = row[x].wrapping_add(predicted);
+        }
+
+        let count = header.width * header.channels();
+        match header.bit_depth {
+            8 => samples.extend_from_slice(&row[..count]),
+            16 => samples.extend(row.chunks_exact(2).take(count).map(|s| s[0])),
+            depth => {
+                let max = (1u16 << depth) - 1;
+                for n in 0..count {
+                    let bit = n * depth as usize;
+                    let value = (row[bit / 8] >> (8 - depth as usize - bit % 8)) as u16 & max;
+                    let value = if header.color_type == 3 {
+                        value
+                    } else {
+                        value * 255 / max
+                    };
+                    samples.push(value as u8);
+                }
+            }
+        }
+        std::mem::swap(&mut previous, &mut row);
+    }
+    Ok(samples)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Blend a sample with coverage `alpha` onto a white background
+fn blend(sample: u8, alpha: u8) -> u8 {
+    let (sample, alpha) = (sample as u16, alpha as u16);
+    ((sample * alpha + 255 * (255 - alpha)) / 255) as u8
+}
@@ -1,14 +1,37 @@
 use std::collections::HashMap;
-use taffy::style::AvailableSpace;
-use taffy::{LengthPercentage, LengthPercentageAuto, NodeId, Rect, Size, Style, TaffyTree};
+use taffy::style::{AvailableSpace, GridTemplateComponent};
+use taffy::style_helpers::{TaffyAuto, fr, length, line, percent, span};
+use taffy::{
+    Dimension, Display, LengthPercentage, LengthPercentageAuto, Line, NodeId, Rect, Size, Style,
+    TaffyTree,
+};
 
-use crate::hlir::{FuncId, HLIRModule, HlirElement, Id, Op, StyleAttributes};
+use crate::hlir::{ColumnWidth, FuncId, HLIRModule, HlirElement, Id, Op, StyleAttributes};
+use crate::image::ImageInfo;
 
 pub fn setup_layout(hlir_module: &HLIRModule) -> LayoutEngine {
     let layout = LayoutEngine::build_from_hlir_module(hlir_module);
     layout
 }
 
+/// Height of one line of text in points, text is set at this size
+pub const LINE_HEIGHT: f32 = 12.0;
+
+/// Size of an image in points: its pixel size, or the `width` and `height`
+/// styles with the aspect ratio kept when only one of them is set
+pub fn image_size(info: &ImageInfo, attributes: &StyleAttributes) -> (f32, f32) {
+    let length = |property| attributes.get(property).and_then(|v| v.parse().ok());
+    info.display_size(length("width"), length("height"))
+}
+
+/// Space between the border of a table cell and its text, unless the cell
+/// sets its own `padding`
+pub const CELL_PADDING: f32 = 3.0;
+
+pub fn cell_padding(attributes: &StyleAttributes) -> f32 {
+    attributes.padding.unwrap_or(CELL_PADDING)
+}
+
 #[derive(Debug)]
 pub struct LayoutEngine {
     tree: TaffyTree,
@@ -19,6 +42,8 @@ pub struct LayoutEngine {
     id_to_node: HashMap<String, NodeId>,
 }
 
+/// Position and size of an element in points, `x` and `y` are measured
+/// from the top left corner of the document's content area
 #[derive(Debug, Clone)]
 pub struct ComputedLayout {
     pub x: f32,
@@ -41,11 +66,12 @@ impl LayoutEngine {
     pub fn build_from_hlir_module(hlir_module: &HLIRModule) -> Self {
         let mut layout = LayoutEngine::new();
 
-        // Create root node
-        layout.root = layout
-            .tree
-            .new_with_children(Style::default(), &[])
-            .unwrap();
+        // Create root node, elements flow top to bottom like blocks on a page
+        let root_style = Style {
+            display: Display::Block,
+            ..Style::default()
+        };
+        layout.root = layout.tree.new_with_children(root_style, &[]).unwrap();
 
         // Pre-allocate element_to_node to match elements size
         layout.element_to_node = vec![None; hlir_module.elements.len()];
@@ -67,58 +93,21 @@ impl LayoutEngine {
 
     fn process_op(&mut self, op: &Op, hlir_module: &HLIRModule, parent_node: NodeId) {
         match op {
-            Op::HlirElementEmit { index } => {
+            Op::HlirElementEmit { index, .. } => {
                 // Document element - has metadata and computed styles
-                let element = hlir_module.elements.get(*index).expect("element not found");
-                let attributes_ref = match element {
-                    HlirElement::Section { attributes, .. } => *attributes,
-                    HlirElement::List { attributes, .. } => *attributes,
-                    HlirElement::Text { attributes, .. } => *attributes,
-                };
-                self.create_node_from_metadata(*index, attributes_ref, hlir_module, parent_node);
+                self.create_node_from_element(*index, hlir_module, parent_node);
             }
-            Op::Call { func, .. } => {
-                // Template function call - element is in function's returned_element_ref
-                if let Some(function) = hlir_module.functions.get(func) {
-                    if let Some(element_id) = function.body.returned_element_ref {
-                        // Template element - extract styles directly from DocElement
-                        self.create_node_from_element(element_id, hlir_module, parent_node);
-                    }
-                }
+            Op::Call {
+                instance: Some(element_id),
+                ..
+            } => {
+                // Template function call - element is the instance made for this call
+                self.create_node_from_element(*element_id, hlir_module, parent_node);
             }
             _ => {}
         }
     }
 
-    fn create_node_from_metadata(
-        &mut self,
-        element_index: usize,
-        attributes_ref: usize,
-        hlir_module: &HLIRModule,
-        parent_node: NodeId,
-    ) {
-        let attributes = match hlir_module.attributes.find_node(attributes_ref) {
-            Some(a) => &a.computed,
-            None => return,
-        };
-
-        let style = Self::attr_to_style(attributes);
-        let node_id = match self.tree.new_leaf(style) {
-            Ok(id) => id,
-            Err(_) => return,
-        };
-
-        let _ = self.tree.add_child(parent_node, node_id);
-
-        if element_index < self.element_to_node.len() {
-            self.element_to_node[element_index] = Some(node_id);
-        }
-
-        if let Some(id) = &attributes.id {
-            self.id_to_node.insert(id.clone(), node_id);
-        }
-    }
-
     fn create_node_from_element(
         &mut self,
         element_index: usize,
@@ -130,19 +119,21 @@ impl LayoutEngine {
             None => return,
         };
 
-        // Get attributes_ref from the element and look up computed styles
-        let attributes_ref = match element {
-            HlirElement::Section { attributes, .. } => *attributes,
-            HlirElement::List { attributes, .. } => *attributes,
-            HlirElement::Text { attributes, .. } => *attributes,
-        };
+        // The elements of a fragment are children of the container it is in
+        if let HlirElement::Fragment { children, .. } = element {
+            for child_idx in children {
+                self.create_node_from_element(*child_idx, hlir_module, parent_node);
+            }
+            return;
+        }
 
-        let attributes = match hlir_module.attributes.find_node(attributes_ref) {
+        // Look up the computed styles of the element
+        let attributes = match hlir_module.attributes.find_node(element.attributes()) {
             Some(node) => &node.computed,
             None => return,
         };
 
-        let style = Self::attr_to_style(attributes);
+        let style = Self::element_style(element, attributes);
 
         let node_id = match self.tree.new_leaf(style) {
             Ok(id) => id,
@@ -159,26 +150,69 @@ impl LayoutEngine {
             self.id_to_node.insert(id.clone(), node_id);
         }
 
-        // Handle children (for Section, List, etc.)
-        self.process_element_children(element, hlir_module, node_id);
+        // Handle children (for Section, List, etc.), stored as indices into hlir_module.elements
+        for child_idx in element.children() {
+            self.create_node_from_element(*child_idx, hlir_module, node_id);
+        }
     }
 
-    fn process_element_children(
-        &mut self,
-        element: &HlirElement,
-        hlir_module: &HLIRModule,
-        parent_node: NodeId,
-    ) {
-        // HlirElement stores children as indices into hlir_module.elements
-        let children = match element {
-            HlirElement::Section { children, .. } => children,
-            HlirElement::List { children, .. } => children,
-            HlirElement::Text { .. } => return, // No children
+    /// `attr_to_style` as a block, with one line of height for text, a line
+    /// per line of code and the fixed size of an image
+    fn element_style(element: &HlirElement, attributes: &StyleAttributes) -> Style {
+        let mut style = Style {
+            display: Display::Block,
+            ..Self::attr_to_style(attributes)
         };
-
-        for child_idx in children {
-            self.create_node_from_element(*child_idx, hlir_module, parent_node);
+        match element {
+            HlirElement::Text { .. } | HlirElement::Link { .. } => {
+                style.size.height = Dimension::length(LINE_HEIGHT);
+            }
+            HlirElement::Image {
+                info: Some(info), ..
+            } => {
+                let (width, height) = image_size(info, attributes);
+                style.size = Size {
+                    width: Dimension::length(width),
+                    height: Dimension::length(height),
+                };
+            }
+            HlirElement::Code { lines, .. } => {
+                let padding = attributes.padding.unwrap_or(0.0);
+                style.size.height =
+                    Dimension::length(lines.len() as f32 * LINE_HEIGHT + 2.0 * padding);
+            }
+            HlirElement::Table { columns, .. } => {
+                style.display = Display::Grid;
+                style.grid_template_columns = columns
+                    .iter()
+                    .map(|width| match width {
+                        ColumnWidth::Auto => GridTemplateComponent::AUTO,
+                        ColumnWidth::Points(points) => length(*points),
+                        ColumnWidth::Percent(share) => percent(*share / 100.0),
+                        ColumnWidth::Fraction(share) => fr(*share),
+                    })
+                    .collect();
+            }
+            HlirElement::TableCell {
+                row,
+                column,
+                rowspan,
+                colspan,
+                ..
+            } => {
+                let grid_line = |start: usize, count: usize| Line {
+                    start: line(start as i16 + 1),
+                    end: span(count as u16),
+                };
+                style.grid_row = grid_line(*row, *rowspan);
+                style.grid_column = grid_line(*column, *colspan);
+                let padding = cell_padding(attributes);
+                style.padding = Rect::length(padding);
+                style.min_size.height = Dimension::length(LINE_HEIGHT + 2.0 * padding);
+            }
+            _ => {}
         }
+        style
     }
 
     pub fn attr_to_style(attributes: &StyleAttributes) -> Style {
@@ -222,33 +256,17 @@ impl LayoutEngine {
     /// Get computed layout for an element by index
     pub fn get_element_layout(&self, element_index: usize) -> Option<ComputedLayout> {
         let node_id = self.element_to_node.get(element_index).copied()??;
-        let layout = self.tree.layout(node_id).ok()?;
-
-        Some(ComputedLayout {
-            x: layout.location.x,
-            y: layout.location.y,
-            width: layout.size.width,
-            height: layout.size.height,
-            element_index,
-        })
+        self.node_layout(node_id, element_index)
     }
 
     /// Get layout for an element by its CSS ID
     pub fn get_layout_by_id(&self, id: &str) -> Option<ComputedLayout> {
         let node_id = self.id_to_node.get(id)?;
-        let layout = self.tree.layout(*node_id).ok()?;
         let element_index = self
             .element_to_node
             .iter()
             .position(|&n| n == Some(*node_id))?;
-
-        Some(ComputedLayout {
-            x: layout.location.x,
-            y: layout.location.y,
-            width: layout.size.width,
-            height: layout.size.height,
-            element_index,
-        })
+        self.node_layout(*node_id, element_index)
     }
 
     /// Iterate over all elements with their computed layouts
@@ -256,16 +274,28 @@ impl LayoutEngine {
         self.element_to_node
             .iter()
             .enumerate()
-            .filter_map(|(idx, opt_node_id)| {
-                let node_id = opt_node_id.as_ref()?;
-                let layout = self.tree.layout(*node_id).ok()?;
-                Some(ComputedLayout {
-                    x: layout.location.x,
-                    y: layout.location.y,
-                    width: layout.size.width,
-                    height: layout.size.height,
-                    element_index: idx,
-                })
-            })
+            .filter_map(|(idx, opt_node_id)| self.node_layout((*opt_node_id)?, idx))
+    }
+
+    /// Taffy places nodes relative to their parent, add up the offsets of
+    /// the ancestors to get the position in the document
+    fn node_layout(&self, node_id: NodeId, element_index: usize) -> Option<ComputedLayout> {
+        let layout = self.tree.layout(node_id).ok()?;
+        let (mut x, mut y) = (layout.location.x, layout.location.y);
+        let mut ancestor = self.tree.parent(node_id);
+        while let Some(node) = ancestor {
+            let location = self.tree.layout(node).ok()?.location;
+            x += location.x;
+            y += location.y;
+            ancestor = self.tree.parent(node);
+        }
+
+        Some(ComputedLayout {
+            x,
+            y,
+            width: layout.size.width,
+            height: layout.size.height,
+            element_index,
+        })
     }
 }
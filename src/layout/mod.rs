@@ -1,3 +1,6 @@
 mod layout_engine;
 
-pub use layout_engine::{ComputedLayout, LayoutEngine, setup_layout};
+pub use layout_engine::{
+    CELL_PADDING, ComputedLayout, LINE_HEIGHT, LayoutEngine, cell_padding, image_size,
+    setup_layout,
+};
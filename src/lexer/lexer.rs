@@ -1,3 +1,4 @@
+use crate::diagnostics::{Diagnostic, Span};
 use crate::lexer::tokens::TokenKind;
 
 static KEYWORD_TABLE: phf::Map<&'static str, TokenKind> = phf::phf_map! {
@@ -11,14 +12,19 @@ static KEYWORD_TABLE: phf::Map<&'static str, TokenKind> = phf::phf_map! {
     "if" => TokenKind::If,
     "else" => TokenKind::Else,
     "for" => TokenKind::For,
+    "in" => TokenKind::In,
     "while" => TokenKind::While,
     "return" => TokenKind::Return,
+    "true" => TokenKind::True,
+    "false" => TokenKind::False,
     "text" => TokenKind::Text,
     "image" => TokenKind::Image,
     "list" => TokenKind::List,
     "section" => TokenKind::Section,
     "table" => TokenKind::Table,
     "link" => TokenKind::Link,
+    "code" => TokenKind::Code,
+    "markdown" => TokenKind::Markdown,
 };
 
 static SYMBOL_LOOKUP_TABLE: [Option<TokenKind>; 256] = {
@@ -46,25 +52,19 @@ static SYMBOL_LOOKUP_TABLE: [Option<TokenKind>; 256] = {
     t[b'#' as usize] = Some(Hash);
     t[b'!' as usize] = Some(Bang);
     t[b'>' as usize] = Some(Greater);
+    t[b'<' as usize] = Some(Less);
 
     t
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TokenStream {
     pub kinds: Vec<TokenKind>,
     pub ranges: Vec<std::ops::Range<usize>>,
     pub lines: Vec<u32>,
     pub cols: Vec<u32>,
     pub source: String,
-    pub errors: Vec<LexError>,
-}
-
-#[derive(Debug, Clone)]
-pub struct LexError {
-    pub message: String,
-    pub line: u32,
-    pub col: u32,
+    pub errors: Vec<Diagnostic>,
 }
 
 impl TokenStream {
@@ -90,12 +90,12 @@ impl TokenStream {
 
 #[inline]
 fn is_ident_start(c: u8) -> bool {
-    (c >= b'a' && c <= b'z') || (c >= b'A' && c <= b'Z') || c == b'_'
+    c.is_ascii_alphabetic() || c == b'_'
 }
 
 #[inline]
 fn is_ident_continue(c: u8) -> bool {
-    is_ident_start(c) || (c >= b'0' && c <= b'9')
+    is_ident_start(c) || c.is_ascii_digit()
 }
 
 pub fn lex(source: &str) -> TokenStream {
@@ -150,7 +150,8 @@ pub fn lex(source: &str) -> TokenStream {
                 i += 1;
             }
 
-            if i < len && bytes[i] == b'.' {
+            // `1..3` is a range, not the float `1.` followed by `.3`
+            if i < len && bytes[i] == b'.' && bytes.get(i + 1) != Some(&b'.') {
                 is_float = true;
                 i += 1;
                 while i < len && bytes[i].is_ascii_digit() {
@@ -168,12 +169,35 @@ pub fn lex(source: &str) -> TokenStream {
             continue;
         }
 
+        // --- Raw strings, `"""` up to the next `"""` with nothing escaped ---
+        if source[i..].starts_with("\"\"\"") {
+            i += 3;
+            match source[i..].find("\"\"\"") {
+                Some(end) => i += end + 3,
+                None => {
+                    i = len;
+                    out.errors.push(
+                        Diagnostic::error("Unterminated raw string", Span::new(start, len))
+                            .with_help("add a closing `\"\"\"`"),
+                    );
+                }
+            }
+            out.push(TokenKind::RawString, start, i, line, col);
+            for c in source[start..i].chars() {
+                if c == '\n' {
+                    line += 1;
+                    col = 1;
+                } else {
+                    col += 1;
+                }
+            }
+            continue;
+        }
+
         // --- String literals ---
         if c == b'"' {
             i += 1; // skip opening quote
             let mut escaped = false;
-            let string_start_line = line;
-            let string_start_col = col;
             while i < len {
                 if escaped {
                     escaped = false;
@@ -193,11 +217,10 @@ pub fn lex(source: &str) -> TokenStream {
             }
             if i >= len {
                 // Unterminated string
-                out.errors.push(LexError {
-                    message: "Unterminated string literal".to_string(),
-                    line: string_start_line,
-                    col: string_start_col,
-                });
+                out.errors.push(
+                    Diagnostic::error("Unterminated string literal", Span::new(start, len))
+                        .with_help("add a closing `\"`"),
+                );
             } else {
                 i += 1; // Skip closing quote
                 col += 1;
@@ -229,6 +252,26 @@ pub fn lex(source: &str) -> TokenStream {
             }
         }
 
+        // --- Two-character tokens ---
+        if i + 1 < len {
+            let kind = match (c, bytes[i + 1]) {
+                (b'=', b'=') => Some(TokenKind::EqualsEquals),
+                (b'!', b'=') => Some(TokenKind::BangEquals),
+                (b'<', b'=') => Some(TokenKind::LessEquals),
+                (b'>', b'=') => Some(TokenKind::GreaterEquals),
+                (b'&', b'&') => Some(TokenKind::AndAnd),
+                (b'|', b'|') => Some(TokenKind::OrOr),
+                (b'.', b'.') => Some(TokenKind::DotDot),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                out.push(kind, i, i + 2, line, col);
+                i += 2;
+                col += 2;
+                continue;
+            }
+        }
+
         // --- Single-character tokens ---
         if let Some(kind) = SYMBOL_LOOKUP_TABLE[c as usize] {
             out.push(kind, i, i + 1, line, col);
@@ -238,13 +281,17 @@ pub fn lex(source: &str) -> TokenStream {
         }
 
         // --- Unknown character ---
-        eprintln!("Unknown character: {:?}", c as char);
-        i += 1;
+        let unknown = source[i..].chars().next().unwrap_or(c as char);
+        out.errors.push(Diagnostic::error(
+            format!("Unknown character `{}`", unknown),
+            Span::new(i, i + unknown.len_utf8()),
+        ));
+        i += unknown.len_utf8();
         col += 1;
     }
 
     // --- EOF ---
     out.push(TokenKind::Eof, len, len, line, col);
 
-    return out;
+    out
 }
@@ -3,5 +3,5 @@ pub mod tokens;
 
 pub use lexer::lex;
 
-pub use lexer::{LexError, TokenStream};
+pub use lexer::TokenStream;
 pub use tokens::TokenKind;
@@ -24,17 +24,29 @@ pub enum TokenKind {
     Greater,
     Less,
 
+    // Two-char symbols
+    EqualsEquals,  // ==
+    BangEquals,    // !=
+    LessEquals,    // <=
+    GreaterEquals, // >=
+    AndAnd,        // &&
+    OrOr,          // ||
+    DotDot,        // ..
+
     // Literals
     Identifier,
     Int,
     Float,
     StringLiteral,
+    RawString, // """..."""
     Text,
     Image,
     List,
     Table,
     Section,
     Link,
+    Code,
+    Markdown,
 
     // Keywords
     Template,
@@ -47,8 +59,11 @@ pub enum TokenKind {
     If,
     Else,
     For,
+    In,
     While,
     Return,
+    True,
+    False,
 
     // End
     Eof,
@@ -1,7 +1,12 @@
 pub mod ast;
 pub mod backend;
+pub mod diagnostics;
 // pub mod ffi; // still TODO
+pub mod format;
+pub mod highlight;
 pub mod hlir;
+pub mod image;
 pub mod layout;
 pub mod lexer;
+pub mod markdown;
 pub mod parser;
@@ -0,0 +1,243 @@
+/// A block of a Markdown document. Text is kept with its inline markup.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading {
+        level: usize,
+        text: String,
+    },
+    Paragraph(String),
+    List {
+        ordered: bool,
+        items: Vec<ListItem>,
+    },
+    Code {
+        lang: Option<String>,
+        content: String,
+    },
+}
+
+/// An item of a list, with the lists indented under it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub text: String,
+    /// Always `Block::List`
+    pub lists: Vec<Block>,
+}
+
+impl ListItem {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            lists: Vec::new(),
+        }
+    }
+}
+
+/// A list being read: the indentation of its markers, whether it is
+/// ordered and its items so far
+type OpenList = (usize, bool, Vec<ListItem>);
+
+/// Split Markdown into blocks. A list indented under an item is kept in the
+/// item, block quotes are read as their contents and thematic breaks are
+/// dropped.
+pub fn parse_blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    // the open lists, innermost last
+    let mut list: Vec<OpenList> = Vec::new();
+    let mut lines = markdown.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+
+        if let Some((fence, lang)) = code_fence(line) {
+            flush(&mut blocks, &mut paragraph, &mut list);
+            let mut content = Vec::new();
+            for line in lines.by_ref() {
+                if is_closing_fence(line, fence) {
+                    break;
+                }
+                content.push(line);
+            }
+            blocks.push(Block::Code {
+                lang,
+                content: content.join("\n"),
+            });
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            flush(&mut blocks, &mut paragraph, &mut list);
+            continue;
+        }
+
+        // a line of `=` or `-` under a paragraph makes it a heading
+        if !paragraph.is_empty()
+            && let Some(level) = setext_level(trimmed)
+        {
+            let text = paragraph.join(" ");
+            paragraph.clear();
+            blocks.push(Block::Heading { level, text });
+            continue;
+        }
+
+        if is_thematic_break(trimmed) {
+            flush(&mut blocks, &mut paragraph, &mut list);
+            continue;
+        }
+
+        if let Some((level, text)) = heading(trimmed) {
+            flush(&mut blocks, &mut paragraph, &mut list);
+            blocks.push(Block::Heading {
+                level,
+                text: text.to_string(),
+            });
+            continue;
+        }
+
+        // a list does not interrupt a paragraph
+        if paragraph.is_empty()
+            && let Some((ordered, item)) = list_item(trimmed)
+        {
+            let indent = indentation(line);
+            while list.last().is_some_and(|(open, ..)| *open > indent) {
+                close_list(&mut blocks, &mut list);
+            }
+            match list.last_mut() {
+                Some((open, list_ordered, items))
+                    if *open == indent && *list_ordered == ordered =>
+                {
+                    items.push(ListItem::new(item))
+                }
+                // another kind of list at the same level follows this one
+                Some((open, ..)) if *open == indent => {
+                    close_list(&mut blocks, &mut list);
+                    list.push((indent, ordered, vec![ListItem::new(item)]));
+                }
+                _ => list.push((indent, ordered, vec![ListItem::new(item)])),
+            }
+            continue;
+        }
+
+        // anything else continues the open list item or paragraph
+        let text = trimmed.trim_start_matches('>').trim();
+        match list.last_mut() {
+            Some((_, _, items)) => {
+                let item = &mut items.last_mut().expect("a list has an item").text;
+                item.push(' ');
+                item.push_str(text);
+            }
+            None => paragraph.push(text),
+        }
+    }
+    flush(&mut blocks, &mut paragraph, &mut list);
+    blocks
+}
+
+/// End the open paragraph or lists
+fn flush(blocks: &mut Vec<Block>, paragraph: &mut Vec<&str>, list: &mut Vec<OpenList>) {
+    if !paragraph.is_empty() {
+        blocks.push(Block::Paragraph(paragraph.join(" ")));
+        paragraph.clear();
+    }
+    while !list.is_empty() {
+        close_list(blocks, list);
+    }
+}
+
+/// End the innermost open list, adding it to the item it is indented under
+fn close_list(blocks: &mut Vec<Block>, list: &mut Vec<OpenList>) {
+    let Some((_, ordered, items)) = list.pop() else {
+        return;
+    };
+    let block = Block::List { ordered, items };
+    match list.last_mut() {
+        Some((_, _, items)) => items
+            .last_mut()
+            .expect("a list has an item")
+            .lists
+            .push(block),
+        None => blocks.push(block),
+    }
+}
+
+/// Width of the space before the text of a line, a tab counting as four
+fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// The fence of a line opening a code block and the language after it
+fn code_fence(line: &str) -> Option<(&str, Option<String>)> {
+    let trimmed = line.trim_start();
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = trimmed.chars().take_while(|c| *c == marker).count();
+    if length < 3 {
+        return None;
+    }
+    let (fence, info) = trimmed.split_at(length);
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+    let lang = info.split_whitespace().next().map(String::from);
+    Some((fence, lang))
+}
+
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with(fence) && trimmed.chars().all(|c| fence.starts_with(c))
+}
+
+fn setext_level(line: &str) -> Option<usize> {
+    if line.chars().all(|c| c == '=') {
+        Some(1)
+    } else if line.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Three or more `-`, `*` or `_`, which may be spaced out
+fn is_thematic_break(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|mark| marks.chars().all(|c| c == *mark))
+}
+
+/// `# Title` up to `###### Title`, without closing `#`s
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    let text = rest.trim();
+    let text = match text.trim_end_matches('#') {
+        stripped if stripped.is_empty() || stripped.ends_with(' ') => stripped.trim_end(),
+        _ => text,
+    };
+    Some((level, text))
+}
+
+/// Whether the line starts an ordered item, and the text of the item
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(marker) {
+            return Some((false, item.trim()));
+        }
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if !(1..=9).contains(&digits) {
+        return None;
+    }
+    let rest = &line[digits..];
+    let item = rest
+        .strip_prefix(". ")
+        .or_else(|| rest.strip_prefix(") "))?;
+    Some((true, item.trim()))
+}
@@ -0,0 +1,195 @@
+use crate::ast::{Emphasis, Inline, InterpPart};
+
+/// A piece of Markdown text
+#[derive(Debug, Clone)]
+pub enum Piece {
+    Inline(Inline),
+    Link { text: String, href: String },
+    Image { alt: String, src: String },
+}
+
+impl Piece {
+    /// The text a reader sees, with the markup removed
+    pub fn plain_text(pieces: &[Piece]) -> String {
+        let mut text = String::new();
+        for piece in pieces {
+            match piece {
+                Piece::Inline(inline) => {
+                    text.push_str(&Inline::plain_text(std::slice::from_ref(inline)))
+                }
+                Piece::Link { text: content, .. } | Piece::Image { alt: content, .. } => {
+                    text.push_str(content)
+                }
+            }
+        }
+        text
+    }
+}
+
+/// Split Markdown text into plain text, emphasis, code, links and images.
+/// Emphasis inside emphasis is kept as text only, since a run has one look.
+pub fn parse_inlines(text: &str) -> Vec<Piece> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut pieces = Vec::new();
+    let mut plain = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && chars.get(i + 1).is_some_and(char::is_ascii_punctuation) {
+            plain.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+        let found = match c {
+            '`' => code_span(&chars, i),
+            '*' | '_' => emphasis(&chars, i),
+            '[' => link(&chars, i).map(|(text, href, end)| (Piece::Link { text, href }, end)),
+            '!' if chars.get(i + 1) == Some(&'[') => {
+                link(&chars, i + 1).map(|(alt, src, end)| (Piece::Image { alt, src }, end))
+            }
+            '<' => autolink(&chars, i),
+            _ => None,
+        };
+        match found {
+            Some((piece, end)) => {
+                if !plain.is_empty() {
+                    pieces.push(Piece::Inline(Inline::Text(InterpPart::literal(
+                        std::mem::take(&mut plain),
+                    ))));
+                }
+                pieces.push(piece);
+                i = end;
+            }
+            None => {
+                // a run of markers that opens nothing is text as a whole
+                let run = run_length(&chars, i).max(1);
+                let run = if matches!(c, '`' | '*' | '_') { run } else { 1 };
+                plain.extend(&chars[i..i + run]);
+                i += run;
+            }
+        }
+    }
+    if !plain.is_empty() {
+        pieces.push(Piece::Inline(Inline::Text(InterpPart::literal(plain))));
+    }
+    pieces
+}
+
+/// Number of copies of `chars[at]` in a row from `at`
+fn run_length(chars: &[char], at: usize) -> usize {
+    chars[at..].iter().take_while(|c| **c == chars[at]).count()
+}
+
+/// `` `code` ``, closed by a run of as many backticks as opened it
+fn code_span(chars: &[char], open: usize) -> Option<(Piece, usize)> {
+    let length = run_length(chars, open);
+    let mut i = open + length;
+    while i < chars.len() {
+        if chars[i] != '`' {
+            i += 1;
+            continue;
+        }
+        let run = run_length(chars, i);
+        if run == length {
+            let code: String = chars[open + length..i].iter().collect();
+            let code = match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                Some(inner) if !inner.trim().is_empty() => inner.to_string(),
+                _ => code,
+            };
+            let piece = Piece::Inline(Inline::Emphasis(Emphasis::Code, InterpPart::literal(code)));
+            return Some((piece, i + run));
+        }
+        i += run;
+    }
+    None
+}
+
+/// `*italic*`, `_italic_`, `**bold**` or `__bold__`. Markers have to hug the
+/// text they mark, and `_` does not open or close inside a word.
+fn emphasis(chars: &[char], open: usize) -> Option<(Piece, usize)> {
+    let marker = chars[open];
+    let in_word = |i: usize| chars.get(i).is_some_and(|c| c.is_alphanumeric());
+    let opened = run_length(chars, open);
+    let delimiter = opened.min(2);
+    if chars.get(open + opened).is_none_or(|c| c.is_whitespace())
+        || marker == '_' && open.checked_sub(1).is_some_and(in_word)
+    {
+        return None;
+    }
+
+    let mut i = open + opened;
+    while i < chars.len() {
+        if chars[i] != marker {
+            i += 1;
+            continue;
+        }
+        let run = run_length(chars, i);
+        let closes = if delimiter == 1 { run == 1 } else { run >= 2 };
+        if closes && !chars[i - 1].is_whitespace() && !(marker == '_' && in_word(i + run)) {
+            let inner: String = chars[open + delimiter..i + run - delimiter]
+                .iter()
+                .collect();
+            let kind = if delimiter == 1 {
+                Emphasis::Italic
+            } else {
+                Emphasis::Bold
+            };
+            let text = Piece::plain_text(&parse_inlines(&inner));
+            return Some((
+                Piece::Inline(Inline::Emphasis(kind, InterpPart::literal(text))),
+                i + run,
+            ));
+        }
+        i += run;
+    }
+    None
+}
+
+/// `[text](href)`, with `open` at the `[`. A title after the href is ignored.
+fn link(chars: &[char], open: usize) -> Option<(String, String, usize)> {
+    let mut depth = 0;
+    let mut close = None;
+    for (i, c) in chars.iter().enumerate().skip(open) {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let close = close?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = (close + 2..chars.len()).find(|&i| chars[i] == ')')?;
+    let target: String = chars[close + 2..end].iter().collect();
+    let href = target.split_whitespace().next().unwrap_or_default();
+    let href = href
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string();
+    let text: String = chars[open + 1..close].iter().collect();
+    Some((Piece::plain_text(&parse_inlines(&text)), href, end + 1))
+}
+
+/// `<https://example.com>` or `<mailto:someone@example.com>`
+fn autolink(chars: &[char], open: usize) -> Option<(Piece, usize)> {
+    let end = (open + 1..chars.len()).find(|&i| chars[i] == '>' || chars[i].is_whitespace())?;
+    if chars[end] != '>' {
+        return None;
+    }
+    let href: String = chars[open + 1..end].iter().collect();
+    if !["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| href.starts_with(scheme))
+    {
+        return None;
+    }
+    let text = href.strip_prefix("mailto:").unwrap_or(&href).to_string();
+    Some((Piece::Link { text, href }, end + 1))
+}
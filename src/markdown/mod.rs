@@ -0,0 +1,291 @@
+mod blocks;
+mod inlines;
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    Ast, DocElement, DocumentBlock, Emphasis, Expression, Inline, InterpPart, KeyValue, Selector,
+    StyleBlock, StyleRule,
+};
+use crate::diagnostics::Span;
+
+pub use blocks::{Block, ListItem, parse_blocks};
+pub use inlines::{Piece, parse_inlines};
+
+/// Convert a Markdown file into a document with a starter style block,
+/// which has a rule for each kind of element the document uses
+pub fn to_ast(markdown: &str) -> Ast {
+    let span = Span::new(0, 0);
+    let elements = to_elements(markdown, span);
+    let mut kinds = Vec::new();
+    element_kinds(&elements, &mut kinds);
+    let rules = kinds
+        .into_iter()
+        .map(|kind| {
+            let declarations = starter_declarations(kind)
+                .into_iter()
+                .map(|(key, value)| KeyValue {
+                    key: key.to_string(),
+                    value,
+                    span,
+                })
+                .collect();
+            StyleRule::new(vec![Selector::Type(kind.to_string())], declarations, span)
+        })
+        .collect();
+    Ast {
+        template: None,
+        document: Some(DocumentBlock { elements }),
+        style: Some(StyleBlock { statements: rules }),
+    }
+}
+
+/// Kinds of the elements, in the order they first show up
+fn element_kinds(elements: &[DocElement], kinds: &mut Vec<&'static str>) {
+    for element in elements {
+        let kind = match element {
+            DocElement::Section { elements, .. } => {
+                push_kind(kinds, "section");
+                element_kinds(elements, kinds);
+                continue;
+            }
+            DocElement::List { items, .. } => {
+                push_kind(kinds, "list");
+                element_kinds(items, kinds);
+                continue;
+            }
+            DocElement::Text { inlines, .. } => {
+                push_kind(kinds, "text");
+                if inlines
+                    .iter()
+                    .any(|inline| matches!(inline, Inline::Link { .. }))
+                {
+                    push_kind(kinds, "link");
+                }
+                continue;
+            }
+            DocElement::Link { .. } => "link",
+            DocElement::Image { .. } => "image",
+            DocElement::Code { .. } => "code",
+            _ => continue,
+        };
+        push_kind(kinds, kind);
+    }
+}
+
+fn push_kind(kinds: &mut Vec<&'static str>, kind: &'static str) {
+    if !kinds.contains(&kind) {
+        kinds.push(kind);
+    }
+}
+
+/// Something to start from, for the author to change as they see fit
+fn starter_declarations(kind: &str) -> Vec<(&'static str, Expression)> {
+    let span = Span::new(0, 0);
+    let int = |value| Expression::Int(value, span);
+    let string = |value: &str| Expression::StringLiteral(value.to_string(), span);
+    match kind {
+        "section" => vec![("margin", int(8))],
+        "text" => vec![("font-size", int(12)), ("margin", int(2))],
+        "list" => vec![("margin", int(4))],
+        "link" => vec![("color", string("blue"))],
+        "image" => vec![("margin", int(4))],
+        "code" => vec![("font-size", int(10)), ("padding", int(4))],
+        _ => Vec::new(),
+    }
+}
+
+/// Convert Markdown into document elements, all spanning `span`.
+///
+/// A heading opens a section that holds it and the blocks up to the next
+/// heading of the same or a higher level. Headings are bold text with the
+/// class `h1` to `h6`, so the style block can tell them apart. Links and
+/// images in a paragraph are split out of the text around them, while a
+/// list item is only a `Link` when the link is all there is to it. A list
+/// indented under an item follows it in the list around it, and ordered
+/// lists have `ordered=true`.
+pub fn to_elements(markdown: &str, span: Span) -> Vec<DocElement> {
+    // the open sections, innermost last, each with the level of its heading
+    let mut open: Vec<(usize, Vec<DocElement>)> = vec![(0, Vec::new())];
+    for block in parse_blocks(markdown) {
+        let elements = match block {
+            Block::Heading { level, text } => {
+                close_sections(&mut open, level, span);
+                let inlines = parse_inlines(&text)
+                    .into_iter()
+                    .map(|piece| match piece {
+                        Piece::Inline(Inline::Emphasis(Emphasis::Code, code)) => {
+                            Inline::Emphasis(Emphasis::Code, code)
+                        }
+                        other => Inline::Emphasis(
+                            Emphasis::Bold,
+                            InterpPart::literal(Piece::plain_text(std::slice::from_ref(&other))),
+                        ),
+                    })
+                    .collect();
+                let class = format!("h{}", level);
+                let heading = text_element(inlines, attributes(&[("class", class)], span), span);
+                open.push((level, vec![heading]));
+                continue;
+            }
+            Block::Paragraph(text) => paragraph(parse_inlines(&text), span),
+            Block::List { ordered, items } => vec![list_element(ordered, &items, span)],
+            Block::Code { lang, content } => {
+                let attributes = match lang {
+                    Some(lang) => attributes(&[("lang", lang)], span),
+                    None => HashMap::new(),
+                };
+                vec![DocElement::Code {
+                    content,
+                    attributes,
+                    span,
+                }]
+            }
+        };
+        open.last_mut()
+            .expect("the top level is open")
+            .1
+            .extend(elements);
+    }
+    close_sections(&mut open, 1, span);
+    open.pop().map(|(_, elements)| elements).unwrap_or_default()
+}
+
+/// Close the sections whose headings are at `level` or deeper
+fn close_sections(open: &mut Vec<(usize, Vec<DocElement>)>, level: usize, span: Span) {
+    while open.len() > 1
+        && open
+            .last()
+            .is_some_and(|(open_level, _)| *open_level >= level)
+    {
+        let (_, elements) = open.pop().expect("checked above");
+        let section = DocElement::Section {
+            elements,
+            attributes: HashMap::new(),
+            span,
+        };
+        open.last_mut().expect("checked above").1.push(section);
+    }
+}
+
+/// A list and the lists indented under its items
+fn list_element(ordered: bool, items: &[ListItem], span: Span) -> DocElement {
+    let mut elements = Vec::new();
+    for item in items {
+        elements.push(match &parse_inlines(&item.text)[..] {
+            [Piece::Link { text, href }] => link_element(text, href, span),
+            pieces => text_element(inlines_of(pieces, span), HashMap::new(), span),
+        });
+        for list in &item.lists {
+            if let Block::List { ordered, items } = list {
+                elements.push(list_element(*ordered, items, span));
+            }
+        }
+    }
+    let mut attributes = HashMap::new();
+    if ordered {
+        attributes.insert("ordered".to_string(), Expression::Bool(true, span));
+    }
+    DocElement::List {
+        items: elements,
+        attributes,
+        span,
+    }
+}
+
+/// A paragraph as text with its links inside it, and its images as
+/// elements of their own
+fn paragraph(pieces: Vec<Piece>, span: Span) -> Vec<DocElement> {
+    let mut elements = Vec::new();
+    let mut inlines = Vec::new();
+    for piece in pieces {
+        let element = match piece {
+            Piece::Inline(inline) => {
+                inlines.push(inline);
+                continue;
+            }
+            Piece::Link { text, href } => {
+                inlines.push(link_inline(&text, &href, span));
+                continue;
+            }
+            Piece::Image { alt, src } => DocElement::Image {
+                src,
+                attributes: attributes(&[("alt", alt)], span),
+                span,
+            },
+        };
+        push_text(&mut elements, std::mem::take(&mut inlines), span);
+        elements.push(element);
+    }
+    push_text(&mut elements, inlines, span);
+    elements
+}
+
+/// Add the text of `inlines`, unless there is nothing but space to show
+fn push_text(elements: &mut Vec<DocElement>, inlines: Vec<Inline>, span: Span) {
+    if !Inline::plain_text(&inlines).trim().is_empty() {
+        elements.push(text_element(inlines, HashMap::new(), span));
+    }
+}
+
+/// Pieces as runs of text with the links inside them, images reduced to
+/// their description
+fn inlines_of(pieces: &[Piece], span: Span) -> Vec<Inline> {
+    pieces
+        .iter()
+        .map(|piece| match piece {
+            Piece::Inline(inline) => inline.clone(),
+            Piece::Link { text, href } => link_inline(text, href, span),
+            other => Inline::Text(InterpPart::literal(Piece::plain_text(
+                std::slice::from_ref(other),
+            ))),
+        })
+        .collect()
+}
+
+fn text_element(
+    inlines: Vec<Inline>,
+    attributes: HashMap<String, Expression>,
+    span: Span,
+) -> DocElement {
+    DocElement::Text {
+        content: Inline::plain_text(&inlines),
+        inlines,
+        attributes,
+        span,
+    }
+}
+
+/// A link inside text, showing its target without text of its own
+fn link_inline(text: &str, href: &str, span: Span) -> Inline {
+    let content = if text.is_empty() { href } else { text };
+    Inline::Link {
+        href: href.to_string(),
+        content: InterpPart::literal(content),
+        attributes: HashMap::new(),
+        span,
+    }
+}
+
+/// A link without text shows its target, like a `link` without a body
+fn link_element(text: &str, href: &str, span: Span) -> DocElement {
+    let content = if text.is_empty() { href } else { text };
+    DocElement::Link {
+        href: href.to_string(),
+        content: InterpPart::literal(content),
+        attributes: HashMap::new(),
+        span,
+    }
+}
+
+fn attributes(values: &[(&str, String)], span: Span) -> HashMap<String, Expression> {
+    values
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                Expression::StringLiteral(value.clone(), span),
+            )
+        })
+        .collect()
+}
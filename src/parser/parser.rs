@@ -1,139 +1,456 @@
-use core::panic;
+use crate::ast::{
+    Argument, Ast, BinaryOp, DocumentBlock, Expression, InterpPart, StyleBlock, TemplateBlock,
+    UnaryOp,
+};
+use crate::diagnostics::{Diagnostic, Span};
+use crate::lexer::{self, TokenKind, TokenStream};
 
-use crate::ast::{Ast, DocumentBlock, Expression, InterpPart, StyleBlock, TemplateBlock};
-use crate::lexer::{TokenKind, TokenStream};
-
-pub fn parse(tokens: TokenStream) -> Ast {
+/// Parse a token stream. Parsing never aborts: every syntax error is recorded
+/// as a diagnostic and the parser resynchronises at the next block or
+/// statement boundary, so the returned `Ast` holds everything that parsed.
+pub fn parse(tokens: TokenStream) -> (Ast, Vec<Diagnostic>) {
     let p = Parser::new(tokens);
     p.parse()
 }
 
+/// Marker for a syntax error that has already been recorded in
+/// `Parser::diagnostics`
+#[derive(Debug)]
+pub struct ParseError;
+
+pub type PResult<T> = Result<T, ParseError>;
+
 pub struct Parser {
     pub toks: TokenStream,
     pub idx: usize,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Byte offset of `toks.source` in the file, non-zero when parsing an
+    /// interpolation body so spans still point into the original file
+    pub offset: usize,
 }
 
 impl Parser {
     fn new(toks: TokenStream) -> Self {
-        Self { toks, idx: 0 }
+        Self {
+            toks,
+            idx: 0,
+            diagnostics: Vec::new(),
+            offset: 0,
+        }
     }
 
-    fn parse(mut self) -> Ast {
+    fn parse(mut self) -> (Ast, Vec<Diagnostic>) {
         // high level pass
 
         let mut template = None;
         let mut document = None;
         let mut style = None;
 
-        while self.idx < self.toks.kinds.len() {
+        while !self.at_eof() {
             match self.current_token_kind() {
                 TokenKind::Template => {
-                    self.expect(TokenKind::Template);
-                    self.expect(TokenKind::LeftBrace);
+                    self.advance();
+                    if self.expect(TokenKind::LeftBrace).is_err() {
+                        self.synchronize_top_level();
+                        continue;
+                    }
                     let template_block = self.parse_template_block();
                     template = Some(TemplateBlock {
                         statements: template_block,
                     });
                 }
                 TokenKind::Document => {
-                    self.expect(TokenKind::Document);
-                    self.expect(TokenKind::LeftBrace);
+                    self.advance();
+                    if self.expect(TokenKind::LeftBrace).is_err() {
+                        self.synchronize_top_level();
+                        continue;
+                    }
                     let document_block = self.parse_document_block();
                     document = Some(DocumentBlock {
                         elements: document_block,
                     });
                 }
                 TokenKind::Style => {
-                    self.expect(TokenKind::Style);
-                    self.expect(TokenKind::LeftBrace);
+                    self.advance();
+                    if self.expect(TokenKind::LeftBrace).is_err() {
+                        self.synchronize_top_level();
+                        continue;
+                    }
                     let style_block = self.parse_style_block();
                     style = Some(StyleBlock {
                         statements: style_block,
                     });
                 }
-                TokenKind::Eof => break,
-                _ => panic!(
-                    "Parse error: unexpected token at top level (can only be Template, Document, Style at top level). Found: {:?} at {}:{}",
-                    self.current_token_kind(),
-                    self.current_token_line(),
-                    self.current_token_col()
-                ),
+                _ => {
+                    self.error_at_current(format!(
+                        "unexpected token at top level (can only be Template, Document, Style at top level). Found: {:?}",
+                        self.current_token_kind()
+                    ));
+                    self.synchronize_top_level();
+                }
             }
         }
 
-        Ast {
+        let ast = Ast {
             template,
             document,
             style,
+        };
+        (ast, self.diagnostics)
+    }
+
+    /// Skip to the next `template`, `document` or `style` keyword outside of
+    /// any block
+    fn synchronize_top_level(&mut self) {
+        let start = self.idx;
+        loop {
+            self.synchronize(start, |p| {
+                matches!(
+                    p.current_token_kind(),
+                    TokenKind::Template | TokenKind::Document | TokenKind::Style
+                )
+            });
+            // a stray `}` at top level does not close anything, skip it too
+            if !self.match_kind(TokenKind::RightBrace) {
+                break;
+            }
         }
     }
 
-    pub fn parse_expression(&mut self) -> Expression {
-        match self.current_token_kind() {
-            // TODO handle binary operators (eventually)
-            TokenKind::Minus => {
-                self.advance();
-                let right = self.parse_expression();
-                Expression::Unary {
-                    operator: crate::ast::UnaryOp::Negate,
-                    expression: Box::new(right),
-                }
+    pub fn parse_expression(&mut self) -> PResult<Expression> {
+        self.parse_binary_expr(1)
+    }
+
+    /// Precedence climbing: parse operands joined by operators that bind at
+    /// least as tightly as `min_precedence`. All binary operators are left
+    /// associative, so `a - b - c` is `(a - b) - c`.
+    fn parse_binary_expr(&mut self, min_precedence: u8) -> PResult<Expression> {
+        let mut left = self.parse_unary_expr()?;
+        while let Some((operator, precedence)) = binary_operator(self.current_token_kind()) {
+            if precedence < min_precedence {
+                break;
             }
-            TokenKind::Bang => {
-                self.advance();
-                let right = self.parse_expression();
-                Expression::Unary {
-                    operator: crate::ast::UnaryOp::Not,
-                    expression: Box::new(right),
+            self.advance(); // consume operator
+            let right = self.parse_binary_expr(precedence + 1)?;
+            let span = left.span().to(right.span());
+            left = Expression::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+                span,
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_unary_expr(&mut self) -> PResult<Expression> {
+        let start = self.idx;
+        let operator = match self.current_token_kind() {
+            TokenKind::Minus => UnaryOp::Negate,
+            TokenKind::Bang => UnaryOp::Not,
+            _ => return self.parse_postfix_expr(),
+        };
+        self.advance(); // consume operator
+        let expression = self.parse_unary_expr()?;
+        Ok(Expression::Unary {
+            operator,
+            expression: Box::new(expression),
+            span: self.span_from(start),
+        })
+    }
+
+    /// A primary expression followed by any number of `[index]` and `.name`
+    fn parse_postfix_expr(&mut self) -> PResult<Expression> {
+        let start = self.idx;
+        let mut value = self.parse_primary_expr()?;
+        loop {
+            match self.current_token_kind() {
+                TokenKind::LeftBracket => {
+                    self.advance(); // consume [
+                    let index = self.parse_expression()?;
+                    self.expect(TokenKind::RightBracket)?;
+                    value = Expression::Index {
+                        value: Box::new(value),
+                        index: Box::new(index),
+                        span: self.span_from(start),
+                    };
+                }
+                TokenKind::Dot => {
+                    self.advance(); // consume .
+                    let name = self.parse_word()?;
+                    value = Expression::Field {
+                        value: Box::new(value),
+                        name,
+                        span: self.span_from(start),
+                    };
                 }
+                _ => return Ok(value),
             }
+        }
+    }
+
+    fn parse_primary_expr(&mut self) -> PResult<Expression> {
+        let span = self.current_span();
+        match self.current_token_kind() {
             TokenKind::StringLiteral => {
                 let value = self.current_text();
                 self.advance();
                 // Check if the string contains interpolation patterns
-                self.parse_string_with_interpolation(&value)
+                Ok(self.parse_string_with_interpolation(&value, span))
             }
             TokenKind::Float => {
                 let value = self.current_text();
-                self.advance();
-                Expression::Float(value.parse().unwrap())
+                match value.parse() {
+                    Ok(value) => {
+                        self.advance();
+                        Ok(Expression::Float(value, span))
+                    }
+                    Err(_) => {
+                        Err(self.error_at_current(format!("invalid float literal `{}`", value)))
+                    }
+                }
             }
             TokenKind::Int => {
                 let value = self.current_text();
+                match value.parse() {
+                    Ok(value) => {
+                        self.advance();
+                        Ok(Expression::Int(value, span))
+                    }
+                    Err(_) => Err(self
+                        .error_at_current(format!("integer literal `{}` is out of range", value))),
+                }
+            }
+            TokenKind::True | TokenKind::False => {
+                let value = self.current_token_kind() == TokenKind::True;
                 self.advance();
-                Expression::Int(value.parse().unwrap())
+                Ok(Expression::Bool(value, span))
+            }
+            TokenKind::Hash => self.parse_hex_color(),
+            TokenKind::Identifier => {
+                let start = self.idx;
+                let name = self.current_text();
+                self.advance();
+                if self.current_token_kind() == TokenKind::LeftParen {
+                    let args = self.parse_call_args()?;
+                    if name == "rgb" {
+                        return self.rgb_color(&args, self.span_from(start));
+                    }
+                    return Ok(Expression::Call {
+                        name,
+                        args,
+                        span: self.span_from(start),
+                    });
+                }
+                Ok(Expression::Identifier(name, span))
+            }
+            TokenKind::LeftParen => {
+                self.advance(); // consume (
+                let expression = self.parse_expression()?;
+                self.expect(TokenKind::RightParen)?;
+                Ok(expression)
             }
+            TokenKind::LeftBracket => self.parse_list(),
+            TokenKind::LeftBrace => self.parse_map(),
             TokenKind::Dollarsign => {
                 self.advance(); // first $
-                let expression = self.parse_expression();
-                self.advance(); // other $
-                expression
+                let expression = self.parse_expression()?;
+                self.expect(TokenKind::Dollarsign)?; // other $
+                Ok(expression)
             }
-            TokenKind::Identifier => self.parse_binary_expr(),
-            _ => panic!(
-                "Parse error: unexpected token parsing expression. Found: {:?} at {}:{}",
+            _ => Err(self.error_at_current(format!(
+                "unexpected token parsing expression. Found: {:?}",
+                self.current_token_kind()
+            ))),
+        }
+    }
+
+    /// `(args)` of a call: expressions, each optionally written
+    /// `name = value`
+    pub fn parse_call_args(&mut self) -> PResult<Vec<Argument>> {
+        self.expect(TokenKind::LeftParen)?;
+        let mut args = Vec::new();
+        while self.current_token_kind() != TokenKind::RightParen {
+            if matches!(
                 self.current_token_kind(),
-                self.current_token_line(),
-                self.current_token_col()
-            ),
+                TokenKind::Eof | TokenKind::RightBrace | TokenKind::LeftBrace
+            ) {
+                return Err(self.error_at_current(format!(
+                    "unexpected token in function call arguments. Found: {:?}",
+                    self.current_token_kind()
+                )));
+            }
+            let arg_start = self.idx;
+            let name = if self.current_token_kind() == TokenKind::Identifier
+                && self.peek() == Some(TokenKind::Equals)
+            {
+                let name = self.parse_identifier()?;
+                self.advance(); // consume =
+                Some(name)
+            } else {
+                None
+            };
+            let value = self.parse_expression()?;
+            args.push(Argument {
+                name,
+                value,
+                span: self.span_from(arg_start),
+            });
+            if self.current_token_kind() == TokenKind::Comma {
+                self.advance(); // consume comma
+            } else if self.current_token_kind() != TokenKind::RightParen {
+                return Err(self.error_at_current(format!(
+                    "expected ',' or ')' after argument but found {:?}",
+                    self.current_token_kind()
+                )));
+            }
+        }
+        self.expect(TokenKind::RightParen)?;
+        Ok(args)
+    }
+
+    /// `[a, b, c]`, a trailing comma is allowed
+    fn parse_list(&mut self) -> PResult<Expression> {
+        let start = self.idx;
+        self.expect(TokenKind::LeftBracket)?;
+        let mut items = Vec::new();
+        while !self.match_kind(TokenKind::RightBracket) {
+            items.push(self.parse_expression()?);
+            if !self.match_kind(TokenKind::Comma)
+                && self.current_token_kind() != TokenKind::RightBracket
+            {
+                return Err(self.error_at_current(format!(
+                    "expected ',' or ']' after list item but found {:?}",
+                    self.current_token_kind()
+                )));
+            }
         }
+        Ok(Expression::List(items, self.span_from(start)))
+    }
+
+    /// `{ name: value, ... }`, a key is a name or a string. A trailing comma
+    /// is allowed.
+    fn parse_map(&mut self) -> PResult<Expression> {
+        let start = self.idx;
+        self.expect(TokenKind::LeftBrace)?;
+        match self.parse_map_entries() {
+            Ok(entries) => Ok(Expression::Map(entries, self.span_from(start))),
+            Err(error) => {
+                // skip the rest of the map, so its `}` is not taken for the
+                // end of the enclosing block
+                while !self.at_eof() && !self.match_kind(TokenKind::RightBrace) {
+                    self.skip_token_or_block();
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// The entries of a map up to and including the closing `}`
+    fn parse_map_entries(&mut self) -> PResult<Vec<(String, Expression)>> {
+        let mut entries: Vec<(String, Expression)> = Vec::new();
+        while !self.match_kind(TokenKind::RightBrace) {
+            let key_span = self.current_span();
+            let key = if self.current_token_kind() == TokenKind::StringLiteral {
+                let text = self.current_text();
+                self.advance();
+                text[1..text.len() - 1].to_string()
+            } else {
+                self.parse_word()?
+            };
+            // reported, the rest of the map is still parsed
+            if entries.iter().any(|(name, _)| *name == key) {
+                self.error_at(format!("duplicate key `{}` in map", key), key_span);
+            }
+            self.expect(TokenKind::Colon)?;
+            entries.push((key, self.parse_expression()?));
+            if !self.match_kind(TokenKind::Comma)
+                && self.current_token_kind() != TokenKind::RightBrace
+            {
+                return Err(self.error_at_current(format!(
+                    "expected ',' or '}}' after map entry but found {:?}",
+                    self.current_token_kind()
+                )));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Whether the `{` at the current token opens a map, `{ name: ...`,
+    /// rather than a block
+    pub fn at_map(&self) -> bool {
+        self.current_token_kind() == TokenKind::LeftBrace
+            && self.toks.kinds.get(self.idx + 2) == Some(&TokenKind::Colon)
+    }
+
+    /// `#` and the hex digits right after it, `#fff` or `#1e90ff`. The lexer
+    /// splits the digits into numbers and words, so the tokens touching the
+    /// `#` are joined.
+    fn parse_hex_color(&mut self) -> PResult<Expression> {
+        let start = self.idx;
+        self.advance(); // consume #
+        let mut color = String::from("#");
+        while matches!(
+            self.current_token_kind(),
+            TokenKind::Int | TokenKind::Identifier
+        ) && self.current_span().start == self.token_span(self.idx - 1).end
+        {
+            color.push_str(&self.current_text());
+            self.advance();
+        }
+        let span = self.span_from(start);
+        let digits = &color[1..];
+        if !matches!(digits.len(), 3 | 6) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            self.diagnostics.push(
+                Diagnostic::error(format!("invalid colour `{}`", color), span)
+                    .with_help("write a colour as `#rgb` or `#rrggbb`"),
+            );
+            return Err(ParseError);
+        }
+        Ok(Expression::Color(color, span))
+    }
+
+    /// `rgb(r, g, b)` with each channel a whole number from 0 to 255
+    fn rgb_color(&mut self, args: &[Argument], span: Span) -> PResult<Expression> {
+        let channels: Option<Vec<i64>> = args
+            .iter()
+            .map(|arg| match arg.value.as_number() {
+                Some(channel) if arg.name.is_none() && (0..=255).contains(&channel) => {
+                    Some(channel)
+                }
+                _ => None,
+            })
+            .collect();
+        let Some(&[r, g, b]) = channels.as_deref() else {
+            self.diagnostics.push(
+                Diagnostic::error("invalid colour `rgb(...)`", span).with_help(
+                    "`rgb` takes three whole numbers from 0 to 255: `rgb(30, 144, 255)`",
+                ),
+            );
+            return Err(ParseError);
+        };
+        Ok(Expression::Color(format!("rgb({}, {}, {})", r, g, b), span))
     }
 
-    fn parse_string_with_interpolation(&self, s: &str) -> Expression {
+    /// Split a string literal into text and `{expression}` parts. Each
+    /// expression body is lexed and parsed on its own, exactly like an
+    /// expression anywhere else, with spans pointing into the literal.
+    /// `{{` and `}}` (or `\{` and `\}`) are literal braces.
+    fn parse_string_with_interpolation(&mut self, s: &str, span: Span) -> Expression {
         // Strip surrounding quotes if present
-        let content = if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
-            &s[1..s.len() - 1]
+        let (content, content_start) = if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+            (&s[1..s.len() - 1], span.start + 1)
         } else {
-            s
+            (s, span.start)
         };
 
         let mut parts = Vec::new();
-        let mut chars = content.chars().peekable();
+        let mut chars = content.char_indices().peekable();
         let mut current_text = String::new();
 
-        while let Some(ch) = chars.next() {
+        while let Some((pos, ch)) = chars.next() {
             if ch == '{' {
-                if chars.peek() == Some(&'{') {
+                if chars.peek().map(|&(_, c)| c) == Some('{') {
                     chars.next();
                     current_text.push('{');
                     continue;
@@ -144,38 +461,42 @@ impl Parser {
                     current_text.clear();
                 }
 
-                let mut expr_str = String::new();
+                let body_start = pos + 1;
+                let mut body_end = None;
                 let mut brace_depth = 1;
-
-                while let Some(ch) = chars.next() {
+                for (pos, ch) in chars.by_ref() {
                     if ch == '{' {
                         brace_depth += 1;
-                        expr_str.push(ch);
                     } else if ch == '}' {
                         brace_depth -= 1;
                         if brace_depth == 0 {
+                            body_end = Some(pos);
                             break;
-                        } else {
-                            expr_str.push(ch);
                         }
-                    } else {
-                        expr_str.push(ch);
                     }
                 }
 
-                let expr = self.parse_expression_from_str(&expr_str.trim());
-                parts.push(InterpPart::Expression(expr));
+                let open = Span::new(content_start + pos, content_start + pos + 1);
+                let Some(body_end) = body_end else {
+                    self.diagnostics.push(
+                        Diagnostic::error("unclosed `{` in string", open)
+                            .with_help("write `{{` for a literal brace"),
+                    );
+                    break;
+                };
+                let body = &content[body_start..body_end];
+                if let Some(expr) = self.parse_interpolation(body, content_start + body_start, open)
+                {
+                    parts.push(InterpPart::Expression(expr));
+                }
             } else if ch == '}' {
-                if chars.peek() == Some(&'}') {
+                if chars.peek().map(|&(_, c)| c) == Some('}') {
                     chars.next(); // consume second }
-                    current_text.push('}');
-                } else {
-                    // Single } is just added to text (or could be an error)
-                    current_text.push(ch);
                 }
+                current_text.push('}');
             } else if ch == '\\' {
                 // Handle escape sequences
-                if let Some(next_ch) = chars.next() {
+                if let Some((_, next_ch)) = chars.next() {
                     match next_ch {
                         'n' => current_text.push('\n'),
                         't' => current_text.push('\t'),
@@ -204,92 +525,76 @@ impl Parser {
         }
 
         if parts.is_empty() {
-            Expression::StringLiteral(String::new())
+            Expression::StringLiteral(String::new(), span)
         } else if parts.len() == 1 {
             match &parts[0] {
-                InterpPart::Text(text) => Expression::StringLiteral(text.clone()),
-                InterpPart::Expression(_) => Expression::InterpolatedString(parts),
+                InterpPart::Text(text) => Expression::StringLiteral(text.clone(), span),
+                InterpPart::Expression(_) => Expression::InterpolatedString(parts, span),
             }
         } else {
-            Expression::InterpolatedString(parts)
+            Expression::InterpolatedString(parts, span)
         }
     }
 
-    fn parse_expression_from_str(&self, expr_str: &str) -> Expression {
-        let trimmed = expr_str.trim();
-        if trimmed.is_empty() {
-            return Expression::StringLiteral(String::new());
-        }
-        if let Ok(n) = trimmed.parse::<i64>() {
-            return Expression::Int(n);
-        }
-        if let Ok(f) = trimmed.parse::<f64>() {
-            return Expression::Float(f);
+    /// Parse the body of one `{...}` with a fresh parser whose spans are
+    /// shifted to `offset`. Errors are added to this parser's diagnostics.
+    pub fn parse_interpolation(
+        &mut self,
+        body: &str,
+        offset: usize,
+        open: Span,
+    ) -> Option<Expression> {
+        if body.trim().is_empty() {
+            self.diagnostics.push(
+                Diagnostic::error("empty interpolation `{}`", open)
+                    .with_help("write `{{}}` for literal braces"),
+            );
+            return None;
         }
 
-        for (op_pos, op_char) in trimmed.chars().enumerate() {
-            match op_char {
-                '+' | '-' | '*' | '/' | '=' => {
-                    let left = &trimmed[..op_pos];
-                    let right = &trimmed[op_pos + 1..];
-                    let operator = match op_char {
-                        '+' => crate::ast::BinaryOp::Add,
-                        '-' => crate::ast::BinaryOp::Subtract,
-                        '*' => crate::ast::BinaryOp::Multiply,
-                        '/' => crate::ast::BinaryOp::Divide,
-                        '=' => crate::ast::BinaryOp::Equals,
-                        _ => unreachable!(),
-                    };
-                    return Expression::Binary {
-                        left: Box::new(self.parse_expression_from_str(left.trim())),
-                        operator,
-                        right: Box::new(self.parse_expression_from_str(right.trim())),
-                    };
-                }
-                _ => {}
-            }
+        let tokens = lexer::lex(body);
+        if !tokens.errors.is_empty() {
+            self.diagnostics
+                .extend(tokens.errors.into_iter().map(|mut error| {
+                    error.span = Span::new(error.span.start + offset, error.span.end + offset);
+                    error
+                }));
+            return None;
         }
 
-        Expression::Identifier(trimmed.to_string())
-    }
-
-    fn parse_binary_expr(&mut self) -> Expression {
-        let left = match self.current_token_kind() {
-            TokenKind::Identifier => {
-                let name = self.current_text();
-                self.advance();
-                Expression::Identifier(name)
-            }
-            _ => panic!(
-                "Parse error: unexpected token in binary expression {:?} at {}:{}",
-                self.current_token_kind(),
-                self.current_token_line(),
-                self.current_token_col()
-            ),
-        };
-
-        while let TokenKind::Plus
-        | TokenKind::Minus
-        | TokenKind::Star
-        | TokenKind::Slash
-        | TokenKind::Equals = self.current_token_kind()
-        {
-            let operator = match self.current_token_kind() {
-                TokenKind::Plus => crate::ast::BinaryOp::Add,
-                TokenKind::Minus => crate::ast::BinaryOp::Subtract,
-                TokenKind::Star => crate::ast::BinaryOp::Multiply,
-                TokenKind::Slash => crate::ast::BinaryOp::Divide,
-                TokenKind::Equals => crate::ast::BinaryOp::Equals,
-                _ => unreachable!(),
-            };
-            self.advance(); // consume operator
-            let right = self.parse_expression();
-            return Expression::Binary {
-                left: Box::new(left),
-                operator,
-                right: Box::new(right),
-            };
+        let mut parser = Parser::new(tokens);
+        parser.offset = offset;
+        let expr = parser.parse_expression().ok();
+        if expr.is_some() && !parser.at_eof() {
+            parser.error_at_current(format!(
+                "expected `}}` after interpolated expression but found {:?}",
+                parser.current_token_kind()
+            ));
         }
-        left
+        let failed = !parser.diagnostics.is_empty();
+        self.diagnostics.append(&mut parser.diagnostics);
+        if failed { None } else { expr }
     }
 }
+
+/// Operator and precedence for a binary operator token, higher binds tighter
+fn binary_operator(kind: TokenKind) -> Option<(BinaryOp, u8)> {
+    let operator = match kind {
+        TokenKind::OrOr => (BinaryOp::Or, 1),
+        TokenKind::AndAnd => (BinaryOp::And, 2),
+        // a single `=` is still accepted as equality inside expressions
+        TokenKind::EqualsEquals | TokenKind::Equals => (BinaryOp::Equals, 3),
+        TokenKind::BangEquals => (BinaryOp::NotEquals, 3),
+        TokenKind::Less => (BinaryOp::Less, 4),
+        TokenKind::LessEquals => (BinaryOp::LessEquals, 4),
+        TokenKind::Greater => (BinaryOp::Greater, 4),
+        TokenKind::GreaterEquals => (BinaryOp::GreaterEquals, 4),
+        TokenKind::Plus => (BinaryOp::Add, 5),
+        TokenKind::Minus => (BinaryOp::Subtract, 5),
+        TokenKind::Star => (BinaryOp::Multiply, 6),
+        TokenKind::Slash => (BinaryOp::Divide, 6),
+        TokenKind::Percent => (BinaryOp::Mod, 6),
+        _ => return None,
+    };
+    Some(operator)
+}
@@ -1,218 +1,777 @@
 use std::collections::HashMap;
-use std::panic;
 
-use crate::ast::{ArgType, DocElement, Expression};
+use crate::ast::{DocElement, Emphasis, Expression, Inline, InterpPart};
+use crate::diagnostics::{Diagnostic, Span};
 use crate::lexer::TokenKind;
-use crate::parser::parser::Parser;
+use crate::markdown;
+use crate::parser::parser::{PResult, Parser};
 
 impl Parser {
     pub fn parse_document_block(&mut self) -> Vec<DocElement> {
         let mut elements: Vec<DocElement> = Vec::new();
-        while self.idx < self.toks.kinds.len() {
+        loop {
             match self.current_token_kind() {
                 TokenKind::RightBrace => {
                     self.advance(); // exit block
                     break;
                 }
-                TokenKind::Eof => break,
+                TokenKind::Eof => {
+                    self.expect_block_end("document");
+                    break;
+                }
                 _ => {
-                    let statement = self.parse_document_element();
-                    elements.push(statement);
+                    let start = self.idx;
+                    match self.parse_document_element() {
+                        Ok(element) => elements.push(element),
+                        Err(_) => self.synchronize(start, Self::at_element_start),
+                    }
                 }
             }
         }
         elements
     }
 
+    /// Tokens that can begin a new document element, used to recover after an error
+    fn at_element_start(&self) -> bool {
+        match self.current_token_kind() {
+            TokenKind::Text
+            | TokenKind::List
+            | TokenKind::Section
+            | TokenKind::Image
+            | TokenKind::Link
+            | TokenKind::Table
+            | TokenKind::Code
+            | TokenKind::Markdown
+            | TokenKind::If
+            | TokenKind::For => true,
+            TokenKind::Identifier => self.peek() == Some(TokenKind::LeftParen),
+            _ => false,
+        }
+    }
+
     // TODO handle text formatting properly
     // TODO handle markdown formatting properly (bold, italics, etc.)
     // TODO handle code snippets properly
-    pub fn parse_document_element(&mut self) -> DocElement {
+    pub fn parse_document_element(&mut self) -> PResult<DocElement> {
+        let start = self.idx;
         match self.current_token_kind() {
             TokenKind::Text => {
                 self.advance(); // consume text label
-                let attributes = self.parse_style_attributes();
-                self.expect(TokenKind::LeftBrace);
-                let text_content = self.parse_document_text_content();
-                self.expect(TokenKind::RightBrace);
-                DocElement::Text {
-                    content: text_content,
+                let attributes = self.parse_style_attributes()?;
+                self.expect(TokenKind::LeftBrace)?;
+                let inlines = self.parse_inline_content()?;
+                self.expect(TokenKind::RightBrace)?;
+                Ok(DocElement::Text {
+                    content: Inline::plain_text(&inlines),
+                    inlines,
                     attributes,
-                }
+                    span: self.span_from(start),
+                })
             }
             TokenKind::List => {
                 self.advance(); // consume list label
-                let attributes = self.parse_style_attributes();
-                self.expect(TokenKind::LeftBrace);
+                let attributes = self.parse_style_attributes()?;
+                self.expect(TokenKind::LeftBrace)?;
                 let list_items = self.parse_document_list();
-                self.expect(TokenKind::RightBrace);
-                DocElement::List {
+                self.expect(TokenKind::RightBrace)?;
+                Ok(DocElement::List {
                     items: list_items,
                     attributes,
-                }
+                    span: self.span_from(start),
+                })
             }
             TokenKind::Image => {
-                todo!("Image support not implemented");
+                self.advance(); // consume image label
+                let mut attributes = self.parse_style_attributes()?;
+                let span = self.span_from(start);
+                let src = match attributes.remove("src") {
+                    Some(Expression::StringLiteral(src, _)) => src,
+                    Some(other) => {
+                        return Err(self.error_at("image `src` must be a string", other.span()));
+                    }
+                    None => {
+                        return Err(self.error_at("image is missing a `src` attribute", span));
+                    }
+                };
+                Ok(DocElement::Image {
+                    src,
+                    attributes,
+                    span,
+                })
             }
             TokenKind::Link => {
-                todo!("Link support not implemented");
+                self.advance(); // consume link label
+                let mut attributes = self.parse_style_attributes()?;
+                let href = match attributes.remove("href") {
+                    Some(Expression::StringLiteral(href, _)) => href,
+                    Some(other) => {
+                        return Err(self.error_at("link `href` must be a string", other.span()));
+                    }
+                    None => {
+                        let span = self.span_from(start);
+                        return Err(self.error_at("link is missing an `href` attribute", span));
+                    }
+                };
+                // without a body the link shows its target
+                let content = if self.match_kind(TokenKind::LeftBrace) {
+                    let content = self.parse_document_text_content();
+                    self.expect(TokenKind::RightBrace)?;
+                    content
+                } else {
+                    InterpPart::literal(href.clone())
+                };
+                Ok(DocElement::Link {
+                    href,
+                    content,
+                    attributes,
+                    span: self.span_from(start),
+                })
             }
             TokenKind::Table => {
-                todo!("Table support not implemented");
+                self.advance(); // consume table label
+                let attributes = self.parse_style_attributes()?;
+                self.expect(TokenKind::LeftBrace)?;
+                let rows = self.parse_table_rows();
+                self.expect(TokenKind::RightBrace)?;
+                Ok(DocElement::Table {
+                    rows,
+                    attributes,
+                    span: self.span_from(start),
+                })
+            }
+            TokenKind::Code => {
+                self.advance(); // consume code label
+                let attributes = self.parse_style_attributes()?;
+                let content = self.parse_raw_block("code")?;
+                Ok(DocElement::Code {
+                    content,
+                    attributes,
+                    span: self.span_from(start),
+                })
+            }
+            TokenKind::Markdown => {
+                self.advance(); // consume markdown label
+                let attributes = self.parse_style_attributes()?;
+                let markdown = self.parse_raw_block("markdown")?;
+                // the converted elements are grouped in a section carrying
+                // the attributes of the markdown element
+                let span = self.span_from(start);
+                Ok(DocElement::Section {
+                    elements: markdown::to_elements(&markdown, span),
+                    attributes,
+                    span,
+                })
             }
             TokenKind::Section => {
                 self.advance(); // consume section label
-                let attributes = self.parse_style_attributes();
-                self.expect(TokenKind::LeftBrace);
+                let attributes = self.parse_style_attributes()?;
+                self.expect(TokenKind::LeftBrace)?;
                 let section_content = self.parse_document_block();
-                DocElement::Section {
+                Ok(DocElement::Section {
                     elements: section_content,
                     attributes,
-                }
+                    span: self.span_from(start),
+                })
             }
             TokenKind::Identifier => {
                 // function call
                 self.parse_document_function_call()
             }
-            _ => {
-                panic!(
-                    "Parse error: unexpected token while parsing document element at {}:{}",
-                    self.current_token_line(),
-                    self.current_token_col()
-                )
-            }
+            TokenKind::If => self.parse_document_if(Self::parse_document_block),
+            TokenKind::For => self.parse_document_for(Self::parse_document_block),
+            _ => Err(self.error_at_current(format!(
+                "unexpected token while parsing document element. Found: {:?}",
+                self.current_token_kind()
+            ))),
         }
     }
 
-    fn parse_style_attributes(&mut self) -> HashMap<String, Expression> {
+    /// `if cond { ... } else { ... }` around elements. `parse_body` parses
+    /// the elements of a branch and its closing `}`, so the same syntax
+    /// works for the items of a list.
+    fn parse_document_if(
+        &mut self,
+        parse_body: fn(&mut Self) -> Vec<DocElement>,
+    ) -> PResult<DocElement> {
+        let start = self.idx;
+        self.expect(TokenKind::If)?;
+        let condition = self.parse_expression()?;
+        self.expect(TokenKind::LeftBrace)?;
+        let body = parse_body(self);
+
+        let else_body = if self.match_kind(TokenKind::Else) {
+            if self.current_token_kind() == TokenKind::If {
+                Some(vec![self.parse_document_if(parse_body)?])
+            } else {
+                self.expect(TokenKind::LeftBrace)?;
+                Some(parse_body(self))
+            }
+        } else {
+            None
+        };
+
+        Ok(DocElement::If {
+            condition,
+            body,
+            else_body,
+            span: self.span_from(start),
+        })
+    }
+
+    /// `for x in iterable { ... }` around elements, see `parse_document_if`
+    fn parse_document_for(
+        &mut self,
+        parse_body: fn(&mut Self) -> Vec<DocElement>,
+    ) -> PResult<DocElement> {
+        let start = self.idx;
+        self.expect(TokenKind::For)?;
+        let iterator = self.parse_identifier()?;
+        self.expect(TokenKind::In)?;
+        let iterable = self.parse_iterable()?;
+        self.expect(TokenKind::LeftBrace)?;
+        let body = parse_body(self);
+        Ok(DocElement::For {
+            iterator,
+            iterable,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_style_attributes(&mut self) -> PResult<HashMap<String, Expression>> {
         let mut attributes = HashMap::new();
         if self.current_token_kind() == TokenKind::LeftParen {
             // arg attributes present
             self.advance(); // consume left paren
             while self.current_token_kind() != TokenKind::RightParen {
-                let name = self.current_text();
-                self.advance(); // consume identifier
-                self.expect(TokenKind::Equals);
-                let value = self.parse_expression();
+                let name = self.parse_word()?;
+                self.expect(TokenKind::Equals)?;
+                let value = self.parse_expression()?;
                 attributes.insert(name, value);
                 if self.current_token_kind() == TokenKind::Comma {
                     self.advance(); // consume comma
+                } else if self.current_token_kind() != TokenKind::RightParen {
+                    return Err(self.error_at_current(format!(
+                        "expected ',' or ')' after attribute but found {:?}",
+                        self.current_token_kind()
+                    )));
                 }
             }
-            self.expect(TokenKind::RightParen); // consume right paren
+            self.expect(TokenKind::RightParen)?; // consume right paren
         }
-        attributes
+        Ok(attributes)
     }
 
-    fn parse_document_text_content(&mut self) -> String {
-        let mut content = String::new();
-        while self.current_token_kind() != TokenKind::RightBrace {
-            let text = self.current_text();
-            // Strip quotes from string literals
-            let text = if self.current_token_kind() == TokenKind::StringLiteral {
-                text.trim_matches('"').to_string()
-            } else {
-                text
-            };
-            content.push_str(&text);
+    /// Body of an element that is shown as written, like `code`: a `"""`
+    /// raw string, or a plain string for a single line
+    /// Parse a `{ """...""" }` body. A plain string is accepted too. On a
+    /// bad body the rest of the block is skipped, so recovery resumes after it
+    fn parse_raw_block(&mut self, element: &str) -> PResult<String> {
+        self.expect(TokenKind::LeftBrace)?;
+        let text = self.current_text();
+        let content = match self.current_token_kind() {
+            TokenKind::RawString => raw_string_contents(&text),
+            TokenKind::StringLiteral => unescape(string_contents(&text)),
+            kind => {
+                let error = self.error_at_current(format!(
+                    "expected a `\"\"\"` string in `{}` but found {:?}",
+                    element, kind
+                ));
+                while !matches!(
+                    self.current_token_kind(),
+                    TokenKind::RightBrace | TokenKind::Eof
+                ) {
+                    self.skip_token_or_block();
+                }
+                self.advance();
+                return Err(error);
+            }
+        };
+        self.advance();
+        self.expect(TokenKind::RightBrace)?;
+        Ok(content)
+    }
+
+    /// The body of a text element up to its closing `}`: strings and words
+    /// split on markup, `span(...) { ... }` around parts of the text and
+    /// `link(...)`s inside it
+    fn parse_inline_content(&mut self) -> PResult<Vec<Inline>> {
+        let mut inlines = Vec::new();
+        let mut plain = Vec::new();
+        while !matches!(
+            self.current_token_kind(),
+            TokenKind::RightBrace | TokenKind::Eof
+        ) {
+            if self.current_token_kind() == TokenKind::Link && self.at_inline_element() {
+                inlines.extend(split_markup(&std::mem::take(&mut plain)));
+                if let DocElement::Link {
+                    href,
+                    content,
+                    attributes,
+                    span,
+                } = self.parse_document_element()?
+                {
+                    inlines.push(Inline::Link {
+                        href,
+                        content,
+                        attributes,
+                        span,
+                    });
+                }
+                continue;
+            }
+            if self.at_inline_element() {
+                inlines.extend(split_markup(&std::mem::take(&mut plain)));
+                let start = self.idx;
+                self.advance(); // consume span label
+                let attributes = self.parse_style_attributes()?;
+                self.expect(TokenKind::LeftBrace)?;
+                let content = self.parse_inline_content()?;
+                self.expect(TokenKind::RightBrace)?;
+                inlines.push(Inline::Span {
+                    content,
+                    attributes,
+                    span: self.span_from(start),
+                });
+                continue;
+            }
+            let text = self.current_text_items();
+            plain.extend(text);
             self.advance();
-            // Add a space after each token (except before the closing brace)
-            if self.current_token_kind() != TokenKind::RightBrace {
-                content.push(' ');
+            // words are separated by a space, spans bring their own spacing
+            if self.current_token_kind() != TokenKind::RightBrace && !self.at_inline_element() {
+                plain.push(TextItem::Char(' ', false));
             }
         }
-        content
+        inlines.extend(split_markup(&plain));
+        Ok(inlines)
     }
 
-    fn parse_document_list(&mut self) -> Vec<DocElement> {
+    /// The characters and `{expression}` parts of the current string or
+    /// word of a text body
+    fn current_text_items(&mut self) -> Vec<TextItem> {
+        let text = self.current_text();
+        let start = self.current_span().start;
+        if self.current_token_kind() == TokenKind::StringLiteral {
+            self.text_items(string_contents(&text), start + 1)
+        } else {
+            self.text_items(&text, start)
+        }
+    }
+
+    /// Characters of document text found at byte `start` of the file, each
+    /// with whether it was escaped, and its `{expression}` parts parsed like
+    /// those of a string literal. `\\`, `\"`, the markup markers and braces
+    /// can be escaped, any other `\` is kept as written. `{{` and `}}` are
+    /// literal braces too.
+    fn text_items(&mut self, text: &str, start: usize) -> Vec<TextItem> {
         let mut items = Vec::new();
-        while self.current_token_kind() != TokenKind::RightBrace {
-            match self.current_token_kind() {
-                TokenKind::Identifier => {
-                    if self.current_text() == "item" {
-                        self.advance(); // consume item label
-                        let attributes = self.parse_style_attributes();
-                        self.expect(TokenKind::LeftBrace);
-                        let content = self.parse_document_text_content();
-                        self.expect(TokenKind::RightBrace);
-                        items.push(DocElement::Text {
-                            content,
-                            attributes,
-                        });
-                    } else {
-                        panic!(
-                            "Parse error: expected 'item' at {}:{}",
-                            self.current_token_line(),
-                            self.current_token_col()
+        let mut rest = text.char_indices().peekable();
+        while let Some((pos, c)) = rest.next() {
+            match (c, rest.peek().map(|&(_, next)| next)) {
+                ('\\', Some(next @ ('\\' | '"' | '*' | '_' | '`' | '{' | '}'))) => {
+                    items.push(TextItem::Char(next, true));
+                    rest.next();
+                }
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    items.push(TextItem::Char(c, true));
+                    rest.next();
+                }
+                ('{', _) => {
+                    let open = Span::new(start + pos, start + pos + 1);
+                    let mut depth = 1;
+                    let close = rest.by_ref().find_map(|(pos, c)| {
+                        match c {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                        (depth == 0).then_some(pos)
+                    });
+                    let Some(close) = close else {
+                        self.diagnostics.push(
+                            Diagnostic::error("unclosed `{` in text", open)
+                                .with_help("write `{{` for a literal brace"),
                         );
+                        break;
+                    };
+                    let body = &text[pos + 1..close];
+                    if let Some(expr) = self.parse_interpolation(body, start + pos + 1, open) {
+                        items.push(TextItem::Value(expr));
                     }
                 }
-                _ => {
-                    panic!(
-                        "Parse error for document list: expected 'item' at {}:{}",
-                        self.current_token_line(),
-                        self.current_token_col()
-                    );
-                }
+                _ => items.push(TextItem::Char(c, false)),
+            }
+        }
+        items
+    }
+
+    /// `span` is only a label in front of its attributes or body, and
+    /// `link` in front of its attributes, so both can still be used as plain
+    /// words
+    fn at_inline_element(&self) -> bool {
+        match self.current_token_kind() {
+            TokenKind::Identifier => {
+                self.current_text() == "span"
+                    && matches!(
+                        self.peek(),
+                        Some(TokenKind::LeftParen | TokenKind::LeftBrace)
+                    )
+            }
+            TokenKind::Link => self.peek() == Some(TokenKind::LeftParen),
+            _ => false,
+        }
+    }
+
+    fn parse_document_text_content(&mut self) -> Vec<InterpPart> {
+        let mut content = Vec::new();
+        while !matches!(
+            self.current_token_kind(),
+            TokenKind::RightBrace | TokenKind::Eof
+        ) {
+            let text = self.current_text_items();
+            content.extend(text);
+            self.advance();
+            // Add a space after each token (except before the closing brace)
+            if self.current_token_kind() != TokenKind::RightBrace {
+                content.push(TextItem::Char(' ', false));
+            }
+        }
+        text_parts(&content)
+    }
+
+    fn parse_document_list(&mut self) -> Vec<DocElement> {
+        let mut items = Vec::new();
+        while !matches!(
+            self.current_token_kind(),
+            TokenKind::RightBrace | TokenKind::Eof
+        ) {
+            let start = self.idx;
+            match self.parse_document_list_item() {
+                Ok(item) => items.push(item),
+                Err(_) => self.synchronize(start, |p| match p.current_token_kind() {
+                    TokenKind::If | TokenKind::For | TokenKind::Link | TokenKind::List => true,
+                    TokenKind::Identifier => p.current_text() == "item",
+                    _ => false,
+                }),
             }
         }
         items
     }
 
-    fn parse_document_function_call(&mut self) -> DocElement {
-        if self.toks.kinds.get(self.idx + 1) != Some(&TokenKind::LeftParen) {
+    /// Items of an `if` or `for` inside a list, up to and including the `}`
+    fn parse_document_list_block(&mut self) -> Vec<DocElement> {
+        let items = self.parse_document_list();
+        self.expect_block_end("list");
+        items
+    }
+
+    fn parse_document_list_item(&mut self) -> PResult<DocElement> {
+        match self.current_token_kind() {
+            TokenKind::If => return self.parse_document_if(Self::parse_document_list_block),
+            TokenKind::For => return self.parse_document_for(Self::parse_document_list_block),
+            // an item can be a link as a whole, or a list nested in this one
+            TokenKind::Link | TokenKind::List => return self.parse_document_element(),
+            _ => {}
+        }
+        if self.current_token_kind() != TokenKind::Identifier || self.current_text() != "item" {
+            return Err(self.error_at_current(format!(
+                "expected 'item', 'link' or 'list' in list but found {:?}",
+                self.current_token_kind()
+            )));
+        }
+        let start = self.idx;
+        self.advance(); // consume item label
+        let attributes = self.parse_style_attributes()?;
+        self.expect(TokenKind::LeftBrace)?;
+        let inlines = self.parse_inline_content()?;
+        self.expect(TokenKind::RightBrace)?;
+        Ok(DocElement::Text {
+            content: Inline::plain_text(&inlines),
+            inlines,
+            attributes,
+            span: self.span_from(start),
+        })
+    }
+
+    /// `row { ... }` and `header { ... }` up to the closing `}` of the table
+    fn parse_table_rows(&mut self) -> Vec<DocElement> {
+        let mut rows = Vec::new();
+        while !matches!(
+            self.current_token_kind(),
+            TokenKind::RightBrace | TokenKind::Eof
+        ) {
+            let start = self.idx;
+            match self.parse_table_row() {
+                Ok(row) => rows.push(row),
+                Err(_) => self.synchronize(start, |p| match p.current_token_kind() {
+                    TokenKind::If | TokenKind::For => true,
+                    TokenKind::Identifier => matches!(p.current_text().as_str(), "row" | "header"),
+                    _ => false,
+                }),
+            }
+        }
+        rows
+    }
+
+    /// Rows of an `if` or `for` inside a table, up to and including the `}`
+    fn parse_table_block(&mut self) -> Vec<DocElement> {
+        let rows = self.parse_table_rows();
+        self.expect_block_end("table");
+        rows
+    }
+
+    fn parse_table_row(&mut self) -> PResult<DocElement> {
+        let header = match self.current_token_kind() {
+            TokenKind::If => return self.parse_document_if(Self::parse_table_block),
+            TokenKind::For => return self.parse_document_for(Self::parse_table_block),
+            TokenKind::Identifier if self.current_text() == "row" => false,
+            TokenKind::Identifier if self.current_text() == "header" => true,
+            _ => {
+                return Err(self.error_at_current(format!(
+                    "expected 'row' or 'header' in table but found {:?}",
+                    self.current_token_kind()
+                )));
+            }
+        };
+        let start = self.idx;
+        self.advance(); // consume row label
+        self.expect(TokenKind::LeftBrace)?;
+        let cells = self.parse_table_cells();
+        self.expect(TokenKind::RightBrace)?;
+        Ok(DocElement::TableRow {
+            header,
+            cells,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_table_cells(&mut self) -> Vec<DocElement> {
+        let mut cells = Vec::new();
+        while !matches!(
+            self.current_token_kind(),
+            TokenKind::RightBrace | TokenKind::Eof
+        ) {
+            let start = self.idx;
+            match self.parse_table_cell() {
+                Ok(cell) => cells.push(cell),
+                Err(_) => self.synchronize(start, |p| match p.current_token_kind() {
+                    TokenKind::If | TokenKind::For => true,
+                    TokenKind::Identifier => p.current_text() == "cell",
+                    _ => false,
+                }),
+            }
+        }
+        cells
+    }
+
+    /// Cells of an `if` or `for` inside a row, up to and including the `}`
+    fn parse_table_row_block(&mut self) -> Vec<DocElement> {
+        let cells = self.parse_table_cells();
+        self.expect_block_end("row");
+        cells
+    }
+
+    fn parse_table_cell(&mut self) -> PResult<DocElement> {
+        match self.current_token_kind() {
+            TokenKind::If => return self.parse_document_if(Self::parse_table_row_block),
+            TokenKind::For => return self.parse_document_for(Self::parse_table_row_block),
+            _ => {}
+        }
+        if self.current_token_kind() != TokenKind::Identifier || self.current_text() != "cell" {
+            return Err(self.error_at_current(format!(
+                "expected 'cell' in table row but found {:?}",
+                self.current_token_kind()
+            )));
+        }
+        let start = self.idx;
+        self.advance(); // consume cell label
+        let mut attributes = self.parse_style_attributes()?;
+        let colspan = self.parse_cell_span(&mut attributes, "colspan")?;
+        let rowspan = self.parse_cell_span(&mut attributes, "rowspan")?;
+        self.expect(TokenKind::LeftBrace)?;
+        let content = self.parse_document_text_content();
+        self.expect(TokenKind::RightBrace)?;
+        Ok(DocElement::TableCell {
+            content,
+            colspan,
+            rowspan,
+            attributes,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Take `colspan` or `rowspan` out of a cell's attributes
+    fn parse_cell_span(
+        &mut self,
+        attributes: &mut HashMap<String, Expression>,
+        name: &str,
+    ) -> PResult<usize> {
+        match attributes.remove(name) {
+            None => Ok(1),
+            Some(Expression::Int(count, _)) if count >= 1 => Ok(count as usize),
+            Some(other) => Err(self.error_at(
+                format!("cell `{}` must be a whole number of at least 1", name),
+                other.span(),
+            )),
+        }
+    }
+
+    fn parse_document_function_call(&mut self) -> PResult<DocElement> {
+        if self.peek() != Some(TokenKind::LeftParen) {
             // check if it is a function call
-            panic!(
-                "Parse error: expected '(' after function name at {}:{}",
-                self.current_token_line(),
-                self.current_token_col()
-            );
+            return Err(self.error_at_current(format!(
+                "expected '(' after function name `{}`",
+                self.current_text()
+            )));
         }
         // function call
+        let start = self.idx;
         let func_name = self.current_text();
         self.advance(); // consume function name
-        self.expect(TokenKind::LeftParen);
-        let mut args: Vec<ArgType> = Vec::new();
-        while self.current_token_kind() != TokenKind::RightParen {
-            let name = self.current_text();
-            // TODO, bad form but woirking for right now
-            let ty;
-            if name.starts_with('"') {
-                ty = "string";
-            } else if let Ok(_) = name.parse::<i32>() {
-                ty = "int";
-            } else if let Ok(_) = name.parse::<f64>() {
-                ty = "float";
-            } else {
-                ty = "var";
+        let args = self.parse_call_args()?;
+        Ok(DocElement::Call {
+            name: func_name,
+            args,
+            span: self.span_from(start),
+        })
+    }
+}
+
+/// A character of document text with whether it was escaped, or an
+/// `{expression}` part
+#[derive(Clone)]
+enum TextItem {
+    Char(char, bool),
+    Value(Expression),
+}
+
+impl TextItem {
+    fn is(&self, check: impl Fn(char) -> bool) -> bool {
+        matches!(self, TextItem::Char(c, _) if check(*c))
+    }
+}
+
+/// Split `*bold*`, `_italic_` and `` `code` `` out of plain text. A `*` or
+/// `_` only opens at the start of a word and closes at the end of one, so
+/// `snake_case` and `2 * 3` are left alone. A marker written with a `\` in
+/// front of it is always plain text.
+fn split_markup(items: &[TextItem]) -> Vec<Inline> {
+    let mut inlines = Vec::new();
+    let mut plain = 0;
+    let mut i = 0;
+    while i < items.len() {
+        let emphasis = match items[i] {
+            TextItem::Char('*', false) => Some(Emphasis::Bold),
+            TextItem::Char('_', false) => Some(Emphasis::Italic),
+            TextItem::Char('`', false) => Some(Emphasis::Code),
+            _ => None,
+        };
+        if let Some(emphasis) = emphasis
+            && let Some(end) = closing_marker(items, i)
+        {
+            if plain < i {
+                inlines.push(Inline::Text(text_parts(&items[plain..i])));
             }
-            self.advance(); // consume arg name
-            if self.current_token_kind() == TokenKind::Comma {
-                args.push(ArgType {
-                    name: name,
-                    ty: ty.to_string(),
-                });
-                self.advance(); // consume comma
-                continue;
-            } else if self.current_token_kind() == TokenKind::RightParen {
-                args.push(ArgType {
-                    name: name,
-                    ty: ty.to_string(),
-                });
-                break;
-            } else {
-                panic!(
-                    "Parse error: unexpected token in function call arguments. Found: {:?} at {}:{}",
-                    self.current_token_kind(),
-                    self.current_token_line(),
-                    self.current_token_col()
-                );
+            inlines.push(Inline::Emphasis(emphasis, text_parts(&items[i + 1..end])));
+            i = end + 1;
+            plain = i;
+            continue;
+        }
+        i += 1;
+    }
+    if plain < items.len() {
+        inlines.push(Inline::Text(text_parts(&items[plain..])));
+    }
+    inlines
+}
+
+/// Index of the marker closing the one at `open`. Code can hold anything,
+/// emphasis has to hug the words it marks.
+fn closing_marker(items: &[TextItem], open: usize) -> Option<usize> {
+    let TextItem::Char(marker, _) = items[open] else {
+        return None;
+    };
+    let in_word = |i: usize| {
+        items
+            .get(i)
+            .is_some_and(|item| item.is(char::is_alphanumeric))
+    };
+    if marker != '`'
+        && (open.checked_sub(1).is_some_and(in_word)
+            || items
+                .get(open + 1)
+                .is_none_or(|item| item.is(char::is_whitespace)))
+    {
+        return None;
+    }
+    (open + 2..items.len()).find(|&end| {
+        matches!(items[end], TextItem::Char(c, false) if c == marker)
+            && (marker == '`' || !items[end - 1].is(char::is_whitespace) && !in_word(end + 1))
+    })
+}
+
+/// The text and `{expression}` parts of document text, its escapes resolved
+fn text_parts(items: &[TextItem]) -> Vec<InterpPart> {
+    let mut parts = Vec::new();
+    for item in items {
+        match (item, parts.last_mut()) {
+            (TextItem::Char(c, _), Some(InterpPart::Text(text))) => text.push(*c),
+            (TextItem::Char(c, _), _) => parts.push(InterpPart::Text(c.to_string())),
+            (TextItem::Value(expr), _) => parts.push(InterpPart::Expression(expr.clone())),
+        }
+    }
+    parts
+}
+
+/// The text between the quotes of a string token. Only one quote is taken
+/// off each end, so an escaped quote at the end of the text is kept.
+fn string_contents(token: &str) -> &str {
+    let inner = token.strip_prefix('"').unwrap_or(token);
+    inner.strip_suffix('"').unwrap_or(inner)
+}
+
+/// Text of a string shown as written, its escapes resolved. `\\`, `\"`, the
+/// markup markers and braces can be escaped, any other `\` is kept.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text.chars().peekable();
+    while let Some(c) = rest.next() {
+        match rest.peek() {
+            Some(&next)
+                if c == '\\' && matches!(next, '\\' | '"' | '*' | '_' | '`' | '{' | '}') =>
+            {
+                unescaped.push(next);
+                rest.next();
             }
+            _ => unescaped.push(c),
         }
-        self.expect(TokenKind::RightParen);
-        return DocElement::Call {
-            name: func_name,
-            args: args,
-        };
     }
+    unescaped
+}
+
+/// Text of a `"""` raw string token. A line break right after the opening
+/// quotes is dropped, and when the closing quotes are on a line of their own
+/// their indentation is removed from every line, so a raw string can be
+/// indented along with the document around it.
+fn raw_string_contents(token: &str) -> String {
+    let inner = token.strip_prefix("\"\"\"").unwrap_or(token);
+    let inner = inner.strip_suffix("\"\"\"").unwrap_or(inner);
+    let inner = inner
+        .strip_prefix("\r\n")
+        .or_else(|| inner.strip_prefix('\n'))
+        .unwrap_or(inner);
+
+    let Some((body, indent)) = inner.rsplit_once('\n') else {
+        return inner.to_string();
+    };
+    if !indent.chars().all(|c| c == ' ' || c == '\t') {
+        return inner.to_string();
+    }
+    body.strip_suffix('\r')
+        .unwrap_or(body)
+        .split('\n')
+        .map(|line| line.strip_prefix(indent).unwrap_or(line.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
@@ -1,4 +1,4 @@
-use crate::parser::parser::Parser;
+use crate::parser::parser::{PResult, Parser};
 
 use crate::ast::{KeyValue, Selector, StyleRule};
 use crate::lexer::TokenKind;
@@ -6,65 +6,102 @@ use crate::lexer::TokenKind;
 impl Parser {
     pub fn parse_style_block(&mut self) -> Vec<StyleRule> {
         let mut rules: Vec<StyleRule> = Vec::new();
-        while self.idx < self.toks.kinds.len() {
+        loop {
             match self.current_token_kind() {
                 TokenKind::RightBrace => {
                     self.advance(); // exit block
                     break;
                 }
-                TokenKind::Eof => break,
+                TokenKind::Eof => {
+                    self.expect_block_end("style");
+                    break;
+                }
                 _ => {
-                    let statement = self.parse_style_rule();
-                    rules.push(statement);
+                    let start = self.idx;
+                    match self.parse_style_rule() {
+                        Ok(rule) => rules.push(rule),
+                        // a rule without a selector stops at its `{`, skip just the block
+                        Err(_)
+                            if self.idx == start
+                                && self.current_token_kind() == TokenKind::LeftBrace =>
+                        {
+                            self.skip_token_or_block();
+                        }
+                        Err(_) => {
+                            // skip the rest of the broken rule, including its declaration block
+                            self.synchronize(start, |p| {
+                                p.current_token_kind() == TokenKind::LeftBrace
+                            });
+                            if self.current_token_kind() == TokenKind::LeftBrace {
+                                self.skip_token_or_block();
+                            }
+                        }
+                    }
                 }
             }
         }
         rules
     }
-    pub fn parse_style_rule(&mut self) -> StyleRule {
-        let selectors = self.parse_selector_list();
-        let declarations = self.parse_style_declarations();
-        StyleRule::new(selectors, declarations)
+
+    pub fn parse_style_rule(&mut self) -> PResult<StyleRule> {
+        let start = self.idx;
+        let selectors = self.parse_selector_list()?;
+        let declarations = self.parse_style_declarations()?;
+        Ok(StyleRule::new(
+            selectors,
+            declarations,
+            self.span_from(start),
+        ))
     }
 
-    pub fn parse_selector_list(&mut self) -> Vec<Selector> {
+    pub fn parse_selector_list(&mut self) -> PResult<Vec<Selector>> {
         let mut selectors = Vec::new();
-        while self.idx < self.toks.kinds.len() {
+        loop {
             match self.current_token_kind() {
                 TokenKind::Comma => {
                     self.advance(); // skip comma
                 }
+                TokenKind::LeftBrace if selectors.is_empty() => {
+                    return Err(self.error_at_current("expected a selector before `{`"));
+                }
                 TokenKind::LeftBrace => {
                     self.advance(); // exit selector list
                     break;
                 }
-                TokenKind::Eof => break,
-                _ => {
-                    let selector = match self.current_text().as_str() {
-                        "." => {
-                            self.advance();
-                            Selector::Class(self.current_text().to_string())
-                        }
-                        "#" => {
-                            self.advance();
-                            Selector::Id(self.current_text().to_string())
-                        }
-                        _ => {
-                            // TODO: have a check to make sure the type is valid CSS type
-                            Selector::Type(self.current_text().to_string())
-                        }
-                    };
-                    selectors.push(selector);
+                TokenKind::Dot => {
                     self.advance();
+                    selectors.push(Selector::Class(self.parse_dashed_word()?));
+                }
+                TokenKind::Hash => {
+                    self.advance();
+                    selectors.push(Selector::Id(self.parse_dashed_word()?));
+                }
+                TokenKind::Identifier
+                | TokenKind::Text
+                | TokenKind::List
+                | TokenKind::Section
+                | TokenKind::Image
+                | TokenKind::Link
+                | TokenKind::Table
+                | TokenKind::Code => {
+                    // TODO: have a check to make sure the type is valid CSS type
+                    selectors.push(Selector::Type(self.current_text()));
+                    self.advance();
+                }
+                _ => {
+                    return Err(self.error_at_current(format!(
+                        "expected a selector but found {:?}",
+                        self.current_token_kind()
+                    )));
                 }
             }
         }
-        selectors
+        Ok(selectors)
     }
 
-    pub fn parse_style_declarations(&mut self) -> Vec<KeyValue> {
+    pub fn parse_style_declarations(&mut self) -> PResult<Vec<KeyValue>> {
         let mut declarations = Vec::new();
-        while self.idx < self.toks.kinds.len() {
+        loop {
             match self.current_token_kind() {
                 TokenKind::Semicolon => {
                     self.advance(); // skip semicolon
@@ -73,24 +110,35 @@ impl Parser {
                     self.advance(); // exit declaration block
                     break;
                 }
-                TokenKind::Eof => break,
+                TokenKind::Eof => {
+                    self.expect_block_end("style rule");
+                    break;
+                }
                 _ => {
-                    let mut property: String = String::new();
-                    while self.current_token_kind() != TokenKind::Equals {
-                        property.push_str(&self.current_text().to_string());
-                        self.advance();
+                    let start = self.idx;
+                    match self.parse_style_declaration() {
+                        Ok(declaration) => declarations.push(declaration),
+                        // recover at the next `property =` inside the same rule
+                        Err(_) => self.synchronize(start, |p| {
+                            p.at_word()
+                                && matches!(p.peek(), Some(TokenKind::Equals | TokenKind::Minus))
+                        }),
                     }
-                    self.advance(); // skip equals
-                    let value = self.parse_expression();
-                    declarations.push({
-                        KeyValue {
-                            key: property,
-                            value: value,
-                        }
-                    });
                 }
             }
         }
-        declarations
+        Ok(declarations)
+    }
+
+    fn parse_style_declaration(&mut self) -> PResult<KeyValue> {
+        let start = self.idx;
+        let property = self.parse_dashed_word()?;
+        self.expect(TokenKind::Equals)?;
+        let value = self.parse_expression()?;
+        Ok(KeyValue {
+            key: property,
+            value,
+            span: self.span_from(start),
+        })
     }
 }
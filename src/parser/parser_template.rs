@@ -1,163 +1,325 @@
-use crate::ast::Statement;
+use crate::ast::{Expression, Statement};
 use crate::lexer::TokenKind;
-use crate::parser::parser::Parser;
+use crate::parser::parser::{PResult, Parser};
 
 impl Parser {
     pub fn parse_template_block(&mut self) -> Vec<Statement> {
         let mut statements: Vec<Statement> = Vec::new();
-        while self.idx < self.toks.kinds.len() {
+        loop {
             match self.current_token_kind() {
                 TokenKind::RightBrace => {
                     self.advance(); // exit block
                     break;
                 }
-                TokenKind::Func => {
-                    let statement = self.parse_func_decl();
-                    statements.push(statement);
+                TokenKind::Eof => {
+                    self.expect_block_end("template");
+                    break;
                 }
-                TokenKind::Eof => break,
                 _ => {
-                    let statement = self.parse_statement();
-                    statements.push(statement);
+                    let start = self.idx;
+                    let statement = if self.current_token_kind() == TokenKind::Func {
+                        self.parse_func_decl()
+                    } else {
+                        self.parse_statement()
+                    };
+                    match statement {
+                        Ok(statement) => statements.push(statement),
+                        Err(_) => self.synchronize(start, Self::at_statement_start),
+                    }
                 }
             }
         }
         statements
     }
 
-    fn parse_statement(&mut self) -> Statement {
+    /// Tokens that can begin a new statement, used to recover after an error
+    fn at_statement_start(&self) -> bool {
+        match self.current_token_kind() {
+            TokenKind::Let
+            | TokenKind::Const
+            | TokenKind::Var
+            | TokenKind::Func
+            | TokenKind::Return
+            | TokenKind::If
+            | TokenKind::For
+            | TokenKind::While => true,
+            TokenKind::Identifier => self.peek() == Some(TokenKind::Equals),
+            _ => false,
+        }
+    }
+
+    fn parse_statement(&mut self) -> PResult<Statement> {
+        let start = self.idx;
         match self.current_token_kind() {
             TokenKind::Identifier => {
                 let varname = self.current_text();
                 self.advance();
-                self.expect(TokenKind::Equals);
-                let expr = self.parse_expression();
-                Statement::DefaultSet {
+                self.expect(TokenKind::Equals)?;
+                let expr = self.parse_expression()?;
+                Ok(Statement::DefaultSet {
                     key: varname,
                     value: expr,
-                }
+                    span: self.span_from(start),
+                })
             }
             TokenKind::Let => {
                 self.advance();
-                let varname = self.current_text();
-                self.advance();
-                self.expect(TokenKind::Equals);
-                let expr = self.parse_expression();
-                Statement::VarAssign {
+                let varname = self.parse_identifier()?;
+                let ty = self.parse_type_annotation()?;
+                self.expect(TokenKind::Equals)?;
+                let expr = self.parse_expression()?;
+                Ok(Statement::VarAssign {
                     name: varname,
+                    ty,
                     value: expr,
-                }
+                    span: self.span_from(start),
+                })
             }
             TokenKind::Const => {
                 self.advance();
-                let varname = self.current_text();
-                self.advance();
-                self.expect(TokenKind::Equals);
-                let expr = self.parse_expression();
-                Statement::ConstAssign {
+                let varname = self.parse_identifier()?;
+                let ty = self.parse_type_annotation()?;
+                self.expect(TokenKind::Equals)?;
+                let expr = self.parse_expression()?;
+                Ok(Statement::ConstAssign {
                     name: varname,
+                    ty,
                     value: expr,
-                }
+                    span: self.span_from(start),
+                })
             }
             TokenKind::Var => {
                 self.advance();
-                let varname = self.current_text();
-                self.advance();
-                self.expect(TokenKind::Equals);
-                let expr = self.parse_expression();
-                Statement::VarAssign {
+                let varname = self.parse_identifier()?;
+                let ty = self.parse_type_annotation()?;
+                self.expect(TokenKind::Equals)?;
+                let expr = self.parse_expression()?;
+                Ok(Statement::VarAssign {
                     name: varname,
+                    ty,
                     value: expr,
-                }
+                    span: self.span_from(start),
+                })
             }
             TokenKind::Return => {
                 self.advance(); // consume 'return'
                 match self.current_token_kind() {
-                    // TODO add the other types of return types later, for rigt now only returning DocElements
-                    _ => {
-                        let return_value = self.parse_document_element();
-                        Statement::Return {
-                            doc_element: return_value,
-                        }
+                    // a fragment, the elements between the braces
+                    TokenKind::LeftBrace if !self.at_map() => {
+                        self.advance();
+                        return Ok(Statement::Return {
+                            doc_elements: self.parse_document_block(),
+                            span: self.span_from(start),
+                        });
                     }
+                    // nothing, the end of the function's block
+                    TokenKind::RightBrace => {
+                        return Ok(Statement::Return {
+                            doc_elements: Vec::new(),
+                            span: self.span_from(start),
+                        });
+                    }
+                    _ => {}
+                }
+                if !matches!(
+                    self.current_token_kind(),
+                    TokenKind::Text
+                        | TokenKind::List
+                        | TokenKind::Image
+                        | TokenKind::Link
+                        | TokenKind::Table
+                        | TokenKind::Code
+                        | TokenKind::Markdown
+                        | TokenKind::Section
+                ) {
+                    let value = self.parse_expression()?;
+                    return Ok(Statement::ReturnValue {
+                        value,
+                        span: self.span_from(start),
+                    });
                 }
+                let return_value = self.parse_document_element()?;
+                Ok(Statement::Return {
+                    doc_elements: vec![return_value],
+                    span: self.span_from(start),
+                })
+            }
+            TokenKind::If => self.parse_if(),
+            TokenKind::While => {
+                self.advance(); // consume 'while'
+                let condition = self.parse_expression()?;
+                self.expect(TokenKind::LeftBrace)?;
+                let body = self.parse_statement_block("while");
+                Ok(Statement::While {
+                    condition,
+                    body,
+                    span: self.span_from(start),
+                })
+            }
+            TokenKind::For => {
+                self.advance(); // consume 'for'
+                let iterator = self.parse_identifier()?;
+                self.expect(TokenKind::In)?;
+                let iterable = self.parse_iterable()?;
+                self.expect(TokenKind::LeftBrace)?;
+                let body = self.parse_statement_block("for");
+                Ok(Statement::For {
+                    iterator,
+                    iterable,
+                    body,
+                    span: self.span_from(start),
+                })
             }
-            // TODO handle if statements
-            // TODO handle for loops
-            // TODO handle while loops
-            _ => panic!(
-                "Parse error: unexpected token parsing statement. Found: {:?} at {}:{}",
-                self.current_token_kind(),
-                self.current_token_line(),
-                self.current_token_col()
-            ),
+            _ => Err(self.error_at_current(format!(
+                "unexpected token parsing statement. Found: {:?}",
+                self.current_token_kind()
+            ))),
         }
     }
 
-    fn parse_func_decl(&mut self) -> Statement {
-        self.expect(TokenKind::Func);
+    /// `if cond { ... }`, optionally followed by `else { ... }` or `else if`.
+    /// An `else if` is stored as an `else` body holding a single `If`.
+    fn parse_if(&mut self) -> PResult<Statement> {
+        let start = self.idx;
+        self.expect(TokenKind::If)?;
+        let condition = self.parse_expression()?;
+        self.expect(TokenKind::LeftBrace)?;
+        let body = self.parse_statement_block("if");
 
-        self.expect(TokenKind::Identifier);
-        let name = self.toks.source[self.toks.ranges[self.idx - 1].clone()].to_string();
+        let else_body = if self.match_kind(TokenKind::Else) {
+            if self.current_token_kind() == TokenKind::If {
+                Some(vec![self.parse_if()?])
+            } else {
+                self.expect(TokenKind::LeftBrace)?;
+                Some(self.parse_statement_block("else"))
+            }
+        } else {
+            None
+        };
+
+        Ok(Statement::If {
+            condition,
+            body,
+            else_body,
+            span: self.span_from(start),
+        })
+    }
 
-        self.expect(TokenKind::LeftParen);
-        let args = self.parse_args();
+    /// The expression after `in`, either a value or a `start..end` range
+    pub fn parse_iterable(&mut self) -> PResult<Expression> {
+        let start = self.idx;
+        let iterable = self.parse_expression()?;
+        if !self.match_kind(TokenKind::DotDot) {
+            return Ok(iterable);
+        }
+        let end = self.parse_expression()?;
+        Ok(Expression::Range {
+            start: Box::new(iterable),
+            end: Box::new(end),
+            span: self.span_from(start),
+        })
+    }
+
+    pub fn parse_identifier(&mut self) -> PResult<String> {
+        let name = self.current_text();
+        self.expect(TokenKind::Identifier)?;
+        Ok(name)
+    }
+
+    fn parse_func_decl(&mut self) -> PResult<Statement> {
+        let start = self.idx;
+        self.expect(TokenKind::Func)?;
+
+        let name = self.parse_identifier()?;
+
+        self.expect(TokenKind::LeftParen)?;
+        let args = self.parse_args()?;
 
         // Optional return type annotation: -> Type
+        let mut return_type = None;
         if self.current_token_kind() == TokenKind::Minus && self.peek() == Some(TokenKind::Greater)
         {
             self.advance(); // consume -
             self.advance(); // consume >
-            let _return_type = self.current_text(); // consume type name
-            self.advance();
+            return_type = Some(self.parse_identifier()?);
         }
 
-        self.expect(TokenKind::LeftBrace);
-        let body = self.parse_func_decl_body();
+        self.expect(TokenKind::LeftBrace)?;
+        let body = self.parse_statement_block("func");
 
-        Statement::FunctionDecl { name, args, body }
+        Ok(Statement::FunctionDecl {
+            name,
+            args,
+            return_type,
+            body,
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_args(&mut self) -> Vec<crate::ast::FuncParam> {
+    /// The optional `: Type` after the name in `let`, `var` and `const`
+    fn parse_type_annotation(&mut self) -> PResult<Option<String>> {
+        if !self.match_kind(TokenKind::Colon) {
+            return Ok(None);
+        }
+        self.parse_identifier().map(Some)
+    }
+
+    fn parse_args(&mut self) -> PResult<Vec<crate::ast::FuncParam>> {
         let mut params = Vec::new();
         loop {
             // I dont really like the loop keyword, but I like warnings even less
             match self.current_token_kind() {
                 TokenKind::RightParen => break,
                 TokenKind::Identifier => {
-                    let param_name = self.parse_expression();
-                    self.expect(TokenKind::Colon);
-                    let param_type = self.current_text();
-                    self.advance();
+                    let start = self.idx;
+                    let param_name = self.parse_expression()?;
+                    self.expect(TokenKind::Colon)?;
+                    let param_type = self.parse_identifier()?;
+                    let default = if self.match_kind(TokenKind::Equals) {
+                        Some(self.parse_expression()?)
+                    } else {
+                        None
+                    };
                     params.push(crate::ast::FuncParam {
                         ty: param_type,
                         value: param_name,
+                        default,
+                        span: self.span_from(start),
                     });
                     self.match_kind(TokenKind::Comma);
                 }
-                _ => panic!("Expected parameter or ')'"),
+                _ => {
+                    return Err(self.error_at_current(format!(
+                        "expected parameter or ')' but found {:?}",
+                        self.current_token_kind()
+                    )));
+                }
             }
         }
-        self.expect(TokenKind::RightParen);
-        params
+        self.expect(TokenKind::RightParen)?;
+        Ok(params)
     }
 
-    fn parse_func_decl_body(&mut self) -> Vec<Statement> {
+    /// Statements up to and including the `}` closing a function or
+    /// control-flow body. `block` names the construct in errors.
+    fn parse_statement_block(&mut self, block: &str) -> Vec<Statement> {
         let mut statements: Vec<Statement> = Vec::new();
-        while self.idx < self.toks.kinds.len() {
+        loop {
             match self.current_token_kind() {
                 TokenKind::RightBrace => {
-                    self.expect(TokenKind::RightBrace);
+                    self.advance();
+                    break;
+                }
+                TokenKind::Eof => {
+                    self.expect_block_end(block);
                     break;
                 }
-                TokenKind::Eof => panic!(
-                    "Parse error: unexpected end of file while parsing block at {}:{}",
-                    self.current_token_line(),
-                    self.current_token_col()
-                ),
                 _ => {
-                    let statement = self.parse_statement();
-                    statements.push(statement);
+                    let start = self.idx;
+                    match self.parse_statement() {
+                        Ok(statement) => statements.push(statement),
+                        Err(_) => self.synchronize(start, Self::at_statement_start),
+                    }
                 }
             }
         }
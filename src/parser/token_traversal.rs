@@ -1,17 +1,30 @@
+use crate::diagnostics::{Diagnostic, Span};
 use crate::lexer::TokenKind;
-use crate::parser::parser::Parser;
+use crate::parser::parser::{PResult, ParseError, Parser};
 
 impl Parser {
     pub fn current_token_kind(&self) -> TokenKind {
         self.toks.kinds[self.idx]
     }
 
-    pub fn current_token_line(&self) -> u32 {
-        self.toks.lines[self.idx]
+    pub fn current_span(&self) -> Span {
+        self.token_span(self.idx)
     }
 
-    pub fn current_token_col(&self) -> u32 {
-        self.toks.cols[self.idx]
+    pub fn token_span(&self, idx: usize) -> Span {
+        let range = &self.toks.ranges[idx];
+        Span::new(range.start + self.offset, range.end + self.offset)
+    }
+
+    /// Span covering every token from the token at index `start` up to the
+    /// last consumed token
+    pub fn span_from(&self, start: usize) -> Span {
+        let first = self.token_span(start);
+        if self.idx > start {
+            first.to(self.token_span(self.idx - 1))
+        } else {
+            first
+        }
     }
 
     pub fn current_text(&self) -> String {
@@ -19,24 +32,28 @@ impl Parser {
         self.toks.source[range.start..range.end].to_string()
     }
 
+    /// Move to the next token. Never moves past the final `Eof` token.
     pub fn advance(&mut self) -> TokenKind {
-        if self.idx < self.toks.kinds.len() {
+        let kind = self.toks.kinds[self.idx];
+        if self.idx + 1 < self.toks.kinds.len() {
             self.idx += 1;
         }
-        self.toks.kinds[self.idx - 1]
+        kind
     }
 
-    pub fn expect(&mut self, kind: TokenKind) -> TokenKind {
+    pub fn at_eof(&self) -> bool {
+        self.current_token_kind() == TokenKind::Eof
+    }
+
+    pub fn expect(&mut self, kind: TokenKind) -> PResult<TokenKind> {
         if self.current_token_kind() == kind {
-            return self.advance().clone();
+            return Ok(self.advance());
         }
-        panic!(
-            "Parse error: expected {:?} but found {:?} at {}:{}",
+        Err(self.error_at_current(format!(
+            "expected {:?} but found {:?}",
             kind,
-            self.current_token_kind(),
-            self.current_token_line(),
-            self.current_token_col()
-        );
+            self.current_token_kind()
+        )))
     }
 
     pub fn match_kind(&mut self, kind: TokenKind) -> bool {
@@ -55,28 +72,105 @@ impl Parser {
         }
     }
 
-    /// If a block `{ ... }` follows, skip it including nested braces.
-    pub fn skip_optional_block(&mut self) {
-        // skip optional whitespace-free tokens; if next is LeftBrace, skip until matching RightBrace
-        if self.idx < self.toks.kinds.len() && self.current_token_kind() == TokenKind::LeftBrace {
-            let mut depth: i32 = 0;
-            while self.idx < self.toks.kinds.len() {
-                match self.current_token_kind() {
-                    TokenKind::LeftBrace => {
-                        depth += 1;
-                    }
-                    TokenKind::RightBrace => {
-                        depth -= 1;
-                        if depth <= 0 {
-                            self.advance();
-                            break;
-                        }
+    /// Identifier or keyword
+    pub fn at_word(&self) -> bool {
+        self.current_token_kind() != TokenKind::Eof
+            && self
+                .current_text()
+                .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+    }
+
+    /// Consume an identifier or keyword. Used for names where keywords are
+    /// fine, like the `style` attribute or the `text` in `text-align`.
+    pub fn parse_word(&mut self) -> PResult<String> {
+        if !self.at_word() {
+            return Err(self.error_at_current(format!(
+                "expected a name but found {:?}",
+                self.current_token_kind()
+            )));
+        }
+        let word = self.current_text();
+        self.advance();
+        Ok(word)
+    }
+
+    /// A name that may contain dashes, like `font-size` or `code-keyword`
+    pub fn parse_dashed_word(&mut self) -> PResult<String> {
+        let mut name = self.parse_word()?;
+        while self.current_token_kind() == TokenKind::Minus {
+            self.advance();
+            name.push('-');
+            if self.current_token_kind() == TokenKind::Int {
+                name.push_str(&self.current_text());
+                self.advance();
+            } else {
+                name.push_str(&self.parse_word()?);
+            }
+        }
+        Ok(name)
+    }
+
+    /// Record an error pointing at the current token
+    pub fn error_at_current(&mut self, message: impl Into<String>) -> ParseError {
+        let span = self.current_span();
+        self.error_at(message, span)
+    }
+
+    pub fn error_at(&mut self, message: impl Into<String>, span: Span) -> ParseError {
+        self.diagnostics.push(Diagnostic::error(message, span));
+        ParseError
+    }
+
+    /// Skip tokens after an error until the parser is back at a point where it
+    /// can continue: a token accepted by `is_restart` at the current nesting
+    /// level, or the `}` that closes the enclosing block. Nested `{ ... }`
+    /// blocks are skipped as a whole. Always makes progress from `start`.
+    pub fn synchronize(&mut self, start: usize, is_restart: impl Fn(&Parser) -> bool) {
+        if self.idx == start && !self.at_eof() {
+            self.skip_token_or_block();
+        }
+        while !self.at_eof() {
+            match self.current_token_kind() {
+                TokenKind::RightBrace => return,
+                _ if is_restart(self) => return,
+                _ => self.skip_token_or_block(),
+            }
+        }
+    }
+
+    pub fn skip_token_or_block(&mut self) {
+        if self.current_token_kind() != TokenKind::LeftBrace {
+            self.advance();
+            return;
+        }
+        let mut depth = 0;
+        while !self.at_eof() {
+            match self.advance() {
+                TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
                     }
-                    TokenKind::Eof => break,
-                    _ => {}
                 }
-                self.advance();
+                _ => {}
             }
         }
     }
+
+    /// Consume the `}` closing a block, reporting an error at end of file
+    pub fn expect_block_end(&mut self, block: &str) {
+        if self.at_eof() {
+            let span = self.current_span();
+            self.diagnostics.push(
+                Diagnostic::error(
+                    format!("unexpected end of file, `{}` block is not closed", block),
+                    span,
+                )
+                .with_help("add a `}` to close the block"),
+            );
+        } else {
+            self.advance();
+        }
+    }
 }
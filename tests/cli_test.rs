@@ -0,0 +1,426 @@
+//! Tests for the `pyrus` command-line interface
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn pyrus(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pyrus"))
+        .args(args)
+        .output()
+        .expect("failed to run pyrus")
+}
+
+/// Per-test scratch directory so tests can run in parallel
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pyrus_cli_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_check_valid_file_is_quiet() {
+    let output = pyrus(&["check", "tests/input/css_test.ink"]);
+    assert!(output.status.success());
+    assert!(
+        output.stdout.is_empty(),
+        "check should not print on success"
+    );
+    assert!(
+        output.stderr.is_empty(),
+        "check should not print on success"
+    );
+}
+
+#[test]
+fn test_check_invalid_file_fails() {
+    let dir = scratch_dir("check_invalid");
+    let input = dir.join("bad.ink");
+    fs::write(&input, "document { foo( }").unwrap();
+
+    let output = pyrus(&["check", input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error"), "stderr was: {}", stderr);
+}
+
+#[test]
+fn test_check_missing_file_fails() {
+    let output = pyrus(&["check", "does/not/exist.ink"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_compile_writes_output_file() {
+    let dir = scratch_dir("compile");
+    let out = dir.join("nested").join("doc.pdf");
+
+    let output = pyrus(&[
+        "compile",
+        "tests/input/css_test.ink",
+        "-o",
+        out.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let bytes = fs::read(&out).expect("output pdf should exist");
+    assert!(bytes.starts_with(b"%PDF"));
+}
+
+#[test]
+fn test_compile_defaults_output_next_to_input() {
+    let dir = scratch_dir("compile_default");
+    let input = dir.join("doc.ink");
+    fs::write(&input, "document { text { \"Hello\" } }").unwrap();
+
+    let output = pyrus(&["compile", input.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(dir.join("doc.pdf").exists());
+}
+
+#[test]
+fn test_fmt_check_and_rewrite() {
+    let dir = scratch_dir("fmt");
+    let input = dir.join("doc.ink");
+    fs::write(&input, "document {\ntext { \"Hello\" }\n}\n").unwrap();
+
+    let output = pyrus(&["fmt", "--check", input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = pyrus(&["fmt", input.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&input).unwrap(),
+        "document {\n    text { \"Hello\" }\n}\n"
+    );
+
+    let output = pyrus(&["fmt", "--check", input.to_str().unwrap()]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_dump_ast_prints_to_stdout() {
+    let output = pyrus(&["dump", "--ast", "tests/input/css_test.ink"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("DocumentBlock"));
+}
+
+#[test]
+fn test_usage_errors_exit_with_two() {
+    assert_eq!(pyrus(&["frobnicate"]).status.code(), Some(2));
+    assert_eq!(pyrus(&["compile"]).status.code(), Some(2));
+    assert_eq!(pyrus(&["check", "--bogus", "a.ink"]).status.code(), Some(2));
+    assert_eq!(
+        pyrus(&["check", "--message-format=xml", "a.ink"])
+            .status
+            .code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn test_check_renders_source_snippet() {
+    let dir = scratch_dir("snippet");
+    let input = dir.join("bad.ink");
+    fs::write(&input, "document {\n    missing(1)\n}\n").unwrap();
+
+    let output = pyrus(&["check", input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error: Function not found: `missing`"));
+    assert!(
+        stderr.contains("2 |     missing(1)"),
+        "stderr was: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("  |     ^^^^^^^^^^"),
+        "stderr was: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_check_json_message_format() {
+    let dir = scratch_dir("json");
+    let input = dir.join("bad.ink");
+    fs::write(&input, "document {\n    missing(1)\n}\n").unwrap();
+
+    let output = pyrus(&["check", "--message-format", "json", input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stderr.is_empty());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "one JSON object per diagnostic");
+    assert!(lines[0].starts_with("{\"severity\":\"error\""));
+    assert!(lines[0].contains("\"line\":2,\"column\":5"));
+}
+
+#[test]
+fn test_warnings_do_not_fail_the_build() {
+    let dir = scratch_dir("warnings");
+    let input = dir.join("warn.ink");
+    fs::write(&input, "document { text (align=\"middle\") { \"a\" } }").unwrap();
+
+    let output = pyrus(&["check", input.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("warning: Invalid alignment value: middle"));
+}
+
+/// A 2x1 RGB PNG, one red and one blue pixel
+fn write_png(path: &std::path::Path) {
+    use std::io::Write;
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&[0, 255, 0, 0, 0, 0, 255]).unwrap();
+    let data = encoder.finish().unwrap();
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, body) in [
+        (&b"IHDR"[..], &[0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0][..]),
+        (b"IDAT", &data),
+        (b"IEND", &[]),
+    ] {
+        png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        png.extend_from_slice(kind);
+        png.extend_from_slice(body);
+        png.extend_from_slice(&[0; 4]); // CRC, not checked
+    }
+    fs::write(path, png).unwrap();
+}
+
+#[test]
+fn test_compile_embeds_image() {
+    let dir = scratch_dir("image");
+    write_png(&dir.join("logo.png"));
+    let input = dir.join("doc.ink");
+    fs::write(
+        &input,
+        "document {\n    image(src=\"logo.png\", alt=\"Logo\", width=100)\n    text { \"After\" }\n}\n",
+    )
+    .unwrap();
+
+    let output = pyrus(&["dump", "--layout", input.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("type: \"image\"") && stdout.contains("w=100.0, h=50.0"),
+        "stdout was: {}",
+        stdout
+    );
+
+    let output = pyrus(&["compile", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let pdf = fs::read(dir.join("doc.pdf")).unwrap();
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert!(pdf.contains("/Subtype/Image"), "no image XObject in output");
+}
+
+#[test]
+fn test_check_reports_missing_image() {
+    let dir = scratch_dir("missing_image");
+    let input = dir.join("doc.ink");
+    fs::write(&input, "document {\n    image(src=\"nope.png\")\n}\n").unwrap();
+
+    let output = pyrus(&["check", input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("error: Cannot read image `nope.png`"),
+        "stderr was: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_compile_writes_link_annotations() {
+    let dir = scratch_dir("links");
+    let input = dir.join("doc.ink");
+    fs::write(
+        &input,
+        r##"document {
+    link(href="#end") { "Skip ahead" }
+    link(href="https://example.com") { "Example" }
+    section(id="end", margin=10) { text { "The end" } }
+}
+"##,
+    )
+    .unwrap();
+
+    // the section is laid out below the two links plus its margin
+    let output = pyrus(&["dump", "--layout", input.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("id: Some(\"end\")): x=10.0, y=34.0"),
+        "stdout was: {}",
+        stdout
+    );
+
+    let output = pyrus(&["compile", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let pdf = fs::read(dir.join("doc.pdf")).unwrap();
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert!(pdf.contains("/Subtype/Link"));
+    assert!(pdf.contains("/S/URI/URI(https://example.com)"));
+    assert!(pdf.contains("/S/GoTo"));
+}
+
+/// Content streams of a PDF, inflated when they are compressed
+fn pdf_streams(pdf: &[u8]) -> Vec<String> {
+    use std::io::Read;
+
+    let mut streams = Vec::new();
+    let mut rest = pdf;
+    while let Some(start) = find(rest, b"stream\n") {
+        let body = &rest[start + b"stream\n".len()..];
+        let Some(end) = find(body, b"endstream") else {
+            break;
+        };
+        let mut inflated = Vec::new();
+        let mut decoder = flate2::read::ZlibDecoder::new(&body[..end]);
+        let content = match decoder.read_to_end(&mut inflated) {
+            Ok(_) => inflated,
+            Err(_) => body[..end].to_vec(),
+        };
+        streams.push(String::from_utf8_lossy(&content).into_owned());
+        rest = &body[end..];
+    }
+    streams
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[test]
+fn test_compile_splits_long_table_and_repeats_header() {
+    let dir = scratch_dir("table");
+    let input = dir.join("doc.ink");
+    fs::write(
+        &input,
+        r#"document {
+    table(columns="2fr 1fr") {
+        header { cell { "Item" } cell { "Price" } }
+        for i in 0..80 { row { cell { "Widget {i}" } cell { "1.00" } } }
+    }
+}
+"#,
+    )
+    .unwrap();
+
+    let output = pyrus(&["compile", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let pdf = fs::read(dir.join("doc.pdf")).unwrap();
+    let pages: Vec<_> = pdf_streams(&pdf)
+        .into_iter()
+        .filter(|stream| stream.contains("Tj"))
+        .collect();
+    assert_eq!(pages.len(), 2);
+    for page in &pages {
+        assert!(page.contains("(Item)"), "page without header: {}", page);
+    }
+    // every row is drawn once, on one of the pages
+    let rows: usize = pages
+        .iter()
+        .map(|page| page.matches("(Widget ").count())
+        .sum();
+    assert_eq!(rows, 80);
+    assert!(pages[1].contains("(Widget 79)"));
+}
+
+#[test]
+fn test_compile_draws_code_in_courier_with_line_numbers() {
+    let dir = scratch_dir("code");
+    let input = dir.join("doc.ink");
+    fs::write(
+        &input,
+        r#"document {
+    code(lang="python", line_numbers=true) {
+        """
+        def f():
+            return "x"
+        """
+    }
+}
+"#,
+    )
+    .unwrap();
+
+    let output = pyrus(&["compile", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let pdf = fs::read(dir.join("doc.pdf")).unwrap();
+    assert!(find(&pdf, b"/BaseFont/Courier").is_some());
+    let page = pdf_streams(&pdf)
+        .into_iter()
+        .find(|stream| stream.contains("Tj"))
+        .unwrap();
+    assert!(page.contains("(1  )"), "{}", page);
+    assert!(page.contains("(2  )"), "{}", page);
+    // indentation is kept and the keyword is its own run
+    assert!(page.contains("(def)"), "{}", page);
+    assert!(page.contains("(    )"), "{}", page);
+    assert!(page.contains("(\"x\")"), "{}", page);
+}
+
+#[test]
+fn test_compile_sets_runs_in_their_own_font_and_colour() {
+    let dir = scratch_dir("runs");
+    let input = dir.join("doc.ink");
+    fs::write(
+        &input,
+        r#"document {
+    text { "Total: " span(class="price") { "*42*" } " in `EUR`" }
+}
+style {
+    .price { color = "red" }
+}
+"#,
+    )
+    .unwrap();
+
+    let output = pyrus(&["compile", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let pdf = fs::read(dir.join("doc.pdf")).unwrap();
+    assert!(find(&pdf, b"/BaseFont/Helvetica-Bold").is_some());
+    assert!(find(&pdf, b"/BaseFont/Courier").is_some());
+    let page = pdf_streams(&pdf)
+        .into_iter()
+        .find(|stream| stream.contains("Tj"))
+        .unwrap();
+    // one text section, the price in red between black runs
+    assert_eq!(page.matches("BT").count(), 1, "{}", page);
+    let price = page.find("(42) Tj").unwrap();
+    assert!(page[..price].ends_with("1 0 0 rg\n"), "{}", page);
+    assert!(page.contains("(Total: )"), "{}", page);
+    assert!(page.contains("(EUR)"), "{}", page);
+}
+
+#[test]
+fn test_import_converts_markdown_next_to_it() {
+    let dir = scratch_dir("import");
+    let input = dir.join("notes.md");
+    fs::write(&input, "# Notes\n\nSome *text*.\n\n```\ncode\n```\n").unwrap();
+
+    let output = pyrus(&["import", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let converted = dir.join("notes.ink");
+    let source = fs::read_to_string(&converted).unwrap();
+    assert!(
+        source.starts_with("document {\n    section {\n"),
+        "{}",
+        source
+    );
+    assert!(source.contains("\nstyle {\n    section {\n"), "{}", source);
+
+    let output = pyrus(&["check", converted.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = pyrus(&["fmt", "--check", converted.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = pyrus(&["import", "a.md", "b.md", "-o", "out.ink"]);
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -1,6 +1,7 @@
 //! Tests for CSS style resolution in HLIR
 
-use pyrus::hlir::{lower, resolve_styles};
+use pyrus::ast::Emphasis;
+use pyrus::hlir::{HlirElement, lower, resolve_styles};
 use pyrus::lexer::lex;
 use pyrus::parser::parse;
 
@@ -22,7 +23,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
 
     assert_eq!(hlir.element_metadata.len(), 1);
@@ -54,7 +55,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -80,7 +81,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -110,7 +111,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -140,7 +141,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -170,7 +171,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -204,7 +205,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -235,7 +236,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -267,7 +268,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -303,7 +304,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -341,7 +342,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -392,7 +393,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -429,7 +430,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -453,7 +454,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -480,7 +481,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -502,7 +503,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -521,7 +522,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
 
     assert!(hlir.css_rules.is_empty());
@@ -535,6 +536,66 @@ document {
     assert!(node.computed.style.is_empty());
 }
 
+// ============================================================================
+// Inline Span Tests
+// ============================================================================
+
+#[test]
+fn test_spans_are_styled_through_the_cascade() {
+    let source = r#"
+document {
+    text(class="total") { "Total: " span(class="price") { "*42*" span(id="cur") { " EUR" } } }
+}
+style {
+    .total { color = "gray" font-size = 14 }
+    span { font-style = "italic" }
+    .price { color = "red" }
+    #cur { color = "blue" }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    resolve_styles(&mut hlir);
+
+    // spans are not elements of their own
+    assert_eq!(hlir.elements.len(), 1);
+    assert_eq!(hlir.span_metadata.len(), 2);
+    let HlirElement::Text { content, runs, .. } = &hlir.elements[0] else {
+        panic!("Expected Text, got {:?}", hlir.elements[0]);
+    };
+    assert_eq!(content, "Total: 42 EUR");
+
+    let styles: Vec<_> = runs
+        .iter()
+        .map(|run| {
+            let computed = &hlir.attributes.find_node(run.attributes).unwrap().computed;
+            (
+                run.text.as_str(),
+                run.emphasis,
+                computed.get("color"),
+                computed.get("font-style"),
+                computed.get("font-size"),
+            )
+        })
+        .collect();
+    let some = |value: &str| Some(value.to_string());
+    assert_eq!(
+        styles,
+        [
+            ("Total: ", None, some("gray"), None, some("14")),
+            (
+                "42",
+                Some(Emphasis::Bold),
+                some("red"),
+                some("italic"),
+                some("14")
+            ),
+            // the inner span takes the rules of the one around it, and its own
+            (" EUR", None, some("blue"), some("italic"), some("14")),
+        ]
+    );
+}
+
 // ============================================================================
 // Complex Integration Test
 // ============================================================================
@@ -574,7 +635,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
     resolve_styles(&mut hlir);
 
@@ -652,7 +713,7 @@ fn test_css_from_file() {
     let data =
         fs::read_to_string("tests/input/css_test.ink").expect("Should be able to read test file");
     let tokens = lex(&data);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let mut hlir = lower(&ast);
 
     // Check that we have the expected structure
@@ -0,0 +1,146 @@
+//! Tests for diagnostic rendering and for errors reported by each stage
+
+use pyrus::diagnostics::{Diagnostic, Severity, Span, has_errors, render, render_json};
+use pyrus::hlir::{lower, resolve_styles};
+use pyrus::lexer::lex;
+use pyrus::parser::parse;
+
+#[test]
+fn test_render_underlines_span() {
+    let source = "document {\n    text (id=) { \"a\" }\n}\n";
+    let start = source.find(')').unwrap();
+    let diagnostic = Diagnostic::error("expected an expression", Span::new(start, start + 1))
+        .with_label("here")
+        .with_note("attribute values cannot be empty")
+        .with_help("write `id=\"intro\"`");
+
+    let rendered = render(&diagnostic, "doc.ink", source);
+    let expected = "\
+error: expected an expression
+ --> doc.ink:2:14
+  |
+2 |     text (id=) { \"a\" }
+  |              ^ here
+  |
+  = note: attribute values cannot be empty
+  = help: write `id=\"intro\"`
+";
+    assert_eq!(rendered, expected);
+}
+
+#[test]
+fn test_render_multiline_span_stops_at_line_end() {
+    let source = "text {\n  \"a\"\n}";
+    let diagnostic = Diagnostic::error("bad", Span::new(0, source.len()));
+    let rendered = render(&diagnostic, "doc.ink", source);
+    assert!(
+        rendered.contains("1 | text {\n  | ^^^^^^\n"),
+        "{}",
+        rendered
+    );
+}
+
+#[test]
+fn test_render_keeps_tabs_before_the_span() {
+    let source = "document {\n\t\ttext (id=) { \"a\" }\n}\n";
+    let start = source.find(')').unwrap();
+    let diagnostic = Diagnostic::error("expected an expression", Span::new(start, start + 1));
+    let rendered = render(&diagnostic, "doc.ink", source);
+    assert!(
+        rendered.contains("2 | \t\ttext (id=) { \"a\" }\n  | \t\t         ^\n"),
+        "{}",
+        rendered
+    );
+}
+
+#[test]
+fn test_render_span_at_end_of_file() {
+    let source = "document {";
+    let diagnostic = Diagnostic::error("unclosed", Span::new(10, 10));
+    let rendered = render(&diagnostic, "doc.ink", source);
+    assert!(rendered.contains(" --> doc.ink:1:11"));
+    assert!(rendered.contains("  |           ^\n"), "{}", rendered);
+}
+
+#[test]
+fn test_render_json_escapes_strings() {
+    let source = "text { \"a\" }";
+    let diagnostic =
+        Diagnostic::warning("quote \" and\nnewline", Span::new(7, 10)).with_note("a note");
+    let json = render_json(&diagnostic, "dir\\doc.ink", source);
+
+    assert!(json.starts_with("{\"severity\":\"warning\",\"message\":\"quote \\\" and\\nnewline\""));
+    assert!(json.contains("\"file\":\"dir\\\\doc.ink\""));
+    assert!(json.contains(
+        "\"span\":{\"start\":7,\"end\":10,\"line\":1,\"column\":8,\"end_line\":1,\"end_column\":11}"
+    ));
+    assert!(json.contains("\"label\":null,\"notes\":[\"a note\"],\"help\":null"));
+    assert!(!json.contains('\n'), "JSON output must be a single line");
+}
+
+#[test]
+fn test_has_errors_ignores_warnings() {
+    let warning = Diagnostic::warning("w", Span::default());
+    assert_eq!(warning.severity, Severity::Warning);
+    assert!(!has_errors(std::slice::from_ref(&warning)));
+    assert!(has_errors(&[
+        warning,
+        Diagnostic::error("e", Span::default())
+    ]));
+}
+
+// ============================================================================
+// Errors From Each Stage
+// ============================================================================
+
+#[test]
+fn test_lexer_reports_unknown_character() {
+    let source = "document { @ }";
+    let tokens = lex(source);
+    assert_eq!(tokens.errors.len(), 1);
+    assert_eq!(tokens.errors[0].message, "Unknown character `@`");
+    assert_eq!(tokens.errors[0].span, Span::new(11, 12));
+}
+
+#[test]
+fn test_lexer_reports_unterminated_string() {
+    let source = "document { text { \"abc } }";
+    let tokens = lex(source);
+    assert_eq!(tokens.errors.len(), 1);
+    assert_eq!(tokens.errors[0].span.start, source.find('"').unwrap());
+    assert!(tokens.errors[0].help.is_some());
+}
+
+#[test]
+fn test_style_reports_invalid_declaration_value() {
+    let source = r#"
+document {
+    text (class="note") { "a" }
+}
+style {
+    .note { margin = "wide" }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    resolve_styles(&mut hlir);
+
+    assert_eq!(hlir.diagnostics.len(), 1);
+    let diagnostic = &hlir.diagnostics[0];
+    assert_eq!(diagnostic.severity, Severity::Warning);
+    assert_eq!(
+        &source[diagnostic.span.start..diagnostic.span.end],
+        "margin = \"wide\""
+    );
+}
+
+#[test]
+fn test_lowering_reports_invalid_attribute_value() {
+    let source = r#"document { text (page_break="sometimes") { "a" } }"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+
+    assert_eq!(hlir.diagnostics.len(), 1);
+    assert!(!has_errors(&hlir.diagnostics));
+    assert!(hlir.diagnostics[0].message.contains("sometimes"));
+}
@@ -0,0 +1,104 @@
+//! Tests for the source formatter used by `pyrus fmt`
+
+use pyrus::format::{format_source, print_ast};
+use pyrus::lexer::lex;
+use pyrus::parser::parse;
+
+#[test]
+fn test_format_reindents_blocks() {
+    let source = "document {\n  text { \"a\" }\n      section {\ntext { \"b\" }\n }\n}\n";
+    let formatted = format_source(source).unwrap();
+    assert_eq!(
+        formatted,
+        "document {\n    text { \"a\" }\n    section {\n        text { \"b\" }\n    }\n}\n"
+    );
+}
+
+#[test]
+fn test_format_keeps_comments() {
+    let source = "// header\ntemplate {\n    title = \"Doc\"   // trailing\n    /* block */\n}\n";
+    let formatted = format_source(source).unwrap();
+    assert_eq!(formatted, source);
+}
+
+#[test]
+fn test_format_collapses_blank_lines_and_spaces() {
+    let source = "document {\n\n\n\n    text (id=\"a\",class=\"b\")   {   \"x\"   }\n}";
+    let formatted = format_source(source).unwrap();
+    assert_eq!(
+        formatted,
+        "document {\n\n    text (id=\"a\", class=\"b\") { \"x\" }\n}\n"
+    );
+}
+
+#[test]
+fn test_format_is_idempotent() {
+    let source = std::fs::read_to_string("temp.ink").unwrap();
+    let once = format_source(&source).unwrap();
+    let twice = format_source(&once).unwrap();
+    assert_eq!(once, twice);
+}
+
+#[test]
+fn test_format_rejects_lex_errors() {
+    let source = "template { let msg = \"unterminated }";
+    assert!(format_source(source).is_err());
+}
+
+#[test]
+fn test_print_ast_is_formatted_and_parses_back() {
+    let source = r##"document {
+    section(class="intro", id="top") {
+        text { "Hi " span(class="name") { "*you*" } ", a \"quote\" and \*stars*" }
+        link(href="https://example.com") { "Home" }
+        link(href="#top")
+    }
+    list {
+        item { "one" }
+        link(href="x") { "two" }
+    }
+    table {
+        header {
+            cell(colspan=2) { "Both" }
+        }
+        for i in 0..3 {
+            row {
+                cell { "{i}" }
+                cell { "b" }
+            }
+        }
+    }
+    if total > 10 {
+        text { "big" }
+    } else if total > 5 {
+        text { "medium" }
+    } else {
+        text { "small" }
+    }
+    code(lang="rust") {
+        """
+        fn main() {
+
+            println!("hi");
+        }
+        """
+    }
+}
+
+style {
+    text, .name {
+        font-size = 12
+        color = "blue"
+    }
+
+    #top {
+        margin = 1.5
+    }
+}
+"##;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let printed = print_ast(&ast);
+    assert_eq!(printed, source);
+    assert_eq!(format_source(&printed).unwrap(), printed);
+}
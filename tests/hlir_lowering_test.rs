@@ -3,8 +3,14 @@
 //! These tests define the expected behavior of the lowering pass and
 //! the validation pass that should catch errors.
 
-use pyrus::hlir::{FuncId, HLIRModule, Id, Op, Type};
-use pyrus::hlir::{lower, resolve_styles};
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use pyrus::highlight::{CodeRun, TokenClass};
+use pyrus::hlir::{Color, ColumnWidth, FuncId, HLIRModule, HlirElement, Id, Literal, Op, Type};
+use pyrus::hlir::{evaluate, load_images, lower, resolve_styles, validate};
+use pyrus::layout::{LINE_HEIGHT, setup_layout};
 use pyrus::lexer::lex;
 use pyrus::parser::parse;
 
@@ -19,7 +25,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // Should have the implicit __document function
@@ -43,7 +49,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     assert_eq!(hlir.globals.len(), 1, "Should have one global");
@@ -60,7 +66,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     assert_eq!(hlir.globals.len(), 1);
@@ -79,12 +85,106 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     assert_eq!(hlir.globals.len(), 3);
 }
 
+#[test]
+fn test_lower_folds_global_expressions() {
+    let source = r#"
+template {
+    const price = 4
+    const quantity = 3
+    let total = price * quantity + 1
+    const rate = -0.5 * 2
+    const label = "Total: {total} ({quantity} items)"
+    func card(name: String) {
+        let doubled = total * 2
+        return text(class="n{quantity}") { "{name}: {label}" }
+    }
+}
+document {
+    card("Tea")
+    text(width=price * 50) { "{total} at {rate}" }
+}
+"#;
+    let hlir = evaluated(source);
+    let init = |name: &str| {
+        let global = hlir.globals.values().find(|g| g.name == name).unwrap();
+        (&global.init, global.ty)
+    };
+    assert!(matches!(init("total"), (Literal::Int(13), Type::Int)));
+    assert!(matches!(init("rate"), (Literal::Float(-1.0), Type::Float)));
+    assert!(matches!(
+        init("label"),
+        (Literal::String(label), Type::String) if label == "Total: 13 (3 items)"
+    ));
+
+    // a local made of globals is folded into a single constant
+    let card = hlir.functions.values().find(|f| f.name == "card").unwrap();
+    assert!(matches!(
+        card.body.ops[0],
+        Op::Const {
+            literal: Literal::Int(26),
+            ..
+        }
+    ));
+
+    let [instance] = instances(&hlir)[..] else {
+        panic!("Expected one call");
+    };
+    assert!(matches!(
+        &hlir.elements[instance],
+        HlirElement::Text { content, .. } if content == "Tea: Total: 13 (3 items)"
+    ));
+    assert_eq!(hlir.element_metadata[instance].classes, ["n3"]);
+    assert_eq!(
+        document_texts(&hlir),
+        ["Tea: Total: 13 (3 items)", "13 at -1"]
+    );
+    let text = hlir
+        .elements
+        .iter()
+        .position(|e| matches!(e, HlirElement::Text { content, .. } if content == "13 at -1"))
+        .unwrap();
+    let node = hlir
+        .attributes
+        .find_node(hlir.elements[text].attributes())
+        .unwrap();
+    assert_eq!(node.inline.get("width"), Some("200".to_string()));
+}
+
+#[test]
+fn test_bad_global_expressions_are_reported() {
+    let source = r#"
+template {
+    const a = missing + 1
+    const s = "x" - 1
+    const d = 1 / 0
+}
+document {
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<&str> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Unknown variable `missing`",
+            "Binary operation type mismatch: cannot apply `-` to `String` and `Int`",
+            "Division by zero",
+        ]
+    );
+    assert!(hlir.globals.is_empty());
+}
+
 #[test]
 fn test_lower_simple_function() {
     let source = r#"
@@ -97,7 +197,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // Should have __document + greeting
@@ -127,7 +227,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     let func = hlir
@@ -151,7 +251,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     let func = hlir
@@ -176,7 +276,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     assert_eq!(hlir.elements.len(), 1);
@@ -195,7 +295,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // Should have: 1 section + 2 text elements
@@ -225,7 +325,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     let meta = &hlir.element_metadata[0];
@@ -233,10 +333,377 @@ document {
     assert_eq!(meta.classes, vec!["large", "bold"]);
 }
 
+#[test]
+fn test_lower_image_and_load_headers() {
+    let dir = std::env::temp_dir().join(format!("pyrus_hlir_image_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // signature and an IHDR chunk for a 40x20 RGB image are all the header needs
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    png.extend_from_slice(&[0, 0, 0, 40, 0, 0, 0, 20, 8, 2, 0, 0, 0, 0, 0, 0, 0]);
+    std::fs::write(dir.join("truncated.png"), &png).unwrap();
+    let mut interlaced = png.clone();
+    interlaced[28] = 1;
+    std::fs::write(dir.join("interlaced.png"), &interlaced).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+    // 20 unfiltered rows of 40 black pixels
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&[0; 20 * (1 + 40 * 3)]).unwrap();
+    let data = encoder.finish().unwrap();
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(b"IDAT");
+    png.extend_from_slice(&data);
+    png.extend_from_slice(&[0; 4]);
+    std::fs::write(dir.join("logo.png"), &png).unwrap();
+
+    let source = r#"
+document {
+    image(src="logo.png", alt="Logo", width=80)
+    image(src="missing.png")
+    image(src="truncated.png")
+    image(src="interlaced.png")
+    image(src="notes.txt")
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+    assert_eq!(hlir.element_metadata[0].element_type, "image");
+
+    load_images(&mut hlir, &dir);
+    match &hlir.elements[0] {
+        HlirElement::Image {
+            src,
+            alt,
+            path,
+            info: Some(info),
+            ..
+        } => {
+            assert_eq!(src, "logo.png");
+            assert_eq!(alt.as_deref(), Some("Logo"));
+            assert_eq!(path, &dir.join("logo.png"));
+            assert_eq!((info.width, info.height), (40, 20));
+        }
+        other => panic!("Expected loaded Image, got {:?}", other),
+    }
+
+    assert_eq!(hlir.diagnostics.len(), 4);
+    assert_eq!(
+        hlir.diagnostics[0].message,
+        "Cannot read image `missing.png`"
+    );
+    let span = hlir.diagnostics[0].span;
+    assert_eq!(&source[span.start..span.end], r#"image(src="missing.png")"#);
+    assert!(
+        hlir.diagnostics[1]
+            .message
+            .starts_with("Cannot decode image `truncated.png`"),
+        "{:?}",
+        hlir.diagnostics[1]
+    );
+    let span = hlir.diagnostics[1].span;
+    assert_eq!(
+        &source[span.start..span.end],
+        r#"image(src="truncated.png")"#
+    );
+    assert!(matches!(
+        &hlir.elements[2],
+        HlirElement::Image { info: None, .. }
+    ));
+    assert_eq!(
+        hlir.diagnostics[2].message,
+        "Cannot use image `interlaced.png`: interlaced PNG images are not supported"
+    );
+    assert_eq!(
+        hlir.diagnostics[2].help.as_deref(),
+        Some("save the image again without interlacing")
+    );
+    assert_eq!(
+        hlir.diagnostics[3].help.as_deref(),
+        Some("images must be PNG or JPEG files")
+    );
+}
+
+#[test]
+fn test_lower_links_and_check_targets() {
+    let source = r##"
+document {
+    link(href="#intro") { "Back to the start" }
+    link(href="https://example.com")
+    link(href="#nowhere") { "Broken" }
+    section(id="intro") { text { "Intro" } }
+    text { "See " link(href="#intro") { "it" } " or " link(href="#gone") { "this" } }
+}
+"##;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+
+    assert_eq!(hlir.element_metadata[0].element_type, "link");
+    match &hlir.elements[0] {
+        HlirElement::Link { href, content, .. } => {
+            assert_eq!(href, "#intro");
+            assert_eq!(content, "Back to the start");
+        }
+        other => panic!("Expected Link, got {:?}", other),
+    }
+
+    // the target may come after the link, only the missing ones are errors,
+    // inside text too
+    let reported: Vec<_> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| (d.message.as_str(), &source[d.span.start..d.span.end]))
+        .collect();
+    assert_eq!(
+        reported,
+        [
+            (
+                "No element with id `nowhere`",
+                r##"link(href="#nowhere") { "Broken" }"##
+            ),
+            (
+                "No element with id `gone`",
+                r##"link(href="#gone") { "this" }"##
+            ),
+        ]
+    );
+}
+
+/// Grid position, spans and header flag of every cell of the table at `index`
+fn table_cells(hlir: &HLIRModule, index: usize) -> Vec<(String, usize, usize, usize, usize, bool)> {
+    let HlirElement::Table { children, .. } = &hlir.elements[index] else {
+        panic!("Expected Table, got {:?}", hlir.elements[index]);
+    };
+    children
+        .iter()
+        .map(|child| match &hlir.elements[*child] {
+            HlirElement::TableCell {
+                content,
+                row,
+                column,
+                rowspan,
+                colspan,
+                header,
+                ..
+            } => (content.clone(), *row, *column, *rowspan, *colspan, *header),
+            other => panic!("Expected TableCell, got {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn test_lower_table_places_cells() {
+    let source = r#"
+document {
+    table(columns="100 auto") {
+        header { cell { "A" } cell { "B" } cell { "C" } }
+        row { cell(rowspan=2) { "tall" } cell(colspan=2) { "wide" } }
+        for i in 0..1 { row { cell { "x{i}" } cell(rowspan=5) { "y" } } }
+    }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+
+    match &hlir.elements[0] {
+        HlirElement::Table {
+            columns,
+            header_rows,
+            ..
+        } => {
+            assert_eq!(*header_rows, 1);
+            assert_eq!(
+                columns,
+                &[
+                    ColumnWidth::Points(100.0),
+                    ColumnWidth::Auto,
+                    ColumnWidth::Fraction(1.0)
+                ]
+            );
+        }
+        other => panic!("Expected Table, got {:?}", other),
+    }
+    let cell = |content: &str, row, column, rowspan, colspan, header| {
+        (content.to_string(), row, column, rowspan, colspan, header)
+    };
+    assert_eq!(
+        table_cells(&hlir, 0),
+        [
+            cell("A", 0, 0, 1, 1, true),
+            cell("B", 0, 1, 1, 1, true),
+            cell("C", 0, 2, 1, 1, true),
+            cell("tall", 1, 0, 2, 1, false),
+            cell("wide", 1, 1, 1, 2, false),
+            // the first column is taken by "tall", the rowspan stops at the last row
+            cell("x0", 2, 1, 1, 1, false),
+            cell("y", 2, 2, 1, 1, false),
+        ]
+    );
+    assert_eq!(hlir.element_metadata[1].element_type, "cell");
+    assert_eq!(hlir.element_metadata[1].parent, Some(0));
+}
+
+#[test]
+fn test_lower_table_diagnostics() {
+    let source = r#"
+document {
+    table(columns="1x 2fr") {
+        row { cell { "a" } cell { "b" } }
+        header { cell { "late" } }
+    }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<_> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Invalid column width: 1x",
+            "Header rows must come first in a table"
+        ]
+    );
+
+    // the invalid hint is ignored as a whole and the late header is a plain row
+    match &hlir.elements[0] {
+        HlirElement::Table {
+            columns,
+            header_rows,
+            ..
+        } => {
+            assert_eq!(*header_rows, 0);
+            assert_eq!(columns, &[ColumnWidth::Fraction(1.0); 2]);
+        }
+        other => panic!("Expected Table, got {:?}", other),
+    }
+    assert!(table_cells(&hlir, 0).iter().all(|cell| !cell.5));
+}
+
 // ============================================================================
 // CSS Style Integration Tests
 // ============================================================================
 
+#[test]
+fn test_lower_code_highlights_and_colors_from_style() {
+    let source = r##"
+document {
+    code(lang="rust", line_numbers=true) { "let x = 1; // one" }
+    code(lang="cobol") { "MOVE A TO B" }
+}
+style {
+    .code-keyword { color = "#ff0000" }
+}
+"##;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    resolve_styles(&mut hlir);
+
+    assert_eq!(hlir.element_metadata[0].element_type, "code");
+    let HlirElement::Code {
+        lang,
+        line_numbers,
+        lines,
+        colors,
+        ..
+    } = &hlir.elements[0]
+    else {
+        panic!("Expected Code, got {:?}", hlir.elements[0]);
+    };
+    assert_eq!(lang.as_deref(), Some("rust"));
+    assert!(*line_numbers);
+    let run = |text: &str, class| CodeRun {
+        text: text.to_string(),
+        class,
+    };
+    assert_eq!(
+        lines[..],
+        [vec![
+            run("let", Some(TokenClass::Keyword)),
+            run(" x = ", None),
+            run("1", Some(TokenClass::Number)),
+            run("; ", None),
+            run("// one", Some(TokenClass::Comment)),
+        ]]
+    );
+    assert_eq!(
+        colors[&TokenClass::Keyword],
+        Color {
+            r: 1.0,
+            g: 0.0,
+            b: 0.0
+        }
+    );
+    assert_eq!(
+        colors[&TokenClass::Comment],
+        TokenClass::Comment.default_color().parse().unwrap()
+    );
+
+    // an unknown language is shown plain, with a warning on the attribute
+    let HlirElement::Code { lines, .. } = &hlir.elements[1] else {
+        panic!("Expected Code, got {:?}", hlir.elements[1]);
+    };
+    assert_eq!(lines[..], [vec![run("MOVE A TO B", None)]]);
+    assert_eq!(hlir.diagnostics.len(), 1, "{:?}", hlir.diagnostics);
+    assert_eq!(hlir.diagnostics[0].message, "Unknown code language `cobol`");
+    let span = hlir.diagnostics[0].span;
+    assert_eq!(&source[span.start..span.end], "\"cobol\"");
+}
+
+#[test]
+fn test_lower_markdown_is_styled_like_written_elements() {
+    let source = r#"
+document {
+    markdown(class="prose") {
+        """
+        # Title
+        Body with a [link](https://example.com).
+        """
+    }
+}
+style {
+    .prose { color = "gray" }
+    .h1 { font-size = 20 }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    resolve_styles(&mut hlir);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+
+    let types: Vec<_> = hlir
+        .element_metadata
+        .iter()
+        .map(|metadata| metadata.element_type.as_str())
+        .collect();
+    assert_eq!(types, ["section", "section", "text", "text"]);
+    let style = |index: usize, property: &str| {
+        let node = hlir
+            .attributes
+            .find_node(hlir.element_metadata[index].attributes_ref)
+            .unwrap();
+        node.computed.get(property)
+    };
+    assert_eq!(style(2, "font-size"), Some("20".to_string()));
+    assert_eq!(style(2, "color"), Some("gray".to_string()));
+    assert_eq!(style(3, "font-size"), None);
+
+    // the link stays inside the paragraph's text
+    let HlirElement::Text { content, runs, .. } = &hlir.elements[3] else {
+        panic!("Expected Text, got {:?}", hlir.elements[3]);
+    };
+    assert_eq!(content, "Body with a link.");
+    let links: Vec<_> = runs
+        .iter()
+        .filter_map(|run| Some((run.text.as_str(), run.href.as_deref()?)))
+        .collect();
+    assert_eq!(links, [("link", "https://example.com")]);
+    assert_eq!(hlir.span_metadata[0].element_type, "link");
+}
+
 #[test]
 fn test_lower_preserves_css_rules() {
     let source = r#"
@@ -253,7 +720,7 @@ style {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     assert_eq!(hlir.css_rules.len(), 2);
@@ -276,7 +743,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // Find __document function body
@@ -308,7 +775,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     let doc_func = hlir
@@ -341,7 +808,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     let doc_func = hlir
@@ -370,7 +837,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // The global should have been created with the right value
@@ -388,13 +855,1492 @@ document {
 }
 
 // ============================================================================
-// Validation Tests (These will fail until validation is implemented)
+// Source Span Tests
+// ============================================================================
+
+#[test]
+fn test_lower_element_metadata_keeps_span() {
+    let source = r#"document { text (id="intro") { "Hello" } }"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+
+    let span = hlir.element_metadata[0].span;
+    assert_eq!(
+        &source[span.start..span.end],
+        r#"text (id="intro") { "Hello" }"#
+    );
+}
+
+#[test]
+fn test_lower_ops_keep_span() {
+    let source = r#"
+template {
+    func card() { return text { "Card" } }
+}
+document {
+    card()
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+
+    let doc_func = hlir
+        .functions
+        .values()
+        .find(|f| f.name == "__document")
+        .unwrap();
+    let call = doc_func
+        .body
+        .ops
+        .iter()
+        .find(|op| matches!(op, Op::Call { .. }))
+        .expect("Should have a call op");
+    let span = call.span();
+    assert_eq!(&source[span.start..span.end], "card()");
+}
+
+#[test]
+fn test_lower_unknown_function_reports_span() {
+    let source = "document { text { \"a\" } missing(1) }";
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+
+    assert_eq!(hlir.diagnostics.len(), 1);
+    let diagnostic = &hlir.diagnostics[0];
+    assert!(diagnostic.message.contains("Function not found"));
+    assert_eq!(
+        &source[diagnostic.span.start..diagnostic.span.end],
+        "missing(1)"
+    );
+}
+
+// ============================================================================
+// Control Flow Tests
 // ============================================================================
 
-// TODO: Uncomment these tests once validation module is implemented
-// The validation module should catch these errors at compile time
+/// Lower and evaluate `source`, failing on any diagnostic
+fn evaluated(source: &str) -> HLIRModule {
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    evaluate(&mut hlir);
+    assert!(
+        hlir.diagnostics.is_empty(),
+        "unexpected diagnostics: {:?}",
+        hlir.diagnostics
+    );
+    hlir
+}
+
+/// Text of the element returned by the function `name`
+fn returned_text(hlir: &HLIRModule, name: &str) -> Option<String> {
+    let func = hlir.functions.values().find(|f| f.name == name).unwrap();
+    match &hlir.elements[func.body.returned_element_ref?] {
+        HlirElement::Text { content, .. } => Some(content.clone()),
+        other => panic!("Expected Text, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_lower_if_else_to_control_flow_op() {
+    let source = r#"
+template {
+    const count = 3
+    func badge() {
+        if count > 1 { return text { "many" } } else { return text { "one" } }
+    }
+}
+document { badge() }
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let func = hlir.functions.values().find(|f| f.name == "badge").unwrap();
+    match func.body.ops.last() {
+        Some(Op::If {
+            then_ops, else_ops, ..
+        }) => {
+            assert!(matches!(then_ops[..], [Op::Return { .. }]));
+            assert!(matches!(else_ops[..], [Op::Return { .. }]));
+        }
+        other => panic!("Expected If op, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_evaluate_if_else_chooses_branch() {
+    let source = r#"
+template {
+    const count = 3
+    func badge() {
+        if count == 1 {
+            return text { "one" }
+        } else if count < 5 {
+            return text { "few" }
+        } else {
+            return text { "many" }
+        }
+    }
+    func nothing() {
+        if count > 10 { return text { "big" } }
+    }
+}
+document { badge() nothing() }
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(returned_text(&hlir, "badge").as_deref(), Some("few"));
+    assert_eq!(returned_text(&hlir, "nothing"), None);
+}
+
+#[test]
+fn test_evaluate_while_and_reassignment() {
+    let source = r#"
+template {
+    func countdown() {
+        let n = 0
+        while n < 10 {
+            n = n + 3
+        }
+        if n == 12 { return text { "twelve" } }
+        return text { "other" }
+    }
+}
+document { countdown() }
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(returned_text(&hlir, "countdown").as_deref(), Some("twelve"));
+}
+
+#[test]
+fn test_evaluate_for_over_range() {
+    let source = r#"
+template {
+    const limit = 4
+    func total() {
+        let sum = 0
+        for i in 1..limit {
+            sum = sum + i
+        }
+        if sum == 6 && !(sum != 6) { return text { "six" } }
+        return text { "wrong" }
+    }
+    func first_even() {
+        for i in 1..10 {
+            if i % 2 == 0 { return text { "found" } }
+        }
+    }
+}
+document { total() first_even() }
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(returned_text(&hlir, "total").as_deref(), Some("six"));
+    assert_eq!(returned_text(&hlir, "first_even").as_deref(), Some("found"));
+}
+
+#[test]
+fn test_evaluate_reports_non_bool_condition() {
+    let source = r#"
+template {
+    func f() {
+        if 1 + 1 { return text { "a" } }
+    }
+}
+document { f() }
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    evaluate(&mut hlir);
+    assert_eq!(hlir.diagnostics.len(), 1);
+    assert!(
+        hlir.diagnostics[0]
+            .message
+            .contains("Condition must be a `Bool`, found `Int`")
+    );
+    let span = hlir.diagnostics[0].span;
+    assert_eq!(
+        &source[span.start..span.end],
+        "if 1 + 1 { return text { \"a\" } }"
+    );
+}
+
+#[test]
+fn test_evaluate_reports_runaway_loop() {
+    let source = r#"
+template {
+    func f() {
+        let n = 0
+        while n >= 0 { n = n + 1 }
+    }
+}
+document { f() }
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    evaluate(&mut hlir);
+    assert_eq!(hlir.diagnostics.len(), 1);
+    assert!(hlir.diagnostics[0].message.contains("did not finish"));
+}
+
+#[test]
+fn test_lower_control_flow_errors() {
+    let source = r#"
+template {
+    const top = 1
+    if top > 0 { let x = 1 }
+    func f() {
+        const c = 1
+        c = 2
+        for i in 0..3 { let inner = i }
+        inner = 1
+        top = 2
+    }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<&str> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Control flow is only allowed inside a function",
+            "Cannot assign twice to constant `c`",
+            "Unknown variable `inner`",
+            "Cannot assign to `top` inside a function",
+        ]
+    );
+}
+
+#[test]
+fn test_control_flow_and_tables_in_returned_elements_are_reported() {
+    let source = r#"
+template {
+    func card(show: Bool) {
+        return section {
+            text { "kept" }
+            if show { text { "shown" } }
+            for i in 0..2 { text { "{i}" } }
+            table { row { cell { "a" } } }
+        }
+    }
+}
+document { card(true) }
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    let messages: Vec<(&str, Option<&str>)> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| (d.message.as_str(), d.help.as_deref()))
+        .collect();
+    let control_flow = (
+        "`if` and `for` cannot be used inside an element a function returns",
+        Some(
+            "return a different element from each branch of an `if` statement, \
+             or loop over the call in the `document` block",
+        ),
+    );
+    assert_eq!(
+        messages,
+        [
+            control_flow,
+            control_flow,
+            (
+                "Tables cannot be returned from a function",
+                Some("write the table in the `document` block")
+            ),
+        ]
+    );
+    let span = hlir.diagnostics[0].span;
+    assert!(source[span.start..span.end].starts_with("if show"));
+
+    // nothing is drawn in their place
+    hlir.diagnostics.clear();
+    evaluate(&mut hlir);
+    assert_eq!(document_texts(&hlir), ["kept"]);
+}
+
+/// Text of every element emitted by the document, children included
+fn document_texts(hlir: &HLIRModule) -> Vec<String> {
+    fn collect(hlir: &HLIRModule, index: usize, out: &mut Vec<String>) {
+        match &hlir.elements[index] {
+            HlirElement::Text { content, .. }
+            | HlirElement::Link { content, .. }
+            | HlirElement::TableCell { content, .. } => out.push(content.clone()),
+            HlirElement::Image { .. } | HlirElement::Code { .. } => {}
+            HlirElement::Section { children, .. }
+            | HlirElement::List { children, .. }
+            | HlirElement::Table { children, .. }
+            | HlirElement::Fragment { children, .. } => {
+                for child in children {
+                    collect(hlir, *child, out);
+                }
+            }
+        }
+    }
+    let document = &hlir.functions[&Id::Func(FuncId(hlir.functions.len() - 1))];
+    let mut out = Vec::new();
+    for op in &document.body.ops {
+        if let Op::HlirElementEmit { index, .. }
+        | Op::Call {
+            instance: Some(index),
+            ..
+        } = op
+        {
+            collect(hlir, *index, &mut out);
+        }
+    }
+    out
+}
+
+#[test]
+fn test_nested_calls_fill_their_placeholders() {
+    let source = r#"
+template {
+    func card(title: String) { return text(class="card") { "{title}" } }
+    func panel(title: String) {
+        let upper = title + "!"
+        return section(class="panel") { card(upper) text { "after {title}" } }
+    }
+    func alias(title: String) { return { card(title) } }
+}
+document {
+    section(id="outer") { text { "before" } card("inner") }
+    panel("deep")
+    alias("short")
+}
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(
+        document_texts(&hlir),
+        ["before", "inner", "deep!", "after deep", "short"]
+    );
+
+    // the copy made for the call takes the place of its placeholder
+    let outer = hlir
+        .element_metadata
+        .iter()
+        .position(|metadata| metadata.id.as_deref() == Some("outer"))
+        .unwrap();
+    let HlirElement::Section { children, .. } = &hlir.elements[outer] else {
+        panic!("Expected Section");
+    };
+    let card = children[1];
+    assert!(
+        matches!(&hlir.elements[card], HlirElement::Text { content, .. } if content == "inner")
+    );
+    assert_eq!(hlir.element_metadata[card].parent, Some(outer));
+    assert_eq!(hlir.element_metadata[card].classes, ["card"]);
+    // the calls in the body are drawn, the nested one only inside the section
+    assert_eq!(instances(&hlir).len(), 2);
+}
+
+#[test]
+fn test_nested_call_recursion_is_reported() {
+    let source = r#"
+template {
+    func forever(n: Int) { return section { forever(n + 1) } }
+}
+document { forever(0) }
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    evaluate(&mut hlir);
+    assert_eq!(hlir.diagnostics.len(), 1, "{:?}", hlir.diagnostics);
+    assert_eq!(
+        hlir.diagnostics[0].message,
+        "Recursion too deep in `forever`"
+    );
+}
+
+#[test]
+fn test_lower_document_if_keeps_taken_branch() {
+    let source = r#"
+template {
+    const draft = 1
+}
+document {
+    if draft == 1 { text { "DRAFT" } } else { text { "final" } }
+    if draft > 1 { text { "never" } }
+    text { "body" }
+}
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(document_texts(&hlir), ["DRAFT", "body"]);
+}
+
+#[test]
+fn test_lower_document_for_emits_element_per_iteration() {
+    let source = r#"
+template {
+    const count = 3
+}
+document {
+    list (class="items") {
+        item { "header" }
+        for i in 1..count + 1 { item { "Item {i}" } }
+    }
+    section {
+        for i in 0..2 {
+            if i == 0 { text { "{i} first" } } else { text { "{i} rest" } }
+        }
+    }
+}
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(
+        document_texts(&hlir),
+        ["header", "Item 1", "Item 2", "Item 3", "0 first", "1 rest"]
+    );
+
+    // every iteration is its own element with metadata pointing at the list
+    let list_index = hlir
+        .element_metadata
+        .iter()
+        .position(|m| m.element_type == "list")
+        .unwrap();
+    let items = hlir
+        .element_metadata
+        .iter()
+        .filter(|m| m.parent == Some(list_index))
+        .count();
+    assert_eq!(items, 4);
+}
+
+#[test]
+fn test_document_text_evaluates_expressions() {
+    let source = r#"
+template {
+    const m = { a: 1 }
+    const xs = [1, 2]
+}
+document {
+    text { "sum {1 + 2}, len {len(xs)}, a {m.a}, {{braces}}" }
+    for x in xs { text { "{x * 10}" } }
+}
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(
+        document_texts(&hlir),
+        ["sum 3, len 2, a 1, {braces}", "10", "20"]
+    );
+}
+
+#[test]
+fn test_document_text_reports_bad_expressions() {
+    let source = r#"
+template {
+    const m = { a: 1 }
+}
+document {
+    text { "{undefined_thing} and {m.b}" }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<_> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        ["Unknown variable `undefined_thing`", "No entry `b` in map"]
+    );
+    let spans: Vec<_> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| &source[d.span.start..d.span.end])
+        .collect();
+    assert_eq!(spans, ["undefined_thing", "m.b"]);
+    assert_eq!(document_texts(&hlir), [" and "]);
+}
+
+#[test]
+fn test_text_escapes_are_read_once() {
+    let source = r#"
+template {
+    const name = "docs"
+    func path(dir: String) {
+        return text { "C:\\{dir}" }
+    }
+}
+document {
+    text { "C:\\{name}\\" }
+    text { "\{name\} {{name}}" }
+    path("bin")
+}
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(
+        document_texts(&hlir),
+        [r"C:\docs\", "{name} {name}", r"C:\bin"]
+    );
+
+    // spans still point at the expression after escapes
+    let source = r#"
+document {
+    text { "\"q\" \\ {missing}" }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let spans: Vec<_> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| &source[d.span.start..d.span.end])
+        .collect();
+    assert_eq!(spans, ["missing"]);
+}
+
+#[test]
+fn test_lower_document_control_flow_errors() {
+    let source = r#"
+document {
+    if 1 { text { "a" } }
+    for i in "abc" { text { "b" } }
+    for i in missing..3 { text { "c" } }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<&str> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Condition must be a `Bool`, found `Int`",
+            "Cannot iterate over a `String`",
+            "Unknown variable `missing`",
+        ]
+    );
+    let span = hlir.diagnostics[1].span;
+    assert_eq!(&source[span.start..span.end], "\"abc\"");
+}
+
+#[test]
+fn test_function_params_fill_text_and_attributes() {
+    let source = r#"
+template {
+    func badge(label: String, count: Int) {
+        if count > 1 {
+            return text(class="badge {label}") { "*{label}*: {count} items" }
+        }
+        return text { "{label}: one item" }
+    }
+}
+document { badge("cart", 3) }
+"#;
+    let hlir = evaluated(source);
+    let func = hlir.functions.values().find(|f| f.name == "badge").unwrap();
+    assert_eq!(func.args, [Type::String, Type::Int]);
+    let names: Vec<&str> = func.params.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["label", "count"]);
+
+    let element = instances(&hlir)[0];
+    let HlirElement::Text { content, runs, .. } = &hlir.elements[element] else {
+        panic!("Expected Text");
+    };
+    assert_eq!(content, "cart: 3 items");
+    assert_eq!(runs[0].text, "cart");
+    assert_eq!(runs[1].text, ": 3 items");
+    assert_eq!(hlir.element_metadata[element].classes, ["badge", "cart"]);
+    // the function's own element keeps the parameters as written
+    assert_eq!(
+        returned_text(&hlir, "badge").as_deref(),
+        Some("{label}: {count} items")
+    );
+}
+
+#[test]
+fn test_params_shadow_globals_declared_before_or_after() {
+    for template in [
+        r#"
+    const label = "global"
+    func badge(label: String) { return text { "{label}" } }
+"#,
+        r#"
+    func badge(label: String) { return text { "{label}" } }
+    const label = "global"
+"#,
+    ] {
+        let source = format!(
+            "template {{{}}}\ndocument {{ badge(\"cart\") }}\n",
+            template
+        );
+        let hlir = evaluated(&source);
+        let HlirElement::Text { content, .. } = &hlir.elements[instances(&hlir)[0]] else {
+            panic!("Expected Text");
+        };
+        assert_eq!(content, "cart");
+    }
+}
+
+/// Elements made for the calls in the document, in order
+fn instances(hlir: &HLIRModule) -> Vec<usize> {
+    let document = hlir
+        .functions
+        .values()
+        .find(|f| f.name == "__document")
+        .unwrap();
+    document
+        .body
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::Call { instance, .. } => *instance,
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_each_call_gets_its_own_element() {
+    let source = r#"
+template {
+    func intro(title: String, kind: String) {
+        return section(class="intro {kind}") {
+            text { "{title}" }
+        }
+    }
+}
+document {
+    intro("First", "plain")
+    intro("Second", "loud")
+}
+style {
+    .loud {
+        color = "red"
+    }
+}
+"#;
+    let mut hlir = evaluated(source);
+    resolve_styles(&mut hlir);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+
+    let calls = instances(&hlir);
+    assert_eq!(calls.len(), 2);
+    assert_ne!(calls[0], calls[1]);
+    let color = |index: usize| {
+        let metadata = &hlir.element_metadata[index];
+        let node = hlir.attributes.find_node(metadata.attributes_ref).unwrap();
+        node.computed.get("color")
+    };
+    for (section, (title, class, expected)) in calls.iter().zip([
+        ("First", "plain", None),
+        ("Second", "loud", Some("red".to_string())),
+    ]) {
+        assert_eq!(hlir.element_metadata[*section].classes, ["intro", class]);
+        assert_eq!(color(*section), expected);
+        let [text] = hlir.elements[*section].children() else {
+            panic!("Expected one child, got {:?}", hlir.elements[*section]);
+        };
+        assert_eq!(hlir.element_metadata[*text].parent, Some(*section));
+        assert!(matches!(
+            &hlir.elements[*text],
+            HlirElement::Text { content, .. } if content == title
+        ));
+        // inherited from the section of this call only
+        assert_eq!(color(*text), expected);
+    }
+}
+
+#[test]
+fn test_call_arguments_checked_against_params() {
+    let source = r#"
+template {
+    const name = "Ada"
+    func greet(who: String, times: Int, size: Float) { return text { "{who}" } }
+}
+document {
+    greet(name, 2)
+    greet(2, "twice", 1)
+    greet(missing, 2, 1.5)
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<&str> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Wrong number of arguments to `greet`: expected 3, found 2",
+            "Type mismatch in argument 1 of `greet`: expected `String`, found `Int`",
+            "Type mismatch in argument 2 of `greet`: expected `Int`, found `String`",
+            "Unknown variable `missing`",
+        ]
+    );
+    let span = hlir.diagnostics[2].span;
+    assert_eq!(&source[span.start..span.end], "\"twice\"");
+}
+
+#[test]
+fn test_named_arguments_defaults_and_expressions() {
+    let source = r#"
+template {
+    const base = 4
+    func card(title: String, price: Int, note: String = "none", scale: Float = 1) {
+        return text { "{title}: {price} ({note}, {scale})" }
+    }
+}
+document {
+    for quarter in 1..3 {
+        card(title="Q{quarter}", price=base * quarter)
+    }
+    card("Plain", base + 1, scale=2.5, note="sale")
+}
+"#;
+    let hlir = evaluated(source);
+    let texts: Vec<&str> = instances(&hlir)
+        .into_iter()
+        .map(|element| match &hlir.elements[element] {
+            HlirElement::Text { content, .. } => content.as_str(),
+            other => panic!("Expected Text, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        texts,
+        ["Q1: 4 (none, 1)", "Q2: 8 (none, 1)", "Plain: 5 (sale, 2.5)"]
+    );
+}
+
+#[test]
+fn test_bad_named_and_default_arguments_are_reported() {
+    let source = r#"
+template {
+    func card(title: String, size: Int = "big") { return text { "{title}" } }
+    func pair(a: Int, b: Int = 1) { return text { "{a}" } }
+}
+document {
+    card(title="a", colour="red")
+    card(title="a", title="b")
+    pair(b=2, 3)
+    pair(b=2)
+    pair(1, 2, 3)
+    pair(a="one")
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<&str> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Default value must be `Int`, found `String`",
+            "`card` has no parameter named `colour`",
+            "Missing argument `size` in call to `card`",
+            "Parameter `title` of `card` is given more than once",
+            "Missing argument `size` in call to `card`",
+            "Positional argument after a named argument",
+            "Missing argument `a` in call to `pair`",
+            "Missing argument `a` in call to `pair`",
+            "Wrong number of arguments to `pair`: expected 1 to 2, found 3",
+            "Type mismatch in argument `a` of `pair`: expected `Int`, found `String`",
+        ]
+    );
+    let span = hlir.diagnostics[1].span;
+    assert_eq!(&source[span.start..span.end], "colour=\"red\"");
+}
+
+#[test]
+fn test_functions_returning_values() {
+    let source = r#"
+template {
+    func tier(price: Int) -> String {
+        if price > 10 {
+            return "premium"
+        }
+        return "basic"
+    }
+    func label(name: String, price: Int) -> String {
+        return "{name} ({price})"
+    }
+    func card(title: String, price: Int) {
+        return text(class="price {tier(price)}") { "{title}" }
+    }
+}
+document {
+    card(label("Tea", 4), 4)
+    card(label("Cake", 12), 12)
+    text(class="{tier(20)}") { "Total" }
+}
+"#;
+    let hlir = evaluated(source);
+    let func = hlir.functions.values().find(|f| f.name == "tier").unwrap();
+    assert_eq!(func.return_type, Some(Type::String));
+
+    let calls = instances(&hlir);
+    assert_eq!(calls.len(), 2);
+    let total = hlir
+        .elements
+        .iter()
+        .position(|e| matches!(e, HlirElement::Text { content, .. } if content == "Total"))
+        .unwrap();
+    assert_eq!(hlir.element_metadata[total].classes, ["premium"]);
+    for (text, (title, tier)) in calls
+        .iter()
+        .zip([("Tea (4)", "basic"), ("Cake (12)", "premium")])
+    {
+        assert!(matches!(
+            &hlir.elements[*text],
+            HlirElement::Text { content, .. } if content == title
+        ));
+        assert_eq!(hlir.element_metadata[*text].classes, ["price", tier]);
+    }
+}
+
+#[test]
+fn test_bad_value_returns_are_reported() {
+    let source = r#"
+template {
+    func name() -> String { return text { "name" } }
+    func count() -> Int { return "many" }
+    func card() { return 3 }
+    func none() -> Int { let x = 1 }
+    func shape() -> Shape { return text { "shape" } }
+}
+document {
+    name()
+    text(class="{count()}") { "count" }
+    text(class="{none()}") { "none" }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let diagnostics = validate(&hlir).expect_err("Should catch bad returns");
+    let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "Unknown return type `Shape`",
+            "`name` returns `String`, not an element",
+            "Return type mismatch in `count`: expected `Int`, found `String`",
+            "Missing return statement in `none`",
+            "Return type mismatch: expected `String`, found an element",
+            "Return type mismatch: expected an element, found a value",
+        ]
+    );
+}
+
+#[test]
+fn test_validation_checks_types_in_function_bodies() {
+    let source = r#"
+template {
+    const LIMIT: Float = 10
+    var title: String = "Report"
+    func scale(n: Int) -> Int {
+        return n * 2
+    }
+    func label(count: Int, unit: String) -> String {
+        let total: Int = count + 0.5
+        let words = unit + "s"
+        if count {
+            return unit
+        }
+        while count < LIMIT {
+            total = "many"
+        }
+        return scale(words) + 1
+    }
+    func maybe(flag: Bool) -> String {
+        if flag {
+            return "yes"
+        }
+    }
+}
+document {
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+    let diagnostics = validate(&hlir).expect_err("Should catch type errors");
+    let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "Type mismatch: `total` is declared `Int`, found `Float`",
+            "Condition must be a `Bool`, found `Int`",
+            "Type mismatch: `total` is declared `Int`, found `String`",
+            "Type mismatch in argument 1 of `scale`: expected `Int`, found `String`",
+            "Return type mismatch in `label`: expected `String`, found `Int`",
+            "Missing return statement in `maybe`",
+        ]
+    );
+    let span = diagnostics[3].span;
+    assert_eq!(&source[span.start..span.end], "scale(words)");
+}
+
+#[test]
+fn test_validation_reports_each_identical_error() {
+    let source = r#"
+template {
+    func a(n: Int, s: String) -> Int {
+        return n + s
+    }
+    func b(n: Int, s: String) -> Int {
+        return n + s
+    }
+}
+document {
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let diagnostics = validate(&hlir).expect_err("Should catch type errors");
+    let spans: Vec<&str> = diagnostics
+        .iter()
+        .map(|d| {
+            assert_eq!(
+                d.message,
+                "Binary operation type mismatch: cannot apply `+` to `Int` and `String`"
+            );
+            &source[d.span.start..d.span.end]
+        })
+        .collect();
+    assert_eq!(spans, ["n + s", "n + s"]);
+    assert!(diagnostics[0].span.start < diagnostics[1].span.start);
+}
+
+#[test]
+fn test_logical_operators_short_circuit() {
+    let source = r#"
+template {
+    const xs = []
+    const ys = [1]
+    const guarded = len(xs) > 0 && xs[0] == 1
+    const fallback = len(xs) == 0 || xs[0] == 1
+    func starts_with_one(items: List) -> Bool {
+        return len(items) > 0 && items[0] == 1
+    }
+    const empty = starts_with_one(xs)
+    const one = starts_with_one(ys)
+}
+document {
+}
+"#;
+    let hlir = evaluated(source);
+    assert!(validate(&hlir).is_ok());
+    let value = |name: &str| match hlir.globals.values().find(|g| g.name == name).unwrap().init {
+        Literal::Bool(value) => value,
+        ref other => panic!("Expected a Bool, got {:?}", other),
+    };
+    assert!(!value("guarded"));
+    assert!(value("fallback"));
+    assert!(!value("empty"));
+    assert!(value("one"));
+}
+
+#[test]
+fn test_functions_recurse_and_call_later_functions() {
+    let source = r#"
+template {
+    const early = double(3)
+    func factorial(n: Int) -> Int {
+        if n <= 1 {
+            return 1
+        }
+        return n * factorial(n - 1)
+    }
+    func quadruple(n: Int) -> Int {
+        return double(double(n))
+    }
+    func double(n: Int) -> Int {
+        return n * 2
+    }
+    const fact = factorial(5)
+    const four = quadruple(1)
+}
+document {
+}
+"#;
+    let hlir = evaluated(source);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+    assert!(validate(&hlir).is_ok());
+    let value = |name: &str| match hlir.globals.values().find(|g| g.name == name).unwrap().init {
+        Literal::Int(value) => value,
+        ref other => panic!("Expected an Int, got {:?}", other),
+    };
+    assert_eq!(value("early"), 6);
+    assert_eq!(value("fact"), 120);
+    assert_eq!(value("four"), 4);
+}
+
+#[test]
+fn test_runaway_recursion_is_reported() {
+    let source = r#"
+template {
+    func forever(n: Int) -> Int {
+        for i in 0..1 {
+            while n >= 0 {
+                return forever(n + i + 1)
+            }
+        }
+        return n
+    }
+    const x = forever(0)
+}
+document {
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    assert_eq!(hlir.diagnostics.len(), 1, "{:?}", hlir.diagnostics);
+    let diagnostic = &hlir.diagnostics[0];
+    assert!(
+        diagnostic
+            .message
+            .contains("Recursion too deep in `forever`")
+    );
+    assert_eq!(
+        &source[diagnostic.span.start..diagnostic.span.end],
+        "forever(n + i + 1)"
+    );
+}
+
+#[test]
+fn test_colour_names_only_where_a_color_is_expected() {
+    let source = r#"
+template {
+    func swatch(label: String, tint: Color = navy) -> String {
+        return "{label} {tint}"
+    }
+    const named = swatch("a", tint=red)
+    const default = swatch("b")
+    const typo = red
+}
+document {
+    text(color=green) { "{named} {default}" }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<_> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(messages, ["Unknown variable `red`"]);
+    let span = hlir.diagnostics[0].span;
+    assert_eq!(span.start, source.rfind("red").unwrap());
+
+    let value = |name: &str| match &hlir.globals.values().find(|g| g.name == name).unwrap().init {
+        Literal::String(value) => value.clone(),
+        other => panic!("Expected a String, got {:?}", other),
+    };
+    assert_eq!(value("named"), "a red");
+    assert_eq!(value("default"), "b navy");
+}
+
+#[test]
+fn test_bool_and_color_values() {
+    let source = r#"
+template {
+    const brand: Color = #1e90ff
+    const on = 1 < 2
+    func card(title: String, tint: Color, quiet: Bool) {
+        return text(class="{title}", hidden=quiet) { "{title}" }
+    }
+    func shade(tint: Color) -> String {
+        if tint == #ff0000 {
+            return "warm"
+        }
+        return "cool"
+    }
+}
+document {
+    card("a", brand, false)
+    card("b", red, on)
+    text(class=shade(orange)) { "c" }
+}
+style {
+    .cool {
+        color = rgb(30, 144, 255)
+    }
+}
+"#;
+    let mut hlir = evaluated(source);
+    resolve_styles(&mut hlir);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+
+    let mut globals: Vec<_> = hlir.globals.values().collect();
+    globals.sort_by_key(|global| global.span.start);
+    assert!(matches!(
+        (globals[0].ty, &globals[0].init),
+        (Type::Color, Literal::Color(color)) if color == "#1e90ff"
+    ));
+    assert!(matches!(
+        (globals[1].ty, &globals[1].init),
+        (Type::Bool, Literal::Bool(true))
+    ));
+
+    let hidden: Vec<bool> = instances(&hlir)
+        .iter()
+        .map(|&card| {
+            let node = hlir.attributes.find_node(hlir.elements[card].attributes());
+            node.unwrap().inline.hidden
+        })
+        .collect();
+    assert_eq!(hidden, [false, true]);
+
+    let metadata = hlir
+        .element_metadata
+        .iter()
+        .find(|metadata| metadata.classes == ["cool"])
+        .expect("Expected the element styled by `shade`");
+    let node = hlir.attributes.find_node(metadata.attributes_ref).unwrap();
+    let color = node.computed.get("color").unwrap();
+    assert_eq!(color, "rgb(30, 144, 255)");
+    assert_eq!(color.parse::<Color>().unwrap().g, 144.0 / 255.0);
+}
+
+#[test]
+fn test_bool_attributes_must_be_bools() {
+    let source = r#"
+document {
+    text(hidden="true") { "a" }
+    text(hidden=1) { "b" }
+    text(hidden=true) { "c" }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<&str> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Invalid hidden value: \"true\", expected `true` or `false` without quotes",
+            "Invalid hidden value: 1, expected `true` or `false`",
+        ]
+    );
+}
+
+#[test]
+fn test_lists_and_maps() {
+    let source = r#"
+template {
+    const books = [
+        { title: "Dune", year: 1965, tags: ["sf", "classic"] },
+        { title: "Emma", year: 1815, tags: [] },
+    ]
+    const author = { name: "Ada", "home page": "ada.dev" }
+    func tag_count(items: List) -> Int {
+        var total = 0
+        for book in items {
+            total = total + len(book.tags)
+        }
+        return total
+    }
+    func shelf(items: List) {
+        return text(class="n{len(items)}") { "{items[0].title} and {tag_count(items)} tags" }
+    }
+}
+document {
+    text { "By {author.name}" }
+    for book in books {
+        text(class=book.title) { "{book.title} ({book.year})" }
+    }
+    for i in 0..len(books) {
+        text { "{i}: {books[i].tags}" }
+    }
+    for key in author {
+        text { "{key}" }
+    }
+    shelf(books)
+}
+"#;
+    let hlir = evaluated(source);
+
+    let books = hlir.globals.values().find(|g| g.name == "books").unwrap();
+    assert_eq!(books.ty, Type::List);
+    let Literal::List(items) = &books.init else {
+        panic!("Expected a list, got {:?}", books.init);
+    };
+    assert!(matches!(
+        &items[1],
+        Literal::Map(entries) if entries[0].0 == "title" && matches!(entries[1].1, Literal::Int(1815))
+    ));
+
+    assert_eq!(
+        document_texts(&hlir),
+        [
+            "By Ada",
+            "Dune (1965)",
+            "Emma (1815)",
+            "0: [sf, classic]",
+            "1: []",
+            "name",
+            "home page",
+            "Dune and 2 tags",
+        ]
+    );
+    let classes: Vec<&str> = hlir
+        .element_metadata
+        .iter()
+        .flat_map(|metadata| metadata.classes.iter().map(String::as_str))
+        .collect();
+    // the first is the element of `shelf` as written
+    assert_eq!(classes[1..], ["Dune", "Emma", "n2"]);
+
+    let shelf = instances(&hlir)[0];
+    let HlirElement::Text { content, .. } = &hlir.elements[shelf] else {
+        panic!("Expected text, got {:?}", hlir.elements[shelf]);
+    };
+    assert_eq!(content, "Dune and 2 tags");
+}
+
+#[test]
+fn test_list_and_map_errors() {
+    let source = r#"
+template {
+    const xs = [1, 2]
+    func pick(n: Int, items: List) -> Int {
+        let a = n[0]
+        let b = n.size
+        let c = len(n, n)
+        for v in n {
+        }
+        return items["a"]
+    }
+}
+document {
+    text(class=xs[5]) { "a" }
+    text(class=xs[0].name) { "b" }
+    text(class=len(1)) { "c" }
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let hlir = lower(&ast);
+    let messages: Vec<&str> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "`len` takes 1 argument, found 2",
+            "Index 5 is out of bounds for a list of length 2",
+            "Cannot access `.name` of a `Int`",
+            "`len` takes a `List`, `Map` or `String`, found `Int`",
+        ]
+    );
+
+    let diagnostics = validate(&hlir).expect_err("Should catch type errors");
+    let messages: Vec<&str> = diagnostics[4..]
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Cannot index a `Int`",
+            "Cannot access `.size` of a `Int`",
+            "Cannot iterate over a `Int`",
+            "List index must be an `Int`, found `String`",
+        ]
+    );
+}
+
+#[test]
+fn test_fragments_are_placed_in_the_caller() {
+    let source = r#"
+template {
+    func pair(first: String, second: String) {
+        return {
+            text { "{first}" }
+            text(class="{second}") { "{second}" }
+        }
+    }
+    func nothing() {
+        return
+    }
+}
+document {
+    text { "before" }
+    pair("one", "two")
+    nothing()
+    text { "after" }
+}
+style {
+    .two {
+        color = "red"
+    }
+}
+"#;
+    let mut hlir = evaluated(source);
+    resolve_styles(&mut hlir);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+
+    let calls = instances(&hlir);
+    let [pair, nothing] = calls[..] else {
+        panic!("Expected two calls, got {:?}", calls);
+    };
+    let HlirElement::Fragment { children, .. } = &hlir.elements[pair] else {
+        panic!("Expected Fragment, got {:?}", hlir.elements[pair]);
+    };
+    let texts: Vec<&str> = children
+        .iter()
+        .map(|child| {
+            assert_eq!(hlir.element_metadata[*child].parent, Some(pair));
+            match &hlir.elements[*child] {
+                HlirElement::Text { content, .. } => content.as_str(),
+                other => panic!("Expected Text, got {:?}", other),
+            }
+        })
+        .collect();
+    assert_eq!(texts, ["one", "two"]);
+    let node = hlir
+        .attributes
+        .find_node(hlir.elements[children[1]].attributes())
+        .unwrap();
+    assert_eq!(node.computed.get("color"), Some("red".to_string()));
+    assert!(hlir.elements[nothing].children().is_empty());
+
+    // the elements follow each other as if written in the document
+    let mut layout = setup_layout(&hlir);
+    layout.compute_layout(595.0, 842.0);
+    assert!(layout.get_element_layout(pair).is_none());
+    let after = hlir
+        .elements
+        .iter()
+        .position(|e| matches!(e, HlirElement::Text { content, .. } if content == "after"))
+        .unwrap();
+    let tops: Vec<f32> = [children[0], children[1], after]
+        .iter()
+        .map(|element| layout.get_element_layout(*element).unwrap().y)
+        .collect();
+    assert_eq!(tops, [LINE_HEIGHT, 2.0 * LINE_HEIGHT, 3.0 * LINE_HEIGHT]);
+}
+
+#[test]
+fn test_function_text_is_built_per_call() {
+    let source = r#"
+template {
+    func row(name: String, price: Int, count: Int) {
+        let total = price * count
+        var label = "cheap"
+        if total > 10 {
+            label = "dear"
+        }
+        return text { "{name} x{count}: {total} ({label}) " span(class="tax") { "tax {total / 5}" } }
+    }
+    func site(page: String) {
+        return link(href="https://example.com") { "{page} page" }
+    }
+}
+document {
+    row("Tea", 5, 2)
+    row("Cake", 4, 3)
+    site("about")
+}
+"#;
+    let hlir = evaluated(source);
+    let calls = instances(&hlir);
+    let texts: Vec<(&str, Vec<&str>)> = calls[..2]
+        .iter()
+        .map(|element| match &hlir.elements[*element] {
+            HlirElement::Text { content, runs, .. } => (
+                content.as_str(),
+                runs.iter().map(|run| run.text.as_str()).collect(),
+            ),
+            other => panic!("Expected Text, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            (
+                "Tea x2: 10 (cheap) tax 2",
+                vec!["Tea x2: 10 (cheap) ", "tax 2"]
+            ),
+            (
+                "Cake x3: 12 (dear) tax 2",
+                vec!["Cake x3: 12 (dear) ", "tax 2"]
+            ),
+        ]
+    );
+    assert!(matches!(
+        &hlir.elements[calls[2]],
+        HlirElement::Link { href, content, .. }
+            if href == "https://example.com" && content == "about page"
+    ));
+}
+
+#[test]
+fn test_bad_function_text_is_reported_at_the_expression() {
+    let source = r#"
+template {
+    func row(count: Int) {
+        return text { "{count} of {missing}" }
+    }
+    func share(count: Int) {
+        return text { "{100 / count} each" }
+    }
+}
+document {
+    row(1)
+    share(0)
+}
+"#;
+    let (ast, _) = parse(lex(source));
+    let mut hlir = lower(&ast);
+    evaluate(&mut hlir);
+    let reported: Vec<(&str, &str)> = hlir
+        .diagnostics
+        .iter()
+        .map(|d| (d.message.as_str(), &source[d.span.start..d.span.end]))
+        .collect();
+    assert_eq!(
+        reported,
+        [
+            ("Unknown variable `missing`", "missing"),
+            ("Division by zero", "100 / count"),
+        ]
+    );
+}
+
+#[test]
+fn test_text_values_are_not_read_as_markup() {
+    let source = r#"
+template {
+    const s = "{{secret}} *bold*"
+    func show(label: String) {
+        return text { "{s} {label}" }
+    }
+}
+document {
+    text { "{s}" }
+    show("{{x}}")
+}
+"#;
+    let hlir = evaluated(source);
+    assert_eq!(
+        document_texts(&hlir),
+        ["{secret} *bold*", "{secret} *bold* {x}"]
+    );
+}
+
+// ============================================================================
+// Validation Tests
+// ============================================================================
 
-/*
 #[test]
 #[should_panic(expected = "Type mismatch")]
 fn test_validation_catches_type_mismatch_in_assignment() {
@@ -406,7 +2352,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // This should fail validation - assigning String to Int variable
@@ -422,7 +2368,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     pyrus::hlir::validate(&hlir).expect("Should catch undefined function");
@@ -442,7 +2388,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     pyrus::hlir::validate(&hlir).expect("Should catch wrong argument count");
@@ -462,7 +2408,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     pyrus::hlir::validate(&hlir).expect("Should catch wrong argument type");
@@ -481,7 +2427,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     pyrus::hlir::validate(&hlir).expect("Should catch return type mismatch");
@@ -500,7 +2446,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     pyrus::hlir::validate(&hlir).expect("Should catch missing return");
@@ -518,7 +2464,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     pyrus::hlir::validate(&hlir).expect("Should catch duplicate symbol");
@@ -536,7 +2482,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     pyrus::hlir::validate(&hlir).expect("Should catch duplicate function");
@@ -553,7 +2499,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     pyrus::hlir::validate(&hlir).expect("Should catch binary op type error");
@@ -573,13 +2519,12 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // This should succeed
     pyrus::hlir::validate(&hlir).expect("Valid code should pass validation");
 }
-*/
 
 // ============================================================================
 // Edge Cases and Error Handling
@@ -597,7 +2542,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // Elements should be in document order
@@ -616,7 +2561,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     assert!(hlir.globals.is_empty());
@@ -635,7 +2580,7 @@ document {
 }
 "#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let hlir = lower(&ast);
 
     // Should have: outer section (0), inner section (1), text (2)
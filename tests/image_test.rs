@@ -0,0 +1,146 @@
+//! Tests for reading image headers and decoding PNGs
+
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use pyrus::image::{ImageFormat, ImageInfo, PixelFormat, decode_png};
+
+/// Build a PNG from already filtered scanlines (each starting with its filter byte)
+fn png(
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    chunks: &[(&[u8; 4], &[u8])],
+) -> Vec<u8> {
+    fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out.extend_from_slice(&[0; 4]); // CRC, not checked
+    }
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &ihdr);
+    for (kind, body) in chunks {
+        if *kind == b"IDAT" {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).unwrap();
+            chunk(&mut out, kind, &encoder.finish().unwrap());
+        } else {
+            chunk(&mut out, kind, body);
+        }
+    }
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[test]
+fn test_png_header() {
+    let bytes = png(640, 480, 8, 2, &[]);
+    let info = ImageInfo::from_bytes(&bytes).unwrap();
+    assert_eq!(info.format, ImageFormat::Png);
+    assert_eq!((info.width, info.height), (640, 480));
+}
+
+#[test]
+fn test_jpeg_header_skips_segments_before_frame() {
+    let mut bytes = vec![0xFF, 0xD8];
+    // APP0 segment with a 4 byte body
+    bytes.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x06, b'J', b'F', b'I', b'F']);
+    // baseline frame: precision, height 300, width 200, 3 components
+    bytes.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x11, 8, 0x01, 0x2C, 0x00, 0xC8, 3]);
+    let info = ImageInfo::from_bytes(&bytes).unwrap();
+    assert_eq!(info.format, ImageFormat::Jpeg { components: 3 });
+    assert_eq!((info.width, info.height), (200, 300));
+}
+
+#[test]
+fn test_unsupported_images() {
+    assert_eq!(
+        ImageInfo::from_bytes(b"GIF89a").unwrap_err(),
+        "unsupported image format"
+    );
+    assert_eq!(
+        ImageInfo::from_bytes(&[0xFF, 0xD8, 0xFF]).unwrap_err(),
+        "truncated JPEG header"
+    );
+    let mut interlaced = png(1, 1, 8, 0, &[]);
+    interlaced[28] = 1;
+    assert_eq!(
+        ImageInfo::from_bytes(&interlaced).unwrap_err(),
+        "interlaced PNG images are not supported"
+    );
+}
+
+#[test]
+fn test_decode_png_rejects_huge_sizes() {
+    // the header alone would ask for gigabytes of pixels
+    let bytes = png(100_000, 100_000, 8, 6, &[(b"IDAT", &[0; 16])]);
+    assert_eq!(
+        decode_png(&bytes).unwrap_err(),
+        "PNG image of 100000x100000 pixels is too large"
+    );
+    let bytes = png(u32::MAX, u32::MAX, 16, 6, &[(b"IDAT", &[0; 16])]);
+    assert!(decode_png(&bytes).unwrap_err().ends_with("is too large"));
+    let bytes = png(4, 4, 8, 2, &[(b"IDAT", &[0; 16])]);
+    assert_eq!(decode_png(&bytes).unwrap_err(), "truncated PNG data");
+}
+
+#[test]
+fn test_display_size_keeps_aspect_ratio() {
+    let info = ImageInfo::from_bytes(&png(200, 100, 8, 2, &[])).unwrap();
+    assert_eq!(info.display_size(None, None), (200.0, 100.0));
+    assert_eq!(info.display_size(Some(50.0), None), (50.0, 25.0));
+    assert_eq!(info.display_size(None, Some(50.0)), (100.0, 50.0));
+    assert_eq!(info.display_size(Some(10.0), Some(10.0)), (10.0, 10.0));
+}
+
+#[test]
+fn test_decode_png_undoes_filters() {
+    // 2x3 RGB with a Sub, an Up and a Paeth row
+    let rows: &[u8] = &[
+        1, 10, 20, 30, 5, 5, 5, // Sub: second pixel is 15 25 35
+        2, 1, 1, 1, 1, 1, 1, // Up: previous row + 1
+        4, 0, 0, 0, 0, 0, 0, // Paeth: copies the row above
+    ];
+    let image = decode_png(&png(2, 3, 8, 2, &[(b"IDAT", rows)])).unwrap();
+    assert_eq!(image.format, PixelFormat::Rgb);
+    assert_eq!(
+        image.pixels,
+        [
+            10, 20, 30, 15, 25, 35, //
+            11, 21, 31, 16, 26, 36, //
+            11, 21, 31, 16, 26, 36,
+        ]
+    );
+}
+
+#[test]
+fn test_decode_png_palette_and_alpha() {
+    // 2 bit palette indices, the second entry fully transparent
+    let palette: &[u8] = &[255, 0, 0, 0, 0, 255];
+    let transparency: &[u8] = &[255, 0];
+    let image = decode_png(&png(
+        2,
+        1,
+        2,
+        3,
+        &[
+            (b"PLTE", palette),
+            (b"tRNS", transparency),
+            (b"IDAT", &[0, 0b0001_0000]),
+        ],
+    ))
+    .unwrap();
+    assert_eq!(image.pixels, [255, 0, 0, 255, 255, 255]);
+
+    // grey with alpha is blended onto white
+    let image = decode_png(&png(1, 1, 8, 4, &[(b"IDAT", &[0, 0, 128])])).unwrap();
+    assert_eq!(image.format, PixelFormat::Grey);
+    assert_eq!(image.pixels, [127]);
+}
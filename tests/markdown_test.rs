@@ -0,0 +1,372 @@
+//! Tests for converting Markdown into document elements
+
+use pyrus::ast::{DocElement, Emphasis, Inline, InterpPart, Selector};
+use pyrus::diagnostics::Span;
+use pyrus::format::{format_source, print_ast};
+use pyrus::hlir::{HlirElement, evaluate, lower, validate};
+use pyrus::lexer::lex;
+use pyrus::markdown::{Block, ListItem, Piece, parse_blocks, parse_inlines, to_ast, to_elements};
+use pyrus::parser::parse;
+
+#[test]
+fn test_parse_blocks() {
+    let markdown = "\
+Title
+=====
+
+A paragraph
+over two lines.
+
+- one
+- two
+  continued
+1. first
+
+```rust extra
+let x = 1;
+
+x
+```
+---
+### Last ###";
+    assert_eq!(
+        parse_blocks(markdown),
+        [
+            Block::Heading {
+                level: 1,
+                text: "Title".to_string()
+            },
+            Block::Paragraph("A paragraph over two lines.".to_string()),
+            Block::List {
+                ordered: false,
+                items: vec![ListItem::new("one"), ListItem::new("two continued")]
+            },
+            Block::List {
+                ordered: true,
+                items: vec![ListItem::new("first")]
+            },
+            Block::Code {
+                lang: Some("rust".to_string()),
+                content: "let x = 1;\n\nx".to_string()
+            },
+            Block::Heading {
+                level: 3,
+                text: "Last".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_blocks_keeps_nested_lists() {
+    let markdown = "\
+- one
+  1. one.a
+  2. one.b
+     - deep
+- two
+\t* tabbed
+3. after";
+    let nested = |ordered, items| Block::List { ordered, items };
+    let item = |text: &str, lists| ListItem {
+        text: text.to_string(),
+        lists,
+    };
+    assert_eq!(
+        parse_blocks(markdown),
+        [
+            nested(
+                false,
+                vec![
+                    item(
+                        "one",
+                        vec![nested(
+                            true,
+                            vec![
+                                ListItem::new("one.a"),
+                                item("one.b", vec![nested(false, vec![ListItem::new("deep")])]),
+                            ]
+                        )]
+                    ),
+                    item("two", vec![nested(false, vec![ListItem::new("tabbed")])]),
+                ]
+            ),
+            nested(true, vec![ListItem::new("after")]),
+        ]
+    );
+}
+
+/// Pieces as (kind, text) pairs, links and images with their target
+fn pieces(text: &str) -> Vec<(String, String)> {
+    parse_inlines(text)
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Inline(Inline::Text(text)) => {
+                ("text".to_string(), InterpPart::plain_text(&text))
+            }
+            Piece::Inline(Inline::Emphasis(emphasis, text)) => {
+                (format!("{:?}", emphasis), InterpPart::plain_text(&text))
+            }
+            Piece::Inline(other) => panic!("Unexpected {:?}", other),
+            Piece::Link { text, href } => (format!("link {}", href), text),
+            Piece::Image { alt, src } => (format!("image {}", src), alt),
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_inlines() {
+    let pair = |kind: &str, text: &str| (kind.to_string(), text.to_string());
+    assert_eq!(
+        pieces("**bold *and* more**, _it_ and `` a`b ``"),
+        [
+            pair("Bold", "bold and more"),
+            pair("text", ", "),
+            pair("Italic", "it"),
+            pair("text", " and "),
+            pair("Code", "a`b"),
+        ]
+    );
+    assert_eq!(
+        pieces("see [the *docs*](https://example.com \"Docs\") or <mailto:a@b.c>"),
+        [
+            pair("text", "see "),
+            pair("link https://example.com", "the docs"),
+            pair("text", " or "),
+            pair("link mailto:a@b.c", "a@b.c"),
+        ]
+    );
+    assert_eq!(
+        pieces("![Logo](logo.png)"),
+        [pair("image logo.png", "Logo")]
+    );
+    // markers that open nothing stay as they are
+    assert_eq!(
+        pieces("snake_case, 2 * 3, \\*not\\* and [no link]"),
+        [pair("text", "snake_case, 2 * 3, *not* and [no link]")]
+    );
+}
+
+#[test]
+fn test_headings_open_nested_sections() {
+    let markdown = "\
+# One
+intro
+## One.a
+- [Home](#top)
+- plain [link](x) in text
+# Two";
+    let elements = to_elements(markdown, Span { start: 0, end: 0 });
+    let [
+        DocElement::Section { elements: one, .. },
+        DocElement::Section { elements: two, .. },
+    ] = &elements[..]
+    else {
+        panic!("Expected two sections, got {:?}", elements);
+    };
+    match &one[..] {
+        [
+            DocElement::Text {
+                inlines,
+                attributes,
+                ..
+            },
+            DocElement::Text { content: intro, .. },
+            DocElement::Section { elements: sub, .. },
+        ] => {
+            assert_eq!(attributes["class"].to_string(), "h1");
+            assert!(matches!(
+                &inlines[..],
+                [Inline::Emphasis(Emphasis::Bold, text)] if InterpPart::plain_text(text) == "One"
+            ));
+            assert_eq!(intro, "intro");
+            let [DocElement::Text { .. }, DocElement::List { items, .. }] = &sub[..] else {
+                panic!("Expected heading and list, got {:?}", sub);
+            };
+            // an item that is only a link is a link, others keep the text
+            assert!(matches!(
+                &items[..],
+                [
+                    DocElement::Link { href, .. },
+                    DocElement::Text { content, .. },
+                ] if href == "#top" && content == "plain link in text"
+            ));
+        }
+        other => panic!("Unexpected section {:?}", other),
+    }
+    assert_eq!(two.len(), 1);
+}
+
+#[test]
+fn test_lists_keep_order_and_nesting() {
+    let markdown = "\
+1. first
+   - inner
+2. second";
+    let elements = to_elements(markdown, Span { start: 0, end: 0 });
+    let [
+        DocElement::List {
+            items, attributes, ..
+        },
+    ] = &elements[..]
+    else {
+        panic!("Expected one list, got {:?}", elements);
+    };
+    assert_eq!(attributes["ordered"].to_string(), "true");
+    let [
+        DocElement::Text { content: first, .. },
+        DocElement::List {
+            items: inner,
+            attributes: inner_attributes,
+            ..
+        },
+        DocElement::Text {
+            content: second, ..
+        },
+    ] = &items[..]
+    else {
+        panic!(
+            "Expected an item, a nested list and an item, got {:?}",
+            items
+        );
+    };
+    assert_eq!((first.as_str(), second.as_str()), ("first", "second"));
+    assert!(!inner_attributes.contains_key("ordered"));
+    assert!(matches!(&inner[..], [DocElement::Text { content, .. }] if content == "inner"));
+
+    // the nested list is written as a list inside the list and reads back
+    let printed = print_ast(&to_ast(markdown));
+    let (parsed, diagnostics) = parse(lex(&printed));
+    assert!(diagnostics.is_empty(), "{:?}\n{}", diagnostics, printed);
+    let elements = parsed.document.unwrap().elements;
+    let [
+        DocElement::List {
+            items, attributes, ..
+        },
+    ] = &elements[..]
+    else {
+        panic!("Expected one list, got {:?}", elements);
+    };
+    assert_eq!(attributes["ordered"].to_string(), "true");
+    assert!(matches!(&items[1], DocElement::List { items, .. } if items.len() == 1));
+}
+
+#[test]
+fn test_to_ast_prints_as_a_document_with_starter_style() {
+    let markdown = "\
+# Notes
+
+Some **bold**text, a \"quote\", back\\\\slash and \\*stars\\*.
+
+See the [docs](https://example.com) on {braces} and `{}`.
+
+- [Home](https://example.com)
+";
+    let ast = to_ast(markdown);
+    let selectors: Vec<String> = ast
+        .style
+        .as_ref()
+        .unwrap()
+        .statements
+        .iter()
+        .map(|rule| match &rule.selector_list[..] {
+            [Selector::Type(kind)] => kind.clone(),
+            other => panic!("Expected a type selector, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(selectors, ["section", "text", "link", "list"]);
+
+    let printed = print_ast(&ast);
+    assert_eq!(format_source(&printed).unwrap(), printed);
+    let (parsed_ast, diagnostics) = parse(lex(&printed));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let elements = &parsed_ast.document.as_ref().unwrap().elements;
+    let [DocElement::Section { elements, .. }] = &elements[..] else {
+        panic!("Expected one section, got {:?}", elements);
+    };
+    let [
+        DocElement::Text { .. },
+        DocElement::Text {
+            content, inlines, ..
+        },
+        DocElement::Text {
+            inlines: linked, ..
+        },
+        DocElement::List { items, .. },
+    ] = &elements[..]
+    else {
+        panic!("Unexpected elements {:?}", elements);
+    };
+    assert_eq!(
+        content,
+        "Some boldtext, a \"quote\", back\\slash and *stars*."
+    );
+    assert!(matches!(
+        &inlines[1],
+        Inline::Span { content, .. }
+            if matches!(&content[..], [Inline::Emphasis(Emphasis::Bold, bold)] if InterpPart::plain_text(bold) == "bold")
+    ));
+    assert!(matches!(
+        &linked[1],
+        Inline::Link { href, content, .. }
+            if href == "https://example.com" && InterpPart::plain_text(content) == "docs"
+    ));
+    assert!(matches!(&items[..], [DocElement::Link { href, .. }] if href == "https://example.com"));
+
+    // and it makes a document with the same text
+    let mut hlir = lower(&parsed_ast);
+    assert!(validate(&hlir).is_ok(), "{:?}", hlir.diagnostics);
+    evaluate(&mut hlir);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+    let texts: Vec<_> = hlir
+        .elements
+        .iter()
+        .filter_map(|element| match element {
+            HlirElement::Text { content, .. } => Some(content.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            "Notes",
+            "Some boldtext, a \"quote\", back\\slash and *stars*.",
+            "See the docs on {braces} and {}.",
+        ]
+    );
+}
+
+#[test]
+fn test_braces_in_markdown_are_text() {
+    let source = r#"
+document {
+    markdown {
+        """
+        Sets look like {1, 2}.
+
+        Use `{}` for maps and *{key}* for a key.
+        """
+    }
+}
+"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let mut hlir = lower(&ast);
+    evaluate(&mut hlir);
+    assert!(hlir.diagnostics.is_empty(), "{:?}", hlir.diagnostics);
+    let texts: Vec<_> = hlir
+        .elements
+        .iter()
+        .filter_map(|element| match element {
+            HlirElement::Text { content, .. } => Some(content.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            "Sets look like {1, 2}.",
+            "Use {} for maps and {key} for a key."
+        ]
+    );
+}
@@ -1,4 +1,6 @@
-use pyrus::ast::{BinaryOp, DocElement, Expression, InterpPart, Statement, UnaryOp};
+use pyrus::ast::{
+    BinaryOp, DocElement, Emphasis, Expression, Inline, InterpPart, Statement, UnaryOp,
+};
 use pyrus::lexer::lex;
 use pyrus::parser::parse;
 
@@ -6,7 +8,7 @@ use pyrus::parser::parse;
 fn test_parse_empty_document() {
     let source = "document { }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     assert!(ast.document.is_some());
     assert!(ast.template.is_none());
     assert!(ast.style.is_none());
@@ -19,7 +21,7 @@ fn test_parse_empty_document() {
 fn test_parse_empty_template() {
     let source = "template { }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     assert!(ast.template.is_some());
     assert!(ast.document.is_none());
     assert!(ast.style.is_none());
@@ -32,7 +34,7 @@ fn test_parse_empty_template() {
 fn test_parse_empty_style() {
     let source = "style { }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     assert!(ast.style.is_some());
     assert!(ast.template.is_none());
     assert!(ast.document.is_none());
@@ -42,7 +44,7 @@ fn test_parse_empty_style() {
 fn test_parse_all_blocks() {
     let source = "template { } document { } style { }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     assert!(ast.template.is_some());
     assert!(ast.document.is_some());
     assert!(ast.style.is_some());
@@ -52,15 +54,15 @@ fn test_parse_all_blocks() {
 fn test_parse_variable_assignment() {
     let source = "template { let x = \"hello\" }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
     assert_eq!(template.statements.len(), 1);
 
     match &template.statements[0] {
-        Statement::VarAssign { name, value } => {
+        Statement::VarAssign { name, value, .. } => {
             assert_eq!(name, "x");
             match value {
-                Expression::StringLiteral(s) => assert_eq!(s, "hello"),
+                Expression::StringLiteral(s, _) => assert_eq!(s, "hello"),
                 _ => panic!("Expected StringLiteral expression"),
             }
         }
@@ -68,19 +70,45 @@ fn test_parse_variable_assignment() {
     }
 }
 
+#[test]
+fn test_parse_typed_declaration() {
+    let source = "template { const LIMIT: Float = 10 let name: String = \"Ada\" let n = 1 }";
+    let tokens = lex(source);
+    let (ast, diagnostics) = parse(tokens);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let template = ast.template.unwrap();
+    assert_eq!(template.statements.len(), 3);
+
+    match &template.statements[0] {
+        Statement::ConstAssign { name, ty, .. } => {
+            assert_eq!(name, "LIMIT");
+            assert_eq!(ty.as_deref(), Some("Float"));
+        }
+        _ => panic!("Expected ConstAssign statement"),
+    }
+    match &template.statements[1] {
+        Statement::VarAssign { ty, .. } => assert_eq!(ty.as_deref(), Some("String")),
+        _ => panic!("Expected VarAssign statement"),
+    }
+    match &template.statements[2] {
+        Statement::VarAssign { ty, .. } => assert_eq!(*ty, None),
+        _ => panic!("Expected VarAssign statement"),
+    }
+}
+
 #[test]
 fn test_parse_const_assignment() {
     let source = "template { const PI = \"3.14\" }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
     assert_eq!(template.statements.len(), 1);
 
     match &template.statements[0] {
-        Statement::ConstAssign { name, value } => {
+        Statement::ConstAssign { name, value, .. } => {
             assert_eq!(name, "PI");
             match value {
-                Expression::StringLiteral(s) => assert_eq!(s, "3.14"),
+                Expression::StringLiteral(s, _) => assert_eq!(s, "3.14"),
                 _ => panic!("Expected StringLiteral expression"),
             }
         }
@@ -92,17 +120,14 @@ fn test_parse_const_assignment() {
 fn test_parse_unary_negation() {
     let source = "template { let x = - 42 }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
-        Statement::VarAssign { name, value } => {
+        Statement::VarAssign { name, value, .. } => {
             assert_eq!(name, "x");
             match value {
-                Expression::Unary {
-                    operator,
-                    expression: _,
-                } => {
+                Expression::Unary { operator, .. } => {
                     match operator {
                         UnaryOp::Negate => {}
                         _ => panic!("Expected Negate operator"),
@@ -120,24 +145,25 @@ fn test_parse_unary_negation() {
 fn test_parse_binary_addition() {
     let source = "template { let sum = x + y }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
-        Statement::VarAssign { name, value } => {
+        Statement::VarAssign { name, value, .. } => {
             assert_eq!(name, "sum");
             match value {
                 Expression::Binary {
                     left,
                     operator,
                     right,
+                    ..
                 } => {
                     match operator {
                         BinaryOp::Add => {}
                         _ => panic!("Expected Add operator"),
                     }
                     match (&**left, &**right) {
-                        (Expression::Identifier(l), Expression::Identifier(r)) => {
+                        (Expression::Identifier(l, _), Expression::Identifier(r, _)) => {
                             assert_eq!(l, "x");
                             assert_eq!(r, "y");
                         }
@@ -155,7 +181,7 @@ fn test_parse_binary_addition() {
 fn test_parse_binary_subtraction() {
     let source = "template { let diff = a - b }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
@@ -174,7 +200,7 @@ fn test_parse_binary_subtraction() {
 fn test_parse_binary_multiplication() {
     let source = "template { let product = a * b }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
@@ -193,7 +219,7 @@ fn test_parse_binary_multiplication() {
 fn test_parse_binary_division() {
     let source = "template { let quotient = a / b }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
@@ -212,7 +238,7 @@ fn test_parse_binary_division() {
 fn test_parse_binary_equals() {
     let source = "template { let result = a = b }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
@@ -231,12 +257,12 @@ fn test_parse_binary_equals() {
 fn test_parse_string_literal() {
     let source = "template { let msg = \"Hello, World!\" }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::StringLiteral(s) => assert_eq!(s, "Hello, World!"),
+            Expression::StringLiteral(s, _) => assert_eq!(s, "Hello, World!"),
             _ => panic!("Expected StringLiteral expression"),
         },
         _ => panic!("Expected VarAssign statement"),
@@ -247,12 +273,12 @@ fn test_parse_string_literal() {
 fn test_parse_string_with_escaped_quote() {
     let source = r#"template { let msg = "foo\"bar" }"#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::StringLiteral(s) => assert_eq!(
+            Expression::StringLiteral(s, _) => assert_eq!(
                 s, "foo\"bar",
                 "Escaped quote should be preserved as literal quote"
             ),
@@ -281,12 +307,12 @@ fn test_lex_unterminated_string() {
 fn test_parse_integer_literal() {
     let source = "template { let num = 42 }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::Int(n) => assert_eq!(*n, 42),
+            Expression::Int(n, _) => assert_eq!(*n, 42),
             _ => panic!("Expected Int expression"),
         },
         _ => panic!("Expected VarAssign statement"),
@@ -297,12 +323,12 @@ fn test_parse_integer_literal() {
 fn test_parse_float_literal() {
     let source = "template { let pi = 3.14 }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::Float(f) => assert!((f - 3.14).abs() < 0.001),
+            Expression::Float(f, _) => assert!((f - 3.14).abs() < 0.001),
             _ => panic!("Expected Float expression"),
         },
         _ => panic!("Expected VarAssign statement"),
@@ -314,29 +340,55 @@ fn test_parse_return_statement() {
     // Return requires a document element (like text { ... }), not a plain string
     let source = "template { return text { done } }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
-        Statement::Return { doc_element } => match doc_element {
-            DocElement::Text { content, .. } => assert_eq!(content, "done"),
+        Statement::Return { doc_elements, .. } => match &doc_elements[..] {
+            [DocElement::Text { content, .. }] => assert_eq!(content, "done"),
             _ => panic!("Expected Text DocElement in return"),
         },
         _ => panic!("Expected Return statement"),
     }
 }
 
+#[test]
+fn test_parse_return_fragment() {
+    let source = r#"template {
+    func pair() { return { text { one } text { two } } }
+    func nothing() { return }
+}"#;
+    let (ast, errors) = parse(lex(source));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let template = ast.template.unwrap();
+
+    let returned: Vec<usize> = template
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::FunctionDecl { body, .. } => match &body[..] {
+                [Statement::Return { doc_elements, .. }] => doc_elements.len(),
+                other => panic!("Expected one Return, got {:?}", other),
+            },
+            other => panic!("Expected FunctionDecl, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(returned, [2, 0]);
+}
+
 #[test]
 fn test_parse_function_declaration() {
     // Function body needs proper document element in return
     let source = "template { func greet(name: string) { return text { Hello } } }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
     assert_eq!(template.statements.len(), 1);
 
     match &template.statements[0] {
-        Statement::FunctionDecl { name, args, body } => {
+        Statement::FunctionDecl {
+            name, args, body, ..
+        } => {
             assert_eq!(name, "greet");
             assert_eq!(args.len(), 1);
             assert_eq!(args[0].ty, "string");
@@ -350,11 +402,11 @@ fn test_parse_function_declaration() {
 fn test_parse_function_call_no_args() {
     let source = "document { greet() }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let doc = ast.document.unwrap();
 
     match &doc.elements[0] {
-        DocElement::Call { name, args } => {
+        DocElement::Call { name, args, .. } => {
             assert_eq!(name, "greet");
             assert_eq!(args.len(), 0);
         }
@@ -366,11 +418,11 @@ fn test_parse_function_call_no_args() {
 fn test_parse_function_call_with_args() {
     let source = "document { print(\"hello\", \"world\") }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let doc = ast.document.unwrap();
 
     match &doc.elements[0] {
-        DocElement::Call { name, args } => {
+        DocElement::Call { name, args, .. } => {
             assert_eq!(name, "print");
             assert_eq!(args.len(), 2);
         }
@@ -378,18 +430,272 @@ fn test_parse_function_call_with_args() {
     }
 }
 
+#[test]
+fn test_parse_call_with_expressions_and_named_args() {
+    let source = r#"document { card(price * 2, title = "Q{quarter}") }"#;
+    let (ast, errors) = parse(lex(source));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let doc = ast.document.unwrap();
+
+    let DocElement::Call { args, .. } = &doc.elements[0] else {
+        panic!("Expected Call DocElement");
+    };
+    assert_eq!(args.len(), 2);
+    assert_eq!(args[0].name, None);
+    assert!(matches!(
+        &args[0].value,
+        Expression::Binary {
+            operator: BinaryOp::Multiply,
+            ..
+        }
+    ));
+    assert_eq!(&source[args[0].span.start..args[0].span.end], "price * 2");
+    assert_eq!(args[1].name.as_deref(), Some("title"));
+    assert!(matches!(&args[1].value, Expression::InterpolatedString(..)));
+    assert_eq!(
+        &source[args[1].span.start..args[1].span.end],
+        "title = \"Q{quarter}\""
+    );
+}
+
+#[test]
+fn test_parse_param_defaults() {
+    let source = r#"template { func card(title: String, size: Int = 2 + 1) { } }"#;
+    let (ast, errors) = parse(lex(source));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let template = ast.template.unwrap();
+
+    let Statement::FunctionDecl { args, .. } = &template.statements[0] else {
+        panic!("Expected FunctionDecl");
+    };
+    assert!(args[0].default.is_none());
+    assert!(matches!(
+        args[1].default,
+        Some(Expression::Binary {
+            operator: BinaryOp::Add,
+            ..
+        })
+    ));
+    assert_eq!(
+        &source[args[1].span.start..args[1].span.end],
+        "size: Int = 2 + 1"
+    );
+}
+
+#[test]
+fn test_parse_return_type_and_value() {
+    let source = r#"template {
+    func price(p: Float) -> String { return "${p}" }
+    func card() { return text { "{price(2.5)}" } }
+}"#;
+    let (ast, errors) = parse(lex(source));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let template = ast.template.unwrap();
+
+    let Statement::FunctionDecl {
+        return_type, body, ..
+    } = &template.statements[0]
+    else {
+        panic!("Expected FunctionDecl");
+    };
+    assert_eq!(return_type.as_deref(), Some("String"));
+    assert!(matches!(
+        &body[0],
+        Statement::ReturnValue {
+            value: Expression::InterpolatedString(..),
+            ..
+        }
+    ));
+
+    let Statement::FunctionDecl {
+        return_type, body, ..
+    } = &template.statements[1]
+    else {
+        panic!("Expected FunctionDecl");
+    };
+    assert!(return_type.is_none());
+    assert!(matches!(&body[0], Statement::Return { .. }));
+}
+
+#[test]
+fn test_parse_call_expression() {
+    let source = r#"template { let total = price(2.5, tax=0.2) + 1 }"#;
+    let (ast, errors) = parse(lex(source));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let template = ast.template.unwrap();
+
+    let Statement::VarAssign { value, .. } = &template.statements[0] else {
+        panic!("Expected VarAssign");
+    };
+    let Expression::Binary { left, .. } = value else {
+        panic!("Expected Binary, got {:?}", value);
+    };
+    let Expression::Call { name, args, span } = left.as_ref() else {
+        panic!("Expected Call, got {:?}", left);
+    };
+    assert_eq!(name, "price");
+    assert_eq!(args.len(), 2);
+    assert_eq!(args[1].name.as_deref(), Some("tax"));
+    assert_eq!(&source[span.start..span.end], "price(2.5, tax=0.2)");
+}
+
+#[test]
+fn test_parse_bool_and_color_literals() {
+    let source = r#"template {
+    let on = true && !false
+    let a = #1e90ff
+    let b = #FFF
+    let c = rgb(30, 144, 255)
+}"#;
+    let (ast, errors) = parse(lex(source));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let values: Vec<&Expression> = ast
+        .template
+        .as_ref()
+        .unwrap()
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::VarAssign { value, .. } => value,
+            other => panic!("Expected VarAssign, got {:?}", other),
+        })
+        .collect();
+
+    let Expression::Binary { left, .. } = values[0] else {
+        panic!("Expected Binary, got {:?}", values[0]);
+    };
+    assert!(matches!(left.as_ref(), Expression::Bool(true, _)));
+    let colors: Vec<&str> = values[1..]
+        .iter()
+        .map(|value| match value {
+            Expression::Color(color, span) => {
+                assert_eq!(&source[span.start..span.end], color);
+                color.as_str()
+            }
+            other => panic!("Expected Color, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(colors, ["#1e90ff", "#FFF", "rgb(30, 144, 255)"]);
+}
+
+#[test]
+fn test_parse_invalid_colors() {
+    let source = r#"template {
+    let a = #12345
+    let b = #ggg
+    let c = rgb(300, 0, 0)
+    let d = rgb(1, 2)
+}"#;
+    let (_, errors) = parse(lex(source));
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "invalid colour `#12345`",
+            "invalid colour `#ggg`",
+            "invalid colour `rgb(...)`",
+            "invalid colour `rgb(...)`",
+        ]
+    );
+}
+
+#[test]
+fn test_parse_lists_maps_and_access() {
+    let source = r#"template {
+    let books = [
+        { title: "Dune", "first year": 1965 },
+        { title: "Emma", "first year": 1815, },
+    ]
+    let a = books[0].title
+    let b = len(books) - 1
+    func first() -> Map {
+        return { title: "Dune" }
+    }
+}"#;
+    let (ast, errors) = parse(lex(source));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let statements = &ast.template.as_ref().unwrap().statements;
+    let values: Vec<&Expression> = statements[..3]
+        .iter()
+        .map(|statement| match statement {
+            Statement::VarAssign { value, .. } => value,
+            other => panic!("Expected VarAssign, got {:?}", other),
+        })
+        .collect();
+
+    let Expression::List(items, _) = values[0] else {
+        panic!("Expected List, got {:?}", values[0]);
+    };
+    assert_eq!(items.len(), 2);
+    let Expression::Map(entries, span) = &items[1] else {
+        panic!("Expected Map, got {:?}", items[1]);
+    };
+    let keys: Vec<&str> = entries.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["title", "first year"]);
+    assert_eq!(
+        &source[span.start..span.end],
+        r#"{ title: "Emma", "first year": 1815, }"#
+    );
+
+    // `books[0].title` is the field of the indexed item
+    let Expression::Field { value, name, span } = values[1] else {
+        panic!("Expected Field, got {:?}", values[1]);
+    };
+    assert_eq!(name, "title");
+    assert_eq!(&source[span.start..span.end], "books[0].title");
+    assert!(matches!(
+        value.as_ref(),
+        Expression::Index { index, .. } if matches!(index.as_ref(), Expression::Int(0, _))
+    ));
+    assert!(matches!(
+        values[2],
+        Expression::Binary { left, .. } if matches!(left.as_ref(), Expression::Call { name, .. } if name == "len")
+    ));
+
+    // a map after `return`, not a fragment
+    let Statement::FunctionDecl { body, .. } = &statements[3] else {
+        panic!("Expected FunctionDecl, got {:?}", statements[3]);
+    };
+    assert!(matches!(
+        &body[0],
+        Statement::ReturnValue {
+            value: Expression::Map(..),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_parse_invalid_lists_and_maps() {
+    let source = r#"template {
+    let a = [1 2]
+    let b = { x: 1, x: 2 }
+    let c = { x 1 }
+}"#;
+    let (_, errors) = parse(lex(source));
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "expected ',' or ']' after list item but found Int",
+            "duplicate key `x` in map",
+            "expected Colon but found Int",
+        ]
+    );
+}
+
 #[test]
 fn test_parse_default_set() {
     let source = "template { width = 100 }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
-        Statement::DefaultSet { key, value } => {
+        Statement::DefaultSet { key, value, .. } => {
             assert_eq!(key, "width");
             match value {
-                Expression::Int(n) => assert_eq!(*n, 100),
+                Expression::Int(n, _) => assert_eq!(*n, 100),
                 _ => panic!("Expected Int expression"),
             }
         }
@@ -401,7 +707,7 @@ fn test_parse_default_set() {
 fn test_parse_multiple_statements() {
     let source = "template { let x = 1 let y = 2 let z = 3 }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
     assert_eq!(template.statements.len(), 3);
 }
@@ -410,7 +716,7 @@ fn test_parse_multiple_statements() {
 fn test_parse_mixed_statements() {
     let source = "template { let x = 10 const MAX = 100 width = 50 }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
     assert_eq!(template.statements.len(), 3);
 
@@ -432,12 +738,12 @@ fn test_parse_mixed_statements() {
 fn test_parse_dollar_sign_interpolation() {
     let source = "template { let msg = $ x $ }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::Identifier(id) => {
+            Expression::Identifier(id, _) => {
                 assert_eq!(id, "x");
             }
             _ => panic!("Expected Identifier expression"),
@@ -450,7 +756,7 @@ fn test_parse_dollar_sign_interpolation() {
 fn test_parse_nested_template_and_document() {
     let source = "template { func render() { return text { html } } } document { greet() }";
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     assert!(ast.template.is_some());
     assert!(ast.document.is_some());
 
@@ -465,12 +771,12 @@ fn test_parse_nested_template_and_document() {
 fn test_parse_string_interpolation_simple() {
     let source = r#"template { let msg = "Hello, {name}!" }"#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::InterpolatedString(parts) => {
+            Expression::InterpolatedString(parts, _) => {
                 assert_eq!(parts.len(), 3);
                 match &parts[0] {
                     InterpPart::Text(text) => assert_eq!(text, "Hello, "),
@@ -478,7 +784,7 @@ fn test_parse_string_interpolation_simple() {
                 }
                 match &parts[1] {
                     InterpPart::Expression(expr) => match expr {
-                        Expression::Identifier(id) => assert_eq!(id, "name"),
+                        Expression::Identifier(id, _) => assert_eq!(id, "name"),
                         _ => panic!("Expected Identifier expression"),
                     },
                     _ => panic!("Expected Expression part"),
@@ -498,17 +804,17 @@ fn test_parse_string_interpolation_simple() {
 fn test_parse_string_interpolation_multiple() {
     let source = r#"template { let msg = "{greeting}, {name}!" }"#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::InterpolatedString(parts) => {
+            Expression::InterpolatedString(parts, _) => {
                 assert_eq!(parts.len(), 4);
                 // {greeting}
                 match &parts[0] {
                     InterpPart::Expression(expr) => match expr {
-                        Expression::Identifier(id) => assert_eq!(id, "greeting"),
+                        Expression::Identifier(id, _) => assert_eq!(id, "greeting"),
                         _ => panic!("Expected Identifier"),
                     },
                     _ => panic!("Expected Expression part"),
@@ -521,7 +827,7 @@ fn test_parse_string_interpolation_multiple() {
                 // {name}
                 match &parts[2] {
                     InterpPart::Expression(expr) => match expr {
-                        Expression::Identifier(id) => assert_eq!(id, "name"),
+                        Expression::Identifier(id, _) => assert_eq!(id, "name"),
                         _ => panic!("Expected Identifier"),
                     },
                     _ => panic!("Expected Expression part"),
@@ -542,12 +848,12 @@ fn test_parse_string_interpolation_multiple() {
 fn test_parse_string_interpolation_with_number() {
     let source = r#"template { let msg = "Count: {count}" }"#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::InterpolatedString(parts) => {
+            Expression::InterpolatedString(parts, _) => {
                 assert_eq!(parts.len(), 2);
                 match &parts[0] {
                     InterpPart::Text(text) => assert_eq!(text, "Count: "),
@@ -555,7 +861,7 @@ fn test_parse_string_interpolation_with_number() {
                 }
                 match &parts[1] {
                     InterpPart::Expression(expr) => match expr {
-                        Expression::Identifier(id) => assert_eq!(id, "count"),
+                        Expression::Identifier(id, _) => assert_eq!(id, "count"),
                         _ => panic!("Expected Identifier expression"),
                     },
                     _ => panic!("Expected Expression part"),
@@ -572,14 +878,832 @@ fn test_parse_string_without_interpolation() {
     // Plain strings without {} should remain as StringLiteral
     let source = r#"template { let msg = "Hello, World!" }"#;
     let tokens = lex(source);
-    let ast = parse(tokens);
+    let (ast, _) = parse(tokens);
     let template = ast.template.unwrap();
 
     match &template.statements[0] {
         Statement::VarAssign { value, .. } => match value {
-            Expression::StringLiteral(s) => assert_eq!(s, "Hello, World!"),
+            Expression::StringLiteral(s, _) => assert_eq!(s, "Hello, World!"),
             _ => panic!("Expected StringLiteral for plain string, got {:?}", value),
         },
         _ => panic!("Expected VarAssign statement"),
     }
 }
+
+// ============================================================================
+// Expression Precedence Tests
+// ============================================================================
+
+/// Parse `let e = <expr>` and return the expression with binaries fully
+/// parenthesised
+fn parse_expr(expr: &str) -> String {
+    let source = format!("template {{ let e = {} }}", expr);
+    let (ast, diagnostics) = parse(lex(&source));
+    assert!(
+        diagnostics.is_empty(),
+        "unexpected diagnostics: {:?}",
+        diagnostics
+    );
+    match &ast.template.unwrap().statements[0] {
+        Statement::VarAssign { value, .. } => value.to_string(),
+        _ => panic!("Expected VarAssign statement"),
+    }
+}
+
+#[test]
+fn test_parse_precedence_and_associativity() {
+    assert_eq!(parse_expr("2 * x + 1"), "((2 * x) + 1)");
+    assert_eq!(parse_expr("1 + 2 * x"), "(1 + (2 * x))");
+    assert_eq!(parse_expr("a - b - c"), "((a - b) - c)");
+    assert_eq!(parse_expr("a / b % c"), "((a / b) % c)");
+}
+
+#[test]
+fn test_parse_parenthesised_groups() {
+    assert_eq!(parse_expr("(a + b) * c"), "((a + b) * c)");
+    assert_eq!(parse_expr("a - (b - c)"), "(a - (b - c))");
+    assert_eq!(parse_expr("((1))"), "1");
+}
+
+#[test]
+fn test_parse_comparisons_and_logic() {
+    assert_eq!(parse_expr("a + 1 < b * 2"), "((a + 1) < (b * 2))");
+    assert_eq!(parse_expr("a <= b == c >= d"), "((a <= b) == (c >= d))");
+    assert_eq!(parse_expr("a != b"), "(a != b)");
+    assert_eq!(
+        parse_expr("a > 1 && b < 2 || c"),
+        "(((a > 1) && (b < 2)) || c)"
+    );
+    assert_eq!(parse_expr("a || b && c"), "(a || (b && c))");
+}
+
+#[test]
+fn test_parse_unary_binds_tighter_than_binary() {
+    assert_eq!(parse_expr("-a + b"), "(-a + b)");
+    assert_eq!(parse_expr("!a && b"), "(!a && b)");
+    assert_eq!(parse_expr("-(a + b)"), "-(a + b)");
+}
+
+#[test]
+fn test_parse_unclosed_paren_reports_error() {
+    let (_, diagnostics) = parse(lex("template { let e = (a + b }"));
+    assert_eq!(diagnostics.len(), 1);
+}
+
+// ============================================================================
+// Control Flow Tests
+// ============================================================================
+
+/// Statements in the body of `func f() { <body> }`
+fn func_body(body: &str) -> Vec<Statement> {
+    let source = format!("template {{ func f() {{ {} }} }}", body);
+    let (ast, diagnostics) = parse(lex(&source));
+    assert!(
+        diagnostics.is_empty(),
+        "unexpected diagnostics: {:?}",
+        diagnostics
+    );
+    match ast.template.unwrap().statements.remove(0) {
+        Statement::FunctionDecl { body, .. } => body,
+        other => panic!("Expected FunctionDecl, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_if_else_if_else() {
+    let body = func_body(
+        r#"if n > 1 { return text { "many" } } else if n == 1 { let x = 1 } else { return text { "none" } }"#,
+    );
+    assert_eq!(body.len(), 1);
+    match &body[0] {
+        Statement::If {
+            condition,
+            body,
+            else_body: Some(else_body),
+            ..
+        } => {
+            assert_eq!(condition.to_string(), "(n > 1)");
+            assert!(matches!(body[..], [Statement::Return { .. }]));
+            match &else_body[..] {
+                [
+                    Statement::If {
+                        condition,
+                        else_body: Some(last),
+                        ..
+                    },
+                ] => {
+                    assert_eq!(condition.to_string(), "(n == 1)");
+                    assert!(matches!(last[..], [Statement::Return { .. }]));
+                }
+                other => panic!("Expected else if, got {:?}", other),
+            }
+        }
+        other => panic!("Expected If, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_while_and_for() {
+    let body = func_body("while i < 3 { i = i + 1 } for j in 0..n + 1 { let k = j }");
+    match &body[0] {
+        Statement::While {
+            condition, body, ..
+        } => {
+            assert_eq!(condition.to_string(), "(i < 3)");
+            assert!(matches!(body[..], [Statement::DefaultSet { .. }]));
+        }
+        other => panic!("Expected While, got {:?}", other),
+    }
+    match &body[1] {
+        Statement::For {
+            iterator, iterable, ..
+        } => {
+            assert_eq!(iterator, "j");
+            assert_eq!(iterable.to_string(), "0..(n + 1)");
+        }
+        other => panic!("Expected For, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_unclosed_control_flow_block() {
+    let (_, diagnostics) = parse(lex("template { func f() { while x { let y = 1 "));
+    assert!(
+        diagnostics[0]
+            .message
+            .contains("`while` block is not closed"),
+        "{:?}",
+        diagnostics
+    );
+}
+
+#[test]
+fn test_parse_document_if_and_for() {
+    let source = r#"
+document {
+    if count > 0 { text { "some" } } else { text { "none" } }
+    list {
+        item { "first" }
+        for i in 0..count { item { "Item {i}" } }
+    }
+}
+"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let elements = ast.document.unwrap().elements;
+    match &elements[0] {
+        DocElement::If {
+            condition,
+            body,
+            else_body: Some(else_body),
+            ..
+        } => {
+            assert_eq!(condition.to_string(), "(count > 0)");
+            assert!(matches!(body[..], [DocElement::Text { .. }]));
+            assert!(matches!(else_body[..], [DocElement::Text { .. }]));
+        }
+        other => panic!("Expected If, got {:?}", other),
+    }
+    match &elements[1] {
+        DocElement::List { items, .. } => match &items[1] {
+            DocElement::For {
+                iterator,
+                iterable,
+                body,
+                ..
+            } => {
+                assert_eq!(iterator, "i");
+                assert_eq!(iterable.to_string(), "0..count");
+                assert!(matches!(body[..], [DocElement::Text { .. }]));
+            }
+            other => panic!("Expected For, got {:?}", other),
+        },
+        other => panic!("Expected List, got {:?}", other),
+    }
+}
+
+// ============================================================================
+// Interpolation Expression Tests
+// ============================================================================
+
+/// Parse `let e = "<string>"` and return the interpolated expressions
+fn interpolated_exprs(source: &str) -> Vec<Expression> {
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(
+        diagnostics.is_empty(),
+        "unexpected diagnostics: {:?}",
+        diagnostics
+    );
+    match &ast.template.unwrap().statements[0] {
+        Statement::VarAssign {
+            value: Expression::InterpolatedString(parts, _),
+            ..
+        } => parts
+            .iter()
+            .filter_map(|part| match part {
+                InterpPart::Expression(expr) => Some(expr.clone()),
+                InterpPart::Text(_) => None,
+            })
+            .collect(),
+        other => panic!("Expected interpolated VarAssign, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_interpolation_uses_expression_parser() {
+    let source = r#"template { let e = "Total: {price * (1 + tax_rate)} and {a - b - c}" }"#;
+    let exprs = interpolated_exprs(source);
+    assert_eq!(exprs.len(), 2);
+    assert_eq!(exprs[0].to_string(), "(price * (1 + tax_rate))");
+    assert_eq!(exprs[1].to_string(), "((a - b) - c)");
+}
+
+#[test]
+fn test_interpolation_unary_and_comparison() {
+    let source = r#"template { let e = "{-1} {count >= 2 && !done}" }"#;
+    let exprs = interpolated_exprs(source);
+    assert_eq!(exprs[0].to_string(), "-1");
+    assert_eq!(exprs[1].to_string(), "((count >= 2) && !done)");
+}
+
+#[test]
+fn test_interpolation_spans_point_into_source() {
+    let source = r#"template { let e = "Hi {first + last}!" }"#;
+    let exprs = interpolated_exprs(source);
+    assert_eq!(slice(source, exprs[0].span()), "first + last");
+    match &exprs[0] {
+        Expression::Binary { right, .. } => assert_eq!(slice(source, right.span()), "last"),
+        other => panic!("Expected Binary, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_interpolation_errors_point_into_source() {
+    let source = r#"template { let e = "a {x +} b {} c {y z}" }"#;
+    let (_, diagnostics) = parse(lex(source));
+    assert_eq!(diagnostics.len(), 3, "{:?}", diagnostics);
+    assert_eq!(slice(source, diagnostics[0].span), "");
+    assert_eq!(diagnostics[0].span.start, source.find("+}").unwrap() + 1);
+    assert_eq!(slice(source, diagnostics[1].span), "{");
+    assert!(diagnostics[1].message.contains("empty interpolation"));
+    assert_eq!(slice(source, diagnostics[2].span), "z");
+}
+
+#[test]
+fn test_interpolation_literal_braces() {
+    let source = r#"template { let e = "{{x}} \{y\}" }"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    match &ast.template.unwrap().statements[0] {
+        Statement::VarAssign { value, .. } => match value {
+            Expression::StringLiteral(s, _) => assert_eq!(s, "{x} {y}"),
+            other => panic!("Expected StringLiteral, got {:?}", other),
+        },
+        _ => panic!("Expected VarAssign statement"),
+    }
+}
+
+// ============================================================================
+// Document Element Tests
+// ============================================================================
+
+#[test]
+fn test_parse_image() {
+    let source = r#"document { image(src="logo.png", alt="Company logo", width=120) }"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    match &ast.document.unwrap().elements[0] {
+        DocElement::Image {
+            src,
+            attributes,
+            span,
+        } => {
+            assert_eq!(src, "logo.png");
+            assert!(!attributes.contains_key("src"));
+            assert_eq!(attributes["alt"].to_string(), "Company logo");
+            assert_eq!(attributes["width"].to_string(), "120");
+            assert_eq!(slice(source, *span), &source[11..source.len() - 2]);
+        }
+        other => panic!("Expected Image, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_image_requires_string_src() {
+    let source = r#"document { image(alt="none") image(src=logo) text { "after" } }"#;
+    let (ast, diagnostics) = parse(lex(source));
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "image is missing a `src` attribute",
+            "image `src` must be a string"
+        ]
+    );
+    assert_eq!(slice(source, diagnostics[0].span), r#"image(alt="none")"#);
+    assert_eq!(slice(source, diagnostics[1].span), "logo");
+    assert!(matches!(
+        ast.document.unwrap().elements[..],
+        [DocElement::Text { .. }]
+    ));
+}
+
+#[test]
+fn test_parse_link() {
+    let source = r##"document { link(href="https://example.com", class="ext") { "Example site" } link(href="#intro") }"##;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let elements = ast.document.unwrap().elements;
+    match &elements[0] {
+        DocElement::Link {
+            href,
+            content,
+            attributes,
+            ..
+        } => {
+            assert_eq!(href, "https://example.com");
+            assert_eq!(InterpPart::plain_text(content), "Example site");
+            assert_eq!(attributes["class"].to_string(), "ext");
+            assert!(!attributes.contains_key("href"));
+        }
+        other => panic!("Expected Link, got {:?}", other),
+    }
+    match &elements[1] {
+        DocElement::Link { href, content, .. } => {
+            assert_eq!(href, "#intro");
+            assert_eq!(InterpPart::plain_text(content), "#intro");
+        }
+        other => panic!("Expected Link, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_link_requires_href() {
+    let source = r#"document { link { "nowhere" } text { "after" } }"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        diagnostics[0].message,
+        "link is missing an `href` attribute"
+    );
+    assert_eq!(slice(source, diagnostics[0].span), "link");
+    assert!(matches!(
+        ast.document.unwrap().elements[..],
+        [DocElement::Text { .. }]
+    ));
+}
+
+#[test]
+fn test_parse_table() {
+    let source = r#"document {
+    table(columns="2fr 1fr") {
+        header { cell { "Name" } cell { "Total" } }
+        for r in 0..2 { row { cell(class="name") { "Row" } cell { "1" } } }
+        row { cell(colspan=2, rowspan=1) { "Sum" } }
+    }
+}"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let DocElement::Table {
+        rows, attributes, ..
+    } = &ast.document.unwrap().elements[0]
+    else {
+        panic!("Expected Table");
+    };
+    assert_eq!(attributes["columns"].to_string(), "2fr 1fr");
+    assert_eq!(rows.len(), 3);
+
+    match &rows[0] {
+        DocElement::TableRow {
+            header: true,
+            cells,
+            span,
+        } => {
+            assert_eq!(cells.len(), 2);
+            assert_eq!(
+                slice(source, *span),
+                r#"header { cell { "Name" } cell { "Total" } }"#
+            );
+        }
+        other => panic!("Expected header row, got {:?}", other),
+    }
+    assert!(matches!(&rows[1], DocElement::For { body, .. } if body.len() == 1));
+    match &rows[2] {
+        DocElement::TableRow {
+            header: false,
+            cells,
+            ..
+        } => match &cells[..] {
+            [
+                DocElement::TableCell {
+                    content,
+                    colspan: 2,
+                    rowspan: 1,
+                    attributes,
+                    ..
+                },
+            ] => {
+                assert_eq!(InterpPart::plain_text(content), "Sum");
+                assert!(attributes.is_empty());
+            }
+            other => panic!("Expected one spanning cell, got {:?}", other),
+        },
+        other => panic!("Expected row, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_table_errors_recover() {
+    let source = r#"document {
+    table {
+        row { cell(colspan=0) { "bad" } cell { "ok" } }
+        column { }
+        row { cell { "last" } }
+    }
+    text { "after" }
+}"#;
+    let (ast, diagnostics) = parse(lex(source));
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "cell `colspan` must be a whole number of at least 1",
+            "expected 'row' or 'header' in table but found Identifier",
+        ]
+    );
+    assert_eq!(slice(source, diagnostics[0].span), "0");
+    let elements = ast.document.unwrap().elements;
+    let DocElement::Table { rows, .. } = &elements[0] else {
+        panic!("Expected Table, got {:?}", elements[0]);
+    };
+    assert!(matches!(
+        &rows[..],
+        [
+            DocElement::TableRow { cells: first, .. },
+            DocElement::TableRow { cells: last, .. },
+        ] if first.len() == 1 && last.len() == 1
+    ));
+    assert!(matches!(elements[1], DocElement::Text { .. }));
+}
+
+#[test]
+fn test_parse_text_spans_and_markup() {
+    let source = r#"document {
+    text { "Total: " span(class="price") { "*{total}*" } " due" }
+    list { item { "*one*" } }
+    text { "see _notes_, `a_b` and snake_case, 2 * 3" }
+    text { span a word }
+}"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let elements = ast.document.unwrap().elements;
+
+    let DocElement::Text {
+        content, inlines, ..
+    } = &elements[0]
+    else {
+        panic!("Expected Text, got {:?}", elements[0]);
+    };
+    assert_eq!(content, "Total: {total} due");
+    match &inlines[..] {
+        [
+            Inline::Text(before),
+            Inline::Span {
+                content,
+                attributes,
+                span,
+            },
+            Inline::Text(after),
+        ] => {
+            assert_eq!(InterpPart::plain_text(before), "Total: ");
+            assert_eq!(InterpPart::plain_text(after), " due");
+            assert_eq!(attributes["class"].to_string(), "price");
+            assert!(matches!(
+                &content[..],
+                [Inline::Emphasis(Emphasis::Bold, text)] if InterpPart::plain_text(text) == "{total}"
+            ));
+            assert_eq!(
+                slice(source, *span),
+                r#"span(class="price") { "*{total}*" }"#
+            );
+        }
+        other => panic!("Unexpected inlines {:?}", other),
+    }
+
+    // list items take markup too
+    let DocElement::List { items, .. } = &elements[1] else {
+        panic!("Expected List, got {:?}", elements[1]);
+    };
+    assert!(matches!(
+        &items[..],
+        [DocElement::Text { inlines, .. }]
+            if matches!(&inlines[..], [Inline::Emphasis(Emphasis::Bold, text)] if InterpPart::plain_text(text) == "one")
+    ));
+
+    // `*` and `_` inside or between words are not markup
+    let DocElement::Text { inlines, .. } = &elements[2] else {
+        panic!("Expected Text, got {:?}", elements[2]);
+    };
+    let pieces: Vec<(_, String)> = inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => (None, InterpPart::plain_text(text)),
+            Inline::Emphasis(emphasis, text) => (Some(*emphasis), InterpPart::plain_text(text)),
+            Inline::Span { .. } | Inline::Link { .. } => panic!("Unexpected {:?}", inline),
+        })
+        .collect();
+    assert_eq!(
+        pieces,
+        [
+            (None, "see ".to_string()),
+            (Some(Emphasis::Italic), "notes".to_string()),
+            (None, ", ".to_string()),
+            (Some(Emphasis::Code), "a_b".to_string()),
+            (None, " and snake_case, 2 * 3".to_string()),
+        ]
+    );
+
+    // a `link` inside text is part of it, a bare `link` is a word
+    let source = r#"document { text { "See " link(href="x") { "*this*" } " or" link "." } }"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let document = ast.document.unwrap();
+    let [
+        DocElement::Text {
+            content, inlines, ..
+        },
+    ] = &document.elements[..]
+    else {
+        panic!("Expected Text, got {:?}", document.elements);
+    };
+    assert_eq!(content, "See *this* or link .");
+    assert!(matches!(
+        &inlines[..],
+        [Inline::Text(_), Inline::Link { href, content, .. }, Inline::Text(_)]
+            if href == "x" && InterpPart::plain_text(content) == "*this*"
+    ));
+
+    // without attributes or a body `span` is just a word
+    assert!(matches!(&elements[3], DocElement::Text { content, .. } if content == "span a word"));
+}
+
+#[test]
+fn test_parse_markdown_into_elements() {
+    let source = r#"document {
+    markdown(class="prose") {
+        """
+        # Notes
+        Some *text*.
+        """
+    }
+    markdown { 1 }
+    text { "after" }
+}"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert_eq!(
+        diagnostics.iter().map(|d| &d.message).collect::<Vec<_>>(),
+        ["expected a `\"\"\"` string in `markdown` but found Int"]
+    );
+    let elements = ast.document.unwrap().elements;
+    let [
+        DocElement::Section {
+            elements: markdown,
+            attributes,
+            span,
+        },
+        DocElement::Text { .. },
+    ] = &elements[..]
+    else {
+        panic!("Expected markdown and text, got {:?}", elements);
+    };
+    assert_eq!(attributes["class"].to_string(), "prose");
+    assert!(slice(source, *span).starts_with("markdown(class=\"prose\") {"));
+    let [DocElement::Section { elements, .. }] = &markdown[..] else {
+        panic!("Expected a section for the heading, got {:?}", markdown);
+    };
+    assert!(matches!(
+        &elements[..],
+        [DocElement::Text { content: heading, .. }, DocElement::Text { content, .. }]
+            if heading == "Notes" && content == "Some text."
+    ));
+}
+
+#[test]
+fn test_parse_code_keeps_raw_body() {
+    let source = "document {\n    code(lang=\"rust\") {\n        \"\"\"\n        fn main() {\n            let s = \"{not} interpolated\";\n        }\n        \"\"\"\n    }\n    code { \"x = 1\" }\n}";
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let elements = ast.document.unwrap().elements;
+    match &elements[0] {
+        DocElement::Code {
+            content,
+            attributes,
+            ..
+        } => {
+            // the first newline and the closing quotes' indentation are dropped
+            assert_eq!(
+                content,
+                "fn main() {\n    let s = \"{not} interpolated\";\n}"
+            );
+            assert_eq!(attributes["lang"].to_string(), "rust");
+        }
+        other => panic!("Expected Code, got {:?}", other),
+    }
+    assert!(matches!(&elements[1], DocElement::Code { content, .. } if content == "x = 1"));
+}
+
+#[test]
+fn test_parse_code_requires_string_body() {
+    let source = r#"document { code { 42 } text { "after" } }"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        diagnostics[0].message,
+        "expected a `\"\"\"` string in `code` but found Int"
+    );
+    assert_eq!(slice(source, diagnostics[0].span), "42");
+    assert!(matches!(
+        ast.document.unwrap().elements[..],
+        [DocElement::Text { .. }]
+    ));
+}
+
+// ============================================================================
+// Error Recovery Tests
+// ============================================================================
+
+#[test]
+fn test_parse_valid_source_has_no_diagnostics() {
+    let source = r#"
+template {
+    let x = 1
+    func greet(name: String) { return text { "Hi" } }
+}
+document {
+    text (id="a") { "Hello" }
+    list { item { "one" } }
+}
+style {
+    #a { font-size = 12 text-align = "center" }
+}
+"#;
+    let (_, diagnostics) = parse(lex(source));
+    assert!(
+        diagnostics.is_empty(),
+        "unexpected diagnostics: {:?}",
+        diagnostics
+    );
+}
+
+#[test]
+fn test_parse_reports_every_error() {
+    let source = r#"
+template {
+    let = 1
+    let ok = 2
+    const = "x"
+}
+document {
+    text (id=) { "a" }
+    text { "fine" }
+    list { nope { "b" } }
+    greet("x" "y")
+}
+style {
+    #header { font-size = }
+}
+"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert_eq!(diagnostics.len(), 6, "diagnostics: {:#?}", diagnostics);
+
+    // valid statements and elements around the errors are kept
+    let template = ast.template.unwrap();
+    assert_eq!(template.statements.len(), 1);
+    let doc = ast.document.unwrap();
+    assert!(
+        doc.elements
+            .iter()
+            .any(|e| matches!(e, DocElement::Text { content, .. } if content == "fine"))
+    );
+    assert_eq!(ast.style.unwrap().statements.len(), 1);
+}
+
+#[test]
+fn test_parse_error_spans_point_at_token() {
+    let source = "document { text (id=) { \"a\" } }";
+    let (_, diagnostics) = parse(lex(source));
+    assert_eq!(diagnostics.len(), 1);
+    let span = diagnostics[0].span;
+    assert_eq!(&source[span.start..span.end], ")");
+}
+
+#[test]
+fn test_parse_rule_without_selector_recovers() {
+    let source = "style { { x = 1 } .a { font-size = 12 } } document { }";
+    let (ast, diagnostics) = parse(lex(source));
+    assert_eq!(diagnostics.len(), 1, "diagnostics: {:#?}", diagnostics);
+    assert!(diagnostics[0].message.contains("expected a selector"));
+    let span = diagnostics[0].span;
+    assert_eq!(span.start, source.find("{ x").unwrap());
+    // the rule after it stays in the style block
+    let rules = ast.style.unwrap().statements;
+    assert_eq!(rules.len(), 1);
+    assert!(ast.document.is_some());
+}
+
+#[test]
+fn test_parse_unexpected_top_level_token() {
+    // both the stray word and the stray closing brace are reported
+    let source = "oops document { text { \"a\" } } } style { }";
+    let (ast, diagnostics) = parse(lex(source));
+    assert_eq!(diagnostics.len(), 2);
+    assert!(ast.document.is_some());
+    assert!(ast.style.is_some());
+}
+
+#[test]
+fn test_parse_unclosed_block_reports_error() {
+    let source = "document { text { \"a\" }";
+    let (ast, diagnostics) = parse(lex(source));
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(ast.document.unwrap().elements.len(), 1);
+}
+
+// ============================================================================
+// Source Span Tests
+// ============================================================================
+
+fn slice(source: &str, span: pyrus::diagnostics::Span) -> &str {
+    &source[span.start..span.end]
+}
+
+#[test]
+fn test_statement_and_expression_spans() {
+    let source = "template { let total = -price + 2 }";
+    let (ast, _) = parse(lex(source));
+    let statement = &ast.template.unwrap().statements[0];
+    assert_eq!(slice(source, statement.span()), "let total = -price + 2");
+    match statement {
+        Statement::VarAssign { value, .. } => {
+            assert_eq!(slice(source, value.span()), "-price + 2");
+        }
+        _ => panic!("Expected VarAssign"),
+    }
+}
+
+#[test]
+fn test_document_element_spans() {
+    let source = "document { section (class=\"a\") { text { \"hi\" } } greet(1) }";
+    let (ast, _) = parse(lex(source));
+    let elements = ast.document.unwrap().elements;
+    assert_eq!(
+        slice(source, elements[0].span()),
+        "section (class=\"a\") { text { \"hi\" } }"
+    );
+    match &elements[0] {
+        DocElement::Section { elements, .. } => {
+            assert_eq!(slice(source, elements[0].span()), "text { \"hi\" }");
+        }
+        _ => panic!("Expected Section"),
+    }
+    assert_eq!(slice(source, elements[1].span()), "greet(1)");
+}
+
+#[test]
+fn test_style_rule_spans() {
+    let source = "style { .note { font-size = 10 } }";
+    let (ast, _) = parse(lex(source));
+    let rule = &ast.style.unwrap().statements[0];
+    assert_eq!(slice(source, rule.span), ".note { font-size = 10 }");
+    assert_eq!(
+        slice(source, rule.declaration_block[0].span),
+        "font-size = 10"
+    );
+}
+
+#[test]
+fn test_parse_text_escapes_and_link_items() {
+    let source = r#"document {
+    text { "a \"quote\", \\ and \*not bold* or \_this_" }
+    list { item { "one" } link(href="x") { "two \"2\"" } }
+}"#;
+    let (ast, diagnostics) = parse(lex(source));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let elements = ast.document.unwrap().elements;
+    let DocElement::Text {
+        content, inlines, ..
+    } = &elements[0]
+    else {
+        panic!("Expected Text, got {:?}", elements[0]);
+    };
+    assert_eq!(content, r#"a "quote", \ and *not bold* or _this_"#);
+    assert!(matches!(&inlines[..], [Inline::Text(_)]));
+    let DocElement::List { items, .. } = &elements[1] else {
+        panic!("Expected List, got {:?}", elements[1]);
+    };
+    assert!(matches!(
+        &items[..],
+        [DocElement::Text { .. }, DocElement::Link { href, content, .. }]
+            if href == "x" && InterpPart::plain_text(content) == r#"two "2""#
+    ));
+}